@@ -0,0 +1,109 @@
+use bytes::{Bytes, BytesMut};
+
+use crate::{
+    cli::AppendStrategy, error::AppError, hashing, source::SourceRange, Backend, Backends,
+    ObjectMeta, Target,
+};
+
+/// Key of the object native appends are tried on before the run; deleted afterwards
+const PROBE_KEY: &str = "s3-ml-uploader-append-probe";
+
+/// Whether `backend` appends with `x-amz-write-offset-bytes`, tried on a two-byte object
+///
+/// A store that doesn't know the header either rejects the append or ignores it and
+/// replaces the object, which its final size gives away.
+pub async fn probe_native(backends: &Backends, backend: Backend) -> bool {
+    let appended = async {
+        backends
+            .put(
+                backend,
+                Bytes::from_static(b"a"),
+                PROBE_KEY,
+                &ObjectMeta::default(),
+            )
+            .await?;
+        let meta = ObjectMeta {
+            write_offset: Some(1),
+            ..ObjectMeta::default()
+        };
+        backends
+            .put(backend, Bytes::from_static(b"b"), PROBE_KEY, &meta)
+            .await?;
+        backends.head(Target::Backend(backend), PROBE_KEY).await
+    }
+    .await;
+
+    if let Err(err) = backends.delete(backend, PROBE_KEY).await {
+        println!(
+            "Warning: could not delete the append probe {} on {}: {}",
+            PROBE_KEY,
+            backend.name(),
+            err
+        );
+    }
+    matches!(appended, Ok(Some(stored)) if stored.size == 2)
+}
+
+/// Append `body` to the object at `key` on `backend`, creating it if there is none yet
+///
+/// A `retry` finds out whether an earlier attempt already appended (its response lost to
+/// a stall or throttle) from the object ending in `body`, and then doesn't append again.
+/// Read-modify-write uploads the whole object, so its `x-amz-meta-sha256` is updated;
+/// a native append leaves the object's metadata as its first write stored it.
+pub async fn append(
+    backends: &Backends,
+    backend: Backend,
+    strategy: AppendStrategy,
+    body: Bytes,
+    key: &str,
+    meta: &ObjectMeta,
+    retry: bool,
+) -> Result<(), AppError> {
+    let Some(stored) = backends.head(Target::Backend(backend), key).await? else {
+        return backends.put(backend, body, key, meta).await;
+    };
+
+    let len = body.len() as u64;
+    if retry && len > 0 && stored.size >= len {
+        let range = SourceRange {
+            start: stored.size - len,
+            end: None,
+        };
+        let tail = backends
+            .get(Target::Backend(backend), key, Some(range))
+            .await?;
+        if tail == body {
+            println!(
+                "Note: an earlier attempt already appended to {} on {}; not appending again",
+                key,
+                backend.name()
+            );
+            return Ok(());
+        }
+    }
+
+    match strategy {
+        AppendStrategy::Native => {
+            let meta = ObjectMeta {
+                write_offset: Some(stored.size),
+                content_md5: meta.content_md5,
+                ..ObjectMeta::default()
+            };
+            backends.put(backend, body, key, &meta).await
+        }
+        AppendStrategy::Rmw => {
+            let existing = backends.get(Target::Backend(backend), key, None).await?;
+            let mut combined = BytesMut::with_capacity(existing.len() + body.len());
+            combined.extend_from_slice(&existing);
+            combined.extend_from_slice(&body);
+            let combined = combined.freeze();
+
+            let digest = hashing::sha256(&combined);
+            let mut meta = meta.clone();
+            meta.sha256 = Some(digest);
+            meta.metadata
+                .insert(hashing::SHA256_METADATA.to_string(), hex::encode(digest));
+            backends.put(backend, combined, key, &meta).await
+        }
+    }
+}
@@ -0,0 +1,346 @@
+use reqwest::Url;
+use std::{str::FromStr, sync::Arc};
+
+use crate::{
+    config::{self, EnvVar},
+    error::AppError,
+    signing::Signer,
+    Backend,
+};
+
+/// Region used when `AWS_REGION` is not set
+pub const DEFAULT_REGION: &str = "us-east-1";
+
+/// An S3 access point or S3 Object Lambda access point ARN used in place of a bucket name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessPointArn {
+    pub partition: String,
+    // `s3` or `s3-object-lambda`
+    pub service: String,
+    pub region: String,
+    pub account: String,
+    pub name: String,
+}
+
+impl FromStr for AccessPointArn {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.splitn(6, ':').collect();
+        let [prefix, partition, service, region, account, resource] = fields[..] else {
+            return Err(format!("'{}' is not an ARN", s));
+        };
+
+        if prefix != "arn" {
+            return Err(format!("'{}' is not an ARN", s));
+        }
+        if service != "s3" && service != "s3-object-lambda" {
+            return Err(format!("'{}' is not an S3 access point ARN", s));
+        }
+        if region.is_empty() || account.is_empty() {
+            return Err(format!(
+                "access point ARN '{}' needs a region and account",
+                s
+            ));
+        }
+
+        // Both `accesspoint/name` and `accesspoint:name` are valid resource forms
+        let name = resource
+            .strip_prefix("accesspoint/")
+            .or_else(|| resource.strip_prefix("accesspoint:"))
+            .filter(|name| !name.is_empty() && !name.contains(['/', ':']))
+            .ok_or_else(|| format!("'{}' is not an access point ARN", s))?;
+
+        Ok(Self {
+            partition: partition.to_string(),
+            service: service.to_string(),
+            region: region.to_string(),
+            account: account.to_string(),
+            name: name.to_string(),
+        })
+    }
+}
+
+impl AccessPointArn {
+    /// Virtual host of the access point, e.g. `ap-123456789012.s3-accesspoint.us-west-2.amazonaws.com`
+    pub fn host(&self) -> String {
+        let endpoint = match self.service.as_str() {
+            "s3-object-lambda" => "s3-object-lambda",
+            _ => "s3-accesspoint",
+        };
+        let suffix = match self.partition.as_str() {
+            "aws-cn" => "amazonaws.com.cn",
+            _ => "amazonaws.com",
+        };
+
+        format!(
+            "{}-{}.{}.{}.{}",
+            self.name, self.account, endpoint, self.region, suffix
+        )
+    }
+}
+
+/// `AWS_BUCKET` as an access point ARN, `None` for a plain bucket name
+pub fn parse(bucket: &str) -> Result<Option<AccessPointArn>, AppError> {
+    if !bucket.starts_with("arn:") {
+        return Ok(None);
+    }
+
+    bucket
+        .parse()
+        .map(Some)
+        .map_err(|e| AppError::Config(format!("AWS_BUCKET: {}", e)))
+}
+
+/// Access key pair a SigV4 signature is computed with
+#[derive(Debug, Clone)]
+pub struct SigningKey {
+    pub access_key: String,
+    pub secret_key: String,
+    // Sent as `x-amz-security-token` with temporary credentials
+    pub session_token: Option<String>,
+}
+
+impl SigningKey {
+    pub fn from_env(access_key: &EnvVar, secret_key: &EnvVar) -> Self {
+        Self {
+            access_key: access_key.get(),
+            secret_key: secret_key.get(),
+            session_token: None,
+        }
+    }
+}
+
+/// Where the HTTP path sends and signs a PUT to `bucket`
+pub struct HttpEndpoint {
+    // `https`, or `http` for a local GCS_ENDPOINT
+    pub scheme: &'static str,
+    pub host: String,
+    // Leads every key: `/<bucket>` on path-style endpoints, empty on virtual-hosted ones
+    pub path: String,
+    pub region: String,
+    // SigV4 signing name
+    pub service: String,
+    // `None` sends the PUT anonymously (`--no-sign-request`)
+    pub credentials: Option<SigningKey>,
+    // Sign `UNSIGNED-PAYLOAD` instead of the body's SHA-256 (`--unsigned-payload`)
+    pub unsigned_payload: bool,
+    // The store behind the endpoint, whose quirks the request follows
+    pub backend: Backend,
+    // Shared by the endpoints of one set of backends, so they reuse its signing keys
+    pub signer: Arc<Signer>,
+}
+
+impl HttpEndpoint {
+    /// Endpoint of `bucket`, in `region` if given, else `AWS_REGION`; ARNs carry their own region
+    ///
+    /// With `accelerate`, plain buckets go through their Transfer Acceleration host, which
+    /// is global but still signed for the bucket's region.
+    pub fn for_bucket(
+        bucket: &str,
+        region: Option<&str>,
+        accelerate: bool,
+    ) -> Result<Self, AppError> {
+        Ok(match parse(bucket)? {
+            Some(arn) => Self {
+                scheme: "https",
+                host: arn.host(),
+                path: String::new(),
+                region: arn.region.clone(),
+                service: arn.service.clone(),
+                credentials: Some(aws_key()),
+                unsigned_payload: false,
+                backend: Backend::Http,
+                signer: Arc::default(),
+            },
+            None => {
+                let region = region.map_or_else(configured_region, str::to_string);
+                let host = if accelerate {
+                    accelerate_host(bucket)
+                } else if region == DEFAULT_REGION {
+                    format!("{}.s3.amazonaws.com", bucket)
+                } else {
+                    format!("{}.s3.{}.amazonaws.com", bucket, region)
+                };
+                Self {
+                    scheme: "https",
+                    host,
+                    path: String::new(),
+                    region,
+                    service: "s3".to_string(),
+                    credentials: Some(aws_key()),
+                    unsigned_payload: false,
+                    backend: Backend::Http,
+                    signer: Arc::default(),
+                }
+            }
+        })
+    }
+
+    /// Path-style endpoint of the AWS bucket behind `url`, a custom endpoint of the AWS SDK
+    /// such as an S3-compatible store's
+    pub fn for_url(url: &str, region: Option<&str>) -> Result<Self, AppError> {
+        let invalid = |problem: String| AppError::Config(format!("AWS endpoint {}", problem));
+        let parsed =
+            Url::parse(url).map_err(|e| invalid(format!("'{}' is not a URL: {}", url, e)))?;
+        let scheme = match parsed.scheme() {
+            "https" => "https",
+            "http" => "http",
+            _ => return Err(invalid(format!("'{}' is not an http(s) URL", url))),
+        };
+        let host = match (parsed.host_str(), parsed.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => return Err(invalid(format!("'{}' names no host", url))),
+        };
+
+        Ok(Self {
+            scheme,
+            host,
+            path: format!(
+                "{}/{}",
+                parsed.path().trim_end_matches('/'),
+                config::AWS_BUCKET.get()
+            ),
+            region: region.map_or_else(configured_region, str::to_string),
+            service: "s3".to_string(),
+            credentials: Some(aws_key()),
+            unsigned_payload: false,
+            backend: Backend::Http,
+            signer: Arc::default(),
+        })
+    }
+
+    /// Sign requests to the endpoint with `signer`, and the keys it already derived
+    pub fn with_signer(mut self, signer: Arc<Signer>) -> Self {
+        self.signer = signer;
+        self
+    }
+}
+
+fn aws_key() -> SigningKey {
+    SigningKey::from_env(&config::AWS_ACCESS_KEY, &config::AWS_SECRET_KEY)
+}
+
+/// Transfer Acceleration host of `bucket`, e.g. `photos.s3-accelerate.amazonaws.com`
+pub fn accelerate_host(bucket: &str) -> String {
+    format!("{}.s3-accelerate.amazonaws.com", bucket)
+}
+
+/// Region from `AWS_REGION`, falling back to `us-east-1`
+pub fn configured_region() -> String {
+    std::env::var("AWS_REGION").unwrap_or_else(|_| DEFAULT_REGION.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn access_point_arns_parse_in_both_resource_forms() {
+        let slash: AccessPointArn = "arn:aws:s3:us-west-2:123456789012:accesspoint/models"
+            .parse()
+            .unwrap();
+        let colon: AccessPointArn = "arn:aws:s3:us-west-2:123456789012:accesspoint:models"
+            .parse()
+            .unwrap();
+
+        assert_eq!(slash, colon);
+        assert_eq!(
+            slash,
+            AccessPointArn {
+                partition: "aws".to_string(),
+                service: "s3".to_string(),
+                region: "us-west-2".to_string(),
+                account: "123456789012".to_string(),
+                name: "models".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn malformed_arns_are_rejected() {
+        for arn in [
+            "arn:aws:s3:us-west-2:123456789012",
+            "arn:aws:ec2:us-west-2:123456789012:accesspoint/models",
+            "arn:aws:s3::123456789012:accesspoint/models",
+            "arn:aws:s3:us-west-2::accesspoint/models",
+            "arn:aws:s3:us-west-2:123456789012:bucket/models",
+            "arn:aws:s3:us-west-2:123456789012:accesspoint/",
+            "arn:aws:s3:us-west-2:123456789012:accesspoint/models/extra",
+        ] {
+            assert!(arn.parse::<AccessPointArn>().is_err(), "{}", arn);
+        }
+    }
+
+    #[test]
+    fn hosts_follow_service_and_partition() {
+        let host = |arn: &str| arn.parse::<AccessPointArn>().unwrap().host();
+
+        assert_eq!(
+            host("arn:aws:s3:us-west-2:123456789012:accesspoint/models"),
+            "models-123456789012.s3-accesspoint.us-west-2.amazonaws.com"
+        );
+        assert_eq!(
+            host("arn:aws:s3-object-lambda:eu-west-1:123456789012:accesspoint/redact"),
+            "redact-123456789012.s3-object-lambda.eu-west-1.amazonaws.com"
+        );
+        assert_eq!(
+            host("arn:aws-cn:s3:cn-north-1:123456789012:accesspoint/models"),
+            "models-123456789012.s3-accesspoint.cn-north-1.amazonaws.com.cn"
+        );
+    }
+
+    #[test]
+    fn plain_bucket_names_are_not_arns() {
+        assert_eq!(parse("training-data").unwrap(), None);
+        assert!(matches!(
+            parse("arn:aws:s3:us-west-2:123456789012:nothing"),
+            Err(AppError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn arn_endpoints_sign_for_the_arn_region_and_service() {
+        let endpoint = HttpEndpoint::for_bucket(
+            "arn:aws:s3-object-lambda:eu-west-1:123456789012:accesspoint/redact",
+            Some("us-east-1"),
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(
+            endpoint.host,
+            "redact-123456789012.s3-object-lambda.eu-west-1.amazonaws.com"
+        );
+        assert_eq!(endpoint.path, "");
+        assert_eq!(endpoint.region, "eu-west-1");
+        assert_eq!(endpoint.service, "s3-object-lambda");
+    }
+
+    #[test]
+    fn plain_buckets_use_their_regional_host() {
+        let host = |region| {
+            HttpEndpoint::for_bucket("photos", Some(region), false)
+                .unwrap()
+                .host
+        };
+
+        assert_eq!(host("us-east-1"), "photos.s3.amazonaws.com");
+        assert_eq!(host("ap-south-1"), "photos.s3.ap-south-1.amazonaws.com");
+    }
+
+    #[test]
+    fn accelerated_buckets_use_the_global_host_but_sign_regionally() {
+        assert_eq!(
+            accelerate_host("photos"),
+            "photos.s3-accelerate.amazonaws.com"
+        );
+
+        let endpoint = HttpEndpoint::for_bucket("photos", Some("ap-south-1"), true).unwrap();
+        assert_eq!(endpoint.host, "photos.s3-accelerate.amazonaws.com");
+        assert_eq!(endpoint.path, "");
+        assert_eq!(endpoint.region, "ap-south-1");
+        assert_eq!(endpoint.service, "s3");
+    }
+}
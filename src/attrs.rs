@@ -0,0 +1,257 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::{
+    collections::HashMap,
+    fs::{File, FileTimes},
+    io,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::fs;
+
+use crate::error::AppError;
+
+/// User metadata entries written by `--preserve-attrs`
+const MODE: &str = "file-mode";
+const MTIME: &str = "file-mtime";
+const UID: &str = "file-uid";
+const GID: &str = "file-gid";
+
+/// User metadata entry with the file name written by `--store-original-name`
+const ORIGINAL_NAME: &str = "original-name";
+/// Set to `base64` when ORIGINAL_NAME holds the name's bytes encoded, as a header can't
+/// carry them as they are
+const ORIGINAL_NAME_ENCODING: &str = "original-name-encoding";
+
+/// Mode, mtime and owner of a local file as object metadata
+///
+/// Mode and owner only exist on Unix; elsewhere just the mtime is stored.
+pub async fn file_attrs(path: &str) -> io::Result<HashMap<String, String>> {
+    let meta = fs::metadata(path).await?;
+    let mut attrs = HashMap::new();
+
+    if let Ok(modified) = meta.modified() {
+        if let Ok(since_epoch) = modified.duration_since(UNIX_EPOCH) {
+            attrs.insert(
+                MTIME.to_string(),
+                format!(
+                    "{}.{:09}",
+                    since_epoch.as_secs(),
+                    since_epoch.subsec_nanos()
+                ),
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        attrs.insert(MODE.to_string(), format!("{:o}", meta.mode() & 0o7777));
+        attrs.insert(UID.to_string(), meta.uid().to_string());
+        attrs.insert(GID.to_string(), meta.gid().to_string());
+    }
+
+    Ok(attrs)
+}
+
+/// Apply attributes stored by `--preserve-attrs` to a downloaded file
+///
+/// Missing entries are skipped; an owner change that isn't permitted only warns.
+pub fn restore_attrs(path: &str, metadata: &HashMap<String, String>) -> Result<(), AppError> {
+    if let Some(mtime) = metadata.get(MTIME).and_then(|v| parse_mtime(v)) {
+        File::options()
+            .write(true)
+            .open(path)?
+            .set_times(FileTimes::new().set_modified(mtime))?;
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{chown, PermissionsExt};
+
+        let id = |name: &str| metadata.get(name).and_then(|v| v.parse::<u32>().ok());
+        if id(UID).is_some() || id(GID).is_some() {
+            if let Err(err) = chown(path, id(UID), id(GID)) {
+                println!("Could not restore owner of {}: {}", path, err);
+            }
+        }
+
+        // After chown, which may clear setuid/setgid bits
+        if let Some(mode) = metadata
+            .get(MODE)
+            .and_then(|v| u32::from_str_radix(v, 8).ok())
+        {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+        }
+    }
+
+    #[cfg(not(unix))]
+    if metadata.contains_key(MODE) || metadata.contains_key(UID) {
+        println!(
+            "Mode and owner of {} are not restored on this platform",
+            path
+        );
+    }
+
+    Ok(())
+}
+
+/// The file name of `path` as object metadata, for `download --restore-names`
+///
+/// Names that aren't printable ASCII are stored base64-encoded.
+pub fn original_name(path: &str) -> HashMap<String, String> {
+    let mut metadata = HashMap::new();
+    let Some(name) = Path::new(path).file_name() else {
+        return metadata;
+    };
+    let bytes = name.as_encoded_bytes();
+    if bytes
+        .iter()
+        .all(|byte| byte.is_ascii_graphic() || *byte == b' ')
+    {
+        metadata.insert(
+            ORIGINAL_NAME.to_string(),
+            String::from_utf8_lossy(bytes).into_owned(),
+        );
+    } else {
+        metadata.insert(ORIGINAL_NAME.to_string(), STANDARD.encode(bytes));
+        metadata.insert(ORIGINAL_NAME_ENCODING.to_string(), "base64".to_string());
+    }
+    metadata
+}
+
+/// The file name stored by `--store-original-name`, if there is one
+///
+/// A name that would leave the current directory (a path, `.` or `..`) is refused.
+pub fn restored_name(metadata: &HashMap<String, String>) -> Result<Option<String>, AppError> {
+    let Some(value) = metadata.get(ORIGINAL_NAME) else {
+        return Ok(None);
+    };
+    let invalid =
+        |problem: &str| AppError::Config(format!("stored original name '{}' {}", value, problem));
+    let name = match metadata.get(ORIGINAL_NAME_ENCODING).map(String::as_str) {
+        None => value.clone(),
+        Some("base64") => {
+            let bytes = STANDARD
+                .decode(value)
+                .map_err(|_| invalid("is not valid base64"))?;
+            String::from_utf8(bytes).map_err(|_| invalid("is not UTF-8"))?
+        }
+        Some(other) => return Err(invalid(&format!("has unknown encoding '{}'", other))),
+    };
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\', '\0']) {
+        return Err(invalid("is not a plain file name"));
+    }
+    Ok(Some(name))
+}
+
+/// `secs.nanos` since the Unix epoch
+fn parse_mtime(value: &str) -> Option<SystemTime> {
+    let (secs, nanos) = value.split_once('.').unwrap_or((value, "0"));
+    let since_epoch = Duration::new(secs.parse().ok()?, nanos.parse().ok()?);
+
+    UNIX_EPOCH.checked_add(since_epoch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+
+    #[test]
+    fn mtimes_keep_their_nanoseconds() {
+        let mtime = UNIX_EPOCH + Duration::new(1_700_000_000, 5);
+        assert_eq!(parse_mtime("1700000000.000000005"), Some(mtime));
+        assert_eq!(
+            parse_mtime("1700000000"),
+            Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+        );
+        assert_eq!(parse_mtime("yesterday"), None);
+    }
+
+    #[tokio::test]
+    async fn stored_attributes_apply_to_another_file() {
+        let dir = TestDir::new();
+        let source = dir.write("source", "content");
+        let target = dir.write("target", "content");
+        let mtime = UNIX_EPOCH + Duration::new(1_600_000_000, 42);
+        File::options()
+            .write(true)
+            .open(&source)
+            .unwrap()
+            .set_times(FileTimes::new().set_modified(mtime))
+            .unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&source, std::fs::Permissions::from_mode(0o604)).unwrap();
+        }
+
+        let attrs = file_attrs(&source).await.unwrap();
+        restore_attrs(&target, &attrs).unwrap();
+
+        let restored = std::fs::metadata(&target).unwrap();
+        assert_eq!(restored.modified().unwrap(), mtime);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(restored.permissions().mode() & 0o7777, 0o604);
+        }
+    }
+
+    #[test]
+    fn missing_entries_are_skipped() {
+        let dir = TestDir::new();
+        let target = dir.write("target", "content");
+        restore_attrs(&target, &HashMap::new()).unwrap();
+    }
+
+    #[test]
+    fn printable_names_are_stored_as_they_are() {
+        let metadata = original_name("data/My Report (v2).pdf");
+        assert_eq!(metadata.len(), 1);
+        assert_eq!(metadata[ORIGINAL_NAME], "My Report (v2).pdf");
+        assert_eq!(
+            restored_name(&metadata).unwrap().as_deref(),
+            Some("My Report (v2).pdf")
+        );
+    }
+
+    #[test]
+    fn other_names_round_trip_base64_encoded() {
+        for name in ["résumé.txt", "日本語.md", "tab\there.txt"] {
+            let metadata = original_name(&format!("data/{}", name));
+            assert_eq!(metadata[ORIGINAL_NAME_ENCODING], "base64");
+            assert!(metadata[ORIGINAL_NAME].is_ascii());
+            assert_eq!(restored_name(&metadata).unwrap().as_deref(), Some(name));
+        }
+    }
+
+    #[test]
+    fn objects_without_a_name_restore_none() {
+        assert_eq!(restored_name(&HashMap::new()).unwrap(), None);
+    }
+
+    #[test]
+    fn names_leaving_the_directory_or_badly_encoded_are_refused() {
+        let stored = |name: &str, encoding: Option<&str>| {
+            let mut metadata = HashMap::from([(ORIGINAL_NAME.to_string(), name.to_string())]);
+            if let Some(encoding) = encoding {
+                metadata.insert(ORIGINAL_NAME_ENCODING.to_string(), encoding.to_string());
+            }
+            restored_name(&metadata)
+        };
+        for name in ["..", ".", "", "../etc/passwd", "a\\b"] {
+            assert!(
+                matches!(stored(name, None), Err(AppError::Config(_))),
+                "{}",
+                name
+            );
+        }
+        let traversal = STANDARD.encode("x/../../y");
+        assert!(stored(&traversal, Some("base64")).is_err());
+        assert!(stored("not base64!", Some("base64")).is_err());
+        assert!(stored(&STANDARD.encode([0xff, 0xfe]), Some("base64")).is_err());
+        assert!(stored("notes.txt", Some("rot13")).is_err());
+    }
+}
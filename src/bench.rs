@@ -0,0 +1,275 @@
+use bytes::Bytes;
+use futures::future::join_all;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    cli::{format_size, BenchArgs},
+    concurrency::ConcurrencyLimiter,
+    error::AppError,
+    source,
+    tls::TlsConfig,
+    Backend, Backends, ObjectMeta,
+};
+
+/// Results of benchmarking a single backend
+struct BenchResult {
+    backend: Backend,
+    objects: usize,
+    errors: usize,
+    bytes: u64,
+    elapsed: Duration,
+    latencies: Vec<Duration>,
+}
+
+impl BenchResult {
+    fn throughput_mb_s(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs == 0.0 {
+            return 0.0;
+        }
+        self.bytes as f64 / 1_000_000.0 / secs
+    }
+
+    fn error_rate(&self) -> f64 {
+        if self.objects == 0 {
+            return 0.0;
+        }
+        self.errors as f64 / self.objects as f64
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "backend": self.backend.name(),
+            "objects": self.objects,
+            "errors": self.errors,
+            "error_rate": self.error_rate(),
+            "bytes": self.bytes,
+            "elapsed_ms": self.elapsed.as_millis() as u64,
+            "throughput_mb_s": self.throughput_mb_s(),
+            "p50_ms": percentile(&self.latencies, 50.0).map(|d| d.as_secs_f64() * 1000.0),
+            "p95_ms": percentile(&self.latencies, 95.0).map(|d| d.as_secs_f64() * 1000.0),
+        })
+    }
+}
+
+/// Upload synthetic objects to every backend and report throughput and latency
+pub async fn run(args: BenchArgs) -> Result<(), AppError> {
+    if !args.read_buffer_sizes.is_empty() {
+        return bench_reads(&args).await;
+    }
+
+    let tls = TlsConfig::load(&args.tls)?;
+    let limiter = ConcurrencyLimiter::new(args.concurrency, false);
+    let backends = Arc::new(
+        Backends::connect(limiter, &tls, &Backend::ALL, false)
+            .await?
+            .with_accelerate(args.accelerate)
+            .await?,
+    );
+
+    // Generated once in memory and shared by every request
+    let body = synthetic_data(args.size as usize);
+
+    let mut results = Vec::new();
+    for backend in Backend::ALL {
+        if !args.json {
+            println!(
+                "Benchmarking {} ({} x {} bytes)...",
+                backend.name(),
+                args.count,
+                args.size
+            );
+        }
+        results.push(bench_backend(&backends, backend, &args, body.clone()).await);
+    }
+
+    if args.json {
+        let report: Vec<_> = results.iter().map(BenchResult::to_json).collect();
+        println!("{}", serde_json::Value::Array(report));
+    } else {
+        print_table(&results);
+    }
+
+    Ok(())
+}
+
+async fn bench_backend(
+    backends: &Arc<Backends>,
+    backend: Backend,
+    args: &BenchArgs,
+    body: Bytes,
+) -> BenchResult {
+    let meta = ObjectMeta::with_content_type("application/octet-stream");
+    let keys: Vec<String> = (0..args.count)
+        .map(|i| format!("{}{}/object-{:04}", args.prefix, backend_slug(backend), i))
+        .collect();
+
+    let started = Instant::now();
+    let outcomes = join_all(keys.iter().map(|key| {
+        let body = body.clone();
+        let meta = &meta;
+        async move {
+            let _permit = backends.limiter.acquire().await;
+            let request_started = Instant::now();
+            let result = backends.put(backend, body, key, meta).await;
+            (result, request_started.elapsed())
+        }
+    }))
+    .await;
+    let elapsed = started.elapsed();
+
+    let mut latencies = Vec::new();
+    let mut errors = 0;
+    for (key, (result, latency)) in keys.iter().zip(outcomes) {
+        match result {
+            Ok(()) => latencies.push(latency),
+            Err(err) => {
+                errors += 1;
+                if !args.json {
+                    eprintln!("  {} {}: {}", backend.name(), key, err);
+                }
+            }
+        }
+    }
+
+    // Remove the probe objects; failures here don't affect the measurement
+    join_all(keys.iter().map(|key| backends.delete(backend, key))).await;
+
+    latencies.sort();
+    BenchResult {
+        backend,
+        objects: args.count,
+        errors,
+        bytes: latencies.len() as u64 * body.len() as u64,
+        elapsed,
+        latencies,
+    }
+}
+
+/// Read a synthetic file `--count` times per `--read-buffer-sizes` entry and report MB/s
+///
+/// After the first pass the file is in the page cache, so this measures the per-call
+/// overhead of the buffer size rather than the disk itself.
+async fn bench_reads(args: &BenchArgs) -> Result<(), AppError> {
+    let path = std::env::temp_dir().join(format!("s3-ml-uploader-bench-{}", std::process::id()));
+    tokio::fs::write(&path, synthetic_data(args.size as usize)).await?;
+    let file = path.to_string_lossy();
+
+    let mut report = Vec::new();
+    for &buffer_size in &args.read_buffer_sizes {
+        let started = Instant::now();
+        let mut result = Ok(());
+        for _ in 0..args.count {
+            if let Err(err) = source::read_source(&file, None, buffer_size).await {
+                result = Err(err);
+                break;
+            }
+        }
+        if let Err(err) = result {
+            let _ = tokio::fs::remove_file(&path).await;
+            return Err(err);
+        }
+
+        let secs = started.elapsed().as_secs_f64();
+        let throughput = if secs == 0.0 {
+            0.0
+        } else {
+            (args.size * args.count as u64) as f64 / 1_000_000.0 / secs
+        };
+        report.push((buffer_size, started.elapsed(), throughput));
+    }
+    let _ = tokio::fs::remove_file(&path).await;
+
+    if args.json {
+        let report: Vec<_> = report
+            .iter()
+            .map(|(buffer_size, elapsed, throughput)| {
+                serde_json::json!({
+                    "read_buffer_size": buffer_size,
+                    "reads": args.count,
+                    "elapsed_ms": elapsed.as_millis() as u64,
+                    "throughput_mb_s": throughput,
+                })
+            })
+            .collect();
+        println!("{}", serde_json::Value::Array(report));
+    } else {
+        println!(
+            "Reading {} x {} bytes per buffer size...",
+            args.count, args.size
+        );
+        println!("{:<12} {:>10}", "buffer", "MB/s");
+        for (buffer_size, _, throughput) in report {
+            println!(
+                "{:<12} {:>10.2}",
+                format_size(buffer_size as u64),
+                throughput
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn backend_slug(backend: Backend) -> &'static str {
+    match backend {
+        Backend::Aws => "aws",
+        Backend::Minio => "minio",
+        Backend::Http => "http",
+        Backend::Gcs => "gcs",
+    }
+}
+
+/// Incompressible pseudo-random bytes (xorshift), so compression can't skew results
+fn synthetic_data(size: usize) -> Bytes {
+    let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+    let mut data = Vec::with_capacity(size + 8);
+
+    while data.len() < size {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        data.extend_from_slice(&state.to_le_bytes());
+    }
+
+    data.truncate(size);
+    data.into()
+}
+
+/// Nearest-rank percentile of sorted latencies
+fn percentile(sorted: &[Duration], pct: f64) -> Option<Duration> {
+    if sorted.is_empty() {
+        return None;
+    }
+
+    let rank = ((pct / 100.0) * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+fn format_ms(latency: Option<Duration>) -> String {
+    latency
+        .map(|d| format!("{:.1}", d.as_secs_f64() * 1000.0))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+fn print_table(results: &[BenchResult]) {
+    println!(
+        "{:<8} {:>8} {:>10} {:>10} {:>10} {:>8}",
+        "backend", "objects", "MB/s", "p50 ms", "p95 ms", "errors"
+    );
+
+    for result in results {
+        println!(
+            "{:<8} {:>8} {:>10.2} {:>10} {:>10} {:>7.1}%",
+            result.backend.name(),
+            result.objects,
+            result.throughput_mb_s(),
+            format_ms(percentile(&result.latencies, 50.0)),
+            format_ms(percentile(&result.latencies, 95.0)),
+            result.error_rate() * 100.0
+        );
+    }
+}
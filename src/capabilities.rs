@@ -0,0 +1,298 @@
+use aws_sdk_s3::{
+    primitives::DateTime as SdkDateTime,
+    types::{ObjectLockMode, ServerSideEncryption},
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use clap::ValueEnum;
+
+use crate::{
+    cli::{LockMode, OnUnsupported, SseMode, UploadArgs},
+    error::AppError,
+};
+
+/// Optional object features a backend declares support for
+#[derive(Debug, Clone, Copy)]
+pub struct Capabilities {
+    pub storage_classes: &'static [&'static str],
+    pub sse_s3: bool,
+    pub sse_kms: bool,
+    pub object_lock: bool,
+}
+
+/// Every storage class accepted by `--storage-class`
+pub const ALL_STORAGE_CLASSES: &[&str] = &[
+    "STANDARD",
+    "REDUCED_REDUNDANCY",
+    "STANDARD_IA",
+    "ONEZONE_IA",
+    "INTELLIGENT_TIERING",
+    "GLACIER",
+    "GLACIER_IR",
+    "DEEP_ARCHIVE",
+];
+
+/// Server-side encryption requested for uploaded objects
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Encryption {
+    S3,
+    Kms { key_id: Option<String> },
+}
+
+/// Object lock retention requested for uploaded objects
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectLock {
+    pub mode: LockMode,
+    pub retain_until: DateTime<Utc>,
+}
+
+/// Storage class, encryption and retention applied to every uploaded object
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StorageOptions {
+    pub storage_class: Option<String>,
+    pub encryption: Option<Encryption>,
+    pub object_lock: Option<ObjectLock>,
+}
+
+impl StorageOptions {
+    /// Options requested on the command line
+    pub fn from_args(args: &UploadArgs) -> Result<Self, AppError> {
+        let encryption = args.sse.map(|mode| match mode {
+            SseMode::Aes256 => Encryption::S3,
+            SseMode::Kms => Encryption::Kms {
+                key_id: args.sse_kms_key_id.clone(),
+            },
+        });
+
+        let object_lock = match (args.object_lock_mode, args.object_lock_retain) {
+            (Some(mode), Some(retain)) => {
+                let retain = chrono::Duration::from_std(retain).map_err(|_| {
+                    AppError::Config("--object-lock-retain is too large".to_string())
+                })?;
+                Some(ObjectLock {
+                    mode,
+                    retain_until: Utc::now() + retain,
+                })
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            storage_class: args.storage_class.clone(),
+            encryption,
+            object_lock,
+        })
+    }
+
+    /// The subset supported by a backend; unsupported options warn or fail per `policy`
+    pub fn supported_by(
+        &self,
+        backend: &'static str,
+        capabilities: &Capabilities,
+        policy: OnUnsupported,
+    ) -> Result<Self, AppError> {
+        let mut supported = self.clone();
+        let reject = |feature: String| match policy {
+            OnUnsupported::Error => Err(AppError::Unsupported { backend, feature }),
+            OnUnsupported::Warn => {
+                println!(
+                    "Warning: {} does not support {}, uploading without it",
+                    backend, feature
+                );
+                Ok(())
+            }
+        };
+
+        if let Some(class) = &self.storage_class {
+            if !capabilities.storage_classes.contains(&class.as_str()) {
+                supported.storage_class = None;
+                reject(format!("storage class {}", class))?;
+            }
+        }
+
+        match &self.encryption {
+            Some(Encryption::S3) if !capabilities.sse_s3 => {
+                supported.encryption = None;
+                reject("SSE-S3 encryption".to_string())?;
+            }
+            Some(Encryption::Kms { .. }) if !capabilities.sse_kms => {
+                supported.encryption = None;
+                reject("SSE-KMS encryption".to_string())?;
+            }
+            _ => {}
+        }
+
+        if self.object_lock.is_some() && !capabilities.object_lock {
+            supported.object_lock = None;
+            reject("object lock".to_string())?;
+        }
+
+        Ok(supported)
+    }
+
+    /// Encryption and KMS key for the AWS SDK's `set_server_side_encryption`/`set_ssekms_key_id`
+    pub fn sdk_encryption(&self) -> (Option<ServerSideEncryption>, Option<String>) {
+        match &self.encryption {
+            Some(Encryption::S3) => (Some(ServerSideEncryption::Aes256), None),
+            Some(Encryption::Kms { key_id }) => {
+                (Some(ServerSideEncryption::AwsKms), key_id.clone())
+            }
+            None => (None, None),
+        }
+    }
+
+    /// Lock mode and retain-until date for the AWS SDK's object lock setters
+    pub fn sdk_object_lock(&self) -> (Option<ObjectLockMode>, Option<SdkDateTime>) {
+        match &self.object_lock {
+            Some(lock) => (
+                Some(ObjectLockMode::from(lock.mode.header().as_str())),
+                Some(SdkDateTime::from_secs(lock.retain_until.timestamp())),
+            ),
+            None => (None, None),
+        }
+    }
+
+    /// `x-amz-*` request headers for backends without typed setters
+    pub fn headers(&self) -> Vec<(String, String)> {
+        let mut headers = Vec::new();
+
+        if let Some(class) = &self.storage_class {
+            headers.push(("x-amz-storage-class".to_string(), class.clone()));
+        }
+
+        match &self.encryption {
+            Some(Encryption::S3) => {
+                headers.push((
+                    "x-amz-server-side-encryption".to_string(),
+                    "AES256".to_string(),
+                ));
+            }
+            Some(Encryption::Kms { key_id }) => {
+                headers.push((
+                    "x-amz-server-side-encryption".to_string(),
+                    "aws:kms".to_string(),
+                ));
+                if let Some(key_id) = key_id {
+                    headers.push((
+                        "x-amz-server-side-encryption-aws-kms-key-id".to_string(),
+                        key_id.clone(),
+                    ));
+                }
+            }
+            None => {}
+        }
+
+        if let Some(lock) = &self.object_lock {
+            headers.push(("x-amz-object-lock-mode".to_string(), lock.mode.header()));
+            headers.push((
+                "x-amz-object-lock-retain-until-date".to_string(),
+                lock.retain_until.to_rfc3339_opts(SecondsFormat::Secs, true),
+            ));
+        }
+
+        headers
+    }
+}
+
+impl LockMode {
+    /// Value of `x-amz-object-lock-mode`
+    pub fn header(&self) -> String {
+        self.to_possible_value()
+            .map(|v| v.get_name().to_uppercase())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITED: Capabilities = Capabilities {
+        storage_classes: &["STANDARD"],
+        sse_s3: true,
+        sse_kms: false,
+        object_lock: false,
+    };
+
+    const FULL: Capabilities = Capabilities {
+        storage_classes: ALL_STORAGE_CLASSES,
+        sse_s3: true,
+        sse_kms: true,
+        object_lock: true,
+    };
+
+    fn requested() -> StorageOptions {
+        StorageOptions {
+            storage_class: Some("GLACIER".to_string()),
+            encryption: Some(Encryption::Kms { key_id: None }),
+            object_lock: Some(ObjectLock {
+                mode: LockMode::Governance,
+                retain_until: Utc::now(),
+            }),
+        }
+    }
+
+    #[test]
+    fn unsupported_features_are_an_error_under_error() {
+        for options in [
+            StorageOptions {
+                storage_class: Some("GLACIER".to_string()),
+                ..StorageOptions::default()
+            },
+            StorageOptions {
+                encryption: Some(Encryption::Kms { key_id: None }),
+                ..StorageOptions::default()
+            },
+            StorageOptions {
+                object_lock: requested().object_lock,
+                ..StorageOptions::default()
+            },
+        ] {
+            let err = options
+                .supported_by("MinIO", &LIMITED, OnUnsupported::Error)
+                .unwrap_err();
+            assert!(
+                matches!(
+                    err,
+                    AppError::Unsupported {
+                        backend: "MinIO",
+                        ..
+                    }
+                ),
+                "{:?}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn unsupported_features_are_dropped_under_warn() {
+        let supported = requested()
+            .supported_by("MinIO", &LIMITED, OnUnsupported::Warn)
+            .unwrap();
+        assert_eq!(supported, StorageOptions::default());
+        assert!(supported.headers().is_empty());
+    }
+
+    #[test]
+    fn supported_features_pass_through() {
+        let requested = requested();
+        assert_eq!(
+            requested
+                .supported_by("AWS S3", &FULL, OnUnsupported::Error)
+                .unwrap(),
+            requested
+        );
+
+        let sse_s3 = StorageOptions {
+            storage_class: Some("STANDARD".to_string()),
+            encryption: Some(Encryption::S3),
+            object_lock: None,
+        };
+        assert_eq!(
+            sse_s3
+                .supported_by("MinIO", &LIMITED, OnUnsupported::Error)
+                .unwrap(),
+            sse_s3
+        );
+    }
+}
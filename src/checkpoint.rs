@@ -0,0 +1,713 @@
+use serde_json::{json, Value};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::UNIX_EPOCH,
+};
+
+use crate::{
+    error::AppError,
+    hashing::{self, Sha256Digest},
+    ml::Classification,
+    source::{self, SourceRange},
+    Backend,
+};
+
+/// Directory holding the progress file of every unfinished batch, unless `--checkpoint-dir`
+/// names another
+const CHECKPOINT_DIR: &str = ".s3-ml-uploader/batches";
+
+/// Identifies a batch by its file arguments and target backends
+///
+/// Patterns are hashed as given, so a glob that matches new files still resumes its batch.
+pub fn batch_id(inputs: &[String], backends: &[Backend]) -> String {
+    let mut material = inputs.join("\n");
+    for backend in backends {
+        material.push('\n');
+        material.push_str(backend.name());
+    }
+    hashing::sha256_hex(material.as_bytes())[..16].to_string()
+}
+
+/// A file recorded as uploaded, with what is needed to tell whether it changed since
+#[derive(Clone)]
+struct Entry {
+    size: u64,
+    mtime_ns: Option<u64>,
+    // Missing for a body read unhashed (`--unsigned-payload`); always kept in a state database
+    sha256: Option<String>,
+}
+
+/// Progress of one batch, so an interrupted run can skip what it already uploaded
+///
+/// Kept as a progress file with `--resume-batch` or `--checkpoint-dir`, or in a `--state-db`
+/// SQLite database (`sqlite` feature) that also holds each file's classification and every
+/// object's status per target, and outlives the batch for incremental re-runs. Without any
+/// of them nothing is kept.
+pub struct Checkpoint {
+    store: Store,
+}
+
+enum Store {
+    /// Progress isn't kept, so the run can't be resumed
+    Off,
+    /// One JSON line per completed file, appended and flushed as files finish so an
+    /// interrupted run loses nothing; removed once the whole batch succeeds
+    Lines {
+        path: PathBuf,
+        done: HashMap<String, Entry>,
+        // Closed by `finish`
+        writer: Mutex<Option<File>>,
+    },
+    #[cfg(feature = "sqlite")]
+    Db(db::StateDb),
+}
+
+/// Outcome of one object on one target, as `--state-db` records it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectStatus {
+    Uploaded,
+    Unchanged,
+    Failed,
+}
+
+impl ObjectStatus {
+    #[cfg_attr(not(feature = "sqlite"), allow(dead_code))]
+    fn as_str(self) -> &'static str {
+        match self {
+            ObjectStatus::Uploaded => "uploaded",
+            ObjectStatus::Unchanged => "unchanged",
+            ObjectStatus::Failed => "failed",
+        }
+    }
+}
+
+impl Checkpoint {
+    /// Open the progress of `batch_id`, keeping earlier entries only when resuming
+    ///
+    /// With `state_db` progress goes to that database, else to a progress file in `dir`
+    /// (by default [`CHECKPOINT_DIR`] when resuming), else nowhere.
+    pub fn open(
+        batch_id: &str,
+        resume: bool,
+        dir: Option<&Path>,
+        state_db: Option<&Path>,
+    ) -> Result<Self, AppError> {
+        if let Some(state_db) = state_db {
+            #[cfg(feature = "sqlite")]
+            return Ok(Self {
+                store: Store::Db(db::StateDb::open(state_db, batch_id, resume)?),
+            });
+
+            #[cfg(not(feature = "sqlite"))]
+            return Err(AppError::Config(format!(
+                "--state-db {} needs a build with the `sqlite` feature",
+                state_db.display()
+            )));
+        }
+
+        let dir = match dir {
+            Some(dir) => dir,
+            None if resume => Path::new(CHECKPOINT_DIR),
+            None => return Ok(Self { store: Store::Off }),
+        };
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!("{}.jsonl", batch_id));
+
+        let done = if resume && path.exists() {
+            load(&path)?
+        } else {
+            HashMap::new()
+        };
+
+        let writer = OpenOptions::new()
+            .create(true)
+            .append(resume)
+            .write(true)
+            .truncate(!resume)
+            .open(&path)?;
+
+        Ok(Self {
+            store: Store::Lines {
+                path,
+                done,
+                writer: Mutex::new(Some(writer)),
+            },
+        })
+    }
+
+    /// Whether `path` was already uploaded by this batch and hasn't changed since
+    ///
+    /// A matching size and mtime is trusted; a file whose mtime moved is re-hashed and
+    /// only counts as done if its content is still the same, so without a recorded digest
+    /// it never does.
+    pub async fn is_done(
+        &self,
+        path: &str,
+        range: Option<SourceRange>,
+        buffer_size: usize,
+    ) -> Result<bool, AppError> {
+        let entry = match &self.store {
+            Store::Off => None,
+            Store::Lines { done, .. } => done.get(path).map(Cow::Borrowed),
+            #[cfg(feature = "sqlite")]
+            Store::Db(db) => db.entry(path)?.map(Cow::Owned),
+        };
+        let Some(entry) = entry else {
+            return Ok(false);
+        };
+
+        let meta = match tokio::fs::metadata(path).await {
+            Ok(meta) => meta,
+            Err(_) => return Ok(false),
+        };
+        if meta.len() != entry.size {
+            return Ok(false);
+        }
+        if entry.mtime_ns.is_some() && mtime_ns(&meta) == entry.mtime_ns {
+            return Ok(true);
+        }
+        let Some(sha256) = entry.sha256.as_deref() else {
+            return Ok(false);
+        };
+
+        let body = source::read_source(path, range, buffer_size).await?;
+        Ok(hex::encode(body.sha256) == sha256)
+    }
+
+    /// Mark `path` as uploaded under `key` with the digest of the body that was sent, if it
+    /// was hashed
+    #[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
+    pub async fn record(
+        &self,
+        path: &str,
+        sha256: Option<&Sha256Digest>,
+        key: &str,
+        classification: &Classification,
+    ) -> Result<(), AppError> {
+        if matches!(self.store, Store::Off) {
+            return Ok(());
+        }
+        let meta = tokio::fs::metadata(path).await?;
+        let entry = Entry {
+            size: meta.len(),
+            mtime_ns: mtime_ns(&meta),
+            sha256: sha256.map(hex::encode),
+        };
+
+        match &self.store {
+            Store::Off => {}
+            Store::Lines { writer, .. } => {
+                let line = json!({
+                    "file": path,
+                    "size": entry.size,
+                    "mtime_ns": entry.mtime_ns,
+                    "sha256": entry.sha256,
+                });
+                let mut writer = writer.lock().unwrap_or_else(|e| e.into_inner());
+                if let Some(writer) = writer.as_mut() {
+                    writeln!(writer, "{}", line)?;
+                    writer.flush()?;
+                }
+            }
+            #[cfg(feature = "sqlite")]
+            Store::Db(db) => db.record(path, &entry, key, classification)?,
+        }
+        Ok(())
+    }
+
+    /// Note what became of object `key` of `path` on `target`; only `--state-db` keeps it
+    #[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
+    pub fn record_object(
+        &self,
+        path: &str,
+        target: &str,
+        key: &str,
+        status: ObjectStatus,
+        error: Option<&AppError>,
+    ) -> Result<(), AppError> {
+        match &self.store {
+            Store::Off | Store::Lines { .. } => Ok(()),
+            #[cfg(feature = "sqlite")]
+            Store::Db(db) => db.record_object(path, target, key, status, error),
+        }
+    }
+
+    /// Remove the progress file after the whole batch succeeded; a state database stays
+    pub fn finish(&self) -> Result<(), AppError> {
+        match &self.store {
+            Store::Off => {}
+            Store::Lines { path, writer, .. } => {
+                writer.lock().unwrap_or_else(|e| e.into_inner()).take();
+                fs::remove_file(path)?;
+            }
+            #[cfg(feature = "sqlite")]
+            Store::Db(_) => {}
+        }
+        Ok(())
+    }
+}
+
+fn load(path: &PathBuf) -> Result<HashMap<String, Entry>, AppError> {
+    let mut done = HashMap::new();
+
+    // A line cut short by the interruption is simply not counted
+    for line in BufReader::new(File::open(path)?).lines() {
+        let Ok(value) = serde_json::from_str::<Value>(&line?) else {
+            continue;
+        };
+        let (Some(file), Some(size)) = (
+            value.get("file").and_then(Value::as_str),
+            value.get("size").and_then(Value::as_u64),
+        ) else {
+            continue;
+        };
+
+        done.insert(
+            file.to_string(),
+            Entry {
+                size,
+                mtime_ns: value.get("mtime_ns").and_then(Value::as_u64),
+                sha256: value
+                    .get("sha256")
+                    .and_then(Value::as_str)
+                    .map(String::from),
+            },
+        );
+    }
+
+    Ok(done)
+}
+
+fn mtime_ns(meta: &std::fs::Metadata) -> Option<u64> {
+    let since_epoch = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    u64::try_from(since_epoch.as_nanos()).ok()
+}
+
+#[cfg(feature = "sqlite")]
+mod db {
+    use rusqlite::{params, Connection, OptionalExtension};
+    use std::{path::Path, sync::Mutex};
+
+    use super::{Entry, ObjectStatus};
+    use crate::{error::AppError, ml::Classification};
+
+    /// Tables of every batch; `files` and `objects` are looked up by their primary keys, and
+    /// `objects` by file as well
+    const SCHEMA: &str = "
+        CREATE TABLE IF NOT EXISTS files (
+            batch TEXT NOT NULL,
+            path TEXT NOT NULL,
+            size INTEGER NOT NULL,
+            mtime_ns INTEGER,
+            sha256 TEXT NOT NULL,
+            key TEXT NOT NULL,
+            category TEXT NOT NULL,
+            confidence REAL NOT NULL,
+            uploaded_at TEXT NOT NULL,
+            PRIMARY KEY (batch, path)
+        );
+        CREATE TABLE IF NOT EXISTS objects (
+            batch TEXT NOT NULL,
+            path TEXT NOT NULL,
+            target TEXT NOT NULL,
+            key TEXT NOT NULL,
+            status TEXT NOT NULL,
+            error TEXT,
+            updated_at TEXT NOT NULL,
+            PRIMARY KEY (batch, key, target)
+        );
+        CREATE INDEX IF NOT EXISTS objects_by_path ON objects (batch, path);
+    ";
+
+    fn failed(err: rusqlite::Error) -> AppError {
+        AppError::Config(format!("--state-db: {}", err))
+    }
+
+    /// Progress of one batch in a SQLite database shared by every batch run against it
+    ///
+    /// WAL mode lets other processes query it while a run writes.
+    pub struct StateDb {
+        connection: Mutex<Connection>,
+        batch: String,
+    }
+
+    impl StateDb {
+        /// Open (or create) the database at `path`, forgetting the batch unless resuming
+        pub fn open(path: &Path, batch: &str, resume: bool) -> Result<Self, AppError> {
+            let connection = Connection::open(path).map_err(failed)?;
+            connection
+                .pragma_update(None, "journal_mode", "WAL")
+                .map_err(failed)?;
+            connection.execute_batch(SCHEMA).map_err(failed)?;
+            if !resume {
+                connection
+                    .execute("DELETE FROM files WHERE batch = ?1", [batch])
+                    .map_err(failed)?;
+                connection
+                    .execute("DELETE FROM objects WHERE batch = ?1", [batch])
+                    .map_err(failed)?;
+            }
+            Ok(Self {
+                connection: Mutex::new(connection),
+                batch: batch.to_string(),
+            })
+        }
+
+        pub fn entry(&self, path: &str) -> Result<Option<Entry>, AppError> {
+            let connection = self.connection.lock().unwrap_or_else(|e| e.into_inner());
+            connection
+                .query_row(
+                    "SELECT size, mtime_ns, sha256 FROM files WHERE batch = ?1 AND path = ?2",
+                    params![self.batch, path],
+                    |row| {
+                        Ok(Entry {
+                            size: row.get::<_, i64>(0)? as u64,
+                            mtime_ns: row.get::<_, Option<i64>>(1)?.map(|ns| ns as u64),
+                            sha256: row.get(2)?,
+                        })
+                    },
+                )
+                .optional()
+                .map_err(failed)
+        }
+
+        pub fn record(
+            &self,
+            path: &str,
+            entry: &Entry,
+            key: &str,
+            classification: &Classification,
+        ) -> Result<(), AppError> {
+            let connection = self.connection.lock().unwrap_or_else(|e| e.into_inner());
+            connection
+                .execute(
+                    "INSERT OR REPLACE INTO files
+                         (batch, path, size, mtime_ns, sha256, key, category, confidence, uploaded_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                    params![
+                        self.batch,
+                        path,
+                        // SQLite integers are signed; sizes and times fit anyway
+                        entry.size as i64,
+                        entry.mtime_ns.map(|ns| ns as i64),
+                        entry.sha256,
+                        key,
+                        classification.category.as_str(),
+                        classification.confidence,
+                        chrono::Utc::now().to_rfc3339(),
+                    ],
+                )
+                .map_err(failed)?;
+            Ok(())
+        }
+
+        pub fn record_object(
+            &self,
+            path: &str,
+            target: &str,
+            key: &str,
+            status: ObjectStatus,
+            error: Option<&AppError>,
+        ) -> Result<(), AppError> {
+            let connection = self.connection.lock().unwrap_or_else(|e| e.into_inner());
+            connection
+                .execute(
+                    "INSERT OR REPLACE INTO objects
+                         (batch, path, target, key, status, error, updated_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![
+                        self.batch,
+                        path,
+                        target,
+                        key,
+                        status.as_str(),
+                        error.map(|err| err.to_string()),
+                        chrono::Utc::now().to_rfc3339(),
+                    ],
+                )
+                .map_err(failed)?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hashing::sha256, ml::FileCategory, testdir::TestDir};
+
+    fn classification() -> Classification {
+        Classification {
+            key: "text/notes.txt".to_string(),
+            category: FileCategory::Text,
+            confidence: 1.0,
+            mime: "text/plain".to_string(),
+        }
+    }
+
+    async fn record(checkpoint: &Checkpoint, file: &str, content: &[u8]) {
+        checkpoint
+            .record(
+                file,
+                Some(&sha256(content)),
+                "text/notes.txt",
+                &classification(),
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn nothing_is_kept_without_resume_or_a_directory() {
+        let files = TestDir::new();
+        let file = files.write("notes.txt", "plain words");
+
+        let checkpoint = Checkpoint::open("batch", false, None, None).unwrap();
+        record(&checkpoint, &file, b"plain words").await;
+
+        assert!(!checkpoint.is_done(&file, None, 4096).await.unwrap());
+        checkpoint.finish().unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_resumed_batch_skips_recorded_files() {
+        let files = TestDir::new();
+        let progress = TestDir::new();
+        let done = files.write("done.txt", "plain words");
+        let pending = files.write("pending.txt", "more words");
+
+        let first = Checkpoint::open("batch", false, Some(progress.path()), None).unwrap();
+        record(&first, &done, b"plain words").await;
+        drop(first);
+        assert!(progress.path().join("batch.jsonl").exists());
+
+        let resumed = Checkpoint::open("batch", true, Some(progress.path()), None).unwrap();
+        assert!(resumed.is_done(&done, None, 4096).await.unwrap());
+        assert!(!resumed.is_done(&pending, None, 4096).await.unwrap());
+
+        resumed.finish().unwrap();
+        assert!(!progress.path().join("batch.jsonl").exists());
+    }
+
+    #[tokio::test]
+    async fn a_file_changed_since_it_was_recorded_is_not_done() {
+        let files = TestDir::new();
+        let progress = TestDir::new();
+        let file = files.write("notes.txt", "plain words");
+
+        let first = Checkpoint::open("batch", false, Some(progress.path()), None).unwrap();
+        record(&first, &file, b"plain words").await;
+        drop(first);
+        files.write("notes.txt", "other, longer words");
+
+        let resumed = Checkpoint::open("batch", true, Some(progress.path()), None).unwrap();
+        assert!(!resumed.is_done(&file, None, 4096).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn an_unhashed_file_is_done_only_while_its_mtime_holds() {
+        let files = TestDir::new();
+        let progress = TestDir::new();
+        let file = files.write("notes.txt", "plain words");
+
+        let first = Checkpoint::open("batch", false, Some(progress.path()), None).unwrap();
+        first
+            .record(&file, None, "text/notes.txt", &classification())
+            .await
+            .unwrap();
+        drop(first);
+
+        let resumed = Checkpoint::open("batch", true, Some(progress.path()), None).unwrap();
+        assert!(resumed.is_done(&file, None, 4096).await.unwrap());
+
+        // Same content, but nothing recorded to compare it against
+        let touched = std::fs::File::options().write(true).open(&file).unwrap();
+        touched
+            .set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(60))
+            .unwrap();
+        assert!(!resumed.is_done(&file, None, 4096).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn a_fresh_run_forgets_earlier_progress() {
+        let files = TestDir::new();
+        let progress = TestDir::new();
+        let file = files.write("notes.txt", "plain words");
+
+        let first = Checkpoint::open("batch", false, Some(progress.path()), None).unwrap();
+        record(&first, &file, b"plain words").await;
+        drop(first);
+        drop(Checkpoint::open("batch", false, Some(progress.path()), None).unwrap());
+
+        let resumed = Checkpoint::open("batch", true, Some(progress.path()), None).unwrap();
+        assert!(!resumed.is_done(&file, None, 4096).await.unwrap());
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    #[test]
+    fn a_state_db_needs_the_sqlite_feature() {
+        let err = Checkpoint::open("batch", false, None, Some(Path::new("state.db")))
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("`sqlite` feature"), "{}", err);
+    }
+
+    #[cfg(feature = "sqlite")]
+    mod state_db {
+        use super::*;
+        use rusqlite::Connection;
+
+        /// A shared in-memory database, alive as long as the returned connection is open, and
+        /// the path `--state-db` opens it by
+        fn memory_db(name: &str) -> (PathBuf, Connection) {
+            let path = PathBuf::from(format!("file:{}?mode=memory&cache=shared", name));
+            let connection = Connection::open(&path).unwrap();
+            (path, connection)
+        }
+
+        fn count(db: &Connection, table: &str) -> i64 {
+            db.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+                row.get(0)
+            })
+            .unwrap()
+        }
+
+        #[tokio::test]
+        async fn files_are_inserted_with_their_classification() {
+            let (path, db) = memory_db("inserted");
+            let files = TestDir::new();
+            let file = files.write("notes.txt", "plain words");
+
+            let checkpoint = Checkpoint::open("batch", false, None, Some(&path)).unwrap();
+            record(&checkpoint, &file, b"plain words").await;
+
+            let row: (String, i64, String, String, String, f64) = db
+                .query_row(
+                    "SELECT batch, size, sha256, key, category, confidence FROM files WHERE path = ?1",
+                    [&file],
+                    |row| {
+                        Ok((
+                            row.get(0)?,
+                            row.get(1)?,
+                            row.get(2)?,
+                            row.get(3)?,
+                            row.get(4)?,
+                            row.get(5)?,
+                        ))
+                    },
+                )
+                .unwrap();
+            assert_eq!(
+                row,
+                (
+                    "batch".to_string(),
+                    11,
+                    hex::encode(sha256(b"plain words")),
+                    "text/notes.txt".to_string(),
+                    "text".to_string(),
+                    1.0
+                )
+            );
+
+            // Unlike a progress file, the database outlives the batch
+            checkpoint.finish().unwrap();
+            assert_eq!(count(&db, "files"), 1);
+        }
+
+        #[tokio::test]
+        async fn a_resumed_batch_skips_recorded_files() {
+            let (path, db) = memory_db("resumed");
+            let files = TestDir::new();
+            let done = files.write("done.txt", "plain words");
+            let pending = files.write("pending.txt", "more words");
+
+            let first = Checkpoint::open("batch", false, None, Some(&path)).unwrap();
+            record(&first, &done, b"plain words").await;
+            drop(first);
+
+            let resumed = Checkpoint::open("batch", true, None, Some(&path)).unwrap();
+            assert!(resumed.is_done(&done, None, 4096).await.unwrap());
+            assert!(!resumed.is_done(&pending, None, 4096).await.unwrap());
+            drop(resumed);
+
+            files.write("done.txt", "changed words");
+            let changed = Checkpoint::open("batch", true, None, Some(&path)).unwrap();
+            assert!(!changed.is_done(&done, None, 4096).await.unwrap());
+            drop(changed);
+
+            drop(Checkpoint::open("batch", false, None, Some(&path)).unwrap());
+            assert_eq!(count(&db, "files"), 0);
+        }
+
+        #[tokio::test]
+        async fn batches_share_a_database_without_sharing_progress() {
+            let (path, db) = memory_db("batches");
+            let files = TestDir::new();
+            let file = files.write("notes.txt", "plain words");
+
+            let first = Checkpoint::open("first", false, None, Some(&path)).unwrap();
+            record(&first, &file, b"plain words").await;
+            let second = Checkpoint::open("second", false, None, Some(&path)).unwrap();
+
+            assert!(first.is_done(&file, None, 4096).await.unwrap());
+            assert!(!second.is_done(&file, None, 4096).await.unwrap());
+            assert_eq!(count(&db, "files"), 1);
+        }
+
+        #[tokio::test]
+        async fn objects_left_to_upload_are_an_indexed_query() {
+            let (path, db) = memory_db("objects");
+            let checkpoint = Checkpoint::open("batch", false, None, Some(&path)).unwrap();
+            let denied = AppError::Config("access denied".to_string());
+            for (file, target, status, error) in [
+                ("a.txt", "AWS S3", ObjectStatus::Uploaded, None),
+                ("a.txt", "MinIO", ObjectStatus::Unchanged, None),
+                ("b.txt", "AWS S3", ObjectStatus::Failed, Some(&denied)),
+                ("b.txt", "MinIO", ObjectStatus::Uploaded, None),
+            ] {
+                let key = format!("text/{}", file);
+                checkpoint
+                    .record_object(file, target, &key, status, error)
+                    .unwrap();
+            }
+            // A retry that succeeds replaces the failed row
+            checkpoint
+                .record_object("a.txt", "MinIO", "text/a.txt", ObjectStatus::Uploaded, None)
+                .unwrap();
+
+            let left: Vec<(String, String, String)> = db
+                .prepare(
+                    "SELECT path, target, error FROM objects
+                     WHERE batch = 'batch' AND status = 'failed' ORDER BY path",
+                )
+                .unwrap()
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            assert_eq!(
+                left,
+                [(
+                    "b.txt".to_string(),
+                    "AWS S3".to_string(),
+                    denied.to_string()
+                )]
+            );
+            assert_eq!(count(&db, "objects"), 4);
+
+            let plan: String = db
+                .query_row(
+                    "EXPLAIN QUERY PLAN SELECT status FROM objects WHERE batch = 'batch' AND path = 'b.txt'",
+                    [],
+                    |row| row.get(3),
+                )
+                .unwrap();
+            assert!(plan.contains("objects_by_path"), "{}", plan);
+        }
+    }
+}
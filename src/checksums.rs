@@ -0,0 +1,87 @@
+use bytes::Bytes;
+use sha2::{Digest, Sha512};
+
+use crate::{cli::DigestAlgorithm, hashing::Sha256Digest};
+
+/// Hex digest of an uploaded body for its checksum object
+///
+/// SHA-256 is the digest already taken while the file was read, so only the other
+/// algorithms hash the body again, from memory.
+pub fn hex_digest(algorithm: DigestAlgorithm, body: &[u8], sha256: &Sha256Digest) -> String {
+    match algorithm {
+        DigestAlgorithm::Sha256 => hex::encode(sha256),
+        DigestAlgorithm::Sha512 => hex::encode(Sha512::digest(body)),
+        DigestAlgorithm::Md5 => hex::encode(md5::compute(body).0),
+    }
+}
+
+/// Key of the checksum object of `key`: `suffix`, or `.<algorithm>`, appended to it
+pub fn key(key: &str, suffix: Option<&str>, algorithm: DigestAlgorithm) -> String {
+    match suffix {
+        Some(suffix) => format!("{}{}", key, suffix),
+        None => format!("{}.{}", key, algorithm.as_str()),
+    }
+}
+
+/// Contents of a checksum object, one line as `sha256sum` and its siblings print it
+///
+/// The name is the object's last key segment, so `sha256sum -c` checks a download saved
+/// under that name.
+pub fn body(hex: &str, key: &str) -> Bytes {
+    let name = key.rsplit('/').next().unwrap_or(key);
+    Bytes::from(format!("{}  {}\n", hex, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashing;
+
+    #[test]
+    fn digests_match_the_coreutils_tools() {
+        let sha256 = hashing::sha256(b"abc");
+        assert_eq!(
+            hex_digest(DigestAlgorithm::Sha256, b"abc", &sha256),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex_digest(DigestAlgorithm::Sha512, b"abc", &sha256),
+            "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+             2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+        );
+        assert_eq!(
+            hex_digest(DigestAlgorithm::Md5, b"abc", &sha256),
+            "900150983cd24fb0d6963f7d28e17f72"
+        );
+    }
+
+    #[test]
+    fn sha256_is_taken_from_the_read_not_the_body() {
+        let sha256 = hashing::sha256(b"as stored");
+        assert_eq!(
+            hex_digest(DigestAlgorithm::Sha256, b"ignored", &sha256),
+            hex::encode(sha256)
+        );
+    }
+
+    #[test]
+    fn keys_take_the_suffix_or_the_algorithm() {
+        assert_eq!(
+            key("text/notes.txt", None, DigestAlgorithm::Sha512),
+            "text/notes.txt.sha512"
+        );
+        assert_eq!(
+            key("text/notes.txt", Some(".digest"), DigestAlgorithm::Md5),
+            "text/notes.txt.digest"
+        );
+    }
+
+    #[test]
+    fn bodies_name_the_last_key_segment() {
+        assert_eq!(
+            &body("abc123", "text/notes.txt")[..],
+            b"abc123  notes.txt\n"
+        );
+        assert_eq!(&body("abc123", "notes.txt")[..], b"abc123  notes.txt\n");
+    }
+}
@@ -0,0 +1,865 @@
+use reqwest::Client as ReqwestClient;
+use serde_json::Value;
+use std::{collections::HashMap, path::Path, time::Duration};
+use tokio::{runtime::Handle, task};
+
+use crate::{
+    cli::{ClassifierArgs, ClassifyBy, ClassifySignal},
+    error::AppError,
+    ml::{Classification, FileCategory, FileTypePredictor},
+};
+
+/// Leading bytes of a file sent to an inference endpoint
+const SAMPLE_BYTES: usize = 64 * 1024;
+
+/// Extensions `--classify-by extension` knows without `--ext-category`, with their content type
+const EXTENSIONS: &[(&str, FileCategory, &str)] = &[
+    ("pdf", FileCategory::Documents, "application/pdf"),
+    ("doc", FileCategory::Documents, "application/msword"),
+    (
+        "docx",
+        FileCategory::Documents,
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    ),
+    ("xls", FileCategory::Documents, "application/vnd.ms-excel"),
+    (
+        "xlsx",
+        FileCategory::Documents,
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    ),
+    (
+        "pptx",
+        FileCategory::Documents,
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+    ),
+    (
+        "odt",
+        FileCategory::Documents,
+        "application/vnd.oasis.opendocument.text",
+    ),
+    ("jpg", FileCategory::Images, "image/jpeg"),
+    ("jpeg", FileCategory::Images, "image/jpeg"),
+    ("png", FileCategory::Images, "image/png"),
+    ("gif", FileCategory::Images, "image/gif"),
+    ("webp", FileCategory::Images, "image/webp"),
+    ("bmp", FileCategory::Images, "image/bmp"),
+    ("tif", FileCategory::Images, "image/tiff"),
+    ("tiff", FileCategory::Images, "image/tiff"),
+    ("svg", FileCategory::Images, "image/svg+xml"),
+    ("zip", FileCategory::Archives, "application/zip"),
+    ("tar", FileCategory::Archives, "application/x-tar"),
+    ("gz", FileCategory::Archives, "application/gzip"),
+    ("tgz", FileCategory::Archives, "application/gzip"),
+    ("bz2", FileCategory::Archives, "application/x-bzip2"),
+    ("xz", FileCategory::Archives, "application/x-xz"),
+    ("zst", FileCategory::Archives, "application/zstd"),
+    ("7z", FileCategory::Archives, "application/x-7z-compressed"),
+    ("txt", FileCategory::Text, "text/plain"),
+    ("log", FileCategory::Text, "text/plain"),
+    ("md", FileCategory::Text, "text/markdown"),
+    ("csv", FileCategory::Text, "text/csv"),
+    ("tsv", FileCategory::Text, "text/tab-separated-values"),
+    ("html", FileCategory::Text, "text/html"),
+    ("json", FileCategory::Text, "application/json"),
+    ("jsonl", FileCategory::Text, "application/x-ndjson"),
+    ("xml", FileCategory::Text, "application/xml"),
+    ("yaml", FileCategory::Text, "application/yaml"),
+    ("yml", FileCategory::Text, "application/yaml"),
+];
+
+/// Decides the category, key and content type of an input file
+///
+/// `run_upload` calls this once per file with the body that is about to be uploaded.
+/// Implementations must be thread-safe since files are classified concurrently.
+pub trait Classifier: Send + Sync {
+    fn classify(&self, path: &Path, content: &[u8]) -> Result<Classification, AppError>;
+
+    /// Leading bytes `classify` looks at, `None` if it may need the whole file
+    ///
+    /// A classifier with a bound is handed just that prefix, read before the rest of the
+    /// file, so classifying a large file costs one small read.
+    fn sample_len(&self) -> Option<usize> {
+        None
+    }
+}
+
+impl Classifier for FileTypePredictor {
+    fn sample_len(&self) -> Option<usize> {
+        Some(FileTypePredictor::sample_len(self))
+    }
+
+    fn classify(&self, path: &Path, content: &[u8]) -> Result<Classification, AppError> {
+        let prediction = self.predict(content);
+        Classification::for_file(
+            path,
+            prediction.category,
+            prediction.confidence,
+            prediction.mime,
+        )
+    }
+}
+
+impl Classification {
+    /// Classification storing `path` as `<category>/<file name>`
+    pub fn for_file(
+        path: &Path,
+        category: FileCategory,
+        confidence: f32,
+        mime: &str,
+    ) -> Result<Self, AppError> {
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| AppError::Classification {
+                path: path.display().to_string(),
+                reason: "path has no UTF-8 file name".to_string(),
+            })?;
+
+        Ok(Self {
+            key: format!("{}/{}", category, file_name),
+            category,
+            confidence,
+            mime: mime.to_string(),
+        })
+    }
+}
+
+/// Classifier selected by `--classify-by` (or `--classify-priority`) and `--classifier-url`, by
+/// default the built-in predictor
+pub fn from_args(args: &ClassifierArgs) -> Result<Box<dyn Classifier>, AppError> {
+    if !args.classify_priority.is_empty() {
+        return Ok(Box::new(PriorityClassifier::from_args(args)?));
+    }
+    if args.classify_by == ClassifyBy::Extension && args.classifier_url.is_some() {
+        return Err(AppError::Config(
+            "--classify-by extension never looks at the content, so it can't use --classifier-url"
+                .to_string(),
+        ));
+    }
+    let content = match args.classify_by {
+        ClassifyBy::Extension => None,
+        ClassifyBy::Content | ClassifyBy::Both => Some(content_classifier(args)?),
+    };
+    match (args.classify_by, content) {
+        (ClassifyBy::Content, Some(content)) => Ok(content),
+        (_, content) => Ok(Box::new(ExtensionClassifier::new(
+            &args.ext_category,
+            content,
+        ))),
+    }
+}
+
+/// Classifier of `--classifier-url`, or the built-in predictor
+fn content_classifier(args: &ClassifierArgs) -> Result<Box<dyn Classifier>, AppError> {
+    let predictor = predictor(args)?;
+    match &args.classifier_url {
+        Some(url) => Ok(Box::new(HttpClassifier::new(
+            url,
+            args.classifier_timeout,
+            (!args.no_classifier_fallback).then_some(predictor),
+        )?)),
+        None => Ok(Box::new(predictor)),
+    }
+}
+
+/// The built-in predictor, with `--classify-entropy`'s thresholds
+fn predictor(args: &ClassifierArgs) -> Result<FileTypePredictor, AppError> {
+    let predictor = FileTypePredictor::new();
+    if !args.classify_entropy {
+        return Ok(predictor);
+    }
+    if args.low_entropy >= args.high_entropy {
+        return Err(AppError::Config(format!(
+            "--low-entropy {} must be below --high-entropy {}",
+            args.low_entropy, args.high_entropy
+        )));
+    }
+    Ok(predictor.with_entropy_thresholds(args.low_entropy, args.high_entropy))
+}
+
+/// Consults the signals of `--classify-priority` in order; the first that recognizes a file
+/// decides its category, and a file none recognizes is `misc`
+///
+/// `magic` and `heuristic` are the two halves of the built-in predictor, so
+/// `magic,heuristic` classifies like `--classify-by content` and
+/// `extension,magic,heuristic` like `both`; putting `heuristic` first lets a text file
+/// that happens to start with a PDF or ZIP signature stay `text`.
+pub struct PriorityClassifier {
+    signals: Vec<ClassifySignal>,
+    predictor: FileTypePredictor,
+    extensions: ExtensionClassifier,
+    remote: Option<HttpClassifier>,
+    // --no-classifier-fallback: a failed endpoint request fails the file instead of passing
+    strict_remote: bool,
+}
+
+impl PriorityClassifier {
+    pub fn from_args(args: &ClassifierArgs) -> Result<Self, AppError> {
+        let signals = &args.classify_priority;
+        let uses_remote = signals.contains(&ClassifySignal::Remote);
+        let remote = match (&args.classifier_url, uses_remote) {
+            (Some(url), true) => Some(HttpClassifier::new(url, args.classifier_timeout, None)?),
+            (None, false) => None,
+            (Some(_), false) => {
+                return Err(AppError::Config(
+                    "--classifier-url is only consulted as `remote` in --classify-priority"
+                        .to_string(),
+                ))
+            }
+            (None, true) => {
+                return Err(AppError::Config(
+                    "--classify-priority remote needs --classifier-url".to_string(),
+                ))
+            }
+        };
+        Ok(Self {
+            signals: signals.clone(),
+            predictor: predictor(args)?,
+            extensions: ExtensionClassifier::new(&args.ext_category, None),
+            remote,
+            strict_remote: args.no_classifier_fallback,
+        })
+    }
+
+    /// What `signal` makes of the file, `None` if it doesn't recognize it
+    fn consult(
+        &self,
+        signal: ClassifySignal,
+        path: &Path,
+        content: &[u8],
+    ) -> Result<Option<Classification>, AppError> {
+        let prediction = match signal {
+            ClassifySignal::Magic => self.predictor.match_signature(content),
+            ClassifySignal::Heuristic => self.predictor.predict_heuristic(content),
+            ClassifySignal::Extension => {
+                return match self.extensions.known(path) {
+                    Some((category, mime)) => {
+                        Classification::for_file(path, *category, 1.0, mime).map(Some)
+                    }
+                    None => Ok(None),
+                };
+            }
+            ClassifySignal::Remote => {
+                let Some(remote) = &self.remote else {
+                    return Ok(None);
+                };
+                return match remote.infer_sample(content) {
+                    Ok(inference) if inference.category == FileCategory::Misc => Ok(None),
+                    Ok(inference) => Classification::for_file(
+                        path,
+                        inference.category,
+                        inference.confidence,
+                        inference
+                            .mime
+                            .as_deref()
+                            .unwrap_or("application/octet-stream"),
+                    )
+                    .map(Some),
+                    Err(reason) if self.strict_remote => Err(AppError::Classification {
+                        path: path.display().to_string(),
+                        reason: format!("classifier endpoint: {}", reason),
+                    }),
+                    Err(reason) => {
+                        println!(
+                            "Classifier endpoint failed for {}: {}, consulting the next signal",
+                            path.display(),
+                            reason
+                        );
+                        Ok(None)
+                    }
+                };
+            }
+        };
+        prediction
+            .map(|prediction| {
+                Classification::for_file(
+                    path,
+                    prediction.category,
+                    prediction.confidence,
+                    prediction.mime,
+                )
+            })
+            .transpose()
+    }
+}
+
+impl Classifier for PriorityClassifier {
+    fn sample_len(&self) -> Option<usize> {
+        let len = |signal: &ClassifySignal| match signal {
+            ClassifySignal::Extension => 0,
+            ClassifySignal::Magic | ClassifySignal::Heuristic => self.predictor.sample_len(),
+            ClassifySignal::Remote => SAMPLE_BYTES,
+        };
+        self.signals.iter().map(len).max()
+    }
+
+    fn classify(&self, path: &Path, content: &[u8]) -> Result<Classification, AppError> {
+        for signal in &self.signals {
+            if let Some(classification) = self.consult(*signal, path, content)? {
+                return Ok(classification);
+            }
+        }
+        Classification::for_file(path, FileCategory::Misc, 1.0, "application/octet-stream")
+    }
+}
+
+/// Classifies files by their extension (`--classify-by extension` or `both`)
+///
+/// Known extensions map to their category with full confidence; `--ext-category` entries
+/// add to or override the built-in ones. A file with any other extension, or none, goes
+/// to `content` when given (`both`) and is `misc` otherwise.
+pub struct ExtensionClassifier {
+    extensions: HashMap<String, (FileCategory, String)>,
+    content: Option<Box<dyn Classifier>>,
+}
+
+impl ExtensionClassifier {
+    pub fn new(overrides: &[(String, FileCategory)], content: Option<Box<dyn Classifier>>) -> Self {
+        let mut extensions: HashMap<_, _> = EXTENSIONS
+            .iter()
+            .map(|(ext, category, mime)| (ext.to_string(), (*category, mime.to_string())))
+            .collect();
+        for (ext, category) in overrides {
+            // A known content type still describes the bytes under another category
+            let mime = match extensions.get(ext) {
+                Some((_, mime)) => mime.clone(),
+                None if *category == FileCategory::Text => "text/plain".to_string(),
+                None => "application/octet-stream".to_string(),
+            };
+            extensions.insert(ext.clone(), (*category, mime));
+        }
+        Self {
+            extensions,
+            content,
+        }
+    }
+
+    /// Category and content type of `path`'s extension, if it is a known one
+    fn known(&self, path: &Path) -> Option<&(FileCategory, String)> {
+        let ext = path.extension()?.to_str()?;
+        self.extensions.get(&ext.to_ascii_lowercase())
+    }
+}
+
+impl Classifier for ExtensionClassifier {
+    fn sample_len(&self) -> Option<usize> {
+        match &self.content {
+            Some(content) => content.sample_len(),
+            None => Some(0),
+        }
+    }
+
+    fn classify(&self, path: &Path, content: &[u8]) -> Result<Classification, AppError> {
+        match (self.known(path), &self.content) {
+            (Some((category, mime)), _) => Classification::for_file(path, *category, 1.0, mime),
+            (None, Some(classifier)) => classifier.classify(path, content),
+            (None, None) => {
+                Classification::for_file(path, FileCategory::Misc, 1.0, "application/octet-stream")
+            }
+        }
+    }
+}
+
+/// Classifies files with a remote inference endpoint
+///
+/// The first 64 KiB of each file are POSTed as `application/octet-stream`; the endpoint
+/// answers with `{"category": "images", "confidence": 0.93}` and optionally a `"mime"`.
+/// Requests block the calling worker thread, so this needs the multi-threaded runtime.
+pub struct HttpClassifier {
+    url: String,
+    client: ReqwestClient,
+    // Used when the endpoint fails; `None` makes the failure an error
+    fallback: Option<FileTypePredictor>,
+}
+
+/// Parsed response of an inference endpoint
+struct Inference {
+    category: FileCategory,
+    confidence: f32,
+    mime: Option<String>,
+}
+
+impl HttpClassifier {
+    /// Classifier calling `url`, which falls back to `fallback` when the endpoint fails
+    pub fn new(
+        url: &str,
+        timeout: Duration,
+        fallback: Option<FileTypePredictor>,
+    ) -> Result<Self, AppError> {
+        let client = ReqwestClient::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| AppError::Config(format!("--classifier-url: {}", e)))?;
+
+        Ok(Self {
+            url: url.to_string(),
+            client,
+            fallback,
+        })
+    }
+
+    /// Ask the endpoint about the sample of `content`, blocking the worker thread
+    fn infer_sample(&self, content: &[u8]) -> Result<Inference, String> {
+        let sample = &content[..content.len().min(SAMPLE_BYTES)];
+        task::block_in_place(|| Handle::current().block_on(self.infer(sample)))
+    }
+
+    async fn infer(&self, sample: &[u8]) -> Result<Inference, String> {
+        let response = self
+            .client
+            .post(&self.url)
+            .header("content-type", "application/octet-stream")
+            .body(sample.to_vec())
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!("endpoint returned {}", status));
+        }
+
+        let body = response.bytes().await.map_err(|e| e.to_string())?;
+        parse_inference(&body)
+    }
+}
+
+impl Classifier for HttpClassifier {
+    fn sample_len(&self) -> Option<usize> {
+        let fallback = self
+            .fallback
+            .as_ref()
+            .map_or(0, FileTypePredictor::sample_len);
+        Some(SAMPLE_BYTES.max(fallback))
+    }
+
+    fn classify(&self, path: &Path, content: &[u8]) -> Result<Classification, AppError> {
+        match (self.infer_sample(content), &self.fallback) {
+            (Ok(inference), _) => Classification::for_file(
+                path,
+                inference.category,
+                inference.confidence,
+                inference
+                    .mime
+                    .as_deref()
+                    .unwrap_or("application/octet-stream"),
+            ),
+            (Err(reason), Some(predictor)) => {
+                println!(
+                    "Classifier endpoint failed for {}: {}, using the built-in predictor",
+                    path.display(),
+                    reason
+                );
+                predictor.classify(path, content)
+            }
+            (Err(reason), None) => Err(AppError::Classification {
+                path: path.display().to_string(),
+                reason: format!("classifier endpoint: {}", reason),
+            }),
+        }
+    }
+}
+
+fn parse_inference(body: &[u8]) -> Result<Inference, String> {
+    let value: Value =
+        serde_json::from_slice(body).map_err(|e| format!("invalid JSON response: {}", e))?;
+
+    let category = value
+        .get("category")
+        .and_then(Value::as_str)
+        .ok_or("response has no \"category\" string")?
+        .parse()?;
+
+    let confidence = value
+        .get("confidence")
+        .and_then(Value::as_f64)
+        .filter(|c| (0.0..=1.0).contains(c))
+        .ok_or("response has no \"confidence\" between 0 and 1")?;
+
+    let mime = value
+        .get("mime")
+        .and_then(Value::as_str)
+        .map(str::to_string);
+
+    Ok(Inference {
+        category,
+        confidence: confidence as f32,
+        mime,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::{
+        body::to_bytes,
+        service::{make_service_fn, service_fn},
+        Body, Response, Server,
+    };
+    use std::{
+        convert::Infallible,
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+    };
+
+    type Samples = Arc<Mutex<Vec<(Option<String>, Vec<u8>)>>>;
+
+    /// Inference endpoint answering every request with `status` and `body`, and the
+    /// content types and bodies it was sent
+    fn endpoint(status: u16, body: &'static str) -> (String, Samples) {
+        let samples = Samples::default();
+        let seen = samples.clone();
+        let make = make_service_fn(move |_| {
+            let seen = seen.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: hyper::Request<Body>| {
+                    let seen = seen.clone();
+                    async move {
+                        let content_type = request
+                            .headers()
+                            .get("content-type")
+                            .and_then(|value| value.to_str().ok())
+                            .map(str::to_string);
+                        let sample = to_bytes(request.into_body()).await.unwrap();
+                        seen.lock().unwrap().push((content_type, sample.to_vec()));
+                        Ok::<_, Infallible>(
+                            Response::builder()
+                                .status(status)
+                                .body(Body::from(body))
+                                .unwrap(),
+                        )
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make);
+        let url = format!("http://{}/classify", server.local_addr());
+        tokio::spawn(server);
+        (url, samples)
+    }
+
+    fn classifier(url: &str, fallback: bool) -> HttpClassifier {
+        HttpClassifier::new(
+            url,
+            Duration::from_secs(5),
+            fallback.then(FileTypePredictor::new),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn endpoint_decides_category_and_mime() {
+        let (url, samples) = endpoint(
+            200,
+            r#"{"category": "images", "confidence": 0.93, "mime": "image/webp"}"#,
+        );
+
+        let classification = classifier(&url, false)
+            .classify(Path::new("scan.bin"), b"not really an image")
+            .unwrap();
+
+        assert_eq!(classification.category, FileCategory::Images);
+        assert_eq!(classification.confidence, 0.93);
+        assert_eq!(classification.mime, "image/webp");
+        assert_eq!(
+            *samples.lock().unwrap(),
+            vec![(
+                Some("application/octet-stream".to_string()),
+                b"not really an image".to_vec()
+            )]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn only_the_sample_is_sent() {
+        let (url, samples) = endpoint(200, r#"{"category": "misc", "confidence": 0.5}"#);
+        let content = vec![b'a'; SAMPLE_BYTES + 100];
+
+        let classification = classifier(&url, false)
+            .classify(Path::new("big.dat"), &content)
+            .unwrap();
+
+        assert_eq!(classification.mime, "application/octet-stream");
+        assert_eq!(samples.lock().unwrap()[0].1.len(), SAMPLE_BYTES);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn failing_endpoint_falls_back_to_the_predictor() {
+        let (url, samples) = endpoint(500, "overloaded");
+
+        let classification = classifier(&url, true)
+            .classify(Path::new("notes.txt"), b"plain words")
+            .unwrap();
+
+        assert_eq!(classification.category, FileCategory::Text);
+        assert_eq!(samples.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn failing_endpoint_without_fallback_is_an_error() {
+        let (url, _) = endpoint(200, r#"{"category": "images", "confidence": 7}"#);
+
+        let err = classifier(&url, false)
+            .classify(Path::new("scan.bin"), b"bytes")
+            .unwrap_err();
+
+        assert!(
+            matches!(&err, AppError::Classification { reason, .. } if reason.contains("confidence")),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn inference_responses_are_validated() {
+        assert!(parse_inference(b"not json").is_err());
+        assert!(parse_inference(br#"{"confidence": 0.5}"#).is_err());
+        assert!(parse_inference(br#"{"category": "videos", "confidence": 0.5}"#).is_err());
+        let inference = parse_inference(br#"{"category": "Text", "confidence": 1}"#).unwrap();
+        assert_eq!(inference.category, FileCategory::Text);
+        assert_eq!(inference.mime, None);
+    }
+
+    /// Classifier of `--classify-by` and friends parsed from `flags`
+    fn classifier_for(flags: &[&str]) -> Result<Box<dyn Classifier>, AppError> {
+        #[derive(clap::Parser)]
+        struct Flags {
+            #[command(flatten)]
+            args: ClassifierArgs,
+        }
+        let flags = <Flags as clap::Parser>::try_parse_from([&["test"], flags].concat()).unwrap();
+        from_args(&flags.args)
+    }
+
+    fn category(classifier: &dyn Classifier, name: &str, content: &[u8]) -> (String, String) {
+        let classification = classifier.classify(Path::new(name), content).unwrap();
+        (classification.key, classification.mime)
+    }
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    #[test]
+    fn content_mode_ignores_extensions() {
+        let classifier = classifier_for(&[]).unwrap();
+        assert_eq!(
+            category(&*classifier, "notes.png", b"plain words\n").0,
+            "text/notes.png"
+        );
+        assert_eq!(
+            category(&*classifier, "photo.txt", PNG).0,
+            "images/photo.txt"
+        );
+    }
+
+    #[test]
+    fn extension_mode_never_reads_the_content() {
+        let classifier = classifier_for(&["--classify-by", "extension"]).unwrap();
+        assert_eq!(classifier.sample_len(), Some(0));
+        assert_eq!(
+            category(&*classifier, "photo.PNG", b""),
+            ("images/photo.PNG".to_string(), "image/png".to_string())
+        );
+        assert_eq!(
+            category(&*classifier, "notes.png", b"plain words\n").0,
+            "images/notes.png"
+        );
+        assert_eq!(
+            category(&*classifier, "weights.unknown", PNG),
+            (
+                "misc/weights.unknown".to_string(),
+                "application/octet-stream".to_string()
+            )
+        );
+        assert_eq!(
+            category(&*classifier, "README", b"plain words\n").0,
+            "misc/README"
+        );
+    }
+
+    #[test]
+    fn both_mode_reads_the_content_of_unknown_extensions() {
+        let classifier = classifier_for(&["--classify-by", "both"]).unwrap();
+        assert_eq!(
+            classifier.sample_len(),
+            Some(FileTypePredictor::new().sample_len())
+        );
+        assert_eq!(
+            category(&*classifier, "notes.png", b"plain words\n").0,
+            "images/notes.png"
+        );
+        assert_eq!(
+            category(&*classifier, "photo.unknown", PNG).0,
+            "images/photo.unknown"
+        );
+        assert_eq!(
+            category(&*classifier, "README", b"plain words\n").0,
+            "text/README"
+        );
+    }
+
+    #[test]
+    fn ext_categories_add_to_and_override_the_built_in_map() {
+        let classifier = classifier_for(&[
+            "--classify-by",
+            "extension",
+            "--ext-category",
+            ".ONNX=archives,txt=documents",
+            "--ext-category",
+            "prompt=text",
+        ])
+        .unwrap();
+        assert_eq!(
+            category(&*classifier, "model.onnx", b""),
+            (
+                "archives/model.onnx".to_string(),
+                "application/octet-stream".to_string()
+            )
+        );
+        assert_eq!(
+            category(&*classifier, "notes.txt", b""),
+            ("documents/notes.txt".to_string(), "text/plain".to_string())
+        );
+        assert_eq!(
+            category(&*classifier, "system.prompt", b""),
+            ("text/system.prompt".to_string(), "text/plain".to_string())
+        );
+    }
+
+    #[test]
+    fn extension_mode_rejects_a_classifier_url() {
+        let err = classifier_for(&[
+            "--classify-by",
+            "extension",
+            "--classifier-url",
+            "http://127.0.0.1:1/infer",
+        ])
+        .err()
+        .unwrap();
+        assert!(matches!(err, AppError::Config(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn the_first_signal_that_recognizes_a_file_decides() {
+        let magic_first = classifier_for(&["--classify-priority", "magic,heuristic"]).unwrap();
+        let heuristic_first = classifier_for(&["--classify-priority", "heuristic,magic"]).unwrap();
+        let pdf_ish = b"%PDF-1.7 is the version these notes are about\n";
+
+        assert_eq!(
+            category(&*magic_first, "notes.txt", pdf_ish).0,
+            "documents/notes.txt"
+        );
+        assert_eq!(
+            category(&*heuristic_first, "notes.txt", pdf_ish).0,
+            "text/notes.txt"
+        );
+        // Both orders agree on files only one signal recognizes
+        for classifier in [&magic_first, &heuristic_first] {
+            assert_eq!(
+                category(&**classifier, "photo.bin", PNG).0,
+                "images/photo.bin"
+            );
+        }
+    }
+
+    #[test]
+    fn magic_and_heuristic_classify_like_the_content_mode() {
+        let content = classifier_for(&[]).unwrap();
+        let priority = classifier_for(&["--classify-priority", "magic,heuristic"]).unwrap();
+        for (name, bytes) in [
+            ("photo.png", PNG),
+            ("notes.txt", &b"plain words\n"[..]),
+            ("blob.bin", &[0u8, 1, 2, 0xfe, 0xff, 0x80][..]),
+        ] {
+            assert_eq!(
+                category(&*priority, name, bytes),
+                category(&*content, name, bytes),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn extensions_first_and_unrecognized_files_are_misc() {
+        let classifier = classifier_for(&["--classify-priority", "extension,magic"]).unwrap();
+        assert_eq!(
+            classifier.sample_len(),
+            Some(FileTypePredictor::new().sample_len())
+        );
+        assert_eq!(
+            category(&*classifier, "notes.png", b"plain words\n").0,
+            "images/notes.png"
+        );
+        assert_eq!(
+            category(&*classifier, "photo.unknown", PNG).0,
+            "images/photo.unknown"
+        );
+        // Only the heuristic would have called it text
+        assert_eq!(
+            category(&*classifier, "README", b"plain words\n"),
+            (
+                "misc/README".to_string(),
+                "application/octet-stream".to_string()
+            )
+        );
+
+        let extension_only = classifier_for(&["--classify-priority", "extension"]).unwrap();
+        assert_eq!(extension_only.sample_len(), Some(0));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn remote_answers_of_misc_or_failures_pass_to_the_next_signal() {
+        let (images, _) = endpoint(200, r#"{"category": "images", "confidence": 0.9}"#);
+        let (misc, _) = endpoint(200, r#"{"category": "misc", "confidence": 0.9}"#);
+        let (failing, _) = endpoint(500, "overloaded");
+        let remote_first = |url: &str| {
+            classifier_for(&[
+                "--classify-priority",
+                "remote,heuristic",
+                "--classifier-url",
+                url,
+            ])
+            .unwrap()
+        };
+
+        assert_eq!(
+            category(&*remote_first(&images), "notes.txt", b"plain words\n").0,
+            "images/notes.txt"
+        );
+        for url in [&misc, &failing] {
+            assert_eq!(
+                category(&*remote_first(url), "notes.txt", b"plain words\n").0,
+                "text/notes.txt"
+            );
+        }
+
+        let strict = classifier_for(&[
+            "--classify-priority",
+            "remote,heuristic",
+            "--classifier-url",
+            &failing,
+            "--no-classifier-fallback",
+        ])
+        .unwrap();
+        let err = strict
+            .classify(Path::new("notes.txt"), b"plain words\n")
+            .unwrap_err();
+        assert!(matches!(err, AppError::Classification { .. }), "{:?}", err);
+    }
+
+    #[test]
+    fn remote_and_a_classifier_url_come_together() {
+        for flags in [
+            &["--classify-priority", "remote,magic"][..],
+            &[
+                "--classify-priority",
+                "magic",
+                "--classifier-url",
+                "http://127.0.0.1:1/infer",
+            ],
+        ] {
+            let err = classifier_for(flags).err().unwrap();
+            assert!(matches!(err, AppError::Config(_)), "{:?}", err);
+        }
+    }
+}
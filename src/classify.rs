@@ -0,0 +1,275 @@
+use futures::{stream, StreamExt};
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    path::{Component, Path},
+};
+
+use crate::{
+    classifier::{self, Classifier},
+    classify_caught,
+    cli::ClassifyArgs,
+    error::AppError,
+    inputs,
+    ml::Classification,
+    source,
+};
+
+/// Bytes read per call from files a classifier needs whole, `--read-buffer-size`'s default
+const READ_BUFFER_SIZE: usize = 256 * 1024;
+
+/// Classified files by top-level directory, then by category (`--by-dir`)
+type DirCounts = BTreeMap<String, BTreeMap<String, usize>>;
+
+/// Classify files the way `upload` would and print the results; nothing is uploaded
+///
+/// Needs no backend configuration. A classifier bounded to a prefix only gets that read,
+/// so classifying a directory of large files stays cheap.
+pub async fn run(args: ClassifyArgs) -> Result<(), AppError> {
+    let classifier = classifier::from_args(&args.classifier)?;
+    let filter = inputs::ExtFilter {
+        include: args.include_ext.clone(),
+        exclude: args.exclude_ext.clone(),
+    };
+    let inputs = inputs::expand_inputs_async(
+        args.files.clone(),
+        args.dir.clone(),
+        args.no_glob,
+        args.allow_empty_glob,
+        filter,
+    )
+    .await?;
+
+    let classifier = classifier.as_ref();
+    let results: Vec<_> = stream::iter(&inputs.files)
+        .map(|file| async move { (file, classify_file(classifier, file).await) })
+        .buffered(args.concurrency.max(1))
+        .collect()
+        .await;
+
+    let by_dir = args
+        .by_dir
+        .then(|| dir_counts(&results, args.dir.as_deref()));
+    if args.json {
+        print_json(&results, by_dir.as_ref());
+    } else {
+        print_table(&results, inputs.filtered);
+        if let Some(by_dir) = &by_dir {
+            print_dir_table(by_dir);
+        }
+    }
+
+    match results.iter().filter(|(_, result)| result.is_err()).count() {
+        0 => Ok(()),
+        failed => Err(AppError::ClassifyFailed(failed)),
+    }
+}
+
+/// Read as much of `file` as the classifier looks at and classify it
+async fn classify_file(
+    classifier: &dyn Classifier,
+    file: &str,
+) -> Result<Classification, AppError> {
+    let content = match classifier.sample_len() {
+        Some(len) => source::read_prefix(file, None, len).await?,
+        None => {
+            source::read_source(file, None, READ_BUFFER_SIZE)
+                .await?
+                .bytes
+        }
+    };
+    classify_caught(classifier, file, &content)
+}
+
+fn print_json(results: &[(&String, Result<Classification, AppError>)], by_dir: Option<&DirCounts>) {
+    let files: Vec<_> = results
+        .iter()
+        .map(|(file, result)| match result {
+            Ok(classification) => json!({
+                "file": file,
+                "category": classification.category.to_string(),
+                // f32 digits past the second decimal are noise
+                "confidence": (f64::from(classification.confidence) * 100.0).round() / 100.0,
+                "mime": classification.mime,
+            }),
+            Err(err) => json!({
+                "file": file,
+                "error": err.to_string(),
+            }),
+        })
+        .collect();
+    let mut output = json!({ "files": files });
+    if let Some(by_dir) = by_dir {
+        let directories = by_dir
+            .iter()
+            .map(|(dir, counts)| {
+                let mut entry = json!(counts);
+                entry["total"] = json!(counts.values().sum::<usize>());
+                (dir.clone(), entry)
+            })
+            .collect();
+        output["directories"] = Value::Object(directories);
+    }
+    println!("{}", output);
+}
+
+/// Count the classified files of `results` by top-level directory and category
+fn dir_counts(
+    results: &[(&String, Result<Classification, AppError>)],
+    root: Option<&Path>,
+) -> DirCounts {
+    let mut counts = DirCounts::new();
+    for (file, result) in results {
+        if let Ok(classification) = result {
+            *counts
+                .entry(top_dir(file, root))
+                .or_default()
+                .entry(classification.category.to_string())
+                .or_default() += 1;
+        }
+    }
+    counts
+}
+
+/// First directory of `file` below `root` (`images/`), or `./` for a file directly in it
+///
+/// Files outside `root`, or any file without one, count from the start of their path.
+fn top_dir(file: &str, root: Option<&Path>) -> String {
+    let path = Path::new(file);
+    let relative = root
+        .and_then(|root| path.strip_prefix(root).ok())
+        .unwrap_or(path);
+    let mut dirs = relative.parent().into_iter().flat_map(Path::components);
+    match dirs.find_map(|component| match component {
+        Component::Normal(name) => Some(name),
+        _ => None,
+    }) {
+        Some(name) => format!("{}/", name.to_string_lossy()),
+        None => "./".to_string(),
+    }
+}
+
+/// Matrix of directories against categories, each cell a count and the row's share
+fn print_dir_table(by_dir: &DirCounts) {
+    let mut categories: Vec<&str> = by_dir
+        .values()
+        .flat_map(|counts| counts.keys().map(String::as_str))
+        .collect();
+    categories.sort_unstable();
+    categories.dedup();
+
+    let dir_width = by_dir.keys().map(String::len).max().unwrap_or(0).max(9);
+    // Wide enough for the category name or a `12345 (100%)` cell
+    let widths: Vec<usize> = categories.iter().map(|name| name.len().max(12)).collect();
+
+    let mut header = format!("\n{:<dir_width$} {:>6}", "directory", "files");
+    for (name, width) in categories.iter().zip(&widths) {
+        header.push_str(&format!("  {:>width$}", name));
+    }
+    println!("{}", header);
+    for (dir, counts) in by_dir {
+        let total: usize = counts.values().sum();
+        let mut row = format!("{:<dir_width$} {:>6}", dir, total);
+        for (name, width) in categories.iter().zip(&widths) {
+            let cell = match counts.get(*name) {
+                Some(count) => format!("{} ({:.0}%)", count, *count as f64 * 100.0 / total as f64),
+                None => "-".to_string(),
+            };
+            row.push_str(&format!("  {:>width$}", cell));
+        }
+        println!("{}", row);
+    }
+}
+
+fn print_table(results: &[(&String, Result<Classification, AppError>)], filtered: usize) {
+    let mut by_category: BTreeMap<String, usize> = BTreeMap::new();
+    for (file, result) in results {
+        match result {
+            Ok(classification) => {
+                println!(
+                    "{:<24} {:>4.0}% {:<32} {}",
+                    classification.category.as_str(),
+                    classification.confidence * 100.0,
+                    classification.mime,
+                    file
+                );
+                *by_category
+                    .entry(classification.category.to_string())
+                    .or_default() += 1;
+            }
+            Err(err) => eprintln!("Could not classify {}: {}", file, err),
+        }
+    }
+
+    let counts: Vec<_> = by_category
+        .iter()
+        .map(|(category, count)| format!("{} {}", category, count))
+        .collect();
+    println!(
+        "Classified {} file(s){}{}",
+        by_category.values().sum::<usize>(),
+        if counts.is_empty() { "" } else { ": " },
+        counts.join(", ")
+    );
+    if filtered > 0 {
+        println!(
+            "Filtered out {} file(s) by --include-ext/--exclude-ext",
+            filtered
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ml::FileCategory;
+
+    #[test]
+    fn top_dirs_count_from_the_root() {
+        let root = Some(Path::new("data"));
+        assert_eq!(top_dir("data/images/cats/a.png", root), "images/");
+        assert_eq!(top_dir("data/a.png", root), "./");
+        // Outside the root, or without one, the path counts from its start
+        assert_eq!(top_dir("other/images/a.png", root), "other/");
+        assert_eq!(top_dir("images/cats/a.png", None), "images/");
+        assert_eq!(top_dir("./../images/a.png", None), "images/");
+        assert_eq!(top_dir("a.png", None), "./");
+    }
+
+    #[test]
+    fn counts_skip_failed_files() {
+        let classified = |key: &str, category| {
+            Ok(Classification {
+                key: key.to_string(),
+                category,
+                confidence: 1.0,
+                mime: "application/octet-stream".to_string(),
+            })
+        };
+        let files = [
+            "d/a/1.png",
+            "d/a/2.png",
+            "d/a/3.txt",
+            "d/b/4.txt",
+            "d/5.txt",
+        ]
+        .map(String::from);
+        let results = [
+            (&files[0], classified("images/1.png", FileCategory::Images)),
+            (&files[1], classified("images/2.png", FileCategory::Images)),
+            (&files[2], classified("text/3.txt", FileCategory::Text)),
+            (&files[3], Err(AppError::Config("unreadable".to_string()))),
+            (&files[4], classified("text/5.txt", FileCategory::Text)),
+        ];
+
+        let counts = dir_counts(&results, Some(Path::new("d")));
+        let expected = DirCounts::from([
+            (
+                "a/".to_string(),
+                BTreeMap::from([("images".to_string(), 2), ("text".to_string(), 1)]),
+            ),
+            ("./".to_string(), BTreeMap::from([("text".to_string(), 1)])),
+        ]);
+        assert_eq!(counts, expected);
+    }
+}
@@ -0,0 +1,283 @@
+use aws_sdk_s3::Client;
+use chrono::{DateTime, Utc};
+use s3::bucket::Bucket;
+
+use crate::{
+    cli::{format_size, CleanupArgs},
+    concurrency::ConcurrencyLimiter,
+    confirm,
+    error::AppError,
+    tls::TlsConfig,
+    Backend, Backends,
+};
+
+/// An incomplete multipart upload found under the prefix
+struct OrphanedUpload {
+    key: String,
+    upload_id: String,
+    initiated: DateTime<Utc>,
+    // Bytes held by uploaded parts; `None` when the backend can't list parts
+    size: Option<u64>,
+}
+
+/// List multipart uploads older than `--older-than` and abort them
+///
+/// The uploads are aborted with `--yes`, or on a terminal once the user agrees at the
+/// prompt; otherwise they are only reported.
+pub async fn run(args: CleanupArgs) -> Result<(), AppError> {
+    let tls = TlsConfig::load(&args.tls)?;
+    let backends = Backends::connect(
+        ConcurrencyLimiter::new(1, false),
+        &tls,
+        &Backend::ALL,
+        false,
+    )
+    .await?;
+
+    let older_than = chrono::Duration::from_std(args.older_than)
+        .map_err(|_| AppError::Config("--older-than is too large".to_string()))?;
+    let cutoff = Utc::now() - older_than;
+    let prefix = args.prefix.as_deref().unwrap_or("");
+
+    // The HTTP path writes to the AWS bucket, so two listings cover every backend. One
+    // that fails, e.g. a MinIO left on its placeholder endpoint, doesn't hide the other.
+    let mut found = Vec::new();
+    for backend in [Backend::Aws, Backend::Minio] {
+        let listed = match backend {
+            Backend::Minio => minio_uploads(&backends.minio_bucket, prefix, cutoff).await,
+            _ => aws_uploads(&backends.aws_client, &backends.aws_bucket, prefix, cutoff).await,
+        };
+        let uploads = match listed {
+            Ok(uploads) => uploads,
+            Err(err) => {
+                eprintln!("{}: failed to list uploads: {}", backend.name(), err);
+                continue;
+            }
+        };
+        println!("{}: {} orphaned upload(s)", backend.name(), uploads.len());
+
+        for upload in &uploads {
+            println!(
+                "  {} (upload {}, started {}, {})",
+                upload.key,
+                upload.upload_id,
+                upload.initiated.format("%Y-%m-%d %H:%M"),
+                upload
+                    .size
+                    .map(format_size)
+                    .unwrap_or_else(|| "size unknown".to_string())
+            );
+        }
+        found.extend(uploads.into_iter().map(|upload| (backend, upload)));
+    }
+
+    let found_totals = Totals::of(found.iter().map(|(_, upload)| upload));
+    let summary = format!(
+        "{} upload(s), ~{}{}",
+        found.len(),
+        format_size(found_totals.bytes),
+        found_totals.unknown()
+    );
+    let proceed = if args.yes {
+        true
+    } else if found.is_empty() {
+        false
+    } else {
+        confirm::ask(&format!("Abort {}?", summary)).await? == Some(true)
+    };
+
+    if !proceed {
+        println!("Would abort {}; pass --yes to abort them", summary);
+        return Ok(());
+    }
+
+    let mut aborted = Vec::new();
+    for (backend, upload) in &found {
+        match abort(&backends, *backend, upload).await {
+            Ok(()) => aborted.push(upload),
+            Err(err) => eprintln!("  Failed to abort {}: {}", upload.key, err),
+        }
+    }
+
+    let totals = Totals::of(aborted.iter().copied());
+    println!(
+        "Aborted {} upload(s), reclaimed ~{}{}",
+        aborted.len(),
+        format_size(totals.bytes),
+        totals.unknown()
+    );
+
+    Ok(())
+}
+
+/// Bytes held by a set of uploads, as far as their sizes are known
+struct Totals {
+    bytes: u64,
+    unknown_sizes: usize,
+}
+
+impl Totals {
+    fn of<'a>(uploads: impl Iterator<Item = &'a OrphanedUpload>) -> Self {
+        let mut totals = Self {
+            bytes: 0,
+            unknown_sizes: 0,
+        };
+        for upload in uploads {
+            match upload.size {
+                Some(size) => totals.bytes += size,
+                None => totals.unknown_sizes += 1,
+            }
+        }
+        totals
+    }
+
+    /// e.g. ` plus 2 upload(s) of unknown size`, empty when every size is known
+    fn unknown(&self) -> String {
+        if self.unknown_sizes > 0 {
+            format!(" plus {} upload(s) of unknown size", self.unknown_sizes)
+        } else {
+            String::new()
+        }
+    }
+}
+
+/// Abort one upload, releasing its parts
+async fn abort(
+    backends: &Backends,
+    backend: Backend,
+    upload: &OrphanedUpload,
+) -> Result<(), AppError> {
+    match backend {
+        Backend::Minio => {
+            backends
+                .minio_bucket
+                .abort_upload(&upload.key, &upload.upload_id)
+                .await?
+        }
+        _ => {
+            backends
+                .aws_client
+                .abort_multipart_upload()
+                .bucket(&backends.aws_bucket)
+                .key(&upload.key)
+                .upload_id(&upload.upload_id)
+                .send()
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Incomplete uploads in the AWS bucket started before `cutoff`, with part sizes
+async fn aws_uploads(
+    client: &Client,
+    bucket: &str,
+    prefix: &str,
+    cutoff: DateTime<Utc>,
+) -> Result<Vec<OrphanedUpload>, AppError> {
+    let mut found = Vec::new();
+    let mut key_marker = None;
+    let mut upload_id_marker = None;
+
+    loop {
+        let page = client
+            .list_multipart_uploads()
+            .bucket(bucket)
+            .prefix(prefix)
+            .set_key_marker(key_marker.take())
+            .set_upload_id_marker(upload_id_marker.take())
+            .send()
+            .await?;
+
+        for upload in page.uploads() {
+            let (Some(key), Some(upload_id), Some(initiated)) =
+                (upload.key(), upload.upload_id(), upload.initiated())
+            else {
+                continue;
+            };
+
+            let Some(initiated) = DateTime::from_timestamp(initiated.secs(), 0) else {
+                continue;
+            };
+            if initiated >= cutoff {
+                continue;
+            }
+
+            found.push(OrphanedUpload {
+                key: key.to_string(),
+                upload_id: upload_id.to_string(),
+                initiated,
+                size: Some(aws_parts_size(client, bucket, key, upload_id).await?),
+            });
+        }
+
+        if !page.is_truncated().unwrap_or(false) {
+            break;
+        }
+        key_marker = page.next_key_marker().map(str::to_string);
+        upload_id_marker = page.next_upload_id_marker().map(str::to_string);
+    }
+
+    Ok(found)
+}
+
+/// Total size of the parts uploaded so far
+async fn aws_parts_size(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+) -> Result<u64, AppError> {
+    let mut size = 0;
+    let mut marker = None;
+
+    loop {
+        let page = client
+            .list_parts()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .set_part_number_marker(marker.take())
+            .send()
+            .await?;
+
+        size += page
+            .parts()
+            .iter()
+            .map(|part| part.size().unwrap_or(0).max(0) as u64)
+            .sum::<u64>();
+
+        if !page.is_truncated().unwrap_or(false) {
+            return Ok(size);
+        }
+        marker = page.next_part_number_marker().map(str::to_string);
+    }
+}
+
+/// Incomplete uploads in the MinIO bucket started before `cutoff`
+async fn minio_uploads(
+    bucket: &Bucket,
+    prefix: &str,
+    cutoff: DateTime<Utc>,
+) -> Result<Vec<OrphanedUpload>, AppError> {
+    let pages = bucket.list_multiparts_uploads(Some(prefix), None).await?;
+
+    // rust-s3 can't list parts, so sizes stay unknown
+    Ok(pages
+        .into_iter()
+        .flat_map(|page| page.uploads)
+        .filter_map(|upload| {
+            let initiated = DateTime::parse_from_rfc3339(&upload.initiated)
+                .ok()?
+                .with_timezone(&Utc);
+
+            (initiated < cutoff).then_some(OrphanedUpload {
+                key: upload.key,
+                upload_id: upload.id,
+                initiated,
+                size: None,
+            })
+        })
+        .collect())
+}
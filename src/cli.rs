@@ -0,0 +1,1383 @@
+use chrono::{DateTime, Utc};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::{path::PathBuf, time::Duration};
+
+use crate::{
+    capabilities::ALL_STORAGE_CLASSES,
+    keys,
+    ml::{FileCategory, DEFAULT_HIGH_ENTROPY, DEFAULT_LOW_ENTROPY},
+    multipart,
+    retry::RetryPolicy,
+    scan::Scanners,
+    secrets::SecretRef,
+    source::SourceRange,
+    Backend,
+};
+
+/// Command line options
+#[derive(Parser, Debug, Clone)]
+#[command(
+    name = "s3-ml-uploader",
+    version,
+    about = "Classify files and upload them to S3",
+    args_conflicts_with_subcommands = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    // Running without a subcommand is the same as `upload`
+    #[command(flatten)]
+    pub upload: UploadArgs,
+}
+
+/// Available subcommands
+// Parsed once per run, so the size of `UploadArgs` doesn't matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Classify files and upload them to every backend (default)
+    Upload(UploadArgs),
+
+    /// Check credentials, region, bucket and a probe round-trip on every backend
+    Doctor(TlsArgs),
+
+    /// Measure upload throughput and latency of every backend with synthetic data
+    Bench(BenchArgs),
+
+    /// Abort incomplete multipart uploads left behind by failed runs
+    Cleanup(CleanupArgs),
+
+    /// List objects under a prefix of the AWS bucket
+    List(ListArgs),
+
+    /// Download one object to a local file
+    Download(DownloadArgs),
+
+    /// List (and optionally download) objects of the AWS bucket by tag
+    Find(FindArgs),
+
+    /// Copy an object server-side, within the AWS bucket or between buckets
+    Copy(CopyArgs),
+
+    /// Print the category, confidence and MIME type of files, without uploading anything
+    Classify(ClassifyArgs),
+
+    /// Write a CSV dataset of file prefix features and their labels, for training a classifier
+    ExportTrainingData(ExportTrainingDataArgs),
+}
+
+/// Uploaded when no files or --dir are given, matching `create-test-files.sh`
+pub const DEFAULT_FILES: [&str; 3] = ["file1.txt", "file2.txt", "file3.txt"];
+
+/// Options for the `upload` subcommand
+#[derive(Args, Debug, Clone)]
+pub struct UploadArgs {
+    /// Files or glob patterns (e.g. 'data/*.parquet') to classify and upload
+    ///
+    /// Defaults to file1.txt, file2.txt and file3.txt when neither files nor --dir are given.
+    pub files: Vec<String>,
+
+    /// Also upload every file under this directory, recursively
+    #[arg(long, value_name = "DIR")]
+    pub dir: Option<PathBuf>,
+
+    /// Start uploading --dir files as the walk finds them instead of after it, in no order
+    #[arg(long, requires = "dir", conflicts_with_all = ["progress", "checksum_manifest"])]
+    pub stream: bool,
+
+    /// Keep the directory structure in keys (relative to --dir) instead of just the file name
+    #[arg(long, conflicts_with = "content_addressed")]
+    pub keep_paths: bool,
+
+    /// With --keep-paths, drop the first N directories of each path, like tar
+    #[arg(long, value_name = "N", requires = "keep_paths")]
+    pub strip_components: Option<usize>,
+
+    /// Print every prediction instead of rolling up repeated ones
+    #[arg(short, long)]
+    pub verbose: bool,
+
+    /// Upload without asking, even above --confirm-files or --confirm-bytes
+    #[arg(short, long)]
+    pub yes: bool,
+
+    /// Ask before uploading more files than this in one run
+    #[arg(long, value_name = "N", default_value_t = 1000)]
+    pub confirm_files: usize,
+
+    /// Ask before uploading more bytes than this in one run (e.g. 10GiB)
+    #[arg(long, value_name = "BYTES", default_value = "10GiB", value_parser = parse_size)]
+    pub confirm_bytes: u64,
+
+    /// Backends to upload to, e.g. `aws,http` (default: those configured in the environment)
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub backends: Vec<Backend>,
+
+    /// Also write every AWS S3 upload to BUCKET in REGION (e.g. `eu-west-1=dr-bucket`), repeatable
+    #[arg(long, value_name = "REGION=BUCKET", value_parser = parse_replica)]
+    pub replicate_to: Vec<(String, String)>,
+
+    /// Treat file arguments literally instead of expanding glob patterns
+    #[arg(long)]
+    pub no_glob: bool,
+
+    /// Do not fail when a glob pattern matches no files
+    #[arg(long)]
+    pub allow_empty_glob: bool,
+
+    /// Only upload glob matches and `--dir` files with one of these extensions, e.g. `jpg,png`
+    #[arg(long, value_name = "EXTS", value_delimiter = ',', value_parser = parse_extension)]
+    pub include_ext: Vec<String>,
+
+    /// Skip glob matches and `--dir` files with one of these extensions; wins over `--include-ext`
+    #[arg(long, value_name = "EXTS", value_delimiter = ',', value_parser = parse_extension)]
+    pub exclude_ext: Vec<String>,
+
+    /// Maximum number of concurrent backend requests
+    #[arg(long, default_value_t = 8)]
+    pub concurrency: usize,
+
+    /// Shrink concurrency when S3 responds with 503 SlowDown and grow it back on success
+    #[arg(long)]
+    pub adaptive_concurrency: bool,
+
+    /// Key prefix of files no category matched, instead of `misc`
+    #[arg(long, value_name = "NAME", default_value = "misc", value_parser = parse_key_segment)]
+    pub default_category: String,
+
+    /// Key prefix of text files, instead of `text`
+    #[arg(long, value_name = "NAME", default_value = "text", value_parser = parse_key_segment)]
+    pub text_category: String,
+
+    /// Key prefix of empty files (default: that of their fallback category, usually text)
+    #[arg(long, value_name = "NAME", value_parser = parse_key_segment)]
+    pub empty_category: Option<String>,
+
+    /// Concurrency cap for one category on top of the global one (e.g. `text=32`), repeatable
+    #[arg(long, value_name = "CATEGORY=N", value_parser = parse_category_value::<usize>)]
+    pub category_concurrency: Vec<(String, usize)>,
+
+    /// Maximum backend requests per second for one category (e.g. `images=50`), repeatable
+    #[arg(long, value_name = "CATEGORY=N", value_parser = parse_category_rate)]
+    pub category_rate: Vec<(String, f64)>,
+
+    /// Store every file under `blobs/<sha256 of its content>` instead of a category key
+    #[arg(long)]
+    pub content_addressed: bool,
+
+    /// CSV (`path,key` lines) or JSON (`{"path": "key"}`) file of exact keys for listed files
+    #[arg(long, value_name = "PATH")]
+    pub key_map: Option<PathBuf>,
+
+    /// With --content-addressed, nest keys under N two-character hash directories
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 0,
+        requires = "content_addressed"
+    )]
+    pub shard_depth: usize,
+
+    /// Normalize keys: lowercase, spaces to `-`, drop problematic characters (extension kept)
+    #[arg(long)]
+    pub slugify: bool,
+
+    /// Lowercase file extensions in keys (`.JPG` -> `.jpg`); the files themselves are untouched
+    #[arg(long)]
+    pub normalize_ext: bool,
+
+    /// Longest key in bytes, sidecar and checksum object suffixes included; S3 takes 1024
+    #[arg(long, value_name = "BYTES", default_value_t = keys::MAX_KEY_LEN, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(64..))]
+    pub max_key_length: usize,
+
+    /// What to do with a file whose key is longer than --max-key-length
+    #[arg(long, value_enum, default_value_t = OnLongKey::Error)]
+    pub on_long_key: OnLongKey,
+
+    /// Prefix keys with `<branch>/<sha8>/` of the current git checkout
+    #[arg(long)]
+    pub git_prefix: bool,
+
+    /// With --git-prefix, upload without the prefix instead of failing outside a git repository
+    #[arg(long, requires = "git_prefix")]
+    pub git_prefix_optional: bool,
+
+    /// Upload only bytes `start:end` of each file (either side may be omitted)
+    #[arg(long, value_name = "START:END")]
+    pub source_range: Option<SourceRange>,
+
+    /// Stop starting new uploads once more than N files have failed
+    #[arg(long, value_name = "N")]
+    pub max_failures: Option<usize>,
+
+    /// Stop starting new uploads once more than this fraction of files has failed (e.g. 0.1)
+    #[arg(long, value_name = "RATE", value_parser = parse_rate)]
+    pub max_failure_rate: Option<f64>,
+
+    /// When a failure threshold trips, cancel in-flight uploads instead of letting them finish
+    #[arg(long)]
+    pub fail_fast: bool,
+
+    /// Tag every data object with its `filetype` (category) and `confidence`, for `find --tag`
+    #[arg(long)]
+    pub tag_classification: bool,
+
+    /// Whether an upload's tags replace the ones its key already has, or are merged into them
+    #[arg(long, value_enum, default_value_t = TagMode::Replace)]
+    pub tag_mode: TagMode,
+
+    /// Send a Content-MD5 header with every upload (every part of multipart ones)
+    #[arg(long)]
+    pub content_md5: bool,
+
+    /// Store each file's mode, mtime and owner as `x-amz-meta-file-*` for `download --restore-attrs`
+    #[arg(long)]
+    pub preserve_attrs: bool,
+
+    /// Store each file's name as `x-amz-meta-original-name` for `download --restore-names`
+    #[arg(long)]
+    pub store_original_name: bool,
+
+    /// Upload only when the stored object's SHA-256 differs from the local file's
+    #[arg(long)]
+    pub overwrite_if_different: bool,
+
+    /// Cache up to this many HEAD results, so repeated checks of an object send one request
+    #[arg(long, value_name = "ENTRIES")]
+    pub head_cache: Option<usize>,
+
+    /// How long a --head-cache entry is trusted
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, default_value = "60s", requires = "head_cache")]
+    pub head_cache_ttl: Duration,
+
+    /// Append each file's bytes to the object at its key instead of replacing it, for log shipping
+    #[arg(long, conflicts_with_all = ["content_addressed", "overwrite_if_different", "replicate_to"])]
+    pub append: bool,
+
+    /// How --append adds to an existing object; `native` falls back to `rmw` where unsupported
+    #[arg(long, value_enum, default_value_t = AppendStrategy::Native, requires = "append")]
+    pub append_strategy: AppendStrategy,
+
+    /// Compute HTTP and GCS request signatures on up to N blocking threads instead of the async workers
+    #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub signing_threads: Option<usize>,
+
+    /// Read every upload back and re-upload it up to ATTEMPTS times while its SHA-256 doesn't match
+    #[arg(long, value_name = "ATTEMPTS", conflicts_with_all = ["append", "stream_above"])]
+    pub verify_and_repair: Option<u32>,
+
+    /// With --overwrite-if-different, what to do when the stored object has no checksum
+    #[arg(long, value_enum, default_value_t = NoChecksum::Upload, requires = "overwrite_if_different")]
+    pub if_no_checksum: NoChecksum,
+
+    /// Verify files against a `sha256sum`-style manifest before uploading anything
+    #[arg(long, value_name = "FILE")]
+    pub checksum_manifest: Option<PathBuf>,
+
+    /// With --checksum-manifest, upload files that no longer match it instead of failing
+    #[arg(long, requires = "checksum_manifest")]
+    pub force: bool,
+
+    /// Skip files an interrupted run of the same batch already uploaded
+    #[arg(long)]
+    pub resume_batch: bool,
+
+    /// Record batch progress in DIR, so a later --resume-batch run can resume it
+    /// [default with --resume-batch: .s3-ml-uploader/batches]
+    #[arg(long, value_name = "DIR", conflicts_with = "state_db")]
+    pub checkpoint_dir: Option<PathBuf>,
+
+    /// Keep batch progress, classifications and per-target status in this SQLite database
+    /// instead of a progress file (needs the `sqlite` feature)
+    #[arg(long, value_name = "PATH")]
+    pub state_db: Option<PathBuf>,
+
+    /// Don't upload files larger than this (e.g. 2GiB); see --on-oversize
+    #[arg(long, value_name = "BYTES", value_parser = parse_size)]
+    pub max_file_size: Option<u64>,
+
+    /// Read each file as exactly this many bytes (e.g. a pipe of known size) and upload it in one PUT
+    #[arg(long, value_name = "BYTES", value_parser = parse_size, conflicts_with_all = ["stream_above", "source_range", "pack", "plan", "dry_run", "on_collision", "resume_batch", "checkpoint_dir", "checksum_manifest"])]
+    pub content_length: Option<u64>,
+
+    /// Stream files larger than this (e.g. 4GiB) to every backend instead of loading them: hashed, then read once for all
+    #[arg(long, value_name = "BYTES", value_parser = parse_size, conflicts_with = "append")]
+    pub stream_above: Option<u64>,
+
+    /// Part size of multipart uploads (e.g. 64MiB, 5MiB to 5GiB); by default 16MiB, larger for files over 156GiB
+    #[arg(long, value_name = "BYTES", value_parser = parse_part_size, conflicts_with = "content_length")]
+    pub part_size: Option<u64>,
+
+    /// Checksum every multipart part carries to AWS S3, computed as the part is sent
+    #[arg(long, value_enum, default_value_t = PartChecksum::Sha256)]
+    pub part_checksum: PartChecksum,
+
+    /// What to do with a file over --max-file-size
+    #[arg(long, value_enum, default_value_t = OnOversize::Skip, requires = "max_file_size")]
+    pub on_oversize: OnOversize,
+
+    /// Skip files smaller than this, e.g. `1` to skip empty files
+    #[arg(long, value_name = "BYTES", value_parser = parse_size)]
+    pub min_file_size: Option<u64>,
+
+    /// What to do with a file whose content is empty once read (and transformed)
+    #[arg(long, value_enum, default_value_t = OnEmpty::Upload)]
+    pub on_empty: OnEmpty,
+
+    /// Abort and retry an upload sending fewer bytes per second than this over 10s (e.g. 64KiB)
+    #[arg(long, value_name = "BYTES", value_parser = parse_size)]
+    pub min_throughput: Option<u64>,
+
+    /// Also retry uploads a backend answers with one of these HTTP statuses, e.g. `502,504`
+    #[arg(long, value_name = "STATUSES", value_delimiter = ',', value_parser = clap::value_parser!(u16).range(100..600))]
+    pub retry_on_status: Vec<u16>,
+
+    /// Which failed uploads are retried; from the library, e.g. `RetryPolicy::new(|err| ...)`
+    #[arg(skip)]
+    pub retry_policy: RetryPolicy,
+
+    /// Scan every body for credentials (AWS keys, private keys, tokens) before any of it is sent
+    #[arg(long)]
+    pub scan_secrets: bool,
+
+    /// What to do with a file a scanner flags
+    #[arg(long, value_enum, default_value_t = OnScanHit::Block)]
+    pub on_scan_hit: OnScanHit,
+
+    /// Scanners run over every body; from the library, e.g. `Scanners::default().with(...)`
+    #[arg(skip)]
+    pub scanners: Scanners,
+
+    /// Re-read and retry a file whose size changes while it is being read
+    #[arg(long)]
+    pub retry_on_change: bool,
+
+    /// Bytes read from disk per read call (e.g. 64KiB, 1MiB): larger is faster, smaller uses less memory
+    #[arg(long, value_name = "SIZE", default_value = "256KiB", value_parser = parse_buffer_size)]
+    pub read_buffer_size: usize,
+
+    /// Leave all-zero 4 KiB blocks (holes of sparse files) out of uploads; `download` rebuilds them
+    #[arg(long, conflicts_with_all = ["append", "pack"])]
+    pub sparse: bool,
+
+    /// Suffix identifying a metadata sidecar (`foo.bin` -> `foo.bin.json`)
+    #[arg(long, default_value = ".json")]
+    pub sidecar_suffix: String,
+
+    /// After each upload, store its hex digest as a `<key>.sha256` object next to it
+    #[arg(long, conflicts_with_all = ["append", "pack"])]
+    pub emit_checksum_objects: bool,
+
+    /// Digest --emit-checksum-objects stores
+    #[arg(long, value_enum, default_value_t = DigestAlgorithm::Sha256, requires = "emit_checksum_objects")]
+    pub checksum_object_algorithm: DigestAlgorithm,
+
+    /// Key suffix of checksum objects (by default `.` and the algorithm, e.g. `.sha256`)
+    #[arg(long, value_name = "SUFFIX", requires = "emit_checksum_objects")]
+    pub checksum_object_suffix: Option<String>,
+
+    /// Store a Merkle tree of each upload's parts: the root as `x-amz-meta-merkle-root`, the
+    /// whole tree as a `<key>.merkle.json` object next to it
+    #[arg(long, conflicts_with_all = ["append", "pack"])]
+    pub merkle_tree: bool,
+
+    /// Also embed small JSON sidecars as `x-amz-meta-sidecar` on the data object
+    #[arg(long)]
+    pub embed_sidecar: bool,
+
+    /// Remove EXIF metadata (camera, GPS, ...) from JPEG and PNG files before uploading
+    #[arg(long)]
+    pub strip_exif: bool,
+
+    /// Remove embedded metadata (EXIF, XMP, PDF author, ...) from `images`, `documents` or both
+    #[arg(
+        long,
+        value_name = "CATEGORIES",
+        num_args = 0..=1,
+        require_equals = true,
+        value_delimiter = ',',
+        default_missing_value = "images,documents",
+        value_parser = parse_metadata_category
+    )]
+    pub strip_metadata: Option<Vec<FileCategory>>,
+
+    /// Convert CRLF line endings to LF in text files before uploading
+    #[arg(long)]
+    pub normalize_newlines: bool,
+
+    /// Content-Disposition of uploaded objects, e.g. `inline`
+    #[arg(long, value_name = "VALUE", value_parser = parse_header_value)]
+    pub content_disposition: Option<String>,
+
+    /// Set `Content-Disposition: attachment; filename="<file name>"` unless given explicitly
+    #[arg(long)]
+    pub force_download: bool,
+
+    /// Expires header: an RFC 3339 timestamp or a duration from now, e.g. `7d`
+    #[arg(long, value_name = "WHEN", value_parser = parse_expires)]
+    pub expires: Option<DateTime<Utc>>,
+
+    /// Storage class of uploaded objects
+    #[arg(long, value_parser = clap::builder::PossibleValuesParser::new(ALL_STORAGE_CLASSES))]
+    pub storage_class: Option<String>,
+
+    /// Server-side encryption of uploaded objects
+    #[arg(long, value_enum)]
+    pub sse: Option<SseMode>,
+
+    /// KMS key for `--sse kms` (the bucket default key if omitted)
+    #[arg(long, requires = "sse")]
+    pub sse_kms_key_id: Option<String>,
+
+    /// Object lock retention mode (the bucket must have object lock enabled)
+    #[arg(long, value_enum, requires = "object_lock_retain")]
+    pub object_lock_mode: Option<LockMode>,
+
+    /// How long uploaded objects are locked (e.g. 30d)
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, requires = "object_lock_mode")]
+    pub object_lock_retain: Option<Duration>,
+
+    /// Show bytes uploaded out of the batch total, with throughput and ETA, on stderr
+    #[arg(long)]
+    pub progress: bool,
+
+    /// Export upload spans and metrics to this OTLP/HTTP collector (needs the `otel` feature)
+    #[arg(long, value_name = "URL")]
+    pub otel_endpoint: Option<String>,
+
+    /// POST a JSON notification to this URL when files finish
+    #[arg(long, value_name = "URL")]
+    pub webhook_url: Option<String>,
+
+    /// Notify after every file or once with the whole batch
+    #[arg(long, value_enum, default_value_t = WebhookMode::PerFile, requires = "webhook_url")]
+    pub webhook_mode: WebhookMode,
+
+    /// Sign notifications with HMAC-SHA256 in `X-Signature-256` (default: $WEBHOOK_SECRET)
+    #[arg(long, value_name = "SECRET", requires = "webhook_url")]
+    pub webhook_secret: Option<String>,
+
+    /// Write a JSON receipt of every uploaded file (key, buckets, SHA-256, time, version ids) here
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["dry_run", "pack"])]
+    pub receipts_dir: Option<PathBuf>,
+
+    /// Sign receipts with HMAC-SHA256 in their `signature` field (default: $RECEIPT_SECRET)
+    #[arg(long, value_name = "SECRET", requires = "receipts_dir")]
+    pub receipt_secret: Option<String>,
+
+    /// Print the end-of-run summary as JSON
+    #[arg(long)]
+    pub json: bool,
+
+    /// Send uploads unsigned, for buckets that allow anonymous writes
+    #[arg(long)]
+    pub no_sign_request: bool,
+
+    /// Retry AWS S3 uploads the store refuses as unsupported (501, NotImplemented) through the HTTP path
+    #[arg(long)]
+    pub sdk_fallback_http: bool,
+
+    /// Sign HTTP backend uploads with `UNSIGNED-PAYLOAD` instead of hashing each body, for trusted networks
+    #[arg(long)]
+    pub unsigned_payload: bool,
+
+    /// Run with placeholder values for unset AWS_*/S3_* variables instead of failing
+    #[arg(long)]
+    pub allow_defaults: bool,
+
+    #[command(flatten)]
+    pub classifier: ClassifierArgs,
+
+    /// Classify every file and derive its key before the first upload, stopping on key collisions
+    #[arg(long, conflicts_with = "stream")]
+    pub plan: bool,
+
+    /// Print the plan (keys, categories, sizes, collisions) and exit without connecting to any backend
+    #[arg(long, conflicts_with = "stream")]
+    pub dry_run: bool,
+
+    /// What to do when files of the run, or a file and a stored object, map to the same key
+    #[arg(long, value_enum, default_value_t = OnCollision::Overwrite, conflicts_with_all = ["stream", "content_addressed", "version_suffix", "append"])]
+    pub on_collision: OnCollision,
+
+    /// Concatenate files under --pack-below into `packs/<sha256>.pack` objects, each with a JSON index
+    #[arg(long, conflicts_with_all = ["stream", "append", "version_suffix"])]
+    pub pack: bool,
+
+    /// With --pack, files smaller than this are packed; larger ones upload on their own
+    #[arg(long, value_name = "BYTES", default_value = "1MiB", value_parser = parse_size, requires = "pack")]
+    pub pack_below: u64,
+
+    /// With --pack, the most bytes one pack holds (a larger file still gets a pack of its own)
+    #[arg(long, value_name = "BYTES", default_value = "64MiB", value_parser = parse_size, requires = "pack")]
+    pub pack_size: u64,
+
+    /// Keep earlier versions in a non-versioned bucket: put a version token before each key's extension
+    #[arg(long, value_enum, conflicts_with_all = ["content_addressed", "append"])]
+    pub version_suffix: Option<VersionSuffix>,
+
+    /// Check files classified as JSON against this JSON Schema before uploading them
+    #[arg(long, value_name = "SCHEMA", conflicts_with = "stream_above")]
+    pub validate_json: Option<PathBuf>,
+
+    /// What to do with a JSON file that fails --validate-json
+    #[arg(long, value_enum, default_value_t = OnInvalid::Skip, requires = "validate_json")]
+    pub on_invalid: OnInvalid,
+
+    /// What to do with a file whose classification errors or panics
+    #[arg(long, value_enum, default_value_t = OnClassifyError::Misc)]
+    pub on_classify_error: OnClassifyError,
+
+    /// Retry AWS S3 and HTTP uploads in the bucket's region when S3 redirects them
+    #[arg(long)]
+    pub auto_region: bool,
+
+    /// Upload to AWS S3 and over HTTP through the bucket's Transfer Acceleration endpoint
+    #[arg(long)]
+    pub accelerate: bool,
+
+    /// What to do when a backend doesn't support a requested feature
+    #[arg(long, value_enum, default_value_t = OnUnsupported::Error)]
+    pub on_unsupported: OnUnsupported,
+
+    #[command(flatten)]
+    pub tls: TlsArgs,
+}
+
+/// Server-side encryption modes
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SseMode {
+    /// SSE-S3 (AES256)
+    Aes256,
+    /// SSE-KMS (aws:kms)
+    Kms,
+}
+
+/// Object lock retention modes
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    Governance,
+    Compliance,
+}
+
+/// Comparison used by --overwrite-if-different when no checksum is stored
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoChecksum {
+    /// Always upload
+    Upload,
+    /// Skip when the stored size matches
+    Size,
+}
+
+/// What decides a file's category
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClassifyBy {
+    /// The content: magic bytes, text heuristics, or --classifier-url
+    Content,
+    /// The file extension alone; the content is never read for it
+    Extension,
+    /// The extension when it is known, otherwise the content
+    Both,
+}
+
+/// A signal `--classify-priority` consults
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClassifySignal {
+    /// The answer of --classifier-url, unless it fails or answers `misc`
+    Remote,
+    /// A magic number at the start of the file
+    Magic,
+    /// A known extension or an --ext-category entry
+    Extension,
+    /// Mostly printable bytes for `text`; byte entropy with --classify-entropy
+    Heuristic,
+}
+
+/// Handling of files the classifier fails on
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnClassifyError {
+    /// Warn and upload the file as `misc`
+    Misc,
+    /// Warn and don't upload the file
+    Skip,
+    /// Count the file as failed
+    Fail,
+}
+
+/// How --append adds bytes to an existing object
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AppendStrategy {
+    /// Write at the object's end with `x-amz-write-offset-bytes` where the backend can
+    Native,
+    /// Download the object, add the bytes and upload it whole again
+    Rmw,
+}
+
+/// Version token --version-suffix puts before a key's extension
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionSuffix {
+    /// Start of the run in UTC, e.g. `2024-06-01T12-00-00Z`
+    Timestamp,
+    /// First 12 hex digits of the content's SHA-256
+    Hash,
+    /// Lowest number from 1 up not yet stored on any backend
+    Counter,
+}
+
+/// Handling of files whose key another file or a stored object already has
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnCollision {
+    /// Upload under the key anyway; the last upload wins
+    Overwrite,
+    /// Number the key `<stem>-1.<ext>`, `<stem>-2.<ext>`, ... up to the first free one
+    Rename,
+}
+
+/// Handling of JSON files that fail --validate-json
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnInvalid {
+    /// Report why and don't upload the file
+    Skip,
+    /// Count the file as failed
+    Fail,
+}
+
+/// Handling of files over --max-file-size
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnOversize {
+    /// Report the file with its size and don't upload it
+    Skip,
+    /// Count the file as failed
+    Error,
+}
+
+/// Handling of files a content scanner flags
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnScanHit {
+    /// Count the file as failed; nothing of it is uploaded
+    Block,
+    /// Print what was found and upload the file anyway
+    Warn,
+}
+
+/// Handling of files with no content
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnEmpty {
+    /// Classify and upload it like any other file
+    Upload,
+    /// Report the file and don't upload it
+    Skip,
+    /// Count the file as failed
+    Error,
+}
+
+/// Handling of keys longer than --max-key-length
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnLongKey {
+    /// Cut the key short, ending it in a hash of the full key and its extension
+    Truncate,
+    /// Replace everything after the first segment with the key's SHA-256 and extension
+    Hash,
+    /// Count the file as failed
+    Error,
+}
+
+/// How --tag-mode treats tags already stored on a key being uploaded
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagMode {
+    /// The object gets the upload's tags alone
+    Replace,
+    /// The stored tags are kept, the upload's win on a conflicting key
+    Merge,
+}
+
+/// When --webhook-url is notified
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookMode {
+    /// One notification per processed file
+    PerFile,
+    /// One notification with every file and the summary at the end of the run
+    Batch,
+}
+
+/// Digest of an uploaded object --emit-checksum-objects stores
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigestAlgorithm {
+    /// SHA-256, already taken of every upload
+    Sha256,
+    /// SHA-512, hashing each body once more
+    Sha512,
+    /// MD5, for tooling that only checks that
+    Md5,
+}
+
+impl DigestAlgorithm {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Sha512 => "sha512",
+            Self::Md5 => "md5",
+        }
+    }
+}
+
+/// Checksum AWS S3 verifies every multipart part against (--part-checksum)
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PartChecksum {
+    /// SHA-256, as --merkle-tree leaves are
+    #[default]
+    Sha256,
+    /// CRC32C, much cheaper to compute on large uploads
+    Crc32c,
+}
+
+/// Handling of features a backend doesn't support
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnUnsupported {
+    /// Warn and upload to that backend without the feature
+    Warn,
+    /// Fail before uploading anything
+    Error,
+}
+
+/// Options for the `bench` subcommand
+#[derive(Args, Debug, Clone)]
+pub struct BenchArgs {
+    /// Size of each synthetic object (e.g. 65536, 512KiB, 8MiB)
+    #[arg(long, default_value = "1MiB", value_parser = parse_size)]
+    pub size: u64,
+
+    /// Number of objects uploaded to each backend
+    #[arg(long, default_value_t = 10)]
+    pub count: usize,
+
+    /// Maximum number of concurrent uploads per backend
+    #[arg(long, default_value_t = 4)]
+    pub concurrency: usize,
+
+    /// Key prefix of the probe objects (removed after the run)
+    #[arg(long, default_value = "s3-ml-uploader-bench/")]
+    pub prefix: String,
+
+    /// Instead of uploading, time reading a `--size` file from disk with each read buffer size
+    #[arg(long, value_name = "SIZES", value_delimiter = ',', value_parser = parse_buffer_size)]
+    pub read_buffer_sizes: Vec<usize>,
+
+    /// Benchmark AWS S3 and HTTP through the bucket's Transfer Acceleration endpoint
+    #[arg(long)]
+    pub accelerate: bool,
+
+    /// Print results as JSON
+    #[arg(long)]
+    pub json: bool,
+
+    #[command(flatten)]
+    pub tls: TlsArgs,
+}
+
+/// Options for the `cleanup` subcommand
+#[derive(Args, Debug, Clone)]
+pub struct CleanupArgs {
+    /// Only consider uploads whose key starts with this prefix
+    #[arg(long)]
+    pub prefix: Option<String>,
+
+    /// Only abort uploads started longer ago than this (e.g. 90m, 24h, 7d)
+    #[arg(long, value_name = "DURATION", default_value = "7d", value_parser = parse_duration)]
+    pub older_than: Duration,
+
+    /// Abort the uploads without asking; without a terminal the command otherwise only reports them
+    #[arg(short, long)]
+    pub yes: bool,
+
+    #[command(flatten)]
+    pub tls: TlsArgs,
+}
+
+/// Options for the `list` subcommand
+#[derive(Args, Debug, Clone)]
+pub struct ListArgs {
+    /// Only list keys starting with this prefix (end it with `/` to list a "folder")
+    pub prefix: Option<String>,
+
+    /// List every key below the prefix instead of rolling sub-folders up
+    #[arg(long, short)]
+    pub recursive: bool,
+
+    /// Print sizes as KiB/MiB/GiB
+    #[arg(long)]
+    pub human_readable: bool,
+
+    /// Print the listing as JSON
+    #[arg(long)]
+    pub json: bool,
+
+    /// List a public bucket without credentials
+    #[arg(long)]
+    pub no_sign_request: bool,
+
+    /// Run with placeholder values for unset AWS_*/S3_* variables instead of failing
+    #[arg(long)]
+    pub allow_defaults: bool,
+
+    #[command(flatten)]
+    pub tls: TlsArgs,
+}
+
+/// Options for the `find` subcommand
+#[derive(Args, Debug, Clone)]
+pub struct FindArgs {
+    /// Only objects whose tags include KEY=VALUE (e.g. `filetype=images`), repeatable; all must match
+    #[arg(long, value_name = "KEY=VALUE", required = true, value_parser = parse_tag)]
+    pub tag: Vec<(String, String)>,
+
+    /// Only consider keys starting with this prefix
+    #[arg(long)]
+    pub prefix: Option<String>,
+
+    /// Download every match into this directory, keeping the key's folders
+    #[arg(long, value_name = "DIR")]
+    pub download: Option<PathBuf>,
+
+    /// Maximum number of concurrent tag lookups and downloads
+    #[arg(long, default_value_t = 16)]
+    pub concurrency: usize,
+
+    /// Print the matches as JSON
+    #[arg(long)]
+    pub json: bool,
+
+    /// Query a public bucket without credentials
+    #[arg(long)]
+    pub no_sign_request: bool,
+
+    /// Run with placeholder values for unset AWS_*/S3_* variables instead of failing
+    #[arg(long)]
+    pub allow_defaults: bool,
+
+    #[command(flatten)]
+    pub tls: TlsArgs,
+}
+
+/// Options for the `copy` subcommand
+#[derive(Args, Debug, Clone)]
+pub struct CopyArgs {
+    /// Key of the object to copy
+    pub source: String,
+
+    /// Key of the copy
+    pub destination: String,
+
+    /// Bucket to copy from (default: $AWS_BUCKET)
+    #[arg(long, value_name = "BUCKET")]
+    pub source_bucket: Option<String>,
+
+    /// Bucket to copy into (default: $AWS_BUCKET)
+    #[arg(long, value_name = "BUCKET")]
+    pub dest_bucket: Option<String>,
+
+    /// Set user metadata KEY=VALUE on the copy, repeatable; the rest of the source's is kept
+    #[arg(long, value_name = "KEY=VALUE", value_parser = parse_metadata)]
+    pub metadata: Vec<(String, String)>,
+
+    /// Leave this user metadata key off the copy, repeatable
+    #[arg(long, value_name = "KEY")]
+    pub remove_metadata: Vec<String>,
+
+    /// Storage class of the copy (default: the source's)
+    #[arg(long, value_parser = clap::builder::PossibleValuesParser::new(ALL_STORAGE_CLASSES))]
+    pub storage_class: Option<String>,
+
+    /// Maximum number of parts copied at once for objects over 5 GiB
+    #[arg(long, default_value_t = 8)]
+    pub concurrency: usize,
+
+    /// Run with placeholder values for unset AWS_*/S3_* variables instead of failing
+    #[arg(long)]
+    pub allow_defaults: bool,
+
+    #[command(flatten)]
+    pub tls: TlsArgs,
+}
+
+/// Options for the `download` subcommand
+#[derive(Args, Debug, Clone)]
+pub struct DownloadArgs {
+    /// Key of the object to download
+    pub key: String,
+
+    /// Output file (default: the key's file name in the current directory); `-` is stdout
+    pub output: Option<String>,
+
+    /// Stream the object to stdout, the same as an output of `-`
+    #[arg(long, conflicts_with = "output")]
+    pub stdout: bool,
+
+    /// Backend to download from
+    #[arg(long, value_enum, default_value_t = Backend::Aws)]
+    pub backend: Backend,
+
+    /// Inflate gzip/zstd bodies according to the object's Content-Encoding
+    #[arg(long)]
+    pub decompress: bool,
+
+    /// Apply the mode, mtime and owner stored by `upload --preserve-attrs`
+    #[arg(long)]
+    pub restore_attrs: bool,
+
+    /// Write the file under the name stored by `upload --store-original-name` instead of the key's
+    #[arg(long, conflicts_with = "output")]
+    pub restore_names: bool,
+
+    /// Download from a public bucket without credentials
+    #[arg(long)]
+    pub no_sign_request: bool,
+
+    /// Run with placeholder values for unset AWS_*/S3_* variables instead of failing
+    #[arg(long)]
+    pub allow_defaults: bool,
+
+    /// Download through `<output>.part` and continue one an interrupted run left behind
+    #[arg(long)]
+    pub resume: bool,
+
+    /// Extract the file KEY names (its key or path) from the pack this `upload --pack` index describes
+    #[arg(long, value_name = "INDEX_KEY", conflicts_with_all = ["resume", "decompress", "restore_attrs"])]
+    pub pack_index: Option<String>,
+
+    #[command(flatten)]
+    pub tls: TlsArgs,
+}
+
+/// Options for the `classify` subcommand
+#[derive(Args, Debug, Clone)]
+pub struct ClassifyArgs {
+    /// Files or glob patterns to classify
+    #[arg(required_unless_present = "dir")]
+    pub files: Vec<String>,
+
+    /// Also classify every file under this directory, recursively
+    #[arg(long, value_name = "DIR")]
+    pub dir: Option<PathBuf>,
+
+    /// Treat file arguments literally instead of expanding glob patterns
+    #[arg(long)]
+    pub no_glob: bool,
+
+    /// Do not fail when a glob pattern matches no files
+    #[arg(long)]
+    pub allow_empty_glob: bool,
+
+    /// Only classify glob matches and `--dir` files with one of these extensions, e.g. `jpg,png`
+    #[arg(long, value_name = "EXTS", value_delimiter = ',', value_parser = parse_extension)]
+    pub include_ext: Vec<String>,
+
+    /// Skip glob matches and `--dir` files with one of these extensions
+    #[arg(long, value_name = "EXTS", value_delimiter = ',', value_parser = parse_extension)]
+    pub exclude_ext: Vec<String>,
+
+    /// Maximum number of files read and classified at once
+    #[arg(long, default_value_t = 8)]
+    pub concurrency: usize,
+
+    /// Print the classifications as JSON
+    #[arg(long)]
+    pub json: bool,
+
+    /// Also break the counts down by top-level directory (under --dir, if given) and category
+    #[arg(long)]
+    pub by_dir: bool,
+
+    #[command(flatten)]
+    pub classifier: ClassifierArgs,
+}
+
+/// Options for the `export-training-data` subcommand
+#[derive(Args, Debug, Clone)]
+pub struct ExportTrainingDataArgs {
+    /// Files or glob patterns to export
+    #[arg(required_unless_present = "dir")]
+    pub files: Vec<String>,
+
+    /// Also export every file under this directory, recursively
+    #[arg(long, value_name = "DIR")]
+    pub dir: Option<PathBuf>,
+
+    /// Treat file arguments literally instead of expanding glob patterns
+    #[arg(long)]
+    pub no_glob: bool,
+
+    /// Do not fail when a glob pattern matches no files
+    #[arg(long)]
+    pub allow_empty_glob: bool,
+
+    /// Only export glob matches and `--dir` files with one of these extensions, e.g. `jpg,png`
+    #[arg(long, value_name = "EXTS", value_delimiter = ',', value_parser = parse_extension)]
+    pub include_ext: Vec<String>,
+
+    /// Skip glob matches and `--dir` files with one of these extensions
+    #[arg(long, value_name = "EXTS", value_delimiter = ',', value_parser = parse_extension)]
+    pub exclude_ext: Vec<String>,
+
+    /// CSV file the dataset is written to
+    #[arg(long, short, value_name = "PATH")]
+    pub output: PathBuf,
+
+    /// Leading bytes of each file kept as its features
+    #[arg(long, value_name = "BYTES", value_parser = parse_size, default_value = "512")]
+    pub prefix_len: u64,
+
+    /// `path,category` lines of labels a person assigned, used instead of the prediction
+    #[arg(long, value_name = "PATH")]
+    pub labels: Option<PathBuf>,
+
+    /// Maximum number of files read and classified at once
+    #[arg(long, default_value_t = 8)]
+    pub concurrency: usize,
+
+    #[command(flatten)]
+    pub classifier: ClassifierArgs,
+}
+
+/// How files are classified, shared by `upload` and `classify`
+#[derive(Args, Debug, Clone)]
+pub struct ClassifierArgs {
+    /// What decides a file's category: `content` (magic bytes, text heuristics), `extension`, or `both`
+    #[arg(long, value_enum, default_value_t = ClassifyBy::Content)]
+    pub classify_by: ClassifyBy,
+
+    /// Signals to consult in order, the first that recognizes a file deciding, e.g. `extension,magic,heuristic`
+    #[arg(
+        long,
+        value_enum,
+        value_name = "SIGNALS",
+        value_delimiter = ',',
+        conflicts_with = "classify_by"
+    )]
+    pub classify_priority: Vec<ClassifySignal>,
+
+    /// Category of an extension for --classify-by extension/both or --classify-priority extension, e.g. `onnx=archives`
+    #[arg(long, value_name = "EXT=CATEGORY", value_parser = parse_ext_category, value_delimiter = ',')]
+    pub ext_category: Vec<(String, FileCategory)>,
+
+    /// HTTP inference endpoint that classifies files instead of the built-in predictor
+    #[arg(long, value_name = "URL")]
+    pub classifier_url: Option<String>,
+
+    /// Timeout of a single request to --classifier-url
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, default_value = "5s")]
+    pub classifier_timeout: Duration,
+
+    /// Don't fall back to the built-in predictor when --classifier-url errors (see --on-classify-error)
+    #[arg(long, requires = "classifier_url")]
+    pub no_classifier_fallback: bool,
+
+    /// Classify binaries no signature matched by byte entropy: `compressed-or-encrypted` or `binary-data`
+    #[arg(long)]
+    pub classify_entropy: bool,
+
+    /// Entropy in bits per byte at or above which --classify-entropy picks `compressed-or-encrypted`
+    #[arg(long, value_name = "BITS", value_parser = parse_entropy, default_value_t = DEFAULT_HIGH_ENTROPY, requires = "classify_entropy")]
+    pub high_entropy: f32,
+
+    /// Entropy in bits per byte at or below which --classify-entropy picks `binary-data`
+    #[arg(long, value_name = "BITS", value_parser = parse_entropy, default_value_t = DEFAULT_LOW_ENTROPY, requires = "classify_entropy")]
+    pub low_entropy: f32,
+}
+
+/// Connection options: TLS for private endpoints that require mutual TLS or a custom CA,
+/// and how requests identify themselves
+#[derive(Args, Debug, Clone, Default)]
+pub struct TlsArgs {
+    /// PEM client certificate (chain) presented to the endpoint
+    #[arg(long, value_name = "PEM", requires = "client_key")]
+    pub client_cert: Option<PathBuf>,
+
+    /// PEM PKCS#8 private key matching --client-cert
+    #[arg(long, value_name = "PEM", requires = "client_cert")]
+    pub client_key: Option<PathBuf>,
+
+    /// PEM bundle of additional CA certificates to trust
+    #[arg(long, value_name = "PEM")]
+    pub ca_cert: Option<PathBuf>,
+
+    /// User-Agent of every S3 request, for access logs and allowlists [default: s3-ml-uploader/<version>]
+    #[arg(long, value_name = "STRING", value_parser = parse_user_agent)]
+    pub user_agent: Option<String>,
+
+    /// Read the AWS SDK's credentials (and optionally endpoint) from a secret: `aws-sm:<secret-id>`
+    #[arg(long, value_name = "REF", value_parser = SecretRef::parse)]
+    pub secret_ref: Option<SecretRef>,
+
+    /// How long credentials from --secret-ref are used before the secret is read again
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, default_value = "15m", requires = "secret_ref")]
+    pub secret_ttl: Duration,
+}
+
+/// Parse a `CATEGORY=VALUE` pair
+pub fn parse_category_value<T: std::str::FromStr>(s: &str) -> Result<(String, T), String> {
+    let (category, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected CATEGORY=VALUE, got '{}'", s))?;
+
+    let category = category.trim();
+    if category.is_empty() {
+        return Err(format!("missing category in '{}'", s));
+    }
+
+    let value = value
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid value for category '{}': '{}'", category, value))?;
+
+    Ok((category.to_string(), value))
+}
+
+/// Parse a file extension, with or without its leading dot, into lowercase
+fn parse_extension(s: &str) -> Result<String, String> {
+    let ext = s.trim().trim_start_matches('.').to_lowercase();
+    if ext.is_empty() || ext.contains(['/', '\\']) {
+        return Err(format!("invalid extension '{}'", s));
+    }
+    Ok(ext)
+}
+
+/// Parse an object tag `KEY=VALUE`; the value may be empty
+fn parse_tag(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got '{}'", s))?;
+    if key.is_empty() {
+        return Err(format!("missing tag key in '{}'", s));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Parse user metadata `KEY=VALUE`: the key becomes an `x-amz-meta-*` header name
+fn parse_metadata(s: &str) -> Result<(String, String), String> {
+    let (key, value) = parse_tag(s)?;
+    if !key
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        return Err(format!(
+            "metadata key '{}' may only use ASCII letters, digits, '-', '_' and '.'",
+            key
+        ));
+    }
+    Ok((key, parse_header_value(&value)?))
+}
+
+/// Parse a `REGION=BUCKET` replica
+fn parse_replica(s: &str) -> Result<(String, String), String> {
+    let (region, bucket) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected REGION=BUCKET, got '{}'", s))?;
+
+    let (region, bucket) = (region.trim(), bucket.trim());
+    if region.is_empty() || bucket.is_empty() {
+        return Err(format!("expected REGION=BUCKET, got '{}'", s));
+    }
+
+    Ok((region.to_string(), bucket.to_string()))
+}
+
+/// Parse an `--ext-category` `EXT=CATEGORY`, the extension lowercased and without its dot
+fn parse_ext_category(s: &str) -> Result<(String, FileCategory), String> {
+    let (ext, category) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected EXT=CATEGORY, got '{}'", s))?;
+    let ext = ext.trim().trim_start_matches('.').to_ascii_lowercase();
+    if ext.is_empty() {
+        return Err(format!("missing extension in '{}'", s));
+    }
+    Ok((ext, category.parse()?))
+}
+
+/// Parse a category `--strip-metadata` knows how to clean
+fn parse_metadata_category(s: &str) -> Result<FileCategory, String> {
+    match s.parse()? {
+        category @ (FileCategory::Images | FileCategory::Documents) => Ok(category),
+        category => Err(format!(
+            "no embedded metadata is stripped from {} (use images or documents)",
+            category
+        )),
+    }
+}
+
+/// Parse a single key segment: ASCII letters, digits, `-`, `_` and `.`, but not `.` or `..`
+fn parse_key_segment(s: &str) -> Result<String, String> {
+    let valid = s
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if s.is_empty() || s == "." || s == ".." || !valid {
+        return Err(format!(
+            "'{}' is not a safe key segment (use letters, digits, '-', '_' and '.')",
+            s
+        ));
+    }
+    Ok(s.to_string())
+}
+
+/// Parse a `CATEGORY=REQUESTS_PER_SECOND` pair
+fn parse_category_rate(s: &str) -> Result<(String, f64), String> {
+    let (category, rate) = parse_category_value::<f64>(s)?;
+
+    if !(rate.is_finite() && rate > 0.0) {
+        return Err(format!("rate for category '{}' must be positive", category));
+    }
+
+    Ok((category, rate))
+}
+
+/// Parse a duration with a unit suffix (`s`, `m`, `h`, `d`, `w`)
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration '{}'", s))?;
+
+    let seconds: u64 = match unit.trim() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        "" => return Err(format!("duration '{}' needs a unit (s, m, h, d, w)", s)),
+        other => return Err(format!("unknown duration unit '{}'", other)),
+    };
+
+    number
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("duration '{}' is too large", s))
+}
+
+/// Reject header values that could smuggle extra headers (CR, LF or other control characters)
+fn parse_header_value(s: &str) -> Result<String, String> {
+    match s.chars().find(|c| c.is_control() && *c != '\t') {
+        Some(c) => Err(format!("header value contains control character {:?}", c)),
+        None => Ok(s.trim().to_string()),
+    }
+}
+
+/// A User-Agent must be a non-empty header value of printable ASCII
+fn parse_user_agent(s: &str) -> Result<String, String> {
+    let value = parse_header_value(s)?;
+    if value.is_empty() {
+        return Err("User-Agent must not be empty".to_string());
+    }
+    if !value.is_ascii() {
+        return Err("User-Agent must be ASCII".to_string());
+    }
+    Ok(value)
+}
+
+/// Parse an RFC 3339 timestamp, or a duration from now
+fn parse_expires(s: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(at) = DateTime::parse_from_rfc3339(s.trim()) {
+        return Ok(at.with_timezone(&Utc));
+    }
+
+    let duration = parse_duration(s)
+        .map_err(|e| format!("expected an RFC 3339 timestamp or a duration: {}", e))?;
+    chrono::Duration::from_std(duration)
+        .ok()
+        .and_then(|duration| Utc::now().checked_add_signed(duration))
+        .ok_or_else(|| format!("expiry '{}' is too far in the future", s))
+}
+
+/// Parse a fraction between 0 and 1
+fn parse_rate(s: &str) -> Result<f64, String> {
+    let rate: f64 = s
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid rate '{}'", s))?;
+
+    if !(0.0..=1.0).contains(&rate) {
+        return Err(format!("rate {} must be between 0 and 1", rate));
+    }
+
+    Ok(rate)
+}
+
+/// Parse an entropy between 0 and 8 bits per byte
+fn parse_entropy(s: &str) -> Result<f32, String> {
+    let bits: f32 = s
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid entropy '{}'", s))?;
+
+    if !(0.0..=8.0).contains(&bits) {
+        return Err(format!(
+            "entropy {} must be between 0 and 8 bits per byte",
+            bits
+        ));
+    }
+
+    Ok(bits)
+}
+
+/// Parse a byte size with an optional unit (`KB`/`MB`/`GB` decimal, `KiB`/`MiB`/`GiB` binary)
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid size '{}'", s))?;
+
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1_000,
+        "m" | "mb" => 1_000_000,
+        "g" | "gb" => 1_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        other => return Err(format!("unknown size unit '{}'", other)),
+    };
+
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("size '{}' is too large", s))
+}
+
+/// Parse a non-zero read buffer size that fits in memory
+pub fn parse_buffer_size(s: &str) -> Result<usize, String> {
+    match parse_size(s)? {
+        0 => Err("read buffer size must be greater than 0".to_string()),
+        size => usize::try_from(size).map_err(|_| format!("buffer size '{}' is too large", s)),
+    }
+}
+
+/// Parse a `--part-size`, which S3 takes from 5 MiB to 5 GiB
+pub fn parse_part_size(s: &str) -> Result<u64, String> {
+    match parse_size(s)? {
+        size if (multipart::MIN_PART_SIZE..=multipart::MAX_PART_SIZE).contains(&size) => Ok(size),
+        _ => Err(format!("part size '{}' is outside 5MiB..5GiB", s)),
+    }
+}
+
+/// Human readable byte count (binary units)
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
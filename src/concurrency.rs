@@ -0,0 +1,540 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    sync::{Mutex as AsyncMutex, OwnedSemaphorePermit, Semaphore},
+    time::{sleep_until, Instant},
+};
+
+use crate::{error::AppError, retry::RetryPolicy};
+
+/// Maximum number of attempts for a request that keeps getting throttled
+const MAX_THROTTLE_ATTEMPTS: u32 = 6;
+
+/// Attempts for an upload that keeps stalling below --min-throughput
+const MAX_STALL_ATTEMPTS: u32 = 3;
+
+/// Attempts for a request failing with any other error the retry policy accepts
+const MAX_RETRY_ATTEMPTS: u32 = 3;
+
+/// Base delay for exponential backoff after a SlowDown response
+const BASE_BACKOFF: Duration = Duration::from_millis(200);
+
+/// Upper bound for a single backoff sleep
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// AIMD state of the limiter
+struct AimdState {
+    // Current number of permits handed out by the semaphore
+    limit: usize,
+    // Permits that must be forgotten as they are released
+    debt: usize,
+    // Consecutive successes since the last change of the limit
+    successes: usize,
+    // Decreases so far; a request remembers the one it started after
+    epoch: u64,
+}
+
+/// Concurrency limiter wrapping a semaphore.
+///
+/// In adaptive mode the permit count is halved whenever a request is
+/// throttled and grows back by one permit after a full window of
+/// consecutive successes (additive increase, multiplicative decrease).
+/// Requests started before the last decrease ran at the old limit, so
+/// their throttles don't halve it again: a burst of them counts once.
+pub struct ConcurrencyLimiter {
+    semaphore: Arc<Semaphore>,
+    max: usize,
+    adaptive: bool,
+    state: Mutex<AimdState>,
+    retry: RetryPolicy,
+}
+
+/// A permit for a single backend request
+pub struct LimiterPermit<'a> {
+    limiter: &'a ConcurrencyLimiter,
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for LimiterPermit<'_> {
+    fn drop(&mut self) {
+        if let Some(permit) = self.permit.take() {
+            let mut state = self.limiter.state.lock().unwrap();
+            if state.debt > 0 {
+                // Shrink the semaphore by not returning this permit
+                state.debt -= 1;
+                permit.forget();
+            }
+        }
+    }
+}
+
+impl ConcurrencyLimiter {
+    pub fn new(max: usize, adaptive: bool) -> Self {
+        let max = max.max(1);
+
+        Self {
+            semaphore: Arc::new(Semaphore::new(max)),
+            max,
+            adaptive,
+            state: Mutex::new(AimdState {
+                limit: max,
+                debt: 0,
+                successes: 0,
+                epoch: 0,
+            }),
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Decide which failed requests `run` retries with `retry` instead of the default
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Current effective concurrency limit
+    pub fn limit(&self) -> usize {
+        self.state.lock().unwrap().limit
+    }
+
+    /// Wait for a free slot
+    pub async fn acquire(&self) -> LimiterPermit<'_> {
+        let permit = Arc::clone(&self.semaphore)
+            .acquire_owned()
+            .await
+            .expect("limiter semaphore is never closed");
+
+        LimiterPermit {
+            limiter: self,
+            permit: Some(permit),
+        }
+    }
+
+    /// Record a successful request; grows the limit after a full window of successes
+    pub fn on_success(&self) {
+        if !self.adaptive {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.successes += 1;
+
+        if state.successes >= state.limit && state.limit < self.max {
+            state.successes = 0;
+            state.limit += 1;
+
+            if state.debt > 0 {
+                state.debt -= 1;
+            } else {
+                self.semaphore.add_permits(1);
+            }
+        }
+    }
+
+    /// Decreases of the limit so far, taken as a request starts
+    fn epoch(&self) -> u64 {
+        self.state.lock().unwrap().epoch
+    }
+
+    /// Record a throttled request started in `epoch`; halves the limit unless it was
+    /// already halved since the request started
+    fn on_throttle(&self, epoch: u64) {
+        if !self.adaptive {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.successes = 0;
+        if epoch < state.epoch {
+            return;
+        }
+        state.epoch += 1;
+
+        let previous = state.limit;
+        let target = (previous / 2).max(1);
+        let mut shrink = previous - target;
+        state.limit = target;
+
+        // Remove idle permits right away, the rest as they are released
+        shrink -= self.semaphore.forget_permits(shrink);
+        state.debt += shrink;
+
+        if target < previous {
+            eprintln!("Throttled by S3, reducing concurrency to {}", target);
+        }
+    }
+
+    /// Run a backend request under the limiter, backing off and retrying on what the retry
+    /// policy accepts, by default SlowDown and stalls
+    ///
+    /// A category's own cap and rate apply first, so a saturated category waits
+    /// without holding global permits other categories could use.
+    pub async fn run<T, F, Fut>(
+        &self,
+        category: Option<&CategoryLimit>,
+        mut request: F,
+    ) -> Result<T, AppError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, AppError>>,
+    {
+        let mut attempt = 0;
+        let mut stalls = 0;
+        let mut retries = 0;
+
+        loop {
+            let (epoch, result) = {
+                let _category_permit = match category {
+                    Some(category) => category.acquire().await,
+                    None => None,
+                };
+                let _permit = self.acquire().await;
+                (self.epoch(), request().await)
+            };
+
+            let retry = matches!(&result, Err(err) if self.retry.should_retry(err));
+            match result {
+                Ok(value) => {
+                    self.on_success();
+                    return Ok(value);
+                }
+                Err(AppError::Throttled { .. }) if retry && attempt + 1 < MAX_THROTTLE_ATTEMPTS => {
+                    self.on_throttle(epoch);
+                    tokio::time::sleep(backoff_delay(attempt)).await;
+                    attempt += 1;
+                }
+                // A stall is the connection, not the load, so concurrency stays as it is
+                Err(err @ AppError::Stalled { .. }) if retry && stalls + 1 < MAX_STALL_ATTEMPTS => {
+                    stalls += 1;
+                    eprintln!("{}, retrying ({}/{})", err, stalls, MAX_STALL_ATTEMPTS - 1);
+                    tokio::time::sleep(backoff_delay(stalls)).await;
+                }
+                // Whatever else the policy retries, e.g. a status from --retry-on-status
+                Err(err)
+                    if retry
+                        && !matches!(
+                            err,
+                            AppError::Throttled { .. } | AppError::Stalled { .. }
+                        )
+                        && retries + 1 < MAX_RETRY_ATTEMPTS =>
+                {
+                    retries += 1;
+                    eprintln!("{}, retrying ({}/{})", err, retries, MAX_RETRY_ATTEMPTS - 1);
+                    tokio::time::sleep(backoff_delay(retries)).await;
+                }
+                Err(err) => {
+                    if matches!(err, AppError::Throttled { .. }) {
+                        self.on_throttle(epoch);
+                    }
+                    return Err(err);
+                }
+            }
+        }
+    }
+}
+
+/// Spaces requests evenly to stay under a maximum rate
+pub struct RateLimiter {
+    interval: Duration,
+    // Earliest instant the next request may start
+    next: AsyncMutex<Instant>,
+}
+
+impl RateLimiter {
+    /// Limiter allowing `per_second` requests per second
+    pub fn new(per_second: f64) -> Self {
+        Self {
+            interval: Duration::from_secs_f64(1.0 / per_second),
+            next: AsyncMutex::new(Instant::now()),
+        }
+    }
+
+    /// Wait for the next request slot
+    pub async fn wait(&self) {
+        let slot = {
+            let mut next = self.next.lock().await;
+            let slot = (*next).max(Instant::now());
+            *next = slot + self.interval;
+            slot
+        };
+
+        sleep_until(slot).await;
+    }
+}
+
+/// Concurrency cap and request rate of a single file category
+#[derive(Default)]
+pub struct CategoryLimit {
+    semaphore: Option<Arc<Semaphore>>,
+    rate: Option<RateLimiter>,
+}
+
+impl CategoryLimit {
+    /// Wait for the category's rate slot and a free slot under its cap
+    async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+        let permit = match &self.semaphore {
+            Some(semaphore) => Some(
+                Arc::clone(semaphore)
+                    .acquire_owned()
+                    .await
+                    .expect("category semaphore is never closed"),
+            ),
+            None => None,
+        };
+
+        if let Some(rate) = &self.rate {
+            rate.wait().await;
+        }
+
+        permit
+    }
+}
+
+/// Per-category limits; categories without an entry only use the global limiter
+#[derive(Default)]
+pub struct CategoryLimits {
+    limits: HashMap<String, Arc<CategoryLimit>>,
+}
+
+impl CategoryLimits {
+    /// Build from `--category-concurrency` and `--category-rate` values
+    pub fn new(concurrency: &[(String, usize)], rate: &[(String, f64)]) -> Self {
+        let mut limits: HashMap<String, CategoryLimit> = HashMap::new();
+
+        for (category, max) in concurrency {
+            limits.entry(category.clone()).or_default().semaphore =
+                Some(Arc::new(Semaphore::new((*max).max(1))));
+        }
+        for (category, per_second) in rate {
+            limits.entry(category.clone()).or_default().rate = Some(RateLimiter::new(*per_second));
+        }
+
+        Self {
+            limits: limits
+                .into_iter()
+                .map(|(category, limit)| (category, Arc::new(limit)))
+                .collect(),
+        }
+    }
+
+    /// Limits of `category`, if it has its own
+    pub fn get(&self, category: &str) -> Option<Arc<CategoryLimit>> {
+        self.limits.get(category).map(Arc::clone)
+    }
+}
+
+/// Exponential backoff with a small amount of jitter
+fn backoff_delay(attempt: u32) -> Duration {
+    let exp = BASE_BACKOFF
+        .saturating_mul(1 << attempt.min(16))
+        .min(MAX_BACKOFF);
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    let jitter = Duration::from_millis(u64::from(nanos % 100));
+
+    exp + jitter
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::join_all;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A backend answering SlowDown whenever more than `max` requests are in flight
+    struct ThrottlingBackend {
+        max: usize,
+        in_flight: AtomicUsize,
+        throttled: AtomicUsize,
+    }
+
+    impl ThrottlingBackend {
+        fn new(max: usize) -> Self {
+            Self {
+                max,
+                in_flight: AtomicUsize::new(0),
+                throttled: AtomicUsize::new(0),
+            }
+        }
+
+        async fn request(&self) -> Result<(), AppError> {
+            let concurrent = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            if concurrent > self.max {
+                self.throttled.fetch_add(1, Ordering::SeqCst);
+                return Err(AppError::Throttled { backend: "mock" });
+            }
+            Ok(())
+        }
+    }
+
+    /// Send 400 requests through a limiter of 32 permits to a backend throttling above 4;
+    /// returns the final limit, throttled attempts and failed requests
+    async fn run_batch(adaptive: bool) -> (usize, usize, usize) {
+        let limiter = ConcurrencyLimiter::new(32, adaptive);
+        let backend = ThrottlingBackend::new(4);
+        let results = join_all((0..400).map(|_| limiter.run(None, || backend.request()))).await;
+        let failed = results.iter().filter(|result| result.is_err()).count();
+        (limiter.limit(), backend.throttled.into_inner(), failed)
+    }
+
+    #[test]
+    fn a_burst_of_throttles_halves_the_limit_once() {
+        let limiter = ConcurrencyLimiter::new(32, true);
+
+        // 32 requests started together, all throttled as they come back
+        let started = limiter.epoch();
+        for _ in 0..32 {
+            limiter.on_throttle(started);
+        }
+        assert_eq!(limiter.limit(), 16);
+
+        // One started after the decrease still counts
+        limiter.on_throttle(limiter.epoch());
+        assert_eq!(limiter.limit(), 8);
+        limiter.on_throttle(started);
+        assert_eq!(limiter.limit(), 8);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn adaptive_limit_converges_below_the_throttling_threshold() {
+        let (limit, throttled, failed) = run_batch(true).await;
+        let (fixed_limit, fixed_throttled, _) = run_batch(false).await;
+
+        assert_eq!(failed, 0);
+        // Additive increase keeps probing past the threshold, and one burst of throttles
+        // halves it only once
+        assert!(limit < 8, "limit {}", limit);
+        assert_eq!(fixed_limit, 32);
+        assert!(
+            throttled * 10 < fixed_throttled,
+            "{} throttled adaptively, {} at a fixed limit",
+            throttled,
+            fixed_throttled
+        );
+    }
+
+    /// Peak number of requests in flight at once
+    #[derive(Default)]
+    struct InFlight {
+        current: AtomicUsize,
+        peak: AtomicUsize,
+    }
+
+    impl InFlight {
+        async fn request(&self, duration: Duration) -> Result<Instant, AppError> {
+            let started = Instant::now();
+            let current = self.current.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(current, Ordering::SeqCst);
+            tokio::time::sleep(duration).await;
+            self.current.fetch_sub(1, Ordering::SeqCst);
+            Ok(started)
+        }
+
+        fn peak(&self) -> usize {
+            self.peak.load(Ordering::SeqCst)
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn category_caps_apply_to_their_own_category_only() {
+        let limiter = ConcurrencyLimiter::new(16, false);
+        let limits = CategoryLimits::new(&[("text".into(), 2), ("models".into(), 1)], &[]);
+        let (text, models, images) = (
+            InFlight::default(),
+            InFlight::default(),
+            InFlight::default(),
+        );
+        let (text_limit, models_limit) =
+            (limits.get("text").unwrap(), limits.get("models").unwrap());
+        assert!(limits.get("images").is_none());
+
+        let ms = Duration::from_millis;
+        let text_batch =
+            join_all((0..8).map(|_| limiter.run(Some(&text_limit), || text.request(ms(10)))));
+        let models_batch =
+            join_all((0..4).map(|_| limiter.run(Some(&models_limit), || models.request(ms(10)))));
+        let images_batch = join_all((0..8).map(|_| limiter.run(None, || images.request(ms(10)))));
+        futures::join!(text_batch, models_batch, images_batch);
+
+        assert_eq!(text.peak(), 2);
+        assert_eq!(models.peak(), 1);
+        // Without a cap of its own a category only shares the global limit
+        assert_eq!(images.peak(), 8);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_saturated_category_does_not_hold_back_the_others() {
+        let limiter = ConcurrencyLimiter::new(4, false);
+        let limits = CategoryLimits::new(&[("models".into(), 1)], &[]);
+        let models_limit = limits.get("models").unwrap();
+        let (models, text) = (InFlight::default(), InFlight::default());
+        let started = Instant::now();
+
+        let slow = join_all((0..4).map(|_| {
+            limiter.run(Some(&models_limit), || {
+                models.request(Duration::from_secs(10))
+            })
+        }));
+        let fast = async {
+            let done = join_all(
+                (0..12).map(|_| limiter.run(None, || text.request(Duration::from_millis(10)))),
+            )
+            .await;
+            (done, started.elapsed())
+        };
+        let (_, (_, text_elapsed)) = futures::join!(slow, fast);
+
+        // Queued models wait on their own cap, not on global permits
+        assert!(text_elapsed < Duration::from_secs(1), "{:?}", text_elapsed);
+        assert_eq!(models.peak(), 1);
+        assert_eq!(text.peak(), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn category_rates_are_independent() {
+        let limiter = ConcurrencyLimiter::new(16, false);
+        let limits = CategoryLimits::new(&[], &[("text".into(), 10.0)]);
+        let text_limit = limits.get("text").unwrap();
+        let (text, images) = (InFlight::default(), InFlight::default());
+        let started = Instant::now();
+
+        let (text_starts, image_starts) = futures::join!(
+            join_all(
+                (0..5).map(|_| limiter.run(Some(&text_limit), || text.request(Duration::ZERO)))
+            ),
+            join_all((0..5).map(|_| limiter.run(None, || images.request(Duration::ZERO)))),
+        );
+
+        let mut text_starts: Vec<Duration> = text_starts
+            .into_iter()
+            .map(|start| start.unwrap() - started)
+            .collect();
+        text_starts.sort();
+        for (i, start) in text_starts.iter().enumerate() {
+            assert!(
+                *start >= Duration::from_millis(100) * i as u32,
+                "request {} started at {:?}",
+                i,
+                start
+            );
+        }
+        for start in image_starts {
+            assert_eq!(start.unwrap(), started);
+        }
+    }
+
+    #[test]
+    fn backoff_grows_and_is_capped() {
+        assert!(backoff_delay(0) < backoff_delay(3));
+        assert!(backoff_delay(30) <= MAX_BACKOFF + Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,176 @@
+use std::env;
+
+use crate::{error::AppError, Backend};
+
+/// Environment variable naming a bucket, endpoint or credential, with the placeholder
+/// that stands in for it under `--allow-defaults`
+pub struct EnvVar {
+    pub name: &'static str,
+    placeholder: &'static str,
+}
+
+pub const AWS_BUCKET: EnvVar = EnvVar {
+    name: "AWS_BUCKET",
+    placeholder: "aws-bucket",
+};
+
+pub const AWS_ACCESS_KEY: EnvVar = EnvVar {
+    name: "AWS_ACCESS_KEY",
+    placeholder: "your-access-key",
+};
+
+pub const AWS_SECRET_KEY: EnvVar = EnvVar {
+    name: "AWS_SECRET_KEY",
+    placeholder: "your-secret-key",
+};
+
+pub const S3_ENDPOINT: EnvVar = EnvVar {
+    name: "S3_ENDPOINT",
+    placeholder: "http://localhost:9000",
+};
+
+pub const S3_BUCKET: EnvVar = EnvVar {
+    name: "S3_BUCKET",
+    placeholder: "minio-bucket",
+};
+
+pub const S3_ACCESS_KEY: EnvVar = EnvVar {
+    name: "S3_ACCESS_KEY",
+    placeholder: "minioadmin",
+};
+
+pub const S3_SECRET_KEY: EnvVar = EnvVar {
+    name: "S3_SECRET_KEY",
+    placeholder: "minioadmin",
+};
+
+pub const GCS_ENDPOINT: EnvVar = EnvVar {
+    name: "GCS_ENDPOINT",
+    placeholder: "https://storage.googleapis.com",
+};
+
+pub const GCS_BUCKET: EnvVar = EnvVar {
+    name: "GCS_BUCKET",
+    placeholder: "gcs-bucket",
+};
+
+pub const GCS_ACCESS_KEY: EnvVar = EnvVar {
+    name: "GCS_ACCESS_KEY",
+    placeholder: "your-hmac-access-id",
+};
+
+pub const GCS_SECRET_KEY: EnvVar = EnvVar {
+    name: "GCS_SECRET_KEY",
+    placeholder: "your-hmac-secret",
+};
+
+impl EnvVar {
+    /// The variable's value, or its placeholder when unset
+    pub fn get(&self) -> String {
+        env::var(self.name).unwrap_or_else(|_| self.placeholder.to_string())
+    }
+
+    fn is_set(&self) -> bool {
+        env::var_os(self.name).is_some()
+    }
+}
+
+/// Variables `backend` can't work without; credentials only when requests are signed
+///
+/// The AWS SDK finds credentials through its own chain (profiles, SSO, instance roles),
+/// so only its bucket is required. `GCS_ENDPOINT` defaults to Google's own endpoint.
+pub fn required_by(backend: Backend, unsigned: bool) -> Vec<&'static EnvVar> {
+    match (backend, unsigned) {
+        (Backend::Aws, _) | (Backend::Http, true) => vec![&AWS_BUCKET],
+        (Backend::Http, false) => vec![&AWS_BUCKET, &AWS_ACCESS_KEY, &AWS_SECRET_KEY],
+        (Backend::Minio, true) => vec![&S3_ENDPOINT, &S3_BUCKET],
+        (Backend::Minio, false) => vec![&S3_ENDPOINT, &S3_BUCKET, &S3_ACCESS_KEY, &S3_SECRET_KEY],
+        (Backend::Gcs, true) => vec![&GCS_BUCKET],
+        (Backend::Gcs, false) => vec![&GCS_BUCKET, &GCS_ACCESS_KEY, &GCS_SECRET_KEY],
+    }
+}
+
+/// Fail with every variable of `required` that is unset, unless `allow_defaults` lets
+/// the placeholders stand in for them
+pub fn require(
+    required: impl IntoIterator<Item = &'static EnvVar>,
+    allow_defaults: bool,
+) -> Result<(), AppError> {
+    if allow_defaults {
+        return Ok(());
+    }
+
+    let mut missing: Vec<&'static str> = Vec::new();
+    for var in required {
+        if !var.is_set() && !missing.contains(&var.name) {
+            missing.push(var.name);
+        }
+    }
+
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::MissingConfig(missing))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Names no environment sets, so tests running in parallel can't disturb them
+    const UNSET: EnvVar = EnvVar {
+        name: "S3_ML_UPLOADER_TEST_UNSET",
+        placeholder: "placeholder",
+    };
+
+    const ALSO_UNSET: EnvVar = EnvVar {
+        name: "S3_ML_UPLOADER_TEST_ALSO_UNSET",
+        placeholder: "placeholder",
+    };
+
+    #[test]
+    fn unset_variables_are_listed_once_each() {
+        let err = require([&UNSET, &ALSO_UNSET, &UNSET], false).unwrap_err();
+
+        assert!(
+            matches!(&err, AppError::MissingConfig(missing)
+                if *missing == [UNSET.name, ALSO_UNSET.name]),
+            "{:?}",
+            err
+        );
+        assert_eq!(
+            err.to_string(),
+            "missing configuration: set S3_ML_UPLOADER_TEST_UNSET, S3_ML_UPLOADER_TEST_ALSO_UNSET \
+             (or pass --allow-defaults to run with placeholder values)"
+        );
+    }
+
+    #[test]
+    fn allow_defaults_lets_placeholders_stand_in() {
+        require([&UNSET], true).unwrap();
+        assert_eq!(UNSET.get(), "placeholder");
+    }
+
+    #[test]
+    fn only_signed_requests_need_credentials() {
+        let names = |backend, unsigned| -> Vec<_> {
+            required_by(backend, unsigned)
+                .into_iter()
+                .map(|var| var.name)
+                .collect()
+        };
+
+        assert_eq!(names(Backend::Aws, false), ["AWS_BUCKET"]);
+        assert_eq!(
+            names(Backend::Http, false),
+            ["AWS_BUCKET", "AWS_ACCESS_KEY", "AWS_SECRET_KEY"]
+        );
+        assert_eq!(names(Backend::Http, true), ["AWS_BUCKET"]);
+        assert_eq!(names(Backend::Minio, true), ["S3_ENDPOINT", "S3_BUCKET"]);
+        assert_eq!(
+            names(Backend::Gcs, false),
+            ["GCS_BUCKET", "GCS_ACCESS_KEY", "GCS_SECRET_KEY"]
+        );
+    }
+}
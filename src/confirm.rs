@@ -0,0 +1,47 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use crate::error::AppError;
+
+/// Ask a yes/no `question` on the terminal; `None` when stdin isn't one, so nobody can answer
+pub async fn ask(question: &str) -> Result<Option<bool>, AppError> {
+    if !io::stdin().is_terminal() {
+        return Ok(None);
+    }
+
+    let question = question.to_string();
+    // Reading stdin blocks, which must not stall an async worker
+    let answer = tokio::task::spawn_blocking(move || -> io::Result<String> {
+        print!("{} [y/N] ", question);
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        io::stdin().lock().read_line(&mut answer)?;
+        Ok(answer)
+    })
+    .await??;
+
+    Ok(Some(matches!(
+        answer.trim().to_ascii_lowercase().as_str(),
+        "y" | "yes"
+    )))
+}
+
+/// Go ahead with `plan` once the user agrees at the prompt, or right away with `--yes`
+///
+/// Without a terminal there is nobody to ask, so the plan is refused unless `yes` is given.
+pub async fn require(plan: &str, yes: bool) -> Result<(), AppError> {
+    if yes {
+        return Ok(());
+    }
+
+    match ask(&format!("{}. Continue?", plan)).await? {
+        Some(true) => Ok(()),
+        Some(false) => Err(AppError::NotConfirmed {
+            plan: plan.to_string(),
+            reason: "declined at the prompt",
+        }),
+        None => Err(AppError::NotConfirmed {
+            plan: plan.to_string(),
+            reason: "stdin is not a terminal; pass --yes to proceed",
+        }),
+    }
+}
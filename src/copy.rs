@@ -0,0 +1,384 @@
+use aws_sdk_s3::{
+    config::Region,
+    operation::head_object::HeadObjectOutput,
+    types::{CompletedMultipartUpload, CompletedPart, MetadataDirective, StorageClass},
+    Client,
+};
+use futures::{stream, StreamExt, TryStreamExt};
+use std::{collections::HashMap, future::Future};
+
+use crate::{
+    cli::{format_size, CopyArgs},
+    config, create_aws_client,
+    error::AppError,
+    multipart::MAX_PARTS,
+    tls::TlsConfig,
+    uri_encode_path, ObjectMeta,
+};
+
+/// Largest object `CopyObject` copies in one request
+const COPY_LIMIT: u64 = 5 * 1024 * 1024 * 1024;
+
+/// Size of every part of a multipart copy but the last, unless that needs over `MAX_PARTS`
+const PART_SIZE: u64 = 512 * 1024 * 1024;
+
+/// Copy one object server-side, within the AWS bucket or into another one
+///
+/// Objects up to 5 GiB take a single `CopyObject`; larger ones are copied in ranges with
+/// `UploadPartCopy`, so no body passes through this machine either way. Either bucket
+/// may live in another region than `AWS_REGION`: a redirect is followed once.
+pub async fn run(args: CopyArgs) -> Result<(), AppError> {
+    // AWS_BUCKET is only the default of the two buckets
+    if args.source_bucket.is_none() || args.dest_bucket.is_none() {
+        config::require([&config::AWS_BUCKET], args.allow_defaults)?;
+    }
+    let tls = TlsConfig::load(&args.tls)?;
+    let client = create_aws_client(&tls, false).await?;
+    let bucket = config::AWS_BUCKET.get();
+    let source_bucket = args.source_bucket.as_deref().unwrap_or(&bucket);
+    let dest_bucket = args.dest_bucket.as_deref().unwrap_or(&bucket);
+    let source_key = args.source.as_str();
+
+    let (source_client, head) = in_bucket_region(&client, source_bucket, |client| async move {
+        Ok(client
+            .head_object()
+            .bucket(source_bucket)
+            .key(source_key)
+            .send()
+            .await?)
+    })
+    .await?;
+
+    let size = head.content_length().unwrap_or(0).max(0) as u64;
+    let source = CopySource {
+        path: format!("{}/{}", source_bucket, uri_encode_path(&args.source)),
+        metadata: remap_metadata(
+            head.metadata().cloned().unwrap_or_default(),
+            &args.metadata,
+            &args.remove_metadata,
+        ),
+        storage_class: args
+            .storage_class
+            .as_deref()
+            .or(head.storage_class().map(StorageClass::as_str))
+            .map(StorageClass::from),
+        replace: !args.metadata.is_empty() || !args.remove_metadata.is_empty(),
+        head,
+    };
+
+    // Copies within one bucket go straight to the region its HEAD was answered in
+    let dest_client = if dest_bucket == source_bucket {
+        &source_client
+    } else {
+        &client
+    };
+
+    if size > COPY_LIMIT {
+        let tags = source_tags(&source_client, source_bucket, &args.source).await?;
+        multipart_copy(
+            dest_client,
+            &source,
+            size,
+            tags,
+            dest_bucket,
+            &args.destination,
+            args.concurrency,
+        )
+        .await?;
+    } else {
+        in_bucket_region(dest_client, dest_bucket, |client| {
+            copy_object(client, &source, dest_bucket, &args.destination)
+        })
+        .await?;
+    }
+
+    println!(
+        "Copied {}/{} -> {}/{} ({})",
+        source_bucket,
+        args.source,
+        dest_bucket,
+        args.destination,
+        format_size(size)
+    );
+    Ok(())
+}
+
+/// The object being copied, as `CopyObject` and `UploadPartCopy` need it
+struct CopySource {
+    // `<bucket>/<URI-encoded key>`
+    path: String,
+    head: HeadObjectOutput,
+    // User metadata of the copy
+    metadata: HashMap<String, String>,
+    storage_class: Option<StorageClass>,
+    // Whether `metadata` differs from the source's, which `CopyObject` must be told
+    replace: bool,
+}
+
+/// User metadata of the source with `set` applied and the keys in `remove` dropped
+///
+/// S3 returns metadata keys lowercased, so both are matched case-insensitively.
+fn remap_metadata(
+    mut metadata: HashMap<String, String>,
+    set: &[(String, String)],
+    remove: &[String],
+) -> HashMap<String, String> {
+    for key in remove {
+        metadata.remove(&key.to_ascii_lowercase());
+    }
+    for (key, value) in set {
+        metadata.insert(key.to_ascii_lowercase(), value.clone());
+    }
+    metadata
+}
+
+/// Run `request` against `bucket`, once more in the bucket's region if S3 redirects it there
+///
+/// Returns the client that succeeded, so related requests go to the same region.
+async fn in_bucket_region<T, F, Fut>(
+    client: &Client,
+    bucket: &str,
+    request: F,
+) -> Result<(Client, T), AppError>
+where
+    F: Fn(Client) -> Fut,
+    Fut: Future<Output = Result<T, AppError>>,
+{
+    match request(client.clone()).await {
+        Err(AppError::WrongRegion { region, .. }) => {
+            println!(
+                "Note: {} is in region {}; sending its requests there",
+                bucket, region
+            );
+            let config = client
+                .config()
+                .to_builder()
+                .region(Region::new(region))
+                .build();
+            let client = Client::from_conf(config);
+            let output = request(client.clone()).await?;
+            Ok((client, output))
+        }
+        result => Ok((client.clone(), result?)),
+    }
+}
+
+/// Copy an object of up to 5 GiB with a single request
+///
+/// Metadata and tags are kept by S3 unless the metadata was remapped; the storage class
+/// must always be given again, or the copy falls back to `STANDARD`.
+async fn copy_object(
+    client: Client,
+    source: &CopySource,
+    bucket: &str,
+    key: &str,
+) -> Result<(), AppError> {
+    let head = &source.head;
+    let mut request = client
+        .copy_object()
+        .copy_source(&source.path)
+        .bucket(bucket)
+        .key(key)
+        .set_storage_class(source.storage_class.clone());
+
+    if source.replace {
+        request = request
+            .metadata_directive(MetadataDirective::Replace)
+            .set_metadata(Some(source.metadata.clone()))
+            .set_content_type(head.content_type().map(str::to_string))
+            .set_content_encoding(head.content_encoding().map(str::to_string))
+            .set_content_disposition(head.content_disposition().map(str::to_string))
+            .set_content_language(head.content_language().map(str::to_string))
+            .set_cache_control(head.cache_control().map(str::to_string));
+    }
+
+    request.send().await?;
+    Ok(())
+}
+
+/// Tags of the source as the query string `x-amz-tagging` expects
+///
+/// `CopyObject` copies tags itself, but a multipart upload starts without any.
+async fn source_tags(client: &Client, bucket: &str, key: &str) -> Result<Option<String>, AppError> {
+    let (_, output) = in_bucket_region(client, bucket, |client| async move {
+        Ok(client
+            .get_object_tagging()
+            .bucket(bucket)
+            .key(key)
+            .send()
+            .await?)
+    })
+    .await?;
+
+    let meta = ObjectMeta {
+        tags: output
+            .tag_set()
+            .iter()
+            .map(|tag| (tag.key().to_string(), tag.value().to_string()))
+            .collect(),
+        ..ObjectMeta::default()
+    };
+    Ok(meta.tagging())
+}
+
+/// Copy an object larger than 5 GiB with `UploadPartCopy`, `concurrency` ranges at a time
+///
+/// Headers, metadata and tags of the source are set on the new upload, since S3 carries
+/// none of them over. A failed part aborts the upload so none are left behind.
+async fn multipart_copy(
+    client: &Client,
+    source: &CopySource,
+    size: u64,
+    tags: Option<String>,
+    bucket: &str,
+    key: &str,
+    concurrency: usize,
+) -> Result<(), AppError> {
+    let head = &source.head;
+    let (client, created) = in_bucket_region(client, bucket, |client| {
+        let tags = tags.clone();
+        async move {
+            Ok(client
+                .create_multipart_upload()
+                .bucket(bucket)
+                .key(key)
+                .set_metadata(Some(source.metadata.clone()).filter(|m| !m.is_empty()))
+                .set_tagging(tags)
+                .set_storage_class(source.storage_class.clone())
+                .set_content_type(head.content_type().map(str::to_string))
+                .set_content_encoding(head.content_encoding().map(str::to_string))
+                .set_content_disposition(head.content_disposition().map(str::to_string))
+                .set_content_language(head.content_language().map(str::to_string))
+                .set_cache_control(head.cache_control().map(str::to_string))
+                .send()
+                .await?)
+        }
+    })
+    .await?;
+
+    let upload_id = created.upload_id().ok_or_else(|| {
+        AppError::Integrity(format!("no upload id for multipart copy to {}", key))
+    })?;
+
+    let part_size = PART_SIZE.max(size.div_ceil(MAX_PARTS));
+    let ranges: Vec<(i32, u64, u64)> = (0..size)
+        .step_by(part_size as usize)
+        .enumerate()
+        .map(|(index, start)| (index as i32 + 1, start, size.min(start + part_size) - 1))
+        .collect();
+    println!(
+        "Copying {} in {} parts of {}",
+        key,
+        ranges.len(),
+        format_size(part_size)
+    );
+
+    let client = &client;
+    let copied: Result<Vec<CompletedPart>, AppError> = stream::iter(ranges)
+        .map(|(part_number, first, last)| async move {
+            let output = client
+                .upload_part_copy()
+                .bucket(bucket)
+                .key(key)
+                .upload_id(upload_id)
+                .part_number(part_number)
+                .copy_source(&source.path)
+                .copy_source_range(format!("bytes={}-{}", first, last))
+                .send()
+                .await?;
+            let etag = output
+                .copy_part_result()
+                .and_then(|result| result.e_tag())
+                .ok_or_else(|| {
+                    AppError::Integrity(format!("no ETag for part {} of {}", part_number, key))
+                })?;
+            Ok(CompletedPart::builder()
+                .part_number(part_number)
+                .e_tag(etag)
+                .build())
+        })
+        .buffered(concurrency.max(1))
+        .try_collect()
+        .await;
+
+    let result = match copied {
+        Ok(parts) => client
+            .complete_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(parts))
+                    .build(),
+            )
+            .send()
+            .await
+            .map(|_| ())
+            .map_err(AppError::from),
+        Err(err) => Err(err),
+    };
+
+    if result.is_err() {
+        if let Err(err) = client
+            .abort_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .send()
+            .await
+        {
+            println!(
+                "Warning: could not abort multipart copy to {}: {}",
+                key,
+                AppError::from(err)
+            );
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn remapped_metadata_sets_and_removes_keys_case_insensitively() {
+        let source = metadata(&[("owner", "data"), ("sha256", "abc"), ("stage", "raw")]);
+        let set = [
+            ("Owner".to_string(), "ml".to_string()),
+            ("Team".to_string(), "vision".to_string()),
+        ];
+        let remove = ["SHA256".to_string()];
+
+        assert_eq!(
+            remap_metadata(source, &set, &remove),
+            metadata(&[("owner", "ml"), ("stage", "raw"), ("team", "vision")])
+        );
+    }
+
+    #[test]
+    fn metadata_is_kept_without_changes() {
+        let source = metadata(&[("owner", "data")]);
+
+        assert_eq!(remap_metadata(source.clone(), &[], &[]), source);
+    }
+
+    #[test]
+    fn a_key_both_removed_and_set_takes_the_new_value() {
+        let source = metadata(&[("owner", "data")]);
+        let set = [("owner".to_string(), "ml".to_string())];
+
+        assert_eq!(
+            remap_metadata(source, &set, &["owner".to_string()]),
+            metadata(&[("owner", "ml")])
+        );
+    }
+}
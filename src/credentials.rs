@@ -0,0 +1,255 @@
+use aws_config::{identity::IdentityCache, SdkConfig};
+use aws_credential_types::provider::{
+    error::CredentialsError, future, ProvideCredentials, SharedCredentialsProvider,
+};
+use aws_sdk_s3::config::Credentials;
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::{sync::Mutex, time::Instant};
+
+use crate::{arn::SigningKey, error::AppError};
+
+/// Credentials are fetched again this long before they expire, as the SDK's own cache does
+const EXPIRY_BUFFER: Duration = Duration::from_secs(10);
+
+/// A refresh this recent stands for the ones requests failing with it would make
+const REFRESH_COOLDOWN: Duration = Duration::from_secs(5);
+
+/// Where the AWS SDK, and with `--secret-ref` the HTTP path, get their credentials
+///
+/// Wraps the SDK's provider (its default chain, or the secret) and caches what it hands
+/// out until shortly before it expires. Unlike the SDK's own cache it can be told the
+/// credentials expired early, as temporary ones revoked or rotated mid-run do: `refresh`
+/// fetches new ones for every later request. Clients using it have the SDK's cache turned
+/// off, so those requests see them at once.
+#[derive(Clone)]
+pub struct CredentialSource {
+    provider: SharedCredentialsProvider,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    current: Option<Credentials>,
+    // When the last refresh was made, and whether it got different credentials
+    refreshed: Option<(Instant, bool)>,
+}
+
+impl CredentialSource {
+    pub fn new(provider: SharedCredentialsProvider) -> Self {
+        Self {
+            provider,
+            state: Arc::default(),
+        }
+    }
+
+    /// The current credentials, fetched if there are none or they are about to expire
+    async fn credentials(&self) -> Result<Credentials, CredentialsError> {
+        let mut state = self.state.lock().await;
+        let fresh = |credentials: &Credentials| {
+            credentials
+                .expiry()
+                .is_none_or(|expiry| SystemTime::now() + EXPIRY_BUFFER < expiry)
+        };
+        if let Some(credentials) = state.current.as_ref().filter(|c| fresh(c)) {
+            return Ok(credentials.clone());
+        }
+        let credentials = self.provider.provide_credentials().await?;
+        state.current = Some(credentials.clone());
+        Ok(credentials)
+    }
+
+    /// Fetch new credentials after a request was refused for expired ones
+    ///
+    /// Returns whether they differ from the refused ones, i.e. whether sending the request
+    /// again can succeed. Requests that fail together share the first one's refresh.
+    pub async fn refresh(&self) -> Result<bool, AppError> {
+        let mut state = self.state.lock().await;
+        if let Some((at, changed)) = state.refreshed {
+            if at.elapsed() < REFRESH_COOLDOWN {
+                return Ok(changed);
+            }
+        }
+
+        let credentials =
+            self.provider.provide_credentials().await.map_err(|err| {
+                AppError::Config(format!("could not refresh credentials: {}", err))
+            })?;
+        let changed = state.current.as_ref().is_none_or(|current| {
+            current.access_key_id() != credentials.access_key_id()
+                || current.secret_access_key() != credentials.secret_access_key()
+                || current.session_token() != credentials.session_token()
+        });
+        state.current = Some(credentials);
+        state.refreshed = Some((Instant::now(), changed));
+        Ok(changed)
+    }
+
+    /// The current credentials as the key the HTTP path signs with
+    ///
+    /// The signing key derived from it changes with the secret key, so a refresh needs no
+    /// more than this.
+    pub async fn signing_key(&self) -> Result<SigningKey, AppError> {
+        let credentials = self
+            .credentials()
+            .await
+            .map_err(|err| AppError::Config(format!("AWS credentials: {}", err)))?;
+        Ok(SigningKey {
+            access_key: credentials.access_key_id().to_string(),
+            secret_key: credentials.secret_access_key().to_string(),
+            session_token: credentials.session_token().map(str::to_string),
+        })
+    }
+}
+
+impl fmt::Debug for CredentialSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CredentialSource").finish_non_exhaustive()
+    }
+}
+
+impl ProvideCredentials for CredentialSource {
+    fn provide_credentials<'a>(&'a self) -> future::ProvideCredentials<'a>
+    where
+        Self: 'a,
+    {
+        future::ProvideCredentials::new(self.credentials())
+    }
+}
+
+/// `config` with its credentials coming from a [`CredentialSource`], and that source
+///
+/// A config without credentials (`--no-sign-request`) is returned as it is.
+pub fn refreshable(config: SdkConfig) -> (SdkConfig, Option<CredentialSource>) {
+    let Some(provider) = config.credentials_provider() else {
+        return (config, None);
+    };
+    let source = CredentialSource::new(provider);
+    let config = config
+        .to_builder()
+        .credentials_provider(SharedCredentialsProvider::new(source.clone()))
+        .identity_cache(IdentityCache::no_cache())
+        .build();
+    (config, Some(source))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Hands out the keys of `keys` in turn, the last one from then on
+    #[derive(Debug)]
+    struct Rotating {
+        keys: Vec<&'static str>,
+        expiry: Option<SystemTime>,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl ProvideCredentials for Rotating {
+        fn provide_credentials<'a>(&'a self) -> future::ProvideCredentials<'a>
+        where
+            Self: 'a,
+        {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst);
+            let key = self.keys[call.min(self.keys.len() - 1)];
+            future::ProvideCredentials::ready(Ok(Credentials::new(
+                key,
+                format!("{}-secret", key),
+                Some(format!("{}-token", key)),
+                self.expiry,
+                "test",
+            )))
+        }
+    }
+
+    /// A source over [`Rotating`] keys, and how often it called its provider
+    fn source(
+        keys: Vec<&'static str>,
+        expiry: Option<SystemTime>,
+    ) -> (CredentialSource, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let provider = Rotating {
+            keys,
+            expiry,
+            calls: Arc::clone(&calls),
+        };
+        (
+            CredentialSource::new(SharedCredentialsProvider::new(provider)),
+            calls,
+        )
+    }
+
+    #[tokio::test]
+    async fn credentials_are_cached_until_they_expire() {
+        let (cached, calls) = source(vec!["AKIDOLD", "AKIDNEW"], None);
+        assert_eq!(
+            cached.credentials().await.unwrap().access_key_id(),
+            "AKIDOLD"
+        );
+        assert_eq!(
+            cached.credentials().await.unwrap().access_key_id(),
+            "AKIDOLD"
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // About to expire, so fetched on every use
+        let soon = SystemTime::now() + EXPIRY_BUFFER / 2;
+        let (expiring, calls) = source(vec!["AKIDOLD", "AKIDNEW"], Some(soon));
+        assert_eq!(
+            expiring.credentials().await.unwrap().access_key_id(),
+            "AKIDOLD"
+        );
+        assert_eq!(
+            expiring.credentials().await.unwrap().access_key_id(),
+            "AKIDNEW"
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn a_refresh_replaces_expired_keys_and_rederives_the_signing_key() {
+        let (source, _) = source(vec!["AKIDOLD", "AKIDNEW"], None);
+        let old = source.signing_key().await.unwrap();
+        assert_eq!(old.access_key, "AKIDOLD");
+
+        assert!(source.refresh().await.unwrap());
+
+        let new = source.signing_key().await.unwrap();
+        assert_eq!(new.access_key, "AKIDNEW");
+        assert_eq!(new.secret_key, "AKIDNEW-secret");
+        assert_eq!(new.session_token.as_deref(), Some("AKIDNEW-token"));
+    }
+
+    #[tokio::test]
+    async fn the_same_keys_again_are_no_refresh() {
+        let (source, _) = source(vec!["AKIDSTATIC"], None);
+        source.credentials().await.unwrap();
+
+        assert!(!source.refresh().await.unwrap());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn uploads_failing_together_share_one_refresh() {
+        let (source, calls) = source(vec!["AKIDOLD", "AKIDNEW", "AKIDNEWER"], None);
+        source.credentials().await.unwrap();
+
+        assert!(source.refresh().await.unwrap());
+        assert!(source.refresh().await.unwrap());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            source.credentials().await.unwrap().access_key_id(),
+            "AKIDNEW"
+        );
+
+        tokio::time::advance(REFRESH_COOLDOWN).await;
+        assert!(source.refresh().await.unwrap());
+        assert_eq!(
+            source.credentials().await.unwrap().access_key_id(),
+            "AKIDNEWER"
+        );
+    }
+}
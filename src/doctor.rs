@@ -0,0 +1,300 @@
+use aws_config::SdkConfig;
+use aws_sdk_s3::{config::ProvideCredentials, Client};
+use bytes::Bytes;
+use reqwest::Client as ReqwestClient;
+use s3::bucket::Bucket;
+use std::env;
+
+use crate::{
+    arn::HttpEndpoint, capabilities::StorageOptions, cli::TlsArgs, config, create_s3_client,
+    error::AppError, load_aws_config, stall::Watch, tls::TlsConfig, upload_via_http, GcsBucket,
+    ObjectMeta,
+};
+
+/// Key of the tiny object written and removed by the round-trip checks
+const PROBE_KEY: &str = "s3-ml-uploader-doctor/probe.txt";
+
+/// Content of the probe object
+const PROBE_BODY: &[u8] = b"s3-ml-uploader connectivity probe";
+
+/// Outcome of a single diagnostic step
+struct Check {
+    name: &'static str,
+    result: Result<String, String>,
+}
+
+impl Check {
+    fn new(name: &'static str, result: Result<String, String>) -> Self {
+        Self { name, result }
+    }
+
+    fn passed(&self) -> bool {
+        self.result.is_ok()
+    }
+
+    fn print(&self) {
+        match &self.result {
+            Ok(detail) => println!("  \x1b[32m✔\x1b[0m {:<22} {}", self.name, detail),
+            Err(detail) => println!("  \x1b[31m✘\x1b[0m {:<22} {}", self.name, detail),
+        }
+    }
+}
+
+/// Run every backend check and print a checklist; exits non-zero on any failure
+pub async fn run(tls: TlsArgs) -> Result<(), AppError> {
+    let tls = TlsConfig::load(&tls)?;
+    let aws_bucket = config::AWS_BUCKET.get();
+    let aws_config = load_aws_config(&tls, false).await?;
+    let aws_client = Client::new(&aws_config);
+    let minio_checks = match create_s3_client(false, tls.user_agent()) {
+        Ok(bucket) => check_minio(&bucket).await,
+        Err(err) => vec![Check::new("configuration", Err(err.to_string()))],
+    };
+    let http_client = tls.http_client()?;
+    let gcs_checks = match GcsBucket::from_env(http_client.clone(), false) {
+        Ok(bucket) => check_gcs(&http_client, &bucket).await,
+        Err(err) => vec![Check::new("configuration", Err(err.to_string()))],
+    };
+
+    let sections = [
+        (
+            "AWS S3 (SDK)",
+            check_aws(&aws_config, &aws_client, &aws_bucket).await,
+        ),
+        ("MinIO", minio_checks),
+        (
+            "HTTP (SigV4)",
+            check_http(&http_client, &aws_client, &aws_bucket).await,
+        ),
+        ("GCS (S3 interop)", gcs_checks),
+    ];
+
+    let mut failed = 0;
+    for (backend, checks) in &sections {
+        println!("{}", backend);
+        for check in checks {
+            check.print();
+            if !check.passed() {
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        println!("{} check(s) failed", failed);
+        std::process::exit(1);
+    }
+
+    println!("All backends are reachable");
+    Ok(())
+}
+
+/// Credentials, region, bucket and probe round-trip through the AWS SDK
+async fn check_aws(config: &SdkConfig, client: &Client, bucket: &str) -> Vec<Check> {
+    let credentials = match config.credentials_provider() {
+        Some(provider) => provider
+            .provide_credentials()
+            .await
+            .map(|c| format!("access key {}", mask(c.access_key_id())))
+            .map_err(|e| e.to_string()),
+        None => Err("no credentials provider configured".to_string()),
+    };
+
+    let region = config
+        .region()
+        .map(|r| r.to_string())
+        .ok_or_else(|| "no region configured".to_string());
+
+    let exists = client
+        .head_bucket()
+        .bucket(bucket)
+        .send()
+        .await
+        .map(|_| format!("{} exists", bucket))
+        .map_err(|e| format!("{}: {}", bucket, describe(&e)));
+
+    let round_trip = aws_round_trip(client, bucket)
+        .await
+        .map(|_| format!("put/get/delete {}", PROBE_KEY))
+        .map_err(|e| e.to_string());
+
+    vec![
+        Check::new("credentials", credentials),
+        Check::new("region", region),
+        Check::new("bucket", exists),
+        Check::new("round-trip", round_trip),
+    ]
+}
+
+async fn aws_round_trip(client: &Client, bucket: &str) -> Result<(), AppError> {
+    client
+        .put_object()
+        .bucket(bucket)
+        .key(PROBE_KEY)
+        .body(PROBE_BODY.to_vec().into())
+        .send()
+        .await?;
+
+    let body = client
+        .get_object()
+        .bucket(bucket)
+        .key(PROBE_KEY)
+        .send()
+        .await?
+        .body
+        .collect()
+        .await?
+        .into_bytes();
+
+    client
+        .delete_object()
+        .bucket(bucket)
+        .key(PROBE_KEY)
+        .send()
+        .await?;
+
+    if body.as_ref() != PROBE_BODY {
+        return Err(AppError::Integrity("probe content mismatch".to_string()));
+    }
+
+    Ok(())
+}
+
+/// Credentials, region, bucket and probe round-trip through rust-s3
+async fn check_minio(bucket: &Bucket) -> Vec<Check> {
+    let credentials = match bucket.credentials().await {
+        Ok(c) => match c.access_key {
+            Some(key) => Ok(format!("access key {}", mask(&key))),
+            None => Err("no access key".to_string()),
+        },
+        Err(e) => Err(e.to_string()),
+    };
+
+    let region = Ok(format!("{} ({})", bucket.region(), bucket.host()));
+
+    let exists = bucket
+        .location()
+        .await
+        .map(|_| format!("{} exists", bucket.name()))
+        .map_err(|e| format!("{}: {}", bucket.name(), e));
+
+    let round_trip = minio_round_trip(bucket)
+        .await
+        .map(|_| format!("put/get/delete {}", PROBE_KEY))
+        .map_err(|e| e.to_string());
+
+    vec![
+        Check::new("credentials", credentials),
+        Check::new("region", region),
+        Check::new("bucket", exists),
+        Check::new("round-trip", round_trip),
+    ]
+}
+
+async fn minio_round_trip(bucket: &Bucket) -> Result<(), AppError> {
+    bucket.put_object(PROBE_KEY, PROBE_BODY).await?;
+    let response = bucket.get_object(PROBE_KEY).await?;
+    bucket.delete_object(PROBE_KEY).await?;
+
+    if response.bytes().as_ref() != PROBE_BODY {
+        return Err(AppError::Integrity("probe content mismatch".to_string()));
+    }
+
+    Ok(())
+}
+
+/// Credentials and acceptance of a request signed by `upload_via_http`
+async fn check_http(http_client: &ReqwestClient, client: &Client, bucket: &str) -> Vec<Check> {
+    let credentials = match (env::var("AWS_ACCESS_KEY"), env::var("AWS_SECRET_KEY")) {
+        (Ok(key), Ok(_)) => Ok(format!("access key {}", mask(&key))),
+        _ => Err("AWS_ACCESS_KEY and AWS_SECRET_KEY must be set".to_string()),
+    };
+
+    let probe = async {
+        upload_via_http(
+            http_client,
+            Bytes::from_static(PROBE_BODY).into(),
+            HttpEndpoint::for_bucket(bucket, None, false)?,
+            PROBE_KEY,
+            &ObjectMeta::default(),
+            &StorageOptions::default(),
+            &Watch::default(),
+        )
+        .await
+    };
+    let signing = match probe.await {
+        Ok(()) => {
+            // The HTTP path only signs PUTs, so clean up through the SDK
+            let _ = client
+                .delete_object()
+                .bucket(bucket)
+                .key(PROBE_KEY)
+                .send()
+                .await;
+            Ok("signed PUT accepted".to_string())
+        }
+        Err(e) => Err(e.to_string()),
+    };
+
+    vec![
+        Check::new("credentials", credentials),
+        Check::new("signed request", signing),
+    ]
+}
+
+/// HMAC key and probe round-trip through GCS's S3 interoperability
+async fn check_gcs(http_client: &ReqwestClient, bucket: &GcsBucket) -> Vec<Check> {
+    let credentials = match (env::var("GCS_ACCESS_KEY"), env::var("GCS_SECRET_KEY")) {
+        (Ok(key), Ok(_)) => Ok(format!("HMAC access ID {}", mask(&key))),
+        _ => Err("GCS_ACCESS_KEY and GCS_SECRET_KEY must be set".to_string()),
+    };
+
+    let round_trip = gcs_round_trip(http_client, bucket)
+        .await
+        .map(|_| format!("put/get/delete {} in {}", PROBE_KEY, bucket.name()))
+        .map_err(|e| e.to_string());
+
+    vec![
+        Check::new("credentials", credentials),
+        Check::new("round-trip", round_trip),
+    ]
+}
+
+async fn gcs_round_trip(http_client: &ReqwestClient, bucket: &GcsBucket) -> Result<(), AppError> {
+    upload_via_http(
+        http_client,
+        Bytes::from_static(PROBE_BODY).into(),
+        bucket.endpoint(),
+        PROBE_KEY,
+        &ObjectMeta::default(),
+        &StorageOptions::default(),
+        &Watch::default(),
+    )
+    .await?;
+    let body = bucket.get(PROBE_KEY, None).await?.bytes().await?;
+    bucket.delete(PROBE_KEY).await?;
+
+    if body.as_ref() != PROBE_BODY {
+        return Err(AppError::Integrity("probe content mismatch".to_string()));
+    }
+
+    Ok(())
+}
+
+/// Show only the first characters of a credential
+fn mask(key: &str) -> String {
+    let visible: String = key.chars().take(4).collect();
+    format!("{}****", visible)
+}
+
+/// Prefer the service error code over the generic SDK message
+fn describe<E, R>(err: &aws_sdk_s3::error::SdkError<E, R>) -> String
+where
+    E: aws_sdk_s3::error::ProvideErrorMetadata + std::error::Error + 'static,
+    R: std::fmt::Debug,
+{
+    match err.as_service_error().and_then(|e| e.code()) {
+        Some(code) => code.to_string(),
+        None => aws_sdk_s3::error::DisplayErrorContext(err).to_string(),
+    }
+}
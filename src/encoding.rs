@@ -0,0 +1,98 @@
+use bytes::Bytes;
+use std::io::Read;
+
+use crate::error::AppError;
+
+/// Inflate a body according to its `Content-Encoding`
+///
+/// Returns the body unchanged for `identity` or a missing header; unknown encodings are an error
+/// rather than silently writing compressed bytes under the original name.
+pub fn decode(body: Bytes, content_encoding: Option<&str>) -> Result<Bytes, AppError> {
+    let Some(encoding) = content_encoding.map(normalize) else {
+        return Ok(body);
+    };
+
+    match encoding.as_str() {
+        "" | "identity" => Ok(body),
+        "gzip" | "x-gzip" => {
+            let mut decoded = Vec::new();
+            flate2::read::MultiGzDecoder::new(&body[..])
+                .read_to_end(&mut decoded)
+                .map_err(|e| AppError::Integrity(format!("gzip body does not inflate: {}", e)))?;
+            Ok(decoded.into())
+        }
+        "zstd" => zstd::stream::decode_all(&body[..])
+            .map(Bytes::from)
+            .map_err(|e| AppError::Integrity(format!("zstd body does not decompress: {}", e))),
+        other => Err(AppError::Config(format!(
+            "cannot decompress Content-Encoding '{}' (supported: gzip, zstd)",
+            other
+        ))),
+    }
+}
+
+/// File name of the decoded body: `data.csv.gz` becomes `data.csv`, other names are kept
+pub fn decoded_file_name(name: &str, content_encoding: Option<&str>) -> String {
+    let suffix = match content_encoding.map(normalize).as_deref() {
+        Some("gzip") | Some("x-gzip") => ".gz",
+        Some("zstd") => ".zst",
+        _ => return name.to_string(),
+    };
+
+    match name.strip_suffix(suffix) {
+        Some(stem) if !stem.is_empty() => stem.to_string(),
+        _ => name.to_string(),
+    }
+}
+
+fn normalize(encoding: &str) -> String {
+    encoding.trim().to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const CONTENT: &[u8] = b"col_a,col_b\n1,2\n3,4\n";
+
+    #[test]
+    fn gzip_and_zstd_bodies_inflate_to_the_source() {
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        gzip.write_all(CONTENT).unwrap();
+        let gzip = Bytes::from(gzip.finish().unwrap());
+        let zstd = Bytes::from(zstd::encode_all(CONTENT, 0).unwrap());
+
+        assert_eq!(decode(gzip.clone(), Some("gzip")).unwrap(), CONTENT);
+        assert_eq!(decode(gzip, Some(" X-GZip ")).unwrap(), CONTENT);
+        assert_eq!(decode(zstd, Some("zstd")).unwrap(), CONTENT);
+    }
+
+    #[test]
+    fn identity_bodies_are_kept_and_unknown_encodings_refused() {
+        let body = Bytes::from_static(CONTENT);
+        assert_eq!(decode(body.clone(), None).unwrap(), CONTENT);
+        assert_eq!(decode(body.clone(), Some("identity")).unwrap(), CONTENT);
+        assert!(matches!(
+            decode(body.clone(), Some("br")),
+            Err(AppError::Config(_))
+        ));
+        assert!(matches!(
+            decode(body, Some("gzip")),
+            Err(AppError::Integrity(_))
+        ));
+    }
+
+    #[test]
+    fn the_encoding_suffix_is_dropped_from_the_file_name() {
+        assert_eq!(decoded_file_name("data.csv.gz", Some("gzip")), "data.csv");
+        assert_eq!(decoded_file_name("data.csv.zst", Some("zstd")), "data.csv");
+        // Only the suffix of the encoding actually decoded
+        assert_eq!(
+            decoded_file_name("data.csv.zst", Some("gzip")),
+            "data.csv.zst"
+        );
+        assert_eq!(decoded_file_name("data.csv.gz", None), "data.csv.gz");
+        assert_eq!(decoded_file_name(".gz", Some("gzip")), ".gz");
+    }
+}
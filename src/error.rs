@@ -0,0 +1,455 @@
+use aws_sdk_s3::{
+    config::http::HttpResponse,
+    error::{ProvideErrorMetadata, SdkError},
+};
+use thiserror::Error;
+
+/// Error type shared by every part of the uploader
+#[derive(Debug, Error)]
+pub enum AppError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("AWS S3 request failed: {message}{}", hint(.code.as_deref()))]
+    AwsSdk {
+        code: Option<String>,
+        status: Option<u16>,
+        message: String,
+    },
+
+    #[error("S3-compatible storage request failed: {0}{}", s3_hint(.0))]
+    S3(#[from] s3::error::S3Error),
+
+    #[error("HTTP request failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("HTTP upload rejected with status {status}{}", hint(.code.as_deref()))]
+    HttpStatus { status: u16, code: Option<String> },
+
+    #[error("GCS rejected the request with status {status}: {message}{}", gcs_hint(.code.as_deref()))]
+    Gcs {
+        status: u16,
+        code: Option<String>,
+        message: String,
+    },
+
+    #[error("request signing failed: {0}")]
+    Signing(String),
+
+    #[error("TLS configuration failed: {0}")]
+    Tls(String),
+
+    #[error("invalid configuration: {0}")]
+    Config(String),
+
+    #[error("missing configuration: set {} (or pass --allow-defaults to run with placeholder values)", .0.join(", "))]
+    MissingConfig(Vec<&'static str>),
+
+    #[error("could not classify {path}: {reason}")]
+    Classification { path: String, reason: String },
+
+    #[error("{backend} request throttled (503 SlowDown, or 429 from GCS); lower --concurrency or enable --adaptive-concurrency")]
+    Throttled { backend: &'static str },
+
+    #[error("{backend}: upload stalled below --min-throughput {}/s", crate::cli::format_size(*.min_throughput))]
+    Stalled {
+        backend: &'static str,
+        min_throughput: u64,
+    },
+
+    #[error("could not read {path} at byte {offset}: {source}")]
+    ReadFailed {
+        path: String,
+        offset: u64,
+        source: std::io::Error,
+    },
+
+    #[error("file changed during upload: {path} was {expected} bytes, now {actual}")]
+    FileChanged {
+        path: String,
+        expected: u64,
+        actual: u64,
+    },
+
+    #[error("{path} had {actual} bytes, not the {expected} --content-length gave")]
+    LengthMismatch {
+        path: String,
+        expected: u64,
+        actual: u64,
+    },
+
+    #[error(
+        "{backend} does not support {feature}; pass --on-unsupported warn to upload without it"
+    )]
+    Unsupported {
+        backend: &'static str,
+        feature: String,
+    },
+
+    #[error("{backend}: the bucket is in region {region}; set AWS_REGION={region} or pass --auto-region")]
+    WrongRegion {
+        backend: &'static str,
+        region: String,
+    },
+
+    #[error("replica {replica}: {source}")]
+    Replica {
+        replica: String,
+        source: Box<AppError>,
+    },
+
+    #[error("{path} is {}, over --max-file-size {}", crate::cli::format_size(*.size), crate::cli::format_size(*.limit))]
+    Oversize { path: String, size: u64, limit: u64 },
+
+    #[error("{path} was not uploaded: scan found {finding}")]
+    ScanHit { path: String, finding: String },
+
+    #[error("{path} is empty")]
+    EmptyFile { path: String },
+
+    #[error("key of {path} is {len} bytes, over the {max} --max-key-length leaves it")]
+    KeyTooLong {
+        path: String,
+        len: usize,
+        max: usize,
+    },
+
+    #[error("{key} would have {count} tags after --tag-mode merge, over the {max} S3 allows")]
+    TooManyTags {
+        key: String,
+        count: usize,
+        max: usize,
+    },
+
+    #[error("{path} is invalid: {reason}")]
+    Invalid { path: String, reason: String },
+
+    #[error("content transform failed: {0}")]
+    Transform(String),
+
+    #[error("integrity check failed: {0}")]
+    Integrity(String),
+
+    #[error("pack {pack} failed to upload: {reason}")]
+    PackFailed { pack: String, reason: String },
+
+    #[error("upload task failed: {0}")]
+    Task(#[from] tokio::task::JoinError),
+
+    #[error("upload task panicked: {0}")]
+    Panicked(String),
+
+    #[error("{0} file(s) failed to upload")]
+    UploadsFailed(usize),
+
+    #[error("{0} file(s) could not be classified")]
+    ClassifyFailed(usize),
+
+    #[error("aborted: {reason}; {failed} file(s) failed, {cancelled} not uploaded")]
+    FailureThreshold {
+        reason: String,
+        failed: usize,
+        cancelled: usize,
+    },
+
+    #[error("not confirmed: {plan}; {reason}")]
+    NotConfirmed { plan: String, reason: &'static str },
+
+    #[error("skipped after the failure threshold was exceeded")]
+    Cancelled,
+
+    #[error("Skipped {path}: {reason}")]
+    Skipped { path: String, reason: String },
+}
+
+impl AppError {
+    /// Process exit code: 3 when a failure threshold aborted the run, 1 otherwise
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            AppError::FailureThreshold { .. } => 3,
+            _ => 1,
+        }
+    }
+
+    /// Whether the AWS SDK's request was refused as something the store doesn't implement
+    ///
+    /// S3-compatible stores answer features they lack (trailing checksums, `aws-chunked`
+    /// bodies, multipart uploads) with 501 or `NotImplemented`, or with a 400 calling the
+    /// request or one of its arguments invalid.
+    pub fn is_unsupported_by_store(&self) -> bool {
+        match self {
+            AppError::AwsSdk { code, status, .. } => {
+                *status == Some(501)
+                    || code.as_deref() == Some("NotImplemented")
+                    || (*status == Some(400)
+                        && matches!(code.as_deref(), Some("InvalidRequest" | "InvalidArgument")))
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether a backend rejected a body that didn't match its `Content-MD5`
+    pub fn is_bad_digest(&self) -> bool {
+        match self {
+            AppError::AwsSdk { code, .. }
+            | AppError::HttpStatus { code, .. }
+            | AppError::Gcs { code, .. } => code.as_deref() == Some("BadDigest"),
+            AppError::S3(s3::error::S3Error::HttpFailWithBody(400, body)) => {
+                xml_error_code(body).as_deref() == Some("BadDigest")
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether a backend refused the request's temporary credentials as expired
+    pub fn is_expired_credentials(&self) -> bool {
+        let code = match self {
+            AppError::AwsSdk { code, .. } | AppError::HttpStatus { code, .. } => code.clone(),
+            AppError::S3(s3::error::S3Error::HttpFailWithBody(400 | 403, body)) => {
+                xml_error_code(body)
+            }
+            _ => None,
+        };
+        matches!(
+            code.as_deref(),
+            Some("ExpiredToken" | "ExpiredTokenException" | "TokenRefreshRequired")
+        )
+    }
+}
+
+impl<E> From<SdkError<E, HttpResponse>> for AppError
+where
+    E: ProvideErrorMetadata + std::error::Error + 'static,
+{
+    fn from(err: SdkError<E, HttpResponse>) -> Self {
+        let status = err.raw_response().map(|r| r.status().as_u16());
+        let bucket_region = err
+            .raw_response()
+            .and_then(|r| r.headers().get(BUCKET_REGION_HEADER));
+
+        let (code, message) = match err.as_service_error() {
+            Some(service) => (
+                service.code().map(str::to_string),
+                service
+                    .message()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| service.to_string()),
+            ),
+            None => (
+                None,
+                aws_sdk_s3::error::DisplayErrorContext(&err).to_string(),
+            ),
+        };
+
+        if let Some(region) = bucket_region {
+            if is_region_redirect(status, code.as_deref()) {
+                return AppError::WrongRegion {
+                    backend: "AWS S3",
+                    region: region.to_string(),
+                };
+            }
+        }
+
+        AppError::AwsSdk {
+            code,
+            status,
+            message,
+        }
+    }
+}
+
+impl From<aws_sdk_s3::primitives::ByteStreamError> for AppError {
+    fn from(err: aws_sdk_s3::primitives::ByteStreamError) -> Self {
+        AppError::Io(std::io::Error::other(err))
+    }
+}
+
+impl From<hmac::digest::InvalidLength> for AppError {
+    fn from(err: hmac::digest::InvalidLength) -> Self {
+        AppError::Signing(err.to_string())
+    }
+}
+
+impl From<s3::creds::error::CredentialsError> for AppError {
+    fn from(err: s3::creds::error::CredentialsError) -> Self {
+        AppError::Config(format!("S3 credentials: {}", err))
+    }
+}
+
+impl From<glob::PatternError> for AppError {
+    fn from(err: glob::PatternError) -> Self {
+        AppError::Config(format!("invalid glob pattern: {}", err))
+    }
+}
+
+impl From<glob::GlobError> for AppError {
+    fn from(err: glob::GlobError) -> Self {
+        AppError::Io(err.into_error())
+    }
+}
+
+/// Response header carrying the region a bucket actually lives in
+pub const BUCKET_REGION_HEADER: &str = "x-amz-bucket-region";
+
+/// Whether S3 rejected a request because it was sent to or signed for the wrong region
+pub fn is_region_redirect(status: Option<u16>, code: Option<&str>) -> bool {
+    status == Some(301)
+        || matches!(
+            code,
+            Some("PermanentRedirect") | Some("AuthorizationHeaderMalformed")
+        )
+}
+
+/// Error code from an S3 XML error body
+pub fn xml_error_code(body: &str) -> Option<String> {
+    xml_tag(body, "Code")
+}
+
+/// Text of the first `<tag>` element of an S3 XML error body
+pub fn xml_tag(body: &str, tag: &str) -> Option<String> {
+    let open = format!("<{}>", tag);
+    let start = body.find(&open)? + open.len();
+    let end = body[start..].find(&format!("</{}>", tag))? + start;
+    Some(body[start..end].to_string())
+}
+
+/// Actionable advice for common S3 error codes
+fn hint(code: Option<&str>) -> &'static str {
+    match code {
+        Some("AccessDenied") => " (check the IAM permissions for this bucket)",
+        Some("InvalidAccessKeyId") | Some("SignatureDoesNotMatch") => {
+            " (check AWS_ACCESS_KEY/AWS_SECRET_KEY or the AWS credential chain)"
+        }
+        Some("NoSuchBucket") => " (check AWS_BUCKET)",
+        Some("ExpiredToken") | Some("ExpiredTokenException") | Some("TokenRefreshRequired") => {
+            " (the temporary credentials expired, and their source had no newer ones)"
+        }
+        Some("BadDigest") | Some("InvalidDigest") | Some("XAmzContentSHA256Mismatch") => {
+            " (the body was corrupted in transit; retry the upload)"
+        }
+        Some("PermanentRedirect") | Some("AuthorizationHeaderMalformed") => {
+            " (the bucket lives in a different region)"
+        }
+        _ => "",
+    }
+}
+
+/// Actionable advice for the error codes of GCS's XML API
+fn gcs_hint(code: Option<&str>) -> &'static str {
+    match code {
+        Some("AccessDenied") => {
+            " (the HMAC key's service account needs object access to the bucket, e.g. roles/storage.objectUser)"
+        }
+        Some("InvalidAccessKeyId") | Some("SignatureDoesNotMatch") => {
+            " (check GCS_ACCESS_KEY/GCS_SECRET_KEY hold an active HMAC key)"
+        }
+        Some("NoSuchBucket") => " (check GCS_BUCKET)",
+        Some("BadDigest") | Some("InvalidDigest") => {
+            " (the body was corrupted in transit; retry the upload)"
+        }
+        _ => "",
+    }
+}
+
+/// Actionable advice for rust-s3 failures
+fn s3_hint(err: &s3::error::S3Error) -> &'static str {
+    match err {
+        s3::error::S3Error::HttpFailWithBody(403, _) => " (check S3_ACCESS_KEY/S3_SECRET_KEY)",
+        s3::error::S3Error::HttpFailWithBody(404, _) => " (check S3_BUCKET)",
+        s3::error::S3Error::Hyper(_) | s3::error::S3Error::Http(_) => {
+            " (check that S3_ENDPOINT is reachable)"
+        }
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn region_redirects_are_recognized_by_status_or_code() {
+        assert!(is_region_redirect(Some(301), None));
+        assert!(is_region_redirect(
+            Some(400),
+            Some("AuthorizationHeaderMalformed")
+        ));
+        assert!(is_region_redirect(None, Some("PermanentRedirect")));
+        assert!(!is_region_redirect(Some(403), Some("AccessDenied")));
+    }
+
+    #[test]
+    fn xml_errors_yield_their_code_and_region() {
+        let body = "<Error><Code>AuthorizationHeaderMalformed</Code>\
+                    <Region>eu-west-1</Region></Error>";
+
+        assert_eq!(
+            xml_error_code(body).as_deref(),
+            Some("AuthorizationHeaderMalformed")
+        );
+        assert_eq!(xml_tag(body, "Region").as_deref(), Some("eu-west-1"));
+        assert_eq!(xml_tag(body, "Message"), None);
+    }
+
+    #[test]
+    fn unsupported_requests_are_recognized_by_status_or_code() {
+        let sdk = |status: u16, code: &str| AppError::AwsSdk {
+            code: Some(code.to_string()),
+            status: Some(status),
+            message: String::new(),
+        };
+        assert!(sdk(501, "Whatever").is_unsupported_by_store());
+        assert!(sdk(400, "NotImplemented").is_unsupported_by_store());
+        assert!(sdk(400, "InvalidRequest").is_unsupported_by_store());
+        assert!(sdk(400, "InvalidArgument").is_unsupported_by_store());
+        assert!(!sdk(400, "BadDigest").is_unsupported_by_store());
+        assert!(!sdk(403, "InvalidRequest").is_unsupported_by_store());
+        assert!(!sdk(503, "SlowDown").is_unsupported_by_store());
+        // Only the SDK path falls back
+        assert!(!AppError::HttpStatus {
+            status: 501,
+            code: Some("NotImplemented".to_string()),
+        }
+        .is_unsupported_by_store());
+    }
+
+    #[test]
+    fn wrong_region_names_the_fix() {
+        let err = AppError::WrongRegion {
+            backend: "AWS S3",
+            region: "eu-west-1".to_string(),
+        };
+
+        assert_eq!(
+            err.to_string(),
+            "AWS S3: the bucket is in region eu-west-1; set AWS_REGION=eu-west-1 or pass --auto-region"
+        );
+    }
+
+    #[test]
+    fn bad_digests_are_recognized_on_every_backend() {
+        let http = AppError::HttpStatus {
+            status: 400,
+            code: Some("BadDigest".to_string()),
+        };
+        let gcs = AppError::Gcs {
+            status: 400,
+            code: Some("BadDigest".to_string()),
+            message: "The Content-MD5 you specified did not match".to_string(),
+        };
+        let minio = AppError::S3(s3::error::S3Error::HttpFailWithBody(
+            400,
+            "<Error><Code>BadDigest</Code></Error>".to_string(),
+        ));
+        let denied = AppError::HttpStatus {
+            status: 403,
+            code: Some("AccessDenied".to_string()),
+        };
+
+        assert!(http.is_bad_digest() && gcs.is_bad_digest() && minio.is_bad_digest());
+        assert!(!denied.is_bad_digest());
+        assert!(http
+            .to_string()
+            .ends_with("(the body was corrupted in transit; retry the upload)"));
+    }
+}
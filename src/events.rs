@@ -0,0 +1,52 @@
+/// Progress of an upload run, sent to the channel given to `run_upload_with_progress`
+///
+/// The events of one file arrive in order: `Started` once it has been read and its key is
+/// known, a `Bytes` for every object of the file (its sidecar included) each backend or
+/// replica stored, then exactly one of `Completed`, `Failed` or `Skipped`. A file that is
+/// skipped or fails before it is read (too large, unreadable) gets no `Started`. Files of
+/// a run are uploaded concurrently, so events of different files interleave. Files
+/// `--resume-batch` finds already uploaded send nothing. The `Bytes` of a `--pack` pack and
+/// its index name the pack's key as `file`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProgressEvent {
+    Started {
+        file: String,
+        key: String,
+        // Size of the body that will be uploaded, after transforms
+        bytes: u64,
+    },
+    Bytes {
+        file: String,
+        key: String,
+        // Name of the backend or replica, as in the summary (e.g. `AWS S3`)
+        target: String,
+        bytes: u64,
+    },
+    Completed {
+        file: String,
+        key: String,
+        // Bytes of the file and its sidecar, uploaded to each target
+        bytes: u64,
+    },
+    Failed {
+        file: String,
+        error: String,
+    },
+    Skipped {
+        file: String,
+        reason: String,
+    },
+}
+
+impl ProgressEvent {
+    /// The file the event is about
+    pub fn file(&self) -> &str {
+        match self {
+            ProgressEvent::Started { file, .. }
+            | ProgressEvent::Bytes { file, .. }
+            | ProgressEvent::Completed { file, .. }
+            | ProgressEvent::Failed { file, .. }
+            | ProgressEvent::Skipped { file, .. } => file,
+        }
+    }
+}
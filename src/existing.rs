@@ -0,0 +1,103 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::collections::HashMap;
+
+use crate::{
+    cli::NoChecksum,
+    hashing::{Sha256Digest, IDEMPOTENCY_METADATA, SHA256_METADATA},
+};
+
+/// What a HEAD request reveals about the object already stored under a key
+#[derive(Debug, Clone, Default)]
+pub struct StoredObject {
+    // Hex SHA-256 from `x-amz-meta-sha256` or the full-object checksum
+    pub sha256: Option<String>,
+    pub size: u64,
+    // `x-amz-meta-idempotency-key` of the upload that wrote the object
+    pub idempotency_key: Option<String>,
+    // Version or generation of the object on stores that keep them
+    pub version_id: Option<String>,
+}
+
+impl StoredObject {
+    /// From the user metadata of a HEAD response, falling back to a base64 `x-amz-checksum-sha256`
+    pub fn new(
+        metadata: Option<&HashMap<String, String>>,
+        checksum: Option<&str>,
+        size: u64,
+    ) -> Self {
+        let stamped = metadata
+            .and_then(|metadata| metadata.get(SHA256_METADATA))
+            .map(|sha256| sha256.to_ascii_lowercase());
+
+        Self {
+            sha256: stamped.or_else(|| checksum.and_then(checksum_hex)),
+            size,
+            idempotency_key: metadata
+                .and_then(|metadata| metadata.get(IDEMPOTENCY_METADATA))
+                .cloned(),
+            version_id: None,
+        }
+    }
+
+    pub fn with_version_id(mut self, version_id: Option<&str>) -> Self {
+        self.version_id = version_id.map(str::to_string);
+        self
+    }
+
+    /// Whether uploading a body with this digest and size would store the same content
+    ///
+    /// Without a stored checksum the result depends on `fallback`: `size` trusts a matching
+    /// size, `upload` always uploads.
+    pub fn matches(&self, sha256: &Sha256Digest, size: u64, fallback: NoChecksum) -> bool {
+        match &self.sha256 {
+            Some(stored) => *stored == hex::encode(sha256),
+            None => fallback == NoChecksum::Size && self.size == size,
+        }
+    }
+}
+
+/// Hex form of a full-object checksum; composite multipart checksums (`...-3`) are skipped
+fn checksum_hex(checksum: &str) -> Option<String> {
+    if checksum.contains('-') {
+        return None;
+    }
+    let digest = STANDARD.decode(checksum).ok()?;
+    (digest.len() == 32).then(|| hex::encode(digest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashing::sha256 as digest;
+
+    #[test]
+    fn stamped_sha256_is_read_back_in_any_case() {
+        let sha256 = digest(b"content");
+        let metadata = HashMap::from([(SHA256_METADATA.to_string(), hex::encode_upper(sha256))]);
+
+        let stored = StoredObject::new(Some(&metadata), None, 7);
+        assert_eq!(stored.sha256, Some(hex::encode(sha256)));
+        assert!(stored.matches(&sha256, 7, NoChecksum::Upload));
+        assert!(!stored.matches(&digest(b"changed"), 7, NoChecksum::Size));
+    }
+
+    #[test]
+    fn full_object_checksum_stands_in_for_the_stamp() {
+        let sha256 = digest(b"content");
+        let stored = StoredObject::new(None, Some(&STANDARD.encode(sha256)), 7);
+        assert_eq!(stored.sha256, Some(hex::encode(sha256)));
+
+        // A composite checksum of a multipart upload is no digest of the content
+        let composite = format!("{}-3", STANDARD.encode(sha256));
+        assert_eq!(StoredObject::new(None, Some(&composite), 7).sha256, None);
+    }
+
+    #[test]
+    fn without_a_checksum_the_fallback_decides() {
+        let stored = StoredObject::new(None, None, 7);
+        let sha256 = digest(b"content");
+        assert!(stored.matches(&sha256, 7, NoChecksum::Size));
+        assert!(!stored.matches(&sha256, 8, NoChecksum::Size));
+        assert!(!stored.matches(&sha256, 7, NoChecksum::Upload));
+    }
+}
@@ -0,0 +1,174 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    OnceLock,
+};
+
+/// Files that must complete before `--max-failure-rate` is evaluated
+const MIN_RATE_SAMPLES: usize = 10;
+
+/// Counts file outcomes and trips once `--max-failures` or `--max-failure-rate` is exceeded
+pub struct FailureBudget {
+    max_failures: Option<usize>,
+    max_rate: Option<f64>,
+    failed: AtomicUsize,
+    completed: AtomicUsize,
+    // Description of the threshold that tripped, set once
+    tripped: OnceLock<String>,
+}
+
+impl FailureBudget {
+    pub fn new(max_failures: Option<usize>, max_rate: Option<f64>) -> Self {
+        Self {
+            max_failures,
+            max_rate,
+            failed: AtomicUsize::new(0),
+            completed: AtomicUsize::new(0),
+            tripped: OnceLock::new(),
+        }
+    }
+
+    /// Record a successful file
+    pub fn record_success(&self) {
+        self.completed.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Record a failed file; returns true if this failure tripped the budget
+    pub fn record_failure(&self) -> bool {
+        let failed = self.failed.fetch_add(1, Ordering::SeqCst) + 1;
+        let completed = self.completed.fetch_add(1, Ordering::SeqCst) + 1;
+
+        if let Some(max) = self.max_failures {
+            if failed > max {
+                return self.trip(format!(
+                    "--max-failures {} exceeded ({} failed)",
+                    max, failed
+                ));
+            }
+        }
+
+        if let Some(max_rate) = self.max_rate {
+            let rate = failed as f64 / completed as f64;
+            if completed >= MIN_RATE_SAMPLES && rate > max_rate {
+                return self.trip(format!(
+                    "--max-failure-rate {} exceeded ({} of {} files failed, {:.1}%)",
+                    max_rate,
+                    failed,
+                    completed,
+                    rate * 100.0
+                ));
+            }
+        }
+
+        false
+    }
+
+    /// Threshold that stopped the run, if any
+    pub fn tripped(&self) -> Option<&str> {
+        self.tripped.get().map(String::as_str)
+    }
+
+    fn trip(&self, reason: String) -> bool {
+        self.tripped.set(reason).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn max_failures_trips_on_the_first_failure_past_it() {
+        let budget = FailureBudget::new(Some(2), None);
+        assert!(!budget.record_failure());
+        assert!(!budget.record_failure());
+        assert_eq!(budget.tripped(), None);
+
+        assert!(budget.record_failure());
+        assert_eq!(
+            budget.tripped(),
+            Some("--max-failures 2 exceeded (3 failed)")
+        );
+        // Only the failure that tripped it reports so
+        assert!(!budget.record_failure());
+        assert_eq!(
+            budget.tripped(),
+            Some("--max-failures 2 exceeded (3 failed)")
+        );
+    }
+
+    #[test]
+    fn max_failures_zero_trips_on_any_failure() {
+        let budget = FailureBudget::new(Some(0), None);
+        budget.record_success();
+        assert!(budget.record_failure());
+    }
+
+    #[test]
+    fn the_rate_waits_for_enough_completed_files() {
+        let budget = FailureBudget::new(None, Some(0.5));
+        for _ in 0..MIN_RATE_SAMPLES - 1 {
+            assert!(!budget.record_failure());
+        }
+        assert_eq!(budget.tripped(), None);
+
+        assert!(budget.record_failure());
+        assert_eq!(
+            budget.tripped(),
+            Some("--max-failure-rate 0.5 exceeded (10 of 10 files failed, 100.0%)")
+        );
+    }
+
+    #[test]
+    fn a_rate_equal_to_the_maximum_does_not_trip() {
+        let budget = FailureBudget::new(None, Some(0.5));
+        for _ in 0..5 {
+            budget.record_success();
+        }
+        for _ in 0..5 {
+            assert!(!budget.record_failure());
+        }
+        assert_eq!(budget.tripped(), None);
+
+        // 6 of 11 is past it
+        assert!(budget.record_failure());
+        assert_eq!(
+            budget.tripped(),
+            Some("--max-failure-rate 0.5 exceeded (6 of 11 files failed, 54.5%)")
+        );
+    }
+
+    #[test]
+    fn the_threshold_crossed_is_the_one_reported() {
+        // The rate is crossed first
+        let budget = FailureBudget::new(Some(5), Some(0.2));
+        for _ in 0..7 {
+            budget.record_success();
+        }
+        for _ in 0..2 {
+            assert!(!budget.record_failure());
+        }
+        assert!(budget.record_failure());
+        assert_eq!(
+            budget.tripped(),
+            Some("--max-failure-rate 0.2 exceeded (3 of 10 files failed, 30.0%)")
+        );
+
+        // The count is crossed while too few files completed for the rate
+        let budget = FailureBudget::new(Some(1), Some(0.2));
+        assert!(!budget.record_failure());
+        assert!(budget.record_failure());
+        assert_eq!(
+            budget.tripped(),
+            Some("--max-failures 1 exceeded (2 failed)")
+        );
+    }
+
+    #[test]
+    fn without_limits_nothing_trips() {
+        let budget = FailureBudget::new(None, None);
+        for _ in 0..100 {
+            assert!(!budget.record_failure());
+        }
+        assert_eq!(budget.tripped(), None);
+    }
+}
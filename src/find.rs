@@ -0,0 +1,168 @@
+use aws_sdk_s3::Client;
+use futures::{stream, StreamExt};
+use serde_json::json;
+use std::{path::Path, sync::Arc};
+
+use crate::{
+    cli::{format_size, FindArgs},
+    config, create_aws_client, download_from_aws_s3,
+    error::AppError,
+    keys,
+    listing::{self, ObjectEntry},
+    tls::TlsConfig,
+    DownloadOptions,
+};
+
+/// A listed object with its tags as `(key, value)` pairs
+type Tagged = (ObjectEntry, Vec<(String, String)>);
+
+/// List (and optionally download) objects of the AWS bucket whose tags match every `--tag`
+///
+/// `ListObjectsV2` doesn't return tags, so each listed object costs one
+/// `GetObjectTagging` request; `--concurrency` bounds how many run at once.
+pub async fn run(args: FindArgs) -> Result<(), AppError> {
+    config::require([&config::AWS_BUCKET], args.allow_defaults)?;
+    let tls = TlsConfig::load(&args.tls)?;
+    let client = Arc::new(create_aws_client(&tls, args.no_sign_request).await?);
+    let bucket = config::AWS_BUCKET.get();
+
+    let prefix = args.prefix.as_deref().unwrap_or("");
+    let listing = listing::list_objects(&client, &bucket, prefix, None).await?;
+
+    let tagged: Vec<Result<Tagged, AppError>> = stream::iter(listing.objects)
+        .map(|object| {
+            let (client, bucket) = (&client, &bucket);
+            async move {
+                let tags = object_tags(client, bucket, &object.key).await?;
+                Ok((object, tags))
+            }
+        })
+        .buffered(args.concurrency.max(1))
+        .collect()
+        .await;
+
+    let mut matches = Vec::new();
+    for result in tagged {
+        let (object, tags) = result?;
+        let matched = args
+            .tag
+            .iter()
+            .all(|wanted| tags.iter().any(|tag| tag == wanted));
+        if matched {
+            matches.push((object, tags));
+        }
+    }
+
+    if args.json {
+        print_json(&matches);
+    } else {
+        print_table(&matches);
+    }
+
+    if let Some(dir) = &args.download {
+        download(&client, &bucket, dir, &matches, args.concurrency).await?;
+    }
+
+    Ok(())
+}
+
+/// Tags of one object as `(key, value)` pairs
+async fn object_tags(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+) -> Result<Vec<(String, String)>, AppError> {
+    let output = client
+        .get_object_tagging()
+        .bucket(bucket)
+        .key(key)
+        .send()
+        .await?;
+
+    Ok(output
+        .tag_set()
+        .iter()
+        .map(|tag| (tag.key().to_string(), tag.value().to_string()))
+        .collect())
+}
+
+/// Download every match under `dir`, keeping the key's folders
+async fn download(
+    client: &Arc<Client>,
+    bucket: &str,
+    dir: &Path,
+    matches: &[Tagged],
+    concurrency: usize,
+) -> Result<(), AppError> {
+    let results: Vec<Result<String, AppError>> = stream::iter(matches)
+        .map(|(object, _)| async move {
+            let relative = keys::key_path(&object.key, None, 0).ok_or_else(|| {
+                AppError::Config(format!(
+                    "cannot derive a file name from key '{}'",
+                    object.key
+                ))
+            })?;
+            let output = dir.join(relative);
+            if let Some(parent) = output.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+
+            download_from_aws_s3(
+                Arc::clone(client),
+                bucket,
+                &object.key,
+                Some(&output.to_string_lossy()),
+                DownloadOptions::default(),
+            )
+            .await
+        })
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await;
+
+    for result in results {
+        result?;
+    }
+    Ok(())
+}
+
+fn format_tags(tags: &[(String, String)]) -> String {
+    tags.iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn print_json(matches: &[Tagged]) {
+    let objects: Vec<_> = matches
+        .iter()
+        .map(|(object, tags)| {
+            let tags: serde_json::Map<_, _> = tags
+                .iter()
+                .map(|(key, value)| (key.clone(), json!(value)))
+                .collect();
+            json!({
+                "key": object.key,
+                "size": object.size,
+                "last_modified": object.last_modified.map(|t| t.to_rfc3339()),
+                "tags": tags,
+            })
+        })
+        .collect();
+
+    println!("{}", json!({ "objects": objects }));
+}
+
+fn print_table(matches: &[Tagged]) {
+    let mut total = 0;
+    for (object, tags) in matches {
+        println!(
+            "{:>12} {} [{}]",
+            format_size(object.size),
+            object.key,
+            format_tags(tags)
+        );
+        total += object.size;
+    }
+    println!("{} object(s), {}", matches.len(), format_size(total));
+}
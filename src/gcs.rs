@@ -0,0 +1,255 @@
+use chrono::Utc;
+use reqwest::{header::HeaderMap, Client as ReqwestClient, Method, Response, StatusCode, Url};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+use crate::{
+    arn::{HttpEndpoint, SigningKey},
+    capabilities::Capabilities,
+    config,
+    error::{self, AppError},
+    existing::StoredObject,
+    hashing, range_header,
+    signing::Signer,
+    sigv4_authorization,
+    source::SourceRange,
+    uri_encode_path, write_download, Backend, DownloadOptions, Downloaded,
+};
+
+/// Region interop requests are signed for; GCS accepts any, and its docs use `auto`
+const REGION: &str = "auto";
+
+/// User metadata prefixes of a GCS response; it answers interop requests with its own
+const METADATA_PREFIXES: [&str; 2] = ["x-goog-meta-", "x-amz-meta-"];
+
+/// GCS encrypts every object itself and has its own storage class names (NEARLINE,
+/// COLDLINE, ARCHIVE); of `--storage-class` only STANDARD means the same there
+pub const CAPABILITIES: Capabilities = Capabilities {
+    storage_classes: &["STANDARD"],
+    sse_s3: false,
+    sse_kms: false,
+    object_lock: false,
+};
+
+/// A GCS bucket reached through the XML API's S3 interoperability, signed with an HMAC key
+pub struct GcsBucket {
+    client: ReqwestClient,
+    scheme: &'static str,
+    host: String,
+    bucket: String,
+    // `None` with `--no-sign-request`
+    credentials: Option<SigningKey>,
+    signer: Arc<Signer>,
+}
+
+impl GcsBucket {
+    /// From `GCS_ENDPOINT`, `GCS_BUCKET` and the HMAC key in `GCS_ACCESS_KEY`/`GCS_SECRET_KEY`
+    pub fn from_env(client: ReqwestClient, unsigned: bool) -> Result<Self, AppError> {
+        let endpoint = config::GCS_ENDPOINT.get();
+        let invalid = |problem: &str| AppError::Config(format!("GCS_ENDPOINT {}", problem));
+        let url = Url::parse(&endpoint)
+            .map_err(|e| invalid(&format!("'{}' is not a URL: {}", endpoint, e)))?;
+        let scheme = match url.scheme() {
+            "https" => "https",
+            "http" => "http",
+            _ => return Err(invalid(&format!("'{}' is not an http(s) URL", endpoint))),
+        };
+        let host = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => return Err(invalid(&format!("'{}' names no host", endpoint))),
+        };
+
+        Ok(Self {
+            client,
+            scheme,
+            host,
+            bucket: config::GCS_BUCKET.get(),
+            credentials: (!unsigned)
+                .then(|| SigningKey::from_env(&config::GCS_ACCESS_KEY, &config::GCS_SECRET_KEY)),
+            signer: Arc::default(),
+        })
+    }
+
+    /// Sign the bucket's requests with `signer`, e.g. the one of the other backends
+    pub fn with_signer(mut self, signer: Arc<Signer>) -> Self {
+        self.signer = signer;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.bucket
+    }
+
+    /// Where `upload_via_http` PUTs objects of the bucket
+    ///
+    /// Path-style, which every GCS bucket name works with, dotted ones included.
+    pub fn endpoint(&self) -> HttpEndpoint {
+        HttpEndpoint {
+            scheme: self.scheme,
+            host: self.host.clone(),
+            path: format!("/{}", self.bucket),
+            region: REGION.to_string(),
+            service: "s3".to_string(),
+            credentials: self.credentials.clone(),
+            unsigned_payload: false,
+            backend: Backend::Gcs,
+            signer: Arc::clone(&self.signer),
+        }
+    }
+
+    /// What is stored under `key`, `None` if nothing is
+    pub async fn head(&self, key: &str) -> Result<Option<StoredObject>, AppError> {
+        let res = self.send(Method::HEAD, key, None).await?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let res = checked(res).await?;
+
+        // A HEAD response has no body, so its length is only in the header
+        let size = res
+            .headers()
+            .get("content-length")
+            .and_then(|value| value.to_str().ok()?.parse().ok())
+            .unwrap_or(0);
+        // GCS versions objects by generation
+        let generation = res
+            .headers()
+            .get("x-goog-generation")
+            .and_then(|value| value.to_str().ok());
+        Ok(Some(
+            StoredObject::new(Some(&metadata(res.headers())), None, size)
+                .with_version_id(generation),
+        ))
+    }
+
+    /// Response with the object at `key`, or just the bytes of `range` if given
+    pub async fn get(&self, key: &str, range: Option<SourceRange>) -> Result<Response, AppError> {
+        checked(self.send(Method::GET, key, range).await?).await
+    }
+
+    pub async fn delete(&self, key: &str) -> Result<(), AppError> {
+        checked(self.send(Method::DELETE, key, None).await?).await?;
+        Ok(())
+    }
+
+    /// Send a signed request without a body for the object at `key`
+    async fn send(
+        &self,
+        method: Method,
+        key: &str,
+        range: Option<SourceRange>,
+    ) -> Result<Response, AppError> {
+        let endpoint = self.endpoint();
+        let canonical_uri = format!("{}/{}", endpoint.path, uri_encode_path(key));
+        let url = format!("{}://{}{}", endpoint.scheme, endpoint.host, canonical_uri);
+        let date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let content_hash = hashing::sha256_hex(b"");
+
+        let mut headers = BTreeMap::new();
+        headers.insert("host".to_string(), endpoint.host.clone());
+        headers.insert("x-amz-content-sha256".to_string(), content_hash.clone());
+        headers.insert("x-amz-date".to_string(), date.clone());
+        if let Some(range) = range {
+            headers.insert("range".to_string(), range_header(range));
+        }
+
+        let mut request = self.client.request(method.clone(), &url);
+        if let Some(signing_key) = &endpoint.credentials {
+            request = request.header(
+                "Authorization",
+                sigv4_authorization(
+                    &method,
+                    &headers,
+                    &canonical_uri,
+                    &content_hash,
+                    &date,
+                    &endpoint,
+                    signing_key,
+                )
+                .await?,
+            );
+        }
+        for (name, value) in headers.iter().filter(|(name, _)| *name != "host") {
+            request = request.header(name, value);
+        }
+        Ok(request.send().await?)
+    }
+}
+
+/// The error of a request GCS didn't accept
+///
+/// GCS throttles with 429 where S3 sends 503 SlowDown, and its error XML carries the
+/// specifics in `<Details>`, its `<Message>` often being generic. It never redirects to
+/// another region: a bucket is reachable from every GCS endpoint.
+pub async fn rejected(res: Response) -> AppError {
+    let status = res.status();
+    if matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+    ) {
+        return AppError::Throttled {
+            backend: Backend::Gcs.name(),
+        };
+    }
+
+    let body = res.text().await.unwrap_or_default();
+    let code = error::xml_error_code(&body);
+    let message = error::xml_tag(&body, "Details")
+        .or_else(|| error::xml_tag(&body, "Message"))
+        .unwrap_or_else(|| status.canonical_reason().unwrap_or("no reason").to_string());
+    AppError::Gcs {
+        status: status.as_u16(),
+        code,
+        message,
+    }
+}
+
+async fn checked(res: Response) -> Result<Response, AppError> {
+    if res.status().is_success() {
+        Ok(res)
+    } else {
+        Err(rejected(res).await)
+    }
+}
+
+/// User metadata of a response, by name without its prefix
+pub fn metadata(headers: &HeaderMap) -> HashMap<String, String> {
+    headers
+        .iter()
+        .filter_map(|(name, value)| {
+            let name = METADATA_PREFIXES
+                .iter()
+                .find_map(|prefix| name.as_str().strip_prefix(prefix))?;
+            Some((name.to_string(), value.to_str().ok()?.to_string()))
+        })
+        .collect()
+}
+
+/// Download the object at `key` from GCS
+pub async fn download(
+    bucket: &GcsBucket,
+    key: &str,
+    output_path: Option<&str>,
+    options: DownloadOptions,
+) -> Result<String, AppError> {
+    let res = bucket.get(key, None).await?;
+    let content_encoding = res
+        .headers()
+        .get("content-encoding")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let metadata = metadata(res.headers());
+
+    let downloaded = Downloaded {
+        data: res.bytes().await?,
+        content_encoding,
+        metadata,
+    };
+    let output_path = write_download(downloaded, key, output_path, options).await?;
+
+    println!("Downloaded from GCS: {} -> {}", key, output_path);
+    Ok(output_path)
+}
@@ -0,0 +1,137 @@
+use std::{path::Path, process::Command};
+
+use crate::error::AppError;
+
+/// Commit and branch of the working directory's repository
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitInfo {
+    pub sha: String,
+    // `None` on a detached HEAD, which is common in CI checkouts
+    pub branch: Option<String>,
+}
+
+impl GitInfo {
+    /// Key prefix `<branch>/<sha8>/`
+    pub fn prefix(&self) -> String {
+        let branch = self.branch.as_deref().unwrap_or("detached");
+        let sha: String = self.sha.chars().take(8).collect();
+
+        format!("{}/{}/", sanitize_branch(branch), sha)
+    }
+}
+
+/// Resolve the commit and branch of the repository `dir` is in by asking `git`; `None`
+/// outside a repository
+pub fn resolve(dir: &Path) -> Option<GitInfo> {
+    let sha = git(dir, &["rev-parse", "HEAD"])?;
+    let branch = git(dir, &["rev-parse", "--abbrev-ref", "HEAD"]).filter(|b| b != "HEAD");
+
+    Some(GitInfo { sha, branch })
+}
+
+/// Key prefix for `--git-prefix`; an error outside a repository unless `optional`
+pub fn key_prefix(optional: bool) -> Result<String, AppError> {
+    match resolve(Path::new(".")) {
+        Some(info) => Ok(info.prefix()),
+        None if optional => {
+            println!("Not in a git repository, uploading without a git prefix");
+            Ok(String::new())
+        }
+        None => Err(AppError::Config(
+            "--git-prefix: not inside a git repository (or git is not installed); \
+             pass --git-prefix-optional to upload without the prefix"
+                .to_string(),
+        )),
+    }
+}
+
+fn git(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let value = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (!value.is_empty()).then_some(value)
+}
+
+/// Branches like `feature/x` become a single key segment
+fn sanitize_branch(branch: &str) -> String {
+    branch
+        .chars()
+        .map(|c| match c {
+            'A'..='Z' | 'a'..='z' | '0'..='9' | '.' | '_' | '-' => c,
+            _ => '-',
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+
+    /// A repository in `dir` with one commit on `branch`, returning the commit's SHA
+    fn repository(dir: &Path, branch: &str) -> Option<String> {
+        let run = |args: &[&str]| {
+            Command::new("git")
+                .args(args)
+                .current_dir(dir)
+                .env("GIT_AUTHOR_NAME", "test")
+                .env("GIT_AUTHOR_EMAIL", "test@example.com")
+                .env("GIT_COMMITTER_NAME", "test")
+                .env("GIT_COMMITTER_EMAIL", "test@example.com")
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+        };
+        run(&["init", "-q", "-b", branch])?;
+        run(&["commit", "-q", "--allow-empty", "-m", "initial"])?;
+        git(dir, &["rev-parse", "HEAD"])
+    }
+
+    #[test]
+    fn resolves_the_commit_and_branch() {
+        let dir = TestDir::new();
+        let Some(sha) = repository(dir.path(), "feature/keys") else {
+            eprintln!("git is not available, skipping");
+            return;
+        };
+
+        let info = resolve(dir.path()).unwrap();
+        assert_eq!(info.sha, sha);
+        assert_eq!(info.branch.as_deref(), Some("feature/keys"));
+        assert_eq!(info.prefix(), format!("feature-keys/{}/", &sha[..8]));
+    }
+
+    #[test]
+    fn a_detached_head_has_no_branch() {
+        let dir = TestDir::new();
+        let Some(sha) = repository(dir.path(), "main") else {
+            eprintln!("git is not available, skipping");
+            return;
+        };
+        git(dir.path(), &["checkout", "-q", "--detach"]);
+
+        let info = resolve(dir.path()).unwrap();
+        assert_eq!(info.branch, None);
+        assert_eq!(info.prefix(), format!("detached/{}/", &sha[..8]));
+    }
+
+    #[test]
+    fn nothing_resolves_outside_a_repository() {
+        let dir = TestDir::new();
+        assert_eq!(resolve(dir.path()), None);
+    }
+
+    #[test]
+    fn branch_names_become_one_key_segment() {
+        assert_eq!(sanitize_branch("release/1.2"), "release-1.2");
+        assert_eq!(sanitize_branch("user@fix #3"), "user-fix--3");
+        assert_eq!(sanitize_branch("main"), "main");
+    }
+}
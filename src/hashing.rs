@@ -0,0 +1,261 @@
+//! Digests of upload bodies, taken in the pass that already moves the bytes
+//!
+//! Where the digest has to be known before a request is sent, the body is hashed as it
+//! is read into memory ([`HashingReader`]): the HTTP path signs `x-amz-content-sha256`
+//! and single PUTs carry the `x-amz-meta-sha256` stamp and `x-amz-checksum-sha256` as
+//! headers. Multipart parts sent through the AWS SDK need no digest up front: the SDK
+//! sends their checksum as an `aws-chunked` trailer, computed as the part streams, and
+//! [`HashingBody`] takes the same digest in that pass to check S3's acknowledgement.
+
+use aws_sdk_s3::primitives::SdkBody;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::Bytes;
+use hyper::{
+    body::{HttpBody, SizeHint},
+    HeaderMap,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{ready, Context, Poll},
+};
+use tokio::io::{AsyncRead, ReadBuf};
+
+use crate::cli::PartChecksum;
+
+/// User metadata entry holding the hex SHA-256 of the object body
+pub const SHA256_METADATA: &str = "sha256";
+
+/// User metadata entry holding the idempotency key of the upload that wrote the object
+pub const IDEMPOTENCY_METADATA: &str = "idempotency-key";
+
+/// Raw SHA-256 digest
+pub type Sha256Digest = [u8; 32];
+
+/// SHA-256 of a buffer
+pub fn sha256(content: &[u8]) -> Sha256Digest {
+    Sha256::digest(content).into()
+}
+
+/// Hex-encoded SHA-256 of a buffer
+pub fn sha256_hex(content: &[u8]) -> String {
+    hex::encode(sha256(content))
+}
+
+/// Base64 MD5 of a buffer, as sent in `Content-MD5`
+pub fn content_md5(content: &[u8]) -> String {
+    STANDARD.encode(md5::compute(content).0)
+}
+
+/// Hex SHA-256 over `fields`, each length-prefixed so different splits can't collide
+pub fn sha256_fields<'a>(fields: impl IntoIterator<Item = &'a [u8]>) -> String {
+    let mut hasher = Sha256::new();
+    for field in fields {
+        hasher.update((field.len() as u64).to_be_bytes());
+        hasher.update(field);
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Reader that hashes bytes as they pass through, so the digest needs no second pass
+pub struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R> HashingReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Digest of every byte read so far
+    pub fn finish(self) -> Sha256Digest {
+        self.hasher.finalize().into()
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for HashingReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let this = &mut *self;
+
+        match Pin::new(&mut this.inner).poll_read(cx, buf) {
+            Poll::Ready(Ok(())) => {
+                this.hasher.update(&buf.filled()[before..]);
+                Poll::Ready(Ok(()))
+            }
+            other => other,
+        }
+    }
+}
+
+/// A request body's checksum, base64 as S3 sends and acknowledges it
+#[derive(Debug, Clone, Default)]
+pub struct SentChecksum(Arc<Mutex<Option<String>>>);
+
+impl SentChecksum {
+    /// Checksum of the last body sent in full, `None` before one was
+    pub fn get(&self) -> Option<String> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+/// Running digest of one [`PartChecksum`] algorithm
+enum Hasher {
+    Sha256(Sha256),
+    Crc32c(u32),
+}
+
+impl Hasher {
+    fn new(algorithm: PartChecksum) -> Self {
+        match algorithm {
+            PartChecksum::Sha256 => Self::Sha256(Sha256::new()),
+            PartChecksum::Crc32c => Self::Crc32c(0),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha256(hasher) => hasher.update(data),
+            Self::Crc32c(crc) => *crc = crc32c::crc32c_append(*crc, data),
+        }
+    }
+
+    fn finish(self) -> String {
+        match self {
+            Self::Sha256(hasher) => STANDARD.encode(hasher.finalize()),
+            Self::Crc32c(crc) => STANDARD.encode(crc.to_be_bytes()),
+        }
+    }
+}
+
+/// SDK request body hashing its bytes as the connection pulls them
+///
+/// Being a stream rather than a buffer, it makes the SDK send the request's checksum as a
+/// trailer instead of hashing the body before sending it, so each byte is hashed in the one
+/// pass that sends it: once by the SDK for the trailer, once here.
+pub struct HashingBody {
+    inner: SdkBody,
+    // `None` once the checksum is recorded
+    hasher: Option<Hasher>,
+    sent: SentChecksum,
+}
+
+impl HashingBody {
+    /// `body` as an SDK body leaving its `algorithm` checksum in `sent` once sent in full
+    ///
+    /// A retried request sends, and hashes, a fresh copy.
+    pub fn sdk_body(body: Bytes, algorithm: PartChecksum, sent: SentChecksum) -> SdkBody {
+        SdkBody::retryable(move || {
+            SdkBody::from_body_0_4(HashingBody {
+                inner: SdkBody::from(body.clone()),
+                hasher: Some(Hasher::new(algorithm)),
+                sent: sent.clone(),
+            })
+        })
+    }
+}
+
+impl HttpBody for HashingBody {
+    type Data = Bytes;
+    type Error = <SdkBody as HttpBody>::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let this = &mut *self;
+        let frame = ready!(Pin::new(&mut this.inner).poll_data(cx));
+        if let (Some(Ok(data)), Some(hasher)) = (&frame, &mut this.hasher) {
+            hasher.update(data);
+        }
+        if frame.is_none() || this.inner.is_end_stream() {
+            if let Some(hasher) = this.hasher.take() {
+                *this.sent.0.lock().unwrap() = Some(hasher.finish());
+            }
+        }
+        Poll::Ready(frame)
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Pin::new(&mut self.inner).poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENT: &[u8] = b"123456789";
+
+    async fn send(algorithm: PartChecksum) -> (Bytes, Option<String>) {
+        let sent = SentChecksum::default();
+        let body = HashingBody::sdk_body(Bytes::from_static(CONTENT), algorithm, sent.clone());
+        assert_eq!(sent.get(), None);
+        (hyper::body::to_bytes(body).await.unwrap(), sent.get())
+    }
+
+    #[tokio::test]
+    async fn the_checksum_is_taken_as_the_body_is_sent() {
+        let (sent, sha256) = send(PartChecksum::Sha256).await;
+        assert_eq!(sent, CONTENT);
+        assert_eq!(sha256, Some(STANDARD.encode(super::sha256(CONTENT))));
+
+        // The CRC-32C check value
+        let (_, crc32c) = send(PartChecksum::Crc32c).await;
+        assert_eq!(crc32c, Some(STANDARD.encode(0xe306_9283_u32.to_be_bytes())));
+    }
+
+    #[tokio::test]
+    async fn a_retried_body_is_hashed_afresh() {
+        let sent = SentChecksum::default();
+        let body = HashingBody::sdk_body(
+            Bytes::from_static(CONTENT),
+            PartChecksum::Sha256,
+            sent.clone(),
+        );
+        let retry = body.try_clone().expect("retryable body");
+
+        hyper::body::to_bytes(body).await.unwrap();
+        hyper::body::to_bytes(retry).await.unwrap();
+        assert_eq!(sent.get(), Some(STANDARD.encode(super::sha256(CONTENT))));
+    }
+
+    #[test]
+    fn content_md5_is_the_base64_digest() {
+        // RFC 1321 test suite values, base64 instead of hex
+        assert_eq!(content_md5(b""), "1B2M2Y8AsgTpgAmY7PhCfg==");
+        assert_eq!(content_md5(b"abc"), "kAFQmDzST7DWlj99KOF/cg==");
+    }
+
+    #[tokio::test]
+    async fn hashing_reader_digests_what_it_reads() {
+        use tokio::io::AsyncReadExt;
+
+        let mut reader = HashingReader::new(CONTENT);
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).await.unwrap();
+        assert_eq!(read, CONTENT);
+        assert_eq!(reader.finish(), super::sha256(CONTENT));
+    }
+}
@@ -0,0 +1,210 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::existing::StoredObject;
+
+/// HEAD results of one run, so repeated checks of an object don't each reach the store
+///
+/// Keys are `<store>/<bucket>/<key>`. A missing object is cached as `None` like any other
+/// result. Entries expire after `ttl`; once `capacity` are held, the oldest makes room.
+pub struct HeadCache {
+    capacity: usize,
+    ttl: Duration,
+    entries: Mutex<Entries>,
+}
+
+#[derive(Default)]
+struct Entries {
+    by_key: HashMap<String, Entry>,
+    // Keys oldest first, each with the insertion that queued it. Inserting a key again,
+    // or dropping it, leaves its earlier record behind; records whose insertion is no
+    // longer the key's are skipped
+    order: VecDeque<(u64, String)>,
+    inserted: u64,
+}
+
+struct Entry {
+    cached_at: Instant,
+    insertion: u64,
+    stored: Option<StoredObject>,
+}
+
+impl Entries {
+    /// Drop the oldest entries until there is room for another, expired ones on the way
+    fn make_room(&mut self, capacity: usize, ttl: Duration) {
+        while let Some((insertion, key)) = self.order.pop_front() {
+            match self.by_key.get(&key).filter(|e| e.insertion == insertion) {
+                Some(entry) if self.by_key.len() < capacity && entry.cached_at.elapsed() < ttl => {
+                    self.order.push_front((insertion, key));
+                    return;
+                }
+                Some(_) => {
+                    self.by_key.remove(&key);
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// Forget the records left behind by keys inserted again or dropped, once they
+    /// outnumber the entries; amortized over the insertions that left them
+    fn compact(&mut self) {
+        if self.order.len() > 2 * self.by_key.len().max(16) {
+            let by_key = &self.by_key;
+            self.order.retain(|(insertion, key)| {
+                by_key.get(key).is_some_and(|e| e.insertion == *insertion)
+            });
+        }
+    }
+}
+
+impl HeadCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity,
+            ttl,
+            entries: Mutex::default(),
+        }
+    }
+
+    /// The cached result for `key`, or `None` if there is none or it expired
+    pub fn get(&self, key: &str) -> Option<Option<StoredObject>> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.by_key.get(key) {
+            Some(entry) if entry.cached_at.elapsed() < self.ttl => Some(entry.stored.clone()),
+            Some(_) => {
+                entries.by_key.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(&self, key: String, stored: Option<StoredObject>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        if !entries.by_key.contains_key(&key) {
+            entries.make_room(self.capacity, self.ttl);
+        }
+        entries.inserted += 1;
+        let insertion = entries.inserted;
+        entries.order.push_back((insertion, key.clone()));
+        entries.by_key.insert(
+            key,
+            Entry {
+                cached_at: Instant::now(),
+                insertion,
+                stored,
+            },
+        );
+        entries.compact();
+    }
+
+    /// Drop the entry for `key`, after a write that may have changed what is stored
+    pub fn invalidate(&self, key: &str) {
+        self.entries.lock().unwrap().by_key.remove(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    fn stored(size: u64) -> Option<StoredObject> {
+        Some(StoredObject {
+            size,
+            ..StoredObject::default()
+        })
+    }
+
+    fn size(cache: &HeadCache, key: &str) -> Option<Option<u64>> {
+        cache
+            .get(key)
+            .map(|stored| stored.map(|stored| stored.size))
+    }
+
+    #[test]
+    fn results_and_missing_objects_are_cached() {
+        let cache = HeadCache::new(4, TTL);
+        cache.insert("aws/bucket/a".to_string(), stored(1));
+        cache.insert("aws/bucket/b".to_string(), None);
+
+        assert_eq!(size(&cache, "aws/bucket/a"), Some(Some(1)));
+        assert_eq!(size(&cache, "aws/bucket/b"), Some(None));
+        assert_eq!(size(&cache, "aws/bucket/c"), None);
+    }
+
+    #[test]
+    fn the_oldest_entry_makes_room() {
+        let cache = HeadCache::new(2, TTL);
+        cache.insert("a".to_string(), stored(1));
+        cache.insert("b".to_string(), stored(2));
+        cache.insert("c".to_string(), stored(3));
+
+        assert_eq!(size(&cache, "a"), None);
+        assert_eq!(size(&cache, "b"), Some(Some(2)));
+        assert_eq!(size(&cache, "c"), Some(Some(3)));
+    }
+
+    #[test]
+    fn inserting_a_key_again_makes_it_the_newest() {
+        let cache = HeadCache::new(2, TTL);
+        cache.insert("a".to_string(), stored(1));
+        cache.insert("b".to_string(), stored(2));
+        cache.insert("a".to_string(), stored(10));
+        cache.insert("c".to_string(), stored(3));
+
+        assert_eq!(size(&cache, "a"), Some(Some(10)));
+        assert_eq!(size(&cache, "b"), None);
+        assert_eq!(size(&cache, "c"), Some(Some(3)));
+    }
+
+    #[test]
+    fn invalidated_keys_leave_room() {
+        let cache = HeadCache::new(2, TTL);
+        cache.insert("a".to_string(), stored(1));
+        cache.insert("b".to_string(), stored(2));
+        cache.invalidate("a");
+        cache.insert("c".to_string(), stored(3));
+
+        assert_eq!(size(&cache, "a"), None);
+        assert_eq!(size(&cache, "b"), Some(Some(2)));
+        assert_eq!(size(&cache, "c"), Some(Some(3)));
+    }
+
+    #[test]
+    fn expired_entries_are_not_returned() {
+        let cache = HeadCache::new(2, Duration::ZERO);
+        cache.insert("a".to_string(), stored(1));
+
+        assert_eq!(size(&cache, "a"), None);
+    }
+
+    #[test]
+    fn a_zero_capacity_caches_nothing() {
+        let cache = HeadCache::new(0, TTL);
+        cache.insert("a".to_string(), stored(1));
+
+        assert_eq!(size(&cache, "a"), None);
+    }
+
+    #[test]
+    fn records_of_reinserted_keys_are_bounded() {
+        let cache = HeadCache::new(4, TTL);
+        for n in 0..10_000 {
+            cache.insert("a".to_string(), stored(n));
+        }
+
+        let entries = cache.entries.lock().unwrap();
+        assert_eq!(entries.by_key.len(), 1);
+        assert!(entries.order.len() <= 33, "{}", entries.order.len());
+    }
+}
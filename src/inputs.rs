@@ -0,0 +1,362 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use crate::error::AppError;
+
+/// `--include-ext`/`--exclude-ext` filter applied to glob matches and `--dir` files
+///
+/// Extensions are lowercase without the leading dot and may span dots (`tar.gz`).
+/// A file matching an excluded extension is dropped even if it is also included.
+#[derive(Debug, Clone, Default)]
+pub struct ExtFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl ExtFilter {
+    /// Whether `path` passes the filter
+    pub fn allows(&self, path: &str) -> bool {
+        let name = Path::new(path)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let has = |ext: &String| {
+            name.len() > ext.len() + 1
+                && name.ends_with(ext.as_str())
+                && name[..name.len() - ext.len()].ends_with('.')
+        };
+
+        !self.exclude.iter().any(has) && (self.include.is_empty() || self.include.iter().any(has))
+    }
+}
+
+/// Files to upload, and how many matched files `ExtFilter` dropped
+pub struct Inputs {
+    pub files: Vec<String>,
+    pub filtered: usize,
+}
+
+/// Combined size of `files`; ones that can't be stat'd count as empty
+pub fn total_size(files: &[String]) -> u64 {
+    files
+        .iter()
+        .filter_map(|file| fs::metadata(file).ok())
+        .map(|meta| meta.len())
+        .sum()
+}
+
+/// Whether an argument should be treated as a glob pattern
+fn is_pattern(arg: &str) -> bool {
+    arg.contains(['*', '?', '['])
+}
+
+/// Expand glob patterns in the positional arguments, followed by the files under `dir`.
+///
+/// Arguments keep their command line order, matches of a single pattern and the
+/// files of `dir` are sorted, and paths seen before are dropped so each file is
+/// uploaded once. Files named literally are never dropped by `filter`.
+pub fn expand_inputs(
+    args: &[String],
+    dir: Option<&Path>,
+    no_glob: bool,
+    allow_empty_glob: bool,
+    filter: &ExtFilter,
+) -> Result<Inputs, AppError> {
+    let mut seen = HashSet::new();
+    let mut files = Vec::new();
+    let mut filtered = 0;
+
+    for arg in args {
+        if no_glob || !is_pattern(arg) {
+            if seen.insert(arg.clone()) {
+                files.push(arg.clone());
+            }
+            continue;
+        }
+
+        let mut matches = Vec::new();
+        for entry in glob::glob(arg)? {
+            let path = entry?;
+            if path.is_file() {
+                matches.push(path.to_string_lossy().into_owned());
+            }
+        }
+
+        if matches.is_empty() && !allow_empty_glob {
+            return Err(AppError::Config(format!(
+                "pattern '{}' matched no files (pass --allow-empty-glob to ignore)",
+                arg
+            )));
+        }
+
+        matches.sort();
+        for path in matches {
+            if seen.insert(path.clone()) {
+                if filter.allows(&path) {
+                    files.push(path);
+                } else {
+                    filtered += 1;
+                }
+            }
+        }
+    }
+
+    if let Some(dir) = dir {
+        // Filtered below, so files a pattern already matched aren't counted twice
+        let found = Mutex::new(Vec::new());
+        walk_parallel(dir, &ExtFilter::default(), &|path| {
+            found.lock().unwrap_or_else(|e| e.into_inner()).push(path)
+        })?;
+        let mut found = found.into_inner().unwrap_or_else(|e| e.into_inner());
+        found.sort();
+        for path in found {
+            if seen.insert(path.clone()) {
+                if filter.allows(&path) {
+                    files.push(path);
+                } else {
+                    filtered += 1;
+                }
+            }
+        }
+    }
+
+    Ok(Inputs { files, filtered })
+}
+
+/// [`expand_inputs`] on the blocking pool
+///
+/// Globbing and walking a large `dir` block for a while, which must not stall the async
+/// workers uploads run on.
+pub async fn expand_inputs_async(
+    args: Vec<String>,
+    dir: Option<PathBuf>,
+    no_glob: bool,
+    allow_empty_glob: bool,
+    filter: ExtFilter,
+) -> Result<Inputs, AppError> {
+    tokio::task::spawn_blocking(move || {
+        expand_inputs(&args, dir.as_deref(), no_glob, allow_empty_glob, &filter)
+    })
+    .await?
+}
+
+/// Call `found` with every file below `dir` that passes `filter`, as soon as it is seen
+///
+/// Directories are read in parallel on the rayon pool, so files arrive in no particular
+/// order; symlinked directories are not followed. The first error stops the walk. Returns
+/// how many files `filter` dropped.
+pub fn walk_parallel(
+    dir: &Path,
+    filter: &ExtFilter,
+    found: &(dyn Fn(String) + Sync),
+) -> Result<usize, AppError> {
+    let walk = Walk {
+        filter,
+        found,
+        filtered: AtomicUsize::new(0),
+        error: Mutex::new(None),
+    };
+    rayon::scope(|scope| walk.visit(scope, dir.to_path_buf()));
+
+    match walk.error.into_inner().unwrap_or_else(|e| e.into_inner()) {
+        Some(err) => Err(err),
+        None => Ok(walk.filtered.into_inner()),
+    }
+}
+
+/// State shared by the directories of one `walk_parallel`
+struct Walk<'a> {
+    filter: &'a ExtFilter,
+    found: &'a (dyn Fn(String) + Sync),
+    filtered: AtomicUsize,
+    error: Mutex<Option<AppError>>,
+}
+
+impl<'a> Walk<'a> {
+    fn visit<'s>(&'s self, scope: &rayon::Scope<'s>, dir: PathBuf) {
+        if let Err(err) = self.read(scope, &dir) {
+            self.error
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .get_or_insert(err);
+        }
+    }
+
+    /// Report the files of `dir` and queue its subdirectories
+    fn read<'s>(&'s self, scope: &rayon::Scope<'s>, dir: &Path) -> Result<(), AppError> {
+        if self
+            .error
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_some()
+        {
+            return Ok(());
+        }
+        let entries = fs::read_dir(dir)
+            .map_err(|e| AppError::Config(format!("--dir {}: {}", dir.display(), e)))?;
+
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                scope.spawn(move |scope| self.visit(scope, path));
+            } else if path.is_file() {
+                let path = path.to_string_lossy().into_owned();
+                if self.filter.allows(&path) {
+                    (self.found)(path);
+                } else {
+                    self.filtered.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+
+    fn filter(include: &[&str], exclude: &[&str]) -> ExtFilter {
+        ExtFilter {
+            include: include.iter().map(|ext| ext.to_string()).collect(),
+            exclude: exclude.iter().map(|ext| ext.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn include_only_keeps_listed_extensions() {
+        let filter = filter(&["jpg", "png"], &[]);
+
+        assert!(filter.allows("photos/cat.jpg"));
+        assert!(filter.allows("photos/CAT.PNG"));
+        assert!(!filter.allows("photos/notes.txt"));
+        assert!(!filter.allows("photos/jpg"));
+    }
+
+    #[test]
+    fn exclude_only_drops_listed_extensions() {
+        let filter = filter(&[], &["tmp", "log"]);
+
+        assert!(filter.allows("data/train.csv"));
+        assert!(filter.allows("data/no-extension"));
+        assert!(!filter.allows("data/run.LOG"));
+        assert!(!filter.allows("data/x.tmp"));
+        // A hidden file is named `log`, it has no extension
+        assert!(filter.allows("data/.log"));
+    }
+
+    #[test]
+    fn exclusion_wins_over_inclusion() {
+        let filter = filter(&["gz"], &["tar.gz"]);
+
+        assert!(filter.allows("dump.sql.gz"));
+        assert!(!filter.allows("backup.tar.gz"));
+        assert!(!filter.allows("backup.tar.bz2"));
+    }
+
+    #[test]
+    fn dir_and_glob_files_are_filtered_and_counted() {
+        let dir = TestDir::new();
+        dir.write("tree/a.jpg", "a");
+        dir.write("tree/nested/b.JPG", "b");
+        dir.write("tree/nested/c.log", "c");
+        dir.write("loose/d.jpg", "d");
+        dir.write("loose/e.tmp", "e");
+        let literal = dir.write("loose/f.log", "f");
+        let root = dir.path().to_string_lossy();
+
+        let inputs = expand_inputs(
+            &[literal.clone(), format!("{}/loose/*", root)],
+            Some(&dir.path().join("tree")),
+            false,
+            false,
+            &filter(&["jpg"], &["log"]),
+        )
+        .unwrap();
+
+        assert_eq!(
+            inputs.files,
+            [
+                literal,
+                format!("{}/loose/d.jpg", root),
+                format!("{}/tree/a.jpg", root),
+                format!("{}/tree/nested/b.JPG", root),
+            ]
+        );
+        // e.tmp from the pattern and c.log from the walk; the literal f.log stays
+        assert_eq!(inputs.filtered, 2);
+    }
+
+    /// `args` expanded with no `--dir` and no filter
+    fn expand(
+        args: &[String],
+        no_glob: bool,
+        allow_empty_glob: bool,
+    ) -> Result<Vec<String>, AppError> {
+        expand_inputs(args, None, no_glob, allow_empty_glob, &ExtFilter::default())
+            .map(|inputs| inputs.files)
+    }
+
+    #[test]
+    fn overlapping_patterns_yield_each_file_once_in_a_stable_order() {
+        let dir = TestDir::new();
+        let b = dir.write("b.parquet", "b");
+        let a = dir.write("a.parquet", "a");
+        let notes = dir.write("notes.txt", "n");
+        let root = dir.path().to_string_lossy();
+
+        let files = expand(
+            &[
+                notes.clone(),
+                format!("{}/*.parquet", root),
+                format!("{}/*", root),
+                a.clone(),
+            ],
+            false,
+            false,
+        )
+        .unwrap();
+
+        // Matches of one pattern sorted, later repeats dropped
+        assert_eq!(files, [notes, a, b]);
+    }
+
+    #[test]
+    fn a_pattern_matching_nothing_fails_unless_allowed() {
+        let dir = TestDir::new();
+        let file = dir.write("a.parquet", "a");
+        let empty = format!("{}/*.csv", dir.path().to_string_lossy());
+
+        let err = expand(&[file.clone(), empty.clone()], false, false).unwrap_err();
+        assert!(
+            matches!(&err, AppError::Config(message) if message.contains(&empty)
+                && message.contains("--allow-empty-glob")),
+            "{:?}",
+            err
+        );
+
+        assert_eq!(expand(&[file.clone(), empty], false, true).unwrap(), [file]);
+    }
+
+    #[test]
+    fn no_glob_takes_wildcards_literally() {
+        let dir = TestDir::new();
+        dir.write("a.parquet", "a");
+        let starred = dir.write("data[1]*.parquet", "literal");
+
+        let args = [starred];
+        assert_eq!(expand(&args, true, false).unwrap(), args);
+        // The same name as a pattern matches neither itself nor a.parquet
+        assert!(expand(&args, false, false).is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use serde_json::Value;
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::error::AppError;
+
+/// Keys `--key-map` assigns, by local path
+pub type KeyMap = HashMap<String, String>;
+
+/// Read a `--key-map` file and report entries whose local files don't exist
+///
+/// A `.json` file holds an object of `"path": "key"` pairs; anything else is read as
+/// `path,key` lines, blank lines and `#` comments skipped. Keys there can't hold a comma,
+/// paths can. A path mapped to two different keys, or to an empty key, fails the run.
+pub fn load(path: &Path) -> Result<KeyMap, AppError> {
+    let contents = fs::read_to_string(path)?;
+    let invalid =
+        |problem: String| AppError::Config(format!("--key-map {}: {}", path.display(), problem));
+
+    let entries: Vec<(String, String)> = if path.extension().is_some_and(|ext| ext == "json") {
+        let map: HashMap<String, Value> =
+            serde_json::from_str(&contents).map_err(|e| invalid(e.to_string()))?;
+        map.into_iter()
+            .map(|(file, key)| match key {
+                Value::String(key) => Ok((file, key)),
+                other => Err(invalid(format!(
+                    "key of {} is not a string: {}",
+                    file, other
+                ))),
+            })
+            .collect::<Result<_, _>>()?
+    } else {
+        let mut entries = Vec::new();
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (file, key) = line.rsplit_once(',').ok_or_else(|| {
+                invalid(format!(
+                    "line {}: expected PATH,KEY, got '{}'",
+                    index + 1,
+                    line
+                ))
+            })?;
+            entries.push((file.trim().to_string(), key.trim().to_string()));
+        }
+        entries
+    };
+
+    let mut map = KeyMap::new();
+    for (file, key) in entries {
+        if key.is_empty() {
+            return Err(invalid(format!("{} is mapped to an empty key", file)));
+        }
+        match map.get(&file) {
+            Some(mapped) if *mapped != key => {
+                return Err(invalid(format!(
+                    "{} is mapped to both {} and {}",
+                    file, mapped, key
+                )));
+            }
+            _ => {
+                map.insert(file, key);
+            }
+        }
+    }
+
+    let mut missing: Vec<_> = map
+        .keys()
+        .filter(|file| !Path::new(file).is_file())
+        .map(String::as_str)
+        .collect();
+    if !missing.is_empty() {
+        missing.sort_unstable();
+        println!(
+            "--key-map names {} file(s) that don't exist: {}",
+            missing.len(),
+            missing.join(", ")
+        );
+    }
+    Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+
+    fn config_error(result: Result<KeyMap, AppError>) -> String {
+        match result {
+            Err(AppError::Config(message)) => message,
+            other => panic!("expected a Config error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn csv_lines_split_on_the_last_comma() {
+        let dir = TestDir::new();
+        let path = dir.write(
+            "keys.csv",
+            "# hand-picked keys\n\nreports/q1,final.pdf , docs/q1.pdf\nnotes.txt,text/n.txt\n",
+        );
+
+        let map = load(Path::new(&path)).unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["reports/q1,final.pdf"], "docs/q1.pdf");
+        assert_eq!(map["notes.txt"], "text/n.txt");
+    }
+
+    #[test]
+    fn json_objects_map_paths_to_keys() {
+        let dir = TestDir::new();
+        let path = dir.write("keys.json", r#"{"notes.txt": "text/n.txt", "a,b": "c"}"#);
+
+        let map = load(Path::new(&path)).unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["a,b"], "c");
+
+        let path = dir.write("bad.json", r#"{"notes.txt": 3}"#);
+        let message = config_error(load(Path::new(&path)));
+        assert!(
+            message.contains("key of notes.txt is not a string: 3"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn conflicting_and_empty_keys_fail() {
+        let dir = TestDir::new();
+        for (contents, problem) in [
+            (
+                "a.txt,one\na.txt,two\n",
+                "a.txt is mapped to both one and two",
+            ),
+            ("a.txt, \n", "a.txt is mapped to an empty key"),
+            ("a.txt,one\njust-a-path\n", "line 2: expected PATH,KEY"),
+        ] {
+            let path = dir.write("keys.csv", contents);
+            let message = config_error(load(Path::new(&path)));
+            assert!(message.contains(problem), "{}", message);
+        }
+
+        // The same key twice is no conflict
+        let path = dir.write("keys.csv", "a.txt,one\na.txt,one\n");
+        assert_eq!(load(Path::new(&path)).unwrap().len(), 1);
+    }
+}
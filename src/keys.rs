@@ -0,0 +1,569 @@
+use std::{
+    collections::BTreeMap,
+    path::{Component, Path},
+};
+
+use crate::{
+    hashing::{self, Sha256Digest},
+    ml::FileCategory,
+};
+
+/// Prefix of the keys written by `--content-addressed`
+pub const BLOB_PREFIX: &str = "blobs";
+
+/// Bytes of UTF-8 S3 takes in an object key
+pub const MAX_KEY_LEN: usize = 1024;
+
+/// Hex digits of a key's SHA-256 that keep truncated keys apart
+const KEY_HASH_LEN: usize = 16;
+
+/// Longest extension, dot included, a shortened key keeps
+const MAX_KEPT_EXTENSION: usize = 16;
+
+/// Key of a content-addressed object: `blobs/<sha256>`, sharded by its leading hex pairs
+///
+/// With `depth` 2 the digest `abcd…` is stored as `blobs/ab/cd/abcd…`; depth is capped at
+/// the 32 byte pairs of the digest.
+pub fn content_addressed_key(digest: &Sha256Digest, depth: usize) -> String {
+    let hex = hex::encode(digest);
+    let mut key = BLOB_PREFIX.to_string();
+    for shard in hex.as_bytes().chunks(2).take(depth) {
+        key.push('/');
+        key.push_str(std::str::from_utf8(shard).unwrap_or_default());
+    }
+    key.push('/');
+    key.push_str(&hex);
+    key
+}
+
+/// Key prefixes of the fallback categories: `--default-category`, `--text-category`
+/// and `--empty-category`
+#[derive(Debug, Clone)]
+pub struct CategoryNames {
+    pub misc: String,
+    pub text: String,
+    // Empty files are classified as text (or misc) unless this is set
+    pub empty: Option<String>,
+}
+
+impl CategoryNames {
+    /// First key segment of a file classified as `category`; `empty` when it has no content
+    pub fn prefix(&self, category: FileCategory, empty: bool) -> &str {
+        match (category, &self.empty) {
+            (FileCategory::Text | FileCategory::Misc, Some(name)) if empty => name,
+            (FileCategory::Text, _) => &self.text,
+            (FileCategory::Misc, _) => &self.misc,
+            _ => category.as_str(),
+        }
+    }
+
+    /// Replace the leading `<category>/` of a classifier's key with its configured prefix
+    ///
+    /// Keys a custom classifier built some other way are left alone.
+    pub fn rename(&self, key: &str, category: FileCategory, empty: bool) -> String {
+        match key
+            .strip_prefix(category.as_str())
+            .and_then(|rest| rest.strip_prefix('/'))
+        {
+            Some(rest) => format!("{}/{}", self.prefix(category, empty), rest),
+            None => key.to_string(),
+        }
+    }
+}
+
+/// Path of a file as used in its key with `--keep-paths`
+///
+/// The path is taken relative to `root` when the file lies under it; `.`, `..` and root
+/// components are dropped and the first `strip` directories removed, but the file name
+/// itself always survives. `None` if the path has no file name at all.
+pub fn key_path(file: &str, root: Option<&Path>, strip: usize) -> Option<String> {
+    let path = Path::new(file);
+    let relative = root
+        .and_then(|root| path.strip_prefix(root).ok())
+        .unwrap_or(path);
+
+    let segments: Vec<String> = relative
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(segment) => Some(segment.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect();
+
+    if segments.is_empty() {
+        return None;
+    }
+
+    let strip = strip.min(segments.len() - 1);
+    Some(segments[strip..].join("/"))
+}
+
+/// Files sharing a file name, by that name, which keys without `--keep-paths` map to one key
+pub fn shared_names(files: &[String]) -> Vec<(String, Vec<&str>)> {
+    let mut by_name: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+    for file in files {
+        if let Some(name) = Path::new(file).file_name() {
+            by_name
+                .entry(name.to_string_lossy().into_owned())
+                .or_default()
+                .push(file);
+        }
+    }
+    by_name
+        .into_iter()
+        .filter(|(_, files)| files.len() > 1)
+        .collect()
+}
+
+/// Slugify every segment of an object key, keeping the file extension intact
+pub fn slugify_key(key: &str) -> String {
+    let segments: Vec<&str> = key.split('/').collect();
+    let last = segments.len() - 1;
+
+    segments
+        .iter()
+        .enumerate()
+        .map(|(i, segment)| {
+            if i == last {
+                slugify_file_name(segment)
+            } else {
+                slugify_segment(segment)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Lowercase the extension of a key's file name (`IMG_01.JPG` -> `IMG_01.jpg`)
+///
+/// Only the last extension counts, and a file name without one (or a hidden file like
+/// `.Env`) stays as it is; folders and the stem keep their case.
+pub fn normalize_extension(key: &str) -> String {
+    let (dir, name) = match key.rsplit_once('/') {
+        Some((dir, name)) => (Some(dir), name),
+        None => (None, key),
+    };
+    let name = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() && !ext.is_empty() => {
+            format!("{}.{}", stem, ext.to_ascii_lowercase())
+        }
+        _ => name.to_string(),
+    };
+    match dir {
+        Some(dir) => format!("{}/{}", dir, name),
+        None => name,
+    }
+}
+
+/// Insert a version token before the extension of a key's file name
+/// (`models/model.onnx` -> `models/model.<token>.onnx`)
+///
+/// Only the last extension moves behind the token, so `data.tar.gz` becomes
+/// `data.tar.<token>.gz`; a file name without one gets the token appended.
+pub fn with_version(key: &str, token: &str) -> String {
+    before_extension(key, &format!(".{}", token))
+}
+
+/// Number a key the way file managers number copies (`text/notes.txt` -> `text/notes-2.txt`)
+pub fn with_number(key: &str, number: u64) -> String {
+    before_extension(key, &format!("-{}", number))
+}
+
+/// `key` cut to at most `max` bytes, ending in `-`, 16 hex digits of its SHA-256 and its
+/// extension (`text/a/very/long/pa-3f2a9c0d1e4b5a6f.txt`)
+///
+/// The cut never splits a character, and keys that only differ past it still differ in
+/// the hash.
+pub fn truncate_key(key: &str, max: usize) -> String {
+    let hash = hashing::sha256_hex(key.as_bytes());
+    let tail = format!("-{}{}", &hash[..KEY_HASH_LEN], kept_extension(key));
+    let mut cut = max.saturating_sub(tail.len()).min(key.len());
+    while !key.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    format!("{}{}", &key[..cut], tail)
+}
+
+/// `key` replaced by its SHA-256 and extension, under its first segment
+/// (`images/<sha256>.png`)
+pub fn hashed_key(key: &str) -> String {
+    let name = format!(
+        "{}{}",
+        hashing::sha256_hex(key.as_bytes()),
+        kept_extension(key)
+    );
+    match key.split_once('/') {
+        Some((first, _)) => format!("{}/{}", first, name),
+        None => name,
+    }
+}
+
+/// Extension of a key's file name, dot included, unless it is too long to keep
+fn kept_extension(key: &str) -> &str {
+    let name = key.rsplit('/').next().unwrap_or(key);
+    match name.rfind('.') {
+        Some(dot) if dot > 0 && name.len() - dot <= MAX_KEPT_EXTENSION => &name[dot..],
+        _ => "",
+    }
+}
+
+/// Insert `suffix` before the last extension of a key's file name, or append it
+fn before_extension(key: &str, suffix: &str) -> String {
+    let (dir, name) = match key.rsplit_once('/') {
+        Some((dir, name)) => (Some(dir), name),
+        None => (None, key),
+    };
+    let name = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() && !ext.is_empty() => {
+            format!("{}{}.{}", stem, suffix, ext)
+        }
+        _ => format!("{}{}", name, suffix),
+    };
+    match dir {
+        Some(dir) => format!("{}/{}", dir, name),
+        None => name,
+    }
+}
+
+/// Slugify the stem of a file name and keep its extension as-is
+fn slugify_file_name(name: &str) -> String {
+    match name.rsplit_once('.') {
+        // A leading dot is a hidden file, not an extension
+        Some((stem, ext)) if !stem.is_empty() && !ext.is_empty() => {
+            let ext: String = ext.chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+            if ext.is_empty() {
+                slugify_segment(name)
+            } else {
+                format!("{}.{}", slugify_segment(stem), ext)
+            }
+        }
+        _ => slugify_segment(name),
+    }
+}
+
+/// Lowercase a path segment, turn whitespace into `-` and drop anything but `[a-z0-9._-]`
+pub fn slugify_segment(segment: &str) -> String {
+    let mut slug = String::with_capacity(segment.len());
+
+    for c in segment.chars().flat_map(char::to_lowercase) {
+        let c = match c {
+            'a'..='z' | '0'..='9' | '.' | '_' | '-' => c,
+            c if c.is_whitespace() => '-',
+            _ => continue,
+        };
+
+        // Collapse runs of separators and dashes next to dots
+        if c == '-' && (slug.ends_with('-') || slug.ends_with('.')) {
+            continue;
+        }
+        if c == '.' && slug.ends_with('-') {
+            slug.pop();
+        }
+        slug.push(c);
+    }
+
+    let slug = slug.trim_matches(['-', '.']);
+    if slug.is_empty() {
+        "file".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_names_lists_files_of_the_same_name_in_other_directories() {
+        let files = [
+            "data/a/report.txt",
+            "data/b/report.txt",
+            "data/readme.txt",
+            "report.txt.bak",
+        ]
+        .map(String::from);
+        assert_eq!(
+            shared_names(&files),
+            [(
+                "report.txt".to_string(),
+                vec!["data/a/report.txt", "data/b/report.txt"]
+            )]
+        );
+        assert!(shared_names(&files[2..]).is_empty());
+    }
+
+    #[test]
+    fn slugify_lowercases_and_dashes_whitespace() {
+        assert_eq!(
+            slugify_key("Text/My Report FINAL.txt"),
+            "text/my-report-final.txt"
+        );
+        assert_eq!(
+            slugify_segment("  Tabs\tand\nnewlines  "),
+            "tabs-and-newlines"
+        );
+    }
+
+    #[test]
+    fn slugify_keeps_the_extension() {
+        assert_eq!(slugify_key("images/Photo (1).JPEG"), "images/photo-1.JPEG");
+        assert_eq!(slugify_key("data/archive.tar.gz"), "data/archive.tar.gz");
+        // Only alphanumerics survive in an extension
+        assert_eq!(slugify_key("misc/file.t x!t"), "misc/file.txt");
+    }
+
+    #[test]
+    fn slugify_handles_tricky_names() {
+        // Hidden files have no extension to keep
+        assert_eq!(slugify_key("text/.Env"), "text/env");
+        // Runs of separators collapse, and dashes next to dots go
+        assert_eq!(slugify_segment("a -- b - .c"), "a-b.c");
+        assert_eq!(slugify_key("text/Crème Brûlée.md"), "text/crme-brle.md");
+        assert_eq!(slugify_key("text/notes.!!!"), "text/notes");
+    }
+
+    #[test]
+    fn slugify_never_leaves_an_empty_segment() {
+        assert_eq!(slugify_segment("???"), "file");
+        assert_eq!(slugify_key("日本/語.txt"), "file/file.txt");
+        assert_eq!(slugify_key("--/..."), "file/file");
+    }
+
+    #[test]
+    fn slugify_is_idempotent() {
+        for key in [
+            "Text/My Report.txt",
+            "images/Photo (1).JPEG",
+            "a/.Env",
+            "x/旅行 2024.png",
+        ] {
+            let once = slugify_key(key);
+            assert_eq!(slugify_key(&once), once);
+        }
+    }
+
+    #[test]
+    fn normalize_extension_lowercases_mixed_case_extensions() {
+        assert_eq!(
+            normalize_extension("images/IMG_01.JPG"),
+            "images/IMG_01.jpg"
+        );
+        assert_eq!(
+            normalize_extension("images/Photo.Jpeg"),
+            "images/Photo.jpeg"
+        );
+        assert_eq!(normalize_extension("text/README.TXT"), "text/README.txt");
+        assert_eq!(normalize_extension("notes.md"), "notes.md");
+    }
+
+    #[test]
+    fn normalize_extension_changes_only_the_last_extension() {
+        assert_eq!(
+            normalize_extension("archives/Data.TAR.GZ"),
+            "archives/Data.TAR.gz"
+        );
+        // Folders with dots and case keep theirs
+        assert_eq!(
+            normalize_extension("misc/V1.Release/Build.LOG"),
+            "misc/V1.Release/Build.log"
+        );
+    }
+
+    #[test]
+    fn normalize_extension_leaves_names_without_one() {
+        assert_eq!(normalize_extension("misc/Makefile"), "misc/Makefile");
+        assert_eq!(normalize_extension("misc/.Env"), "misc/.Env");
+        assert_eq!(normalize_extension("misc/Trailing."), "misc/Trailing.");
+        assert_eq!(
+            normalize_extension("misc/V1.Release/LICENSE"),
+            "misc/V1.Release/LICENSE"
+        );
+    }
+
+    #[test]
+    fn key_path_is_relative_to_the_root() {
+        let root = Path::new("data");
+
+        assert_eq!(
+            key_path("data/train/cats/001.jpg", Some(root), 0).as_deref(),
+            Some("train/cats/001.jpg")
+        );
+        // Files outside the root keep their own path, minus `.` and `..`
+        assert_eq!(
+            key_path("./other/../notes.txt", Some(root), 0).as_deref(),
+            Some("other/notes.txt")
+        );
+        assert_eq!(
+            key_path("/abs/notes.txt", None, 0).as_deref(),
+            Some("abs/notes.txt")
+        );
+        assert_eq!(key_path("..", None, 0), None);
+    }
+
+    #[test]
+    fn strip_components_drops_leading_directories() {
+        let root = Path::new("data");
+        let file = "data/train/cats/001.jpg";
+
+        assert_eq!(
+            key_path(file, Some(root), 1).as_deref(),
+            Some("cats/001.jpg")
+        );
+        assert_eq!(key_path(file, Some(root), 2).as_deref(), Some("001.jpg"));
+        // The file name always survives
+        assert_eq!(key_path(file, Some(root), 9).as_deref(), Some("001.jpg"));
+    }
+
+    #[test]
+    fn content_addressed_keys_shard_by_hex_pairs() {
+        let digest = crate::hashing::sha256(b"plain words");
+        let hex = hex::encode(digest);
+
+        assert_eq!(content_addressed_key(&digest, 0), format!("blobs/{}", hex));
+        assert_eq!(
+            content_addressed_key(&digest, 2),
+            format!("blobs/{}/{}/{}", &hex[..2], &hex[2..4], hex)
+        );
+        // There are only 32 pairs to shard by
+        assert_eq!(
+            content_addressed_key(&digest, 40),
+            content_addressed_key(&digest, 32)
+        );
+        assert_eq!(content_addressed_key(&digest, 32).split('/').count(), 34);
+    }
+
+    fn names(empty: Option<&str>) -> CategoryNames {
+        CategoryNames {
+            misc: "other".to_string(),
+            text: "notes".to_string(),
+            empty: empty.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn configured_names_replace_misc_and_text() {
+        let names = names(None);
+
+        assert_eq!(names.prefix(FileCategory::Misc, false), "other");
+        assert_eq!(names.prefix(FileCategory::Text, false), "notes");
+        assert_eq!(names.prefix(FileCategory::Images, false), "images");
+        // Without an empty name, empty files keep their category's
+        assert_eq!(names.prefix(FileCategory::Text, true), "notes");
+    }
+
+    #[test]
+    fn empty_files_get_their_own_name_when_set() {
+        let names = names(Some("empty"));
+
+        assert_eq!(names.prefix(FileCategory::Text, true), "empty");
+        assert_eq!(names.prefix(FileCategory::Misc, true), "empty");
+        assert_eq!(names.prefix(FileCategory::Text, false), "notes");
+    }
+
+    #[test]
+    fn rename_swaps_only_a_leading_category() {
+        let names = names(None);
+
+        assert_eq!(
+            names.rename("misc/data.bin", FileCategory::Misc, false),
+            "other/data.bin"
+        );
+        assert_eq!(
+            names.rename("text/a/misc/notes.txt", FileCategory::Text, false),
+            "notes/a/misc/notes.txt"
+        );
+        assert_eq!(
+            names.rename("custom/data.bin", FileCategory::Misc, false),
+            "custom/data.bin"
+        );
+    }
+
+    #[test]
+    fn version_goes_before_the_last_extension() {
+        assert_eq!(
+            with_version("models/model.onnx", "3"),
+            "models/model.3.onnx"
+        );
+        assert_eq!(with_version("data.tar.gz", "3"), "data.tar.3.gz");
+        assert_eq!(with_version("a.b/README", "3"), "a.b/README.3");
+        assert_eq!(with_version("text/.env", "3"), "text/.env.3");
+        assert_eq!(with_version("text/notes.", "3"), "text/notes..3");
+    }
+
+    #[test]
+    fn numbers_go_before_the_last_extension() {
+        assert_eq!(with_number("text/notes.txt", 2), "text/notes-2.txt");
+        assert_eq!(with_number("data.tar.gz", 1), "data.tar-1.gz");
+        assert_eq!(with_number("misc/README", 1), "misc/README-1");
+    }
+
+    /// A `len`-byte key under `text/` ending in `.txt`
+    fn key_of_len(len: usize) -> String {
+        format!("text/{}.txt", "a".repeat(len - "text/.txt".len()))
+    }
+
+    #[test]
+    fn truncated_keys_fill_the_limit_and_keep_their_extension() {
+        let key = key_of_len(1025);
+        let truncated = truncate_key(&key, MAX_KEY_LEN);
+
+        assert_eq!(truncated.len(), MAX_KEY_LEN);
+        assert!(truncated.starts_with("text/aaa"), "{}", truncated);
+        let hash = &hashing::sha256_hex(key.as_bytes())[..KEY_HASH_LEN];
+        assert!(
+            truncated.ends_with(&format!("-{}.txt", hash)),
+            "{}",
+            truncated
+        );
+        assert_eq!(
+            truncate_key(&key_of_len(4000), MAX_KEY_LEN).len(),
+            MAX_KEY_LEN
+        );
+    }
+
+    #[test]
+    fn keys_that_differ_past_the_cut_still_differ() {
+        let a = format!("{}-a.txt", key_of_len(1100));
+        let b = format!("{}-b.txt", key_of_len(1100));
+        let (a, b) = (truncate_key(&a, MAX_KEY_LEN), truncate_key(&b, MAX_KEY_LEN));
+        assert_ne!(a, b);
+        assert_eq!(a[..MAX_KEY_LEN - 21], b[..MAX_KEY_LEN - 21]);
+    }
+
+    #[test]
+    fn truncation_never_splits_a_character() {
+        // 3-byte characters put the cut inside one unless it backs off
+        let key = format!("text/{}.txt", "語".repeat(400));
+        let truncated = truncate_key(&key, MAX_KEY_LEN);
+        assert!(truncated.len() <= MAX_KEY_LEN);
+        assert!(truncated.len() > MAX_KEY_LEN - 3);
+        assert!(truncated.contains('語'));
+    }
+
+    #[test]
+    fn long_extensions_are_dropped_instead_of_kept() {
+        let key = format!("{}.{}", key_of_len(1100), "x".repeat(MAX_KEPT_EXTENSION));
+        let truncated = truncate_key(&key, MAX_KEY_LEN);
+        assert_eq!(truncated.len(), MAX_KEY_LEN);
+        assert!(!truncated.ends_with(&"x".repeat(MAX_KEPT_EXTENSION)));
+        assert_eq!(kept_extension("text/.env"), "");
+    }
+
+    #[test]
+    fn hashed_keys_keep_the_first_segment_and_extension() {
+        let key = key_of_len(1025);
+        let hashed = hashed_key(&key);
+        assert_eq!(
+            hashed,
+            format!("text/{}.txt", hashing::sha256_hex(key.as_bytes()))
+        );
+        assert_eq!(hashed.len(), "text/".len() + 64 + ".txt".len());
+        assert_eq!(
+            hashed_key("notes.txt"),
+            format!("{}.txt", hashing::sha256_hex(b"notes.txt"))
+        );
+        // The hash is of the whole key, so equal names in other directories differ
+        assert_ne!(hashed_key("text/a/x.txt"), hashed_key("text/b/x.txt"));
+    }
+}
@@ -0,0 +1,85 @@
+use serde_json::json;
+
+use crate::{
+    cli::{format_size, ListArgs},
+    config, create_aws_client,
+    error::AppError,
+    listing::{self, Listing},
+    tls::TlsConfig,
+};
+
+/// Print the objects under a prefix of the AWS bucket
+pub async fn run(args: ListArgs) -> Result<(), AppError> {
+    config::require([&config::AWS_BUCKET], args.allow_defaults)?;
+    let tls = TlsConfig::load(&args.tls)?;
+    let client = create_aws_client(&tls, args.no_sign_request).await?;
+    let bucket = config::AWS_BUCKET.get();
+
+    let prefix = args.prefix.as_deref().unwrap_or("");
+    let delimiter = (!args.recursive).then_some("/");
+    let listing = listing::list_objects(&client, &bucket, prefix, delimiter).await?;
+
+    if args.json {
+        print_json(&listing);
+    } else {
+        print_table(&listing, args.human_readable);
+    }
+
+    Ok(())
+}
+
+fn print_json(listing: &Listing) {
+    let objects: Vec<_> = listing
+        .objects
+        .iter()
+        .map(|object| {
+            json!({
+                "key": object.key,
+                "size": object.size,
+                "last_modified": object.last_modified.map(|t| t.to_rfc3339()),
+            })
+        })
+        .collect();
+
+    println!(
+        "{}",
+        json!({
+            "prefixes": listing.prefixes,
+            "objects": objects,
+        })
+    );
+}
+
+fn print_table(listing: &Listing, human_readable: bool) {
+    for prefix in &listing.prefixes {
+        println!("{:>19} {:>12} {}", "", "PRE", prefix);
+    }
+
+    let mut total = 0;
+    for object in &listing.objects {
+        let modified = object
+            .last_modified
+            .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+        let size = if human_readable {
+            format_size(object.size)
+        } else {
+            object.size.to_string()
+        };
+
+        println!("{:>19} {:>12} {}", modified, size, object.key);
+        total += object.size;
+    }
+
+    let total = if human_readable {
+        format_size(total)
+    } else {
+        format!("{} bytes", total)
+    };
+    println!(
+        "{} object(s), {} prefix(es), {}",
+        listing.objects.len(),
+        listing.prefixes.len(),
+        total
+    );
+}
@@ -0,0 +1,70 @@
+use aws_sdk_s3::Client;
+use chrono::{DateTime, Utc};
+
+use crate::error::AppError;
+
+/// An object returned by a listing
+#[derive(Debug, Clone)]
+pub struct ObjectEntry {
+    pub key: String,
+    pub size: u64,
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+/// Objects and common prefixes ("folders") under a prefix
+#[derive(Debug, Clone, Default)]
+pub struct Listing {
+    pub objects: Vec<ObjectEntry>,
+    pub prefixes: Vec<String>,
+}
+
+/// List everything under `prefix`, following continuation tokens past 1000 keys
+///
+/// With a `delimiter`, keys below the next delimiter are rolled up into `prefixes`.
+pub async fn list_objects(
+    client: &Client,
+    bucket: &str,
+    prefix: &str,
+    delimiter: Option<&str>,
+) -> Result<Listing, AppError> {
+    let mut listing = Listing::default();
+    let mut continuation_token = None;
+
+    loop {
+        let page = client
+            .list_objects_v2()
+            .bucket(bucket)
+            .prefix(prefix)
+            .set_delimiter(delimiter.map(str::to_string))
+            .set_continuation_token(continuation_token.take())
+            .send()
+            .await?;
+
+        for object in page.contents() {
+            let Some(key) = object.key() else {
+                continue;
+            };
+
+            listing.objects.push(ObjectEntry {
+                key: key.to_string(),
+                size: object.size().unwrap_or(0).max(0) as u64,
+                last_modified: object
+                    .last_modified()
+                    .and_then(|t| DateTime::from_timestamp(t.secs(), t.subsec_nanos())),
+            });
+        }
+
+        listing.prefixes.extend(
+            page.common_prefixes()
+                .iter()
+                .filter_map(|p| p.prefix().map(str::to_string)),
+        );
+
+        match page.next_continuation_token() {
+            Some(token) if page.is_truncated().unwrap_or(false) => {
+                continuation_token = Some(token.to_string());
+            }
+            _ => return Ok(listing),
+        }
+    }
+}
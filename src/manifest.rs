@@ -0,0 +1,155 @@
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, fs::File, io, path::Path};
+
+use crate::error::AppError;
+
+/// Outcome of checking the files of a run against a checksum manifest
+#[derive(Debug, Default)]
+pub struct Verification {
+    pub verified: usize,
+    // `(file, expected hex SHA-256, actual)`
+    pub mismatched: Vec<(String, String, String)>,
+    // Files of the run the manifest has no entry for
+    pub unlisted: usize,
+}
+
+/// Parse a `sha256sum`-style manifest: `<hex sha256>  <path>` per line
+///
+/// The binary-mode marker (`<hex> *<path>`) and blank or `#` comment lines are accepted.
+pub fn parse(text: &str) -> Result<HashMap<String, String>, AppError> {
+    let mut entries = HashMap::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid =
+            || AppError::Config(format!("checksum manifest line {}: '{}'", number + 1, line));
+        let (digest, path) = line.split_once(' ').ok_or_else(invalid)?;
+        let path = path.strip_prefix([' ', '*']).unwrap_or(path);
+        if digest.len() != 64 || !digest.bytes().all(|b| b.is_ascii_hexdigit()) || path.is_empty() {
+            return Err(invalid());
+        }
+
+        entries.insert(normalize(path), digest.to_ascii_lowercase());
+    }
+
+    Ok(entries)
+}
+
+/// Hash every file of the run listed in `manifest` in parallel and compare
+pub async fn verify(manifest: &Path, files: &[String]) -> Result<Verification, AppError> {
+    let entries = parse(&tokio::fs::read_to_string(manifest).await?)?;
+    let listed: Vec<(String, String)> = files
+        .iter()
+        .filter_map(|file| Some((file.clone(), entries.get(&normalize(file))?.clone())))
+        .collect();
+    let unlisted = files.len() - listed.len();
+
+    // Hashing is CPU-bound; keep it off the async workers
+    let hashed = tokio::task::spawn_blocking(move || {
+        listed
+            .into_par_iter()
+            .map(|(file, expected)| {
+                let actual = sha256_file(Path::new(&file))?;
+                Ok((file, expected, actual))
+            })
+            .collect::<Result<Vec<_>, io::Error>>()
+    })
+    .await??;
+
+    let verified = hashed.len();
+    let mismatched = hashed
+        .into_iter()
+        .filter(|(_, expected, actual)| expected != actual)
+        .collect();
+
+    Ok(Verification {
+        verified,
+        mismatched,
+        unlisted,
+    })
+}
+
+/// Hex SHA-256 of a file, streamed so large files aren't held in memory
+fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// `./data/a.bin` and `data/a.bin` name the same entry
+fn normalize(path: &str) -> String {
+    path.strip_prefix("./").unwrap_or(path).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+
+    /// Hex SHA-256 of "hello\n"
+    const HELLO: &str = "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03";
+
+    #[test]
+    fn both_sha256sum_modes_comments_and_blank_lines_parse() {
+        let text = format!(
+            "# made by sha256sum\n\n{}  ./data/a.bin\n{} *b.bin\n",
+            HELLO,
+            HELLO.to_ascii_uppercase()
+        );
+
+        let entries = parse(&text).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries["data/a.bin"], HELLO);
+        assert_eq!(entries["b.bin"], HELLO);
+    }
+
+    #[test]
+    fn malformed_lines_are_reported_with_their_number() {
+        for line in [
+            "no-separator",
+            &format!("{}  ", HELLO),
+            &format!("{}  a.bin", &HELLO[1..]),
+            &format!("{}  a.bin", HELLO.replace('5', "g")),
+        ] {
+            let text = format!("{}  ok.bin\n{}\n", HELLO, line);
+            match parse(&text) {
+                Err(AppError::Config(message)) => assert_eq!(
+                    message,
+                    format!("checksum manifest line 2: '{}'", line.trim_end())
+                ),
+                other => panic!("{:?} parsed as {:?}", line, other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn mismatched_and_unlisted_files_are_counted_apart() {
+        let dir = TestDir::new();
+        let good = dir.write("good.txt", "hello\n");
+        let changed = dir.write("changed.txt", "hello?\n");
+        let unlisted = dir.write("unlisted.txt", "hello\n");
+        let manifest = dir.write(
+            "SHA256SUMS",
+            format!("{}  {}\n{}  {}\n", HELLO, good, HELLO, changed),
+        );
+
+        let verification = verify(Path::new(&manifest), &[good, changed.clone(), unlisted])
+            .await
+            .unwrap();
+        assert_eq!(verification.verified, 2);
+        assert_eq!(verification.unlisted, 1);
+        assert_eq!(
+            verification.mismatched,
+            [(
+                changed,
+                HELLO.to_string(),
+                hex::encode(crate::hashing::sha256(b"hello?\n"))
+            )]
+        );
+    }
+}
@@ -1,10 +1,107 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, Read},
+    str::FromStr,
+};
+
+/// Category a file is classified into; also the first segment of its key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileCategory {
+    Documents,
+    Images,
+    Archives,
+    Text,
+    Misc,
+    // Only with entropy classification: unmatched binaries split by byte entropy
+    CompressedOrEncrypted,
+    BinaryData,
+}
+
+impl FileCategory {
+    /// Name used as the key prefix
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FileCategory::Documents => "documents",
+            FileCategory::Images => "images",
+            FileCategory::Archives => "archives",
+            FileCategory::Text => "text",
+            FileCategory::Misc => "misc",
+            FileCategory::CompressedOrEncrypted => "compressed-or-encrypted",
+            FileCategory::BinaryData => "binary-data",
+        }
+    }
+}
+
+impl FromStr for FileCategory {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "documents" => Ok(FileCategory::Documents),
+            "images" => Ok(FileCategory::Images),
+            "archives" => Ok(FileCategory::Archives),
+            "text" => Ok(FileCategory::Text),
+            "misc" => Ok(FileCategory::Misc),
+            "compressed-or-encrypted" => Ok(FileCategory::CompressedOrEncrypted),
+            "binary-data" => Ok(FileCategory::BinaryData),
+            _ => Err(format!("unknown category '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for FileCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Output of the predictor for a single file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prediction {
+    pub category: FileCategory,
+    // 0.0..=1.0, how sure the heuristic is
+    pub confidence: f32,
+    pub mime: &'static str,
+}
+
+/// Classification of an input file, including the key it is stored under
+#[derive(Debug, Clone, PartialEq)]
+pub struct Classification {
+    pub key: String,
+    pub category: FileCategory,
+    pub confidence: f32,
+    pub mime: String,
+}
+
+/// Confidence reported for a magic number match
+const SIGNATURE_CONFIDENCE: f32 = 0.99;
+
+/// Leading bytes of a file whose printable ratio decides whether it is text
+const TEXT_SAMPLE: usize = 1024;
+
+/// Leading bytes of a file whose entropy is measured
+const ENTROPY_SAMPLE: usize = 64 * 1024;
+
+/// Default entropy (bits per byte) at or above which a binary counts as compressed or encrypted
+pub const DEFAULT_HIGH_ENTROPY: f32 = 7.5;
+
+/// Default entropy (bits per byte) at or below which a binary counts as structured data
+pub const DEFAULT_LOW_ENTROPY: f32 = 4.0;
 
 /// A simple ML model for predicting file types based on content
 pub struct FileTypePredictor {
     // In a real application, this would be a trained ML model
     // For this example, we'll use a simple heuristic approach
-    signatures: HashMap<Vec<u8>, String>,
+    signatures: HashMap<Vec<u8>, (FileCategory, &'static str)>,
+    // `(low, high)` entropy thresholds splitting unmatched binaries; `None` keeps them `misc`
+    entropy: Option<(f32, f32)>,
+}
+
+impl Default for FileTypePredictor {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl FileTypePredictor {
@@ -13,55 +110,329 @@ impl FileTypePredictor {
 
         // Add file signatures for common file types
         // PDF signature
-        signatures.insert(vec![0x25, 0x50, 0x44, 0x46], "documents".to_string());
+        signatures.insert(
+            vec![0x25, 0x50, 0x44, 0x46],
+            (FileCategory::Documents, "application/pdf"),
+        );
 
         // JPEG signature
-        signatures.insert(vec![0xFF, 0xD8, 0xFF], "images".to_string());
+        signatures.insert(vec![0xFF, 0xD8, 0xFF], (FileCategory::Images, "image/jpeg"));
 
         // PNG signature
-        signatures.insert(vec![0x89, 0x50, 0x4E, 0x47], "images".to_string());
+        signatures.insert(
+            vec![0x89, 0x50, 0x4E, 0x47],
+            (FileCategory::Images, "image/png"),
+        );
 
         // ZIP signature
-        signatures.insert(vec![0x50, 0x4B, 0x03, 0x04], "archives".to_string());
+        signatures.insert(
+            vec![0x50, 0x4B, 0x03, 0x04],
+            (FileCategory::Archives, "application/zip"),
+        );
 
         // GIF signature
-        signatures.insert(vec![0x47, 0x49, 0x46, 0x38], "images".to_string());
+        signatures.insert(
+            vec![0x47, 0x49, 0x46, 0x38],
+            (FileCategory::Images, "image/gif"),
+        );
+
+        Self {
+            signatures,
+            entropy: None,
+        }
+    }
+
+    /// Split binaries no signature matched by the entropy of their first 64 KiB
+    ///
+    /// At or above `high` bits per byte they are classified as `compressed-or-encrypted`,
+    /// at or below `low` as `binary-data`; anything in between stays `misc`.
+    pub fn with_entropy_thresholds(mut self, low: f32, high: f32) -> Self {
+        self.entropy = Some((low, high));
+        self
+    }
+
+    /// Leading bytes a prediction depends on; the rest of a file never changes it
+    ///
+    /// Covers every signature and the text sample, plus the entropy sample when entropy
+    /// classification is on.
+    pub fn sample_len(&self) -> usize {
+        let longest_signature = self.signatures.keys().map(Vec::len).max().unwrap_or(0);
+        let sample = match self.entropy {
+            Some(_) => ENTROPY_SAMPLE,
+            None => TEXT_SAMPLE,
+        };
+        sample.max(longest_signature)
+    }
 
-        Self { signatures }
+    /// Predict from the first `sample_len` bytes of `reader`, without reading further
+    ///
+    /// Gives the same prediction as `predict` on the whole content, so large files can be
+    /// classified with one small read.
+    pub fn predict_from_reader(&self, reader: impl Read) -> io::Result<Prediction> {
+        let mut sample = Vec::with_capacity(self.sample_len());
+        reader
+            .take(self.sample_len() as u64)
+            .read_to_end(&mut sample)?;
+        Ok(self.predict(&sample))
     }
 
     /// Predict file type based on content
-    pub fn predict(&self, content: &[u8]) -> String {
-        // Check for file signatures
-        for (signature, file_type) in &self.signatures {
-            if content.len() >= signature.len() && content[0..signature.len()] == signature[..] {
-                return file_type.clone();
-            }
+    pub fn predict(&self, content: &[u8]) -> Prediction {
+        if let Some(prediction) = self
+            .match_signature(content)
+            .or_else(|| self.predict_heuristic(content))
+        {
+            return prediction;
         }
 
+        // Default category for unknown types
+        Prediction {
+            category: FileCategory::Misc,
+            confidence: 1.0 - self.printable_ratio(content),
+            mime: "application/octet-stream",
+        }
+    }
+
+    /// The type whose magic number `content` starts with, if any
+    pub fn match_signature(&self, content: &[u8]) -> Option<Prediction> {
+        self.signatures
+            .iter()
+            .find(|(signature, _)| content.starts_with(signature))
+            .map(|(_, (category, mime))| Prediction {
+                category: *category,
+                confidence: SIGNATURE_CONFIDENCE,
+                mime,
+            })
+    }
+
+    /// `text` for mostly printable content, else the entropy split if it is enabled;
+    /// `None` when neither applies
+    pub fn predict_heuristic(&self, content: &[u8]) -> Option<Prediction> {
         // Text file detection (simple heuristic)
-        if self.is_likely_text(content) {
-            return "text".to_string();
+        let printable = self.printable_ratio(content);
+        if printable > 0.8 {
+            return Some(Prediction {
+                category: FileCategory::Text,
+                confidence: printable,
+                mime: "text/plain",
+            });
         }
 
-        // Default category for unknown types
-        "misc".to_string()
+        let (low, high) = self.entropy?;
+        let entropy = entropy(content);
+        if entropy >= high {
+            return Some(Prediction {
+                category: FileCategory::CompressedOrEncrypted,
+                confidence: entropy / 8.0,
+                mime: "application/octet-stream",
+            });
+        }
+        (entropy <= low).then_some(Prediction {
+            category: FileCategory::BinaryData,
+            confidence: 1.0 - entropy / 8.0,
+            mime: "application/octet-stream",
+        })
     }
 
-    /// Simple heuristic to detect if a file is likely text
-    fn is_likely_text(&self, content: &[u8]) -> bool {
+    /// Share of printable ASCII bytes, used to detect if a file is likely text
+    fn printable_ratio(&self, content: &[u8]) -> f32 {
         if content.is_empty() {
-            return true;
+            return 1.0;
         }
 
         // Check if most bytes are in the ASCII printable range
         let printable_count = content
             .iter()
-            .take(std::cmp::min(content.len(), 1024)) // Check only first 1KB
-            .filter(|&&b| (b >= 32 && b <= 126) || b == b'\n' || b == b'\r' || b == b'\t')
+            .take(std::cmp::min(content.len(), TEXT_SAMPLE)) // Check only first 1KB
+            .filter(|&&b| (32..=126).contains(&b) || b == b'\n' || b == b'\r' || b == b'\t')
             .count();
 
-        let sample_size = std::cmp::min(content.len(), 1024);
-        (printable_count as f32 / sample_size as f32) > 0.8
+        let sample_size = std::cmp::min(content.len(), TEXT_SAMPLE);
+        printable_count as f32 / sample_size as f32
+    }
+}
+
+/// Shannon entropy of the first 64 KiB of `content` in bits per byte, from 0.0 to 8.0
+pub fn entropy(content: &[u8]) -> f32 {
+    let sample = &content[..content.len().min(ENTROPY_SAMPLE)];
+    if sample.is_empty() {
+        return 0.0;
+    }
+
+    let mut counts = [0u32; 256];
+    for &byte in sample {
+        counts[byte as usize] += 1;
+    }
+
+    let len = sample.len() as f32;
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f32 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pseudo-random bytes from xorshift, as incompressible as encrypted data
+    fn random_bytes(len: usize) -> Vec<u8> {
+        let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 56) as u8
+            })
+            .collect()
+    }
+
+    /// Non-printable bytes repeating a short record
+    fn repetitive_bytes(len: usize) -> Vec<u8> {
+        [0x00, 0x01, 0x02, 0x03]
+            .into_iter()
+            .cycle()
+            .take(len)
+            .collect()
+    }
+
+    fn with_entropy() -> FileTypePredictor {
+        FileTypePredictor::new().with_entropy_thresholds(DEFAULT_LOW_ENTROPY, DEFAULT_HIGH_ENTROPY)
+    }
+
+    #[test]
+    fn entropy_spans_zero_to_eight_bits() {
+        assert_eq!(entropy(b""), 0.0);
+        assert_eq!(entropy(&[7; 1000]), 0.0);
+        assert!((entropy(&repetitive_bytes(4096)) - 2.0).abs() < 1e-4);
+        let every_byte: Vec<u8> = (0..=255).collect();
+        assert!((entropy(&every_byte) - 8.0).abs() < 1e-4);
+        assert!(entropy(&random_bytes(ENTROPY_SAMPLE)) > 7.9);
+    }
+
+    #[test]
+    fn only_the_sample_counts() {
+        let mut content = repetitive_bytes(ENTROPY_SAMPLE);
+        content.extend(random_bytes(ENTROPY_SAMPLE));
+
+        assert_eq!(entropy(&content), entropy(&content[..ENTROPY_SAMPLE]));
+    }
+
+    #[test]
+    fn random_bytes_are_compressed_or_encrypted() {
+        let prediction = with_entropy().predict(&random_bytes(ENTROPY_SAMPLE));
+
+        assert_eq!(prediction.category, FileCategory::CompressedOrEncrypted);
+        assert_eq!(prediction.mime, "application/octet-stream");
+        assert!(prediction.confidence > 0.98);
+    }
+
+    #[test]
+    fn repetitive_bytes_are_binary_data() {
+        let prediction = with_entropy().predict(&repetitive_bytes(ENTROPY_SAMPLE));
+
+        assert_eq!(prediction.category, FileCategory::BinaryData);
+        assert!((prediction.confidence - 0.75).abs() < 1e-4);
+    }
+
+    #[test]
+    fn entropy_between_the_thresholds_stays_misc() {
+        // 64 distinct bytes above ASCII, none printable: 6 bits per byte
+        let content: Vec<u8> = (0..64u8).map(|n| n + 128).cycle().take(4096).collect();
+
+        assert_eq!(
+            with_entropy().predict(&content).category,
+            FileCategory::Misc
+        );
+    }
+
+    #[test]
+    fn without_thresholds_binaries_stay_misc() {
+        let predictor = FileTypePredictor::new();
+
+        assert_eq!(
+            predictor.predict(&random_bytes(ENTROPY_SAMPLE)).category,
+            FileCategory::Misc
+        );
+        assert_eq!(
+            predictor
+                .predict(&repetitive_bytes(ENTROPY_SAMPLE))
+                .category,
+            FileCategory::Misc
+        );
+    }
+
+    #[test]
+    fn signatures_and_text_come_before_entropy() {
+        let mut zip = b"PK\x03\x04".to_vec();
+        zip.extend(random_bytes(4096));
+        assert_eq!(
+            with_entropy().predict(&zip).category,
+            FileCategory::Archives
+        );
+
+        let text = "plain words, repeated. ".repeat(100);
+        assert_eq!(
+            with_entropy().predict(text.as_bytes()).category,
+            FileCategory::Text
+        );
+    }
+
+    /// Counts the bytes read through it
+    struct Counting<R> {
+        inner: R,
+        read: usize,
+    }
+
+    impl<R: Read> Read for Counting<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.read += n;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn predicting_from_a_reader_reads_only_the_sample() {
+        let predictor = FileTypePredictor::new();
+        // Endless, so reading past the sample would never finish
+        let mut reader = Counting {
+            inner: io::repeat(b'a'),
+            read: 0,
+        };
+
+        let prediction = predictor.predict_from_reader(&mut reader).unwrap();
+
+        assert_eq!(prediction.category, FileCategory::Text);
+        assert_eq!(reader.read, predictor.sample_len());
+    }
+
+    #[test]
+    fn a_reader_predicts_as_the_whole_content_does() {
+        let mut text = "plain words\n".repeat(1000).into_bytes();
+        // Binary past the text sample doesn't change it
+        text.extend(random_bytes(8192));
+        let mut zip = b"PK\x03\x04".to_vec();
+        zip.extend(random_bytes(100_000));
+
+        for predictor in [FileTypePredictor::new(), with_entropy()] {
+            for content in [&text, &zip, &random_bytes(100_000)] {
+                assert_eq!(
+                    predictor.predict_from_reader(&content[..]).unwrap(),
+                    predictor.predict(content)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn the_sample_grows_with_entropy_classification() {
+        assert_eq!(with_entropy().sample_len(), ENTROPY_SAMPLE);
+        assert!(FileTypePredictor::new().sample_len() < ENTROPY_SAMPLE);
     }
 }
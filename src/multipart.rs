@@ -0,0 +1,296 @@
+use aws_sdk_s3::{
+    primitives::{ByteStream, DateTime as SdkDateTime},
+    types::{ChecksumAlgorithm, CompletedMultipartUpload, CompletedPart, StorageClass},
+    Client,
+};
+use bytes::Bytes;
+use futures::{stream, Stream, StreamExt};
+use std::pin::pin;
+
+use crate::{
+    capabilities::StorageOptions,
+    cli::PartChecksum,
+    error::AppError,
+    hashing::{self, HashingBody, SentChecksum},
+    stall::{self, MeterInterceptor, Watch},
+    ObjectMeta,
+};
+
+/// Bodies larger than this are uploaded to AWS S3 in parts
+pub const THRESHOLD: usize = 64 * 1024 * 1024;
+
+/// Size of every part but the last, unless that needs over `MAX_PARTS`
+pub const PART_SIZE: usize = 16 * 1024 * 1024;
+
+/// Smallest part S3 accepts, the last one aside
+pub const MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+
+/// Largest part S3 accepts
+pub const MAX_PART_SIZE: u64 = 5 * 1024 * 1024 * 1024;
+
+/// Most parts S3 accepts in one multipart upload
+pub const MAX_PARTS: u64 = 10_000;
+
+/// Part size of a `len`-byte multipart upload: `requested` (`--part-size`) or `PART_SIZE`
+///
+/// Either is raised, in whole MiB, as far as `len` needs to fit in `MAX_PARTS` parts, so
+/// parts stay 16 MiB up to about 156 GiB and grow past that instead of hitting the cap.
+pub fn part_size(len: u64, requested: Option<u64>) -> usize {
+    const MIB: u64 = 1024 * 1024;
+    let fitting = len.div_ceil(MAX_PARTS).div_ceil(MIB) * MIB;
+    let size = requested.unwrap_or(PART_SIZE as u64).max(fitting);
+    size.clamp(MIN_PART_SIZE, MAX_PART_SIZE) as usize
+}
+
+/// Multipart upload where every part carries its own checksum (`--part-checksum`)
+///
+/// S3 rejects a part whose body doesn't match its checksum with `BadDigest`, and the
+/// checksum it acknowledges must equal the one that was sent. The checksum is sent as a
+/// trailer, taken as the part streams rather than in a pass before it.
+/// Any failure, including a part stalling below `--min-throughput`, aborts the upload so no
+/// parts are left behind.
+pub async fn upload(
+    client: &Client,
+    body: Bytes,
+    bucket: &str,
+    key: &str,
+    meta: &ObjectMeta,
+    storage: &StorageOptions,
+    watch: &Watch,
+) -> Result<(), AppError> {
+    let part_size = part_size(body.len() as u64, meta.part_size);
+    let parts = (0..body.len())
+        .step_by(part_size)
+        .map(|start| Ok(body.slice(start..body.len().min(start + part_size))));
+    upload_stream(
+        client,
+        stream::iter(parts),
+        bucket,
+        key,
+        meta,
+        storage,
+        watch,
+    )
+    .await
+}
+
+/// Multipart upload of the parts `parts` yields, as they arrive
+///
+/// An error from `parts` aborts the upload like a failed part does.
+pub async fn upload_stream(
+    client: &Client,
+    parts: impl Stream<Item = Result<Bytes, AppError>>,
+    bucket: &str,
+    key: &str,
+    meta: &ObjectMeta,
+    storage: &StorageOptions,
+    watch: &Watch,
+) -> Result<(), AppError> {
+    let (encryption, kms_key_id) = storage.sdk_encryption();
+    let (lock_mode, retain_until) = storage.sdk_object_lock();
+
+    let created = client
+        .create_multipart_upload()
+        .bucket(bucket)
+        .key(key)
+        .set_content_type(meta.content_type.clone())
+        .set_content_disposition(meta.content_disposition.clone())
+        .set_expires(meta.expires.map(|t| SdkDateTime::from_secs(t.timestamp())))
+        .set_metadata(Some(meta.metadata.clone()).filter(|m| !m.is_empty()))
+        .set_tagging(meta.tagging())
+        .checksum_algorithm(algorithm(meta.part_checksum))
+        .set_storage_class(storage.storage_class.as_deref().map(StorageClass::from))
+        .set_server_side_encryption(encryption)
+        .set_ssekms_key_id(kms_key_id)
+        .set_object_lock_mode(lock_mode)
+        .set_object_lock_retain_until_date(retain_until)
+        .send()
+        .await?;
+
+    let upload_id = created.upload_id().ok_or_else(|| {
+        AppError::Integrity(format!("no upload id for multipart upload of {}", key))
+    })?;
+
+    let result = upload_parts(client, parts, bucket, key, upload_id, meta, watch).await;
+    if result.is_err() {
+        if let Err(err) = client
+            .abort_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .send()
+            .await
+        {
+            println!(
+                "Warning: could not abort multipart upload of {}: {}",
+                key,
+                AppError::from(err)
+            );
+        }
+    }
+    result
+}
+
+/// Upload every part, then complete with the per-part checksums
+async fn upload_parts(
+    client: &Client,
+    chunks: impl Stream<Item = Result<Bytes, AppError>>,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+    meta: &ObjectMeta,
+    watch: &Watch,
+) -> Result<(), AppError> {
+    let mut parts = Vec::new();
+    let mut chunks = pin!(chunks);
+
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk?;
+        let part_number = parts.len() as i32 + 1;
+
+        let sent = SentChecksum::default();
+        // Content-MD5 is a header, so unlike the checksum it takes a pass before sending
+        let content_md5 = meta.content_md5.then(|| hashing::content_md5(&chunk));
+        let body = HashingBody::sdk_body(chunk.clone(), meta.part_checksum, sent.clone());
+        let meter = watch.meter();
+        let send = client
+            .upload_part()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .checksum_algorithm(algorithm(meta.part_checksum))
+            .set_content_md5(content_md5)
+            .body(ByteStream::new(body))
+            .customize()
+            .interceptor(MeterInterceptor::new(meter.as_ref()))
+            .send();
+        let output = stall::guard(
+            "AWS S3",
+            watch.min_throughput,
+            chunk.len() as u64,
+            meter.as_ref(),
+            async { Ok(send.await?) },
+        )
+        .await?;
+
+        let checksum = sent.get().ok_or_else(|| {
+            AppError::Integrity(format!(
+                "part {} of {} was not sent in full",
+                part_number, key
+            ))
+        })?;
+        let acknowledged = match meta.part_checksum {
+            PartChecksum::Sha256 => output.checksum_sha256(),
+            PartChecksum::Crc32c => output.checksum_crc32_c(),
+        };
+        if acknowledged != Some(checksum.as_str()) {
+            return Err(AppError::Integrity(format!(
+                "part {} of {}: S3 acknowledged checksum {}, expected {}",
+                part_number,
+                key,
+                acknowledged.unwrap_or("none"),
+                checksum
+            )));
+        }
+
+        let part = CompletedPart::builder()
+            .part_number(part_number)
+            .set_e_tag(output.e_tag);
+        parts.push(
+            match meta.part_checksum {
+                PartChecksum::Sha256 => part.checksum_sha256(checksum),
+                PartChecksum::Crc32c => part.checksum_crc32_c(checksum),
+            }
+            .build(),
+        );
+    }
+
+    client
+        .complete_multipart_upload()
+        .bucket(bucket)
+        .key(key)
+        .upload_id(upload_id)
+        .multipart_upload(
+            CompletedMultipartUpload::builder()
+                .set_parts(Some(parts))
+                .build(),
+        )
+        .send()
+        .await?;
+
+    Ok(())
+}
+
+fn algorithm(checksum: PartChecksum) -> ChecksumAlgorithm {
+    match checksum {
+        PartChecksum::Sha256 => ChecksumAlgorithm::Sha256,
+        PartChecksum::Crc32c => ChecksumAlgorithm::Crc32C,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIB: u64 = 1024 * 1024;
+
+    fn parts(len: u64, size: usize) -> u64 {
+        len.div_ceil(size as u64)
+    }
+
+    #[test]
+    fn parts_stay_16_mib_until_they_would_exceed_the_part_cap() {
+        let most = PART_SIZE as u64 * MAX_PARTS;
+        for len in [0, 1, THRESHOLD as u64 + 1, most - 1, most] {
+            assert_eq!(part_size(len, None), PART_SIZE, "{}", len);
+        }
+        assert_eq!(parts(most, part_size(most, None)), MAX_PARTS);
+
+        // One byte more would be part 10,001; the next whole MiB fits it
+        assert_eq!(part_size(most + 1, None), PART_SIZE + MIB as usize);
+        assert!(parts(most + 1, part_size(most + 1, None)) <= MAX_PARTS);
+    }
+
+    #[test]
+    fn grown_parts_are_whole_mib_and_always_fit() {
+        for len in [
+            200 * 1024 * MIB,
+            1024 * 1024 * MIB + 7,
+            5 * 1024 * 1024 * MIB,
+        ] {
+            let size = part_size(len, None) as u64;
+            assert_eq!(size % MIB, 0, "{}", len);
+            assert!(parts(len, size as usize) <= MAX_PARTS, "{}", len);
+            // A MiB less would need too many parts
+            assert!(parts(len, (size - MIB) as usize) > MAX_PARTS, "{}", len);
+        }
+    }
+
+    #[test]
+    fn a_requested_size_is_kept_unless_it_needs_too_many_parts() {
+        assert_eq!(part_size(1, Some(MIN_PART_SIZE)), MIN_PART_SIZE as usize);
+        assert_eq!(part_size(1, Some(100 * MIB)), 100 * MIB as usize);
+        assert_eq!(
+            part_size(MIN_PART_SIZE * MAX_PARTS, Some(MIN_PART_SIZE)),
+            MIN_PART_SIZE as usize
+        );
+        assert_eq!(
+            part_size(MIN_PART_SIZE * MAX_PARTS + 1, Some(MIN_PART_SIZE)),
+            (MIN_PART_SIZE + MIB) as usize
+        );
+    }
+
+    #[test]
+    fn sizes_stay_within_the_part_limits() {
+        assert_eq!(part_size(1, Some(1)), MIN_PART_SIZE as usize);
+        assert_eq!(
+            part_size(1, Some(MAX_PART_SIZE * 2)),
+            MAX_PART_SIZE as usize
+        );
+        assert_eq!(
+            part_size(MAX_PART_SIZE * MAX_PARTS * 2, None),
+            MAX_PART_SIZE as usize
+        );
+    }
+}
@@ -0,0 +1,285 @@
+use bytes::{Bytes, BytesMut};
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+use crate::{
+    cli::UploadArgs,
+    error::AppError,
+    events::ProgressEvent,
+    hashing::{self, Sha256Digest},
+    ml::Classification,
+    plan::{Plan, PlannedFile},
+    sidecar, source,
+    source::SourceRange,
+    transform, upload_to_backends, Backend, Backends, FileUpload, ObjectMeta, Target, UploadRun,
+};
+
+/// Key prefix of packs and their indexes, after --git-prefix
+const PACK_PREFIX: &str = "packs";
+
+/// One file inside a pack, as its index lists it
+struct PackEntry {
+    file: String,
+    // The key the file would have had on its own, which `download --pack-index` takes
+    key: String,
+    offset: u64,
+    length: u64,
+    sha256: Sha256Digest,
+    classification: Classification,
+    // Of the file on disk, before transforms, for --resume-batch
+    source_sha256: Sha256Digest,
+}
+
+/// Files read so far for the next pack
+#[derive(Default)]
+struct PackBuilder {
+    body: BytesMut,
+    entries: Vec<PackEntry>,
+    started: Option<Instant>,
+}
+
+impl PackBuilder {
+    fn push(&mut self, planned: &PlannedFile, body: Bytes, source_sha256: Sha256Digest) {
+        self.started.get_or_insert_with(Instant::now);
+        self.entries.push(PackEntry {
+            file: planned.file.clone(),
+            key: planned.key.clone(),
+            offset: self.body.len() as u64,
+            length: body.len() as u64,
+            sha256: hashing::sha256(&body),
+            classification: planned.classification.clone(),
+            source_sha256,
+        });
+        self.body.extend_from_slice(&body);
+    }
+
+    fn index(&self, pack_key: &str, size: usize) -> Value {
+        let files: Vec<_> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                json!({
+                    "file": entry.file,
+                    "key": entry.key,
+                    "offset": entry.offset,
+                    "length": entry.length,
+                    "sha256": hex::encode(entry.sha256),
+                    "content_type": entry.classification.mime,
+                })
+            })
+            .collect();
+        json!({
+            "pack": pack_key,
+            "size": size,
+            "files": files,
+        })
+    }
+}
+
+/// Take the planned files `--pack` packs out of `plan`: those under `--pack-below`
+///
+/// A file with a sidecar is left to upload on its own, so the sidecar keeps mirroring its key.
+pub async fn take_packable(plan: &mut Plan, args: &UploadArgs) -> Vec<PlannedFile> {
+    let mut packable = Vec::new();
+    let mut rest = Vec::new();
+    for planned in plan.files.drain(..) {
+        let small = planned.size < args.pack_below;
+        if small
+            && sidecar::find_sidecar(&planned.file, &args.sidecar_suffix)
+                .await
+                .is_none()
+        {
+            packable.push(planned);
+        } else {
+            rest.push(planned);
+        }
+    }
+    plan.files = rest;
+    packable
+}
+
+/// Upload `files` concatenated into packs of up to `--pack-size`, each followed by its
+/// index; returns the outcome of every file, as its own upload would
+///
+/// A pack is stored under `packs/<sha256 of the pack>.pack` and its index under
+/// `packs/<sha256>.index.json`, so packing the same files again lands on the same keys.
+/// A file fails alone when it can't be read; once one is in a pack, it fails with the pack.
+pub async fn upload(
+    run: &UploadRun,
+    files: Vec<PlannedFile>,
+) -> Vec<(String, Duration, Result<FileUpload, AppError>)> {
+    let args = &run.args;
+    let mut outcomes = Vec::new();
+    let mut builder = PackBuilder::default();
+    for planned in files {
+        let started = Instant::now();
+        let read = source::read_source_retrying(
+            &planned.file,
+            args.source_range,
+            args.read_buffer_size,
+            args.retry_on_change,
+        )
+        .await;
+        let body = read.and_then(|source| {
+            let body = match run.transforms.is_empty() {
+                true => source.bytes,
+                false => transform::apply(&run.transforms, source.bytes)?,
+            };
+            Ok((body, source.sha256))
+        });
+        let (body, source_sha256) = match body {
+            Ok(read) => read,
+            Err(err) => {
+                outcomes.push((planned.file, started.elapsed(), Err(err)));
+                continue;
+            }
+        };
+
+        if !builder.entries.is_empty() && (builder.body.len() + body.len()) as u64 > args.pack_size
+        {
+            outcomes.extend(flush(run, std::mem::take(&mut builder)).await);
+        }
+        run.events.emit(ProgressEvent::Started {
+            file: planned.file.clone(),
+            key: planned.key.clone(),
+            bytes: body.len() as u64,
+        });
+        builder.push(&planned, body, source_sha256);
+    }
+    if !builder.entries.is_empty() {
+        outcomes.extend(flush(run, builder).await);
+    }
+    outcomes
+}
+
+/// Upload one pack and its index to every backend and replica
+async fn flush(
+    run: &UploadRun,
+    mut builder: PackBuilder,
+) -> Vec<(String, Duration, Result<FileUpload, AppError>)> {
+    let body = std::mem::take(&mut builder.body).freeze();
+    let size = body.len();
+    let digest = hashing::sha256(&body);
+    let name = format!("{}{}/{}", run.key_prefix, PACK_PREFIX, hex::encode(digest));
+    let (pack_key, index_key) = (format!("{}.pack", name), format!("{}.index.json", name));
+
+    let result = async {
+        run.check_budget()?;
+        let mut meta = ObjectMeta::with_content_type("application/octet-stream");
+        meta.sha256 = Some(digest);
+        meta.content_md5 = run.args.content_md5;
+        upload_to_backends(run, &pack_key, None, body, pack_key.clone(), meta).await?;
+
+        let index = Bytes::from(builder.index(&pack_key, size).to_string());
+        let mut meta = ObjectMeta::with_content_type("application/json");
+        meta.content_md5 = run.args.content_md5;
+        upload_to_backends(run, &pack_key, None, index, index_key.clone(), meta).await
+    }
+    .await;
+
+    let elapsed = builder
+        .started
+        .map(|started| started.elapsed())
+        .unwrap_or_default();
+    if result.is_ok() {
+        println!(
+            "Packed {} file(s) into {} (index {})",
+            builder.entries.len(),
+            pack_key,
+            index_key
+        );
+    }
+
+    let mut outcomes = Vec::new();
+    for entry in builder.entries {
+        // Every file of the pack shares its outcome
+        let result = match &result {
+            Ok(()) => run
+                .checkpoint
+                .record(
+                    &entry.file,
+                    Some(&entry.source_sha256),
+                    &entry.key,
+                    &entry.classification,
+                )
+                .await
+                .map(|()| FileUpload {
+                    key: entry.key,
+                    bytes: entry.length,
+                }),
+            Err(AppError::Cancelled) => Err(AppError::Cancelled),
+            Err(err) => Err(AppError::PackFailed {
+                pack: pack_key.clone(),
+                reason: err.to_string(),
+            }),
+        };
+        outcomes.push((entry.file, elapsed, result));
+    }
+    outcomes
+}
+
+/// Read the file stored under `key` (or with that file name) out of the pack `index_key`
+/// describes, checked against the SHA-256 the index records; returns it with the path the
+/// index lists it under
+///
+/// Only the file's bytes are fetched, with a ranged GET of the pack.
+pub async fn extract(
+    backends: &Backends,
+    backend: Backend,
+    index_key: &str,
+    key: &str,
+) -> Result<(Bytes, String), AppError> {
+    let target = Target::Backend(backend);
+    let index = backends.get(target, index_key, None).await?;
+    let index: Value = serde_json::from_slice(&index)
+        .map_err(|e| AppError::Config(format!("{} is not a pack index: {}", index_key, e)))?;
+    let invalid = || AppError::Config(format!("{} is not a pack index", index_key));
+
+    let pack = index
+        .get("pack")
+        .and_then(Value::as_str)
+        .ok_or_else(invalid)?;
+    let files = index
+        .get("files")
+        .and_then(Value::as_array)
+        .ok_or_else(invalid)?;
+    let entry = files
+        .iter()
+        .find(|entry| {
+            let field = |name| entry.get(name).and_then(Value::as_str);
+            field("key") == Some(key) || field("file") == Some(key)
+        })
+        .ok_or_else(|| AppError::Config(format!("{} lists no file {}", index_key, key)))?;
+    let (Some(file), Some(offset), Some(length), Some(sha256)) = (
+        entry.get("file").and_then(Value::as_str),
+        entry.get("offset").and_then(Value::as_u64),
+        entry.get("length").and_then(Value::as_u64),
+        entry.get("sha256").and_then(Value::as_str),
+    ) else {
+        return Err(invalid());
+    };
+
+    let data = match length {
+        0 => Bytes::new(),
+        _ => {
+            let range = SourceRange {
+                start: offset,
+                end: Some(offset + length),
+            };
+            backends.get(target, pack, Some(range)).await?
+        }
+    };
+    let actual = hex::encode(hashing::sha256(&data));
+    if data.len() as u64 != length || actual != sha256 {
+        return Err(AppError::Integrity(format!(
+            "{} in {} reads back as {} bytes with SHA-256 {} instead of {} bytes with {}",
+            key,
+            pack,
+            data.len(),
+            actual,
+            length,
+            sha256
+        )));
+    }
+    Ok((data, file.to_string()))
+}
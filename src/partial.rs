@@ -0,0 +1,167 @@
+use aws_sdk_s3::{types::ChecksumMode, Client};
+use tokio::{
+    fs::{self, OpenOptions},
+    io::AsyncWriteExt,
+};
+
+use crate::{
+    attrs, download_path, encoding, error::AppError, existing::StoredObject,
+    hashing::HashingReader, restored_name, sparse, DownloadOptions,
+};
+
+/// Download an AWS object through `<output>.part`, continuing a part left by an earlier run
+///
+/// The part holds the raw body received so far and `<output>.part.etag` the ETag it
+/// belongs to. A resumed download asks only for the missing bytes, with `If-Match` so a
+/// changed object starts over instead of being stitched from two versions. The finished
+/// part is checked against the stored SHA-256 before it is decoded or renamed into place.
+pub async fn download(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    output_path: Option<&str>,
+    options: DownloadOptions,
+) -> Result<String, AppError> {
+    let head = client
+        .head_object()
+        .bucket(bucket)
+        .key(key)
+        .checksum_mode(ChecksumMode::Enabled)
+        .send()
+        .await?;
+    let etag = head.e_tag().unwrap_or_default().to_string();
+    let size = head.content_length().unwrap_or(0).max(0) as u64;
+    let content_encoding = head
+        .content_encoding()
+        .map(str::to_string)
+        .filter(|_| options.decompress);
+    let metadata = head.metadata().cloned().unwrap_or_default();
+    let stored = StoredObject::new(Some(&metadata), head.checksum_sha256(), size);
+
+    let restored = restored_name(key, &metadata, options)?;
+    let output_path = output_path.or(restored.as_deref());
+    let output_path = download_path(key, output_path, content_encoding.as_deref())?;
+    let part = format!("{}.part", output_path);
+    let etag_file = format!("{}.etag", part);
+
+    let received = resumable_bytes(&part, &etag_file, &etag, size).await;
+    if received > 0 {
+        println!("Resuming {} at {} of {} bytes", key, received, size);
+    } else {
+        fs::write(&part, b"").await?;
+        fs::write(&etag_file, &etag).await?;
+    }
+
+    if received < size {
+        let mut request = client
+            .get_object()
+            .bucket(bucket)
+            .key(key)
+            .range(format!("bytes={}-", received));
+        if !etag.is_empty() {
+            request = request.if_match(&etag);
+        }
+        let mut resp = match request.send().await.map_err(AppError::from) {
+            // The object changed since the part was started
+            Err(AppError::AwsSdk {
+                status: Some(412), ..
+            }) => {
+                let _ = fs::remove_file(&part).await;
+                let _ = fs::remove_file(&etag_file).await;
+                return Err(AppError::Integrity(format!(
+                    "{} changed since {} was started; run the download again to start over",
+                    key, part
+                )));
+            }
+            result => result?,
+        };
+
+        let mut file = OpenOptions::new().append(true).open(&part).await?;
+        while let Some(chunk) = resp.body.try_next().await? {
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+    }
+
+    verify(&part, &stored, key).await?;
+
+    if let Some(size) = metadata.get(sparse::SIZE_METADATA) {
+        let sha256 = metadata.get(sparse::SHA256_METADATA).map(String::as_str);
+        sparse::write(&output_path, &fs::read(&part).await?, size, sha256).await?;
+        fs::remove_file(&part).await?;
+        let _ = fs::remove_file(&etag_file).await;
+        if options.restore_attrs {
+            attrs::restore_attrs(&output_path, &metadata)?;
+        }
+        println!("Downloaded from AWS S3: {} -> {}", key, output_path);
+        return Ok(output_path);
+    }
+
+    match content_encoding {
+        Some(encoding) => {
+            let data = encoding::decode(fs::read(&part).await?.into(), Some(&encoding))?;
+            fs::write(&output_path, data).await?;
+            fs::remove_file(&part).await?;
+        }
+        None => fs::rename(&part, &output_path).await?,
+    }
+    let _ = fs::remove_file(&etag_file).await;
+
+    if options.restore_attrs {
+        attrs::restore_attrs(&output_path, &metadata)?;
+    }
+
+    println!("Downloaded from AWS S3: {} -> {}", key, output_path);
+    Ok(output_path)
+}
+
+/// Bytes of `part` that can be kept: all of them if it belongs to the object with
+/// `etag` and isn't longer than it, none otherwise
+async fn resumable_bytes(part: &str, etag_file: &str, etag: &str, size: u64) -> u64 {
+    let (Ok(meta), Ok(recorded)) = (
+        fs::metadata(part).await,
+        fs::read_to_string(etag_file).await,
+    ) else {
+        return 0;
+    };
+    if recorded != etag || meta.len() > size {
+        return 0;
+    }
+    meta.len()
+}
+
+/// Compare the finished part with the stored SHA-256, or just its size without one
+///
+/// A mismatching part is deleted, since resuming it again can't repair it.
+async fn verify(part: &str, stored: &StoredObject, key: &str) -> Result<(), AppError> {
+    let mut reader = HashingReader::new(fs::File::open(part).await?);
+    let len = tokio::io::copy(&mut reader, &mut tokio::io::sink()).await?;
+    let digest = reader.finish();
+
+    let problem = if len != stored.size {
+        Some(format!("{} bytes, expected {}", len, stored.size))
+    } else {
+        stored
+            .sha256
+            .as_ref()
+            .filter(|sha256| **sha256 != hex::encode(digest))
+            .map(|sha256| format!("SHA-256 {}, expected {}", hex::encode(digest), sha256))
+    };
+
+    match problem {
+        Some(problem) => {
+            let _ = fs::remove_file(part).await;
+            let _ = fs::remove_file(format!("{}.etag", part)).await;
+            Err(AppError::Integrity(format!("{}: {}", key, problem)))
+        }
+        None => {
+            if stored.sha256.is_none() {
+                println!(
+                    "Note: {} has no stored SHA-256; only its size was verified",
+                    key
+                );
+            }
+            Ok(())
+        }
+    }
+}
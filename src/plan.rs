@@ -0,0 +1,362 @@
+use futures::{stream, StreamExt};
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    check_file_size,
+    classifier::Classifier,
+    cli::{format_size, OnInvalid, UploadArgs},
+    error::AppError,
+    fit_key,
+    hashing::{self, Sha256Digest},
+    key_reserve,
+    keys::{self, CategoryNames},
+    ml::Classification,
+    named_key,
+    predictions::PredictionLog,
+    process_file_with_ml, schema, sidecar, source,
+    transform::{self, ContentTransform},
+    JsonSchema, KeyMap, Versioner,
+};
+
+/// Everything a key depends on, borrowed from the run before any backend is connected
+pub struct Planner<'a> {
+    pub args: &'a UploadArgs,
+    pub classifier: &'a dyn Classifier,
+    pub transforms: &'a [Box<dyn ContentTransform>],
+    pub predictions: &'a PredictionLog,
+    pub json_schema: Option<&'a JsonSchema>,
+    pub versioner: Option<&'a Versioner>,
+    pub key_prefix: &'a str,
+    pub category_names: &'a CategoryNames,
+    pub key_map: &'a KeyMap,
+}
+
+/// One file of the plan with the key it will be uploaded under
+pub struct PlannedFile {
+    pub file: String,
+    pub classification: Classification,
+    pub key: String,
+    pub size: u64,
+    // Known when the whole content was read
+    pub digest: Option<Sha256Digest>,
+}
+
+/// The classified batch, built before anything is uploaded (`--plan`, `--dry-run`)
+pub struct Plan {
+    pub files: Vec<PlannedFile>,
+    // Files the upload would skip or fail, with why
+    pub rejected: Vec<(String, AppError)>,
+    pub elapsed: Duration,
+    content_addressed: bool,
+}
+
+impl Planner<'_> {
+    /// Classify every file, up to `--concurrency` at once, and derive its key
+    pub async fn plan(&self, files: &[String]) -> Plan {
+        let started = Instant::now();
+        let results: Vec<_> = stream::iter(files)
+            .map(|file| async move { (file, self.plan_file(file).await) })
+            .buffered(self.args.concurrency.max(1))
+            .collect()
+            .await;
+
+        let (mut planned, mut rejected) = (Vec::new(), Vec::new());
+        for (file, result) in results {
+            match result {
+                Ok(file) => planned.push(file),
+                Err(err) => rejected.push((file.clone(), err)),
+            }
+        }
+        Plan {
+            files: planned,
+            rejected,
+            elapsed: started.elapsed(),
+            content_addressed: self.args.content_addressed,
+        }
+    }
+
+    /// Classify one file the way its upload would, reading no more of it than a key needs
+    ///
+    /// A classifier bounded to a prefix only gets that read, unless transforms, a
+    /// content-derived key or `--validate-json` need the whole content.
+    async fn plan_file(&self, file: &str) -> Result<PlannedFile, AppError> {
+        let args = self.args;
+        check_file_size(file, args).await?;
+
+        let sample_len = self.classifier.sample_len();
+        let whole = !self.transforms.is_empty()
+            || sample_len.is_none()
+            || args.content_addressed
+            || self.versioner.is_some_and(Versioner::needs_digest)
+            || self.json_schema.is_some();
+        let (content, size, digest) = match (whole, sample_len) {
+            (false, Some(len)) => {
+                let size = source::body_len(file, args.source_range).await?;
+                let sample = source::read_prefix(file, args.source_range, len).await?;
+                (sample, size, None)
+            }
+            _ => {
+                let source = source::read_source_retrying(
+                    file,
+                    args.source_range,
+                    args.read_buffer_size,
+                    args.retry_on_change,
+                )
+                .await?;
+                let (body, digest) = if self.transforms.is_empty() {
+                    (source.bytes, source.sha256)
+                } else {
+                    let body = transform::apply(self.transforms, source.bytes)?;
+                    let digest = hashing::sha256(&body);
+                    (body, digest)
+                };
+                let size = body.len() as u64;
+                (body, size, Some(digest))
+            }
+        };
+
+        let classification = process_file_with_ml(
+            self.classifier,
+            self.predictions,
+            args.on_classify_error,
+            file,
+            &content,
+        )?;
+        if let Some(schema) = self.json_schema {
+            if schema::is_json(file, &classification) {
+                if let Err(reason) = schema.check(&content) {
+                    let path = file.to_string();
+                    return Err(match args.on_invalid {
+                        OnInvalid::Skip => AppError::Skipped { path, reason },
+                        OnInvalid::Fail => AppError::Invalid { path, reason },
+                    });
+                }
+            }
+        }
+
+        let key = match digest.filter(|_| args.content_addressed) {
+            Some(digest) => keys::content_addressed_key(&digest, args.shard_depth),
+            None => named_key(args, self.category_names, file, &classification, size == 0),
+        };
+        let key = match self.key_map.get(file) {
+            Some(mapped) => mapped.clone(),
+            None => {
+                let key = format!("{}{}", self.key_prefix, key);
+                match self.versioner {
+                    Some(versioner) => versioner.preview(&key, digest.as_ref()),
+                    None => key,
+                }
+            }
+        };
+        let has_sidecar = sidecar::find_sidecar(file, &args.sidecar_suffix)
+            .await
+            .is_some();
+        let key = fit_key(args, file, key, key_reserve(args, has_sidecar))?;
+        Ok(PlannedFile {
+            file: file.to_string(),
+            classification,
+            key,
+            size,
+            digest,
+        })
+    }
+}
+
+impl Plan {
+    pub fn bytes(&self) -> u64 {
+        self.files.iter().map(|planned| planned.size).sum()
+    }
+
+    /// Keys more than one file maps to, with those files; a later upload would replace an
+    /// earlier one
+    ///
+    /// With `--content-addressed` a shared key means identical content, which is the point.
+    pub fn collisions(&self) -> Vec<(&str, Vec<&str>)> {
+        if self.content_addressed {
+            return Vec::new();
+        }
+        let mut by_key: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for planned in &self.files {
+            by_key.entry(&planned.key).or_default().push(&planned.file);
+        }
+        by_key
+            .into_iter()
+            .filter(|(_, files)| files.len() > 1)
+            .collect()
+    }
+
+    /// What `--dry-run` shows: every planned key with its file, then what would be skipped,
+    /// failed or overwritten
+    pub fn print(&self, backends: &[&str]) {
+        println!(
+            "Plan: {} file(s), {} to {} (classified in {:.1}s)",
+            self.files.len(),
+            format_size(self.bytes()),
+            backends.join(", "),
+            self.elapsed.as_secs_f64()
+        );
+        for planned in &self.files {
+            println!(
+                "{:>12} {} <- {} ({}, {:.0}%)",
+                format_size(planned.size),
+                planned.key,
+                planned.file,
+                planned.classification.category,
+                planned.classification.confidence * 100.0
+            );
+        }
+        for (file, err) in &self.rejected {
+            match err {
+                AppError::Skipped { .. } => println!("{}", err),
+                err => println!("Would fail {}: {}", file, err),
+            }
+        }
+        let collisions = self.collisions();
+        for (key, files) in &collisions {
+            println!("Collision: {} <- {}", key, files.join(", "));
+        }
+
+        let skipped = self
+            .rejected
+            .iter()
+            .filter(|(_, err)| matches!(err, AppError::Skipped { .. }))
+            .count();
+        println!(
+            "Dry run: nothing was uploaded ({} planned, {} skipped, {} would fail, {} collision(s))",
+            self.files.len(),
+            skipped,
+            self.rejected.len() - skipped,
+            collisions.len()
+        );
+    }
+
+    /// Planned classifications by file, for the uploads to reuse
+    pub fn into_classifications(self) -> Vec<(String, Classification)> {
+        self.files
+            .into_iter()
+            .map(|planned| (planned.file, planned.classification))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use crate::{
+        classifier,
+        cli::{Cli, Command},
+        testdir::TestDir,
+    };
+
+    /// The plan `upload <flags>` makes of `files`
+    async fn plan_of(flags: &[&str], files: &[String]) -> Plan {
+        let cli = Cli::try_parse_from([&["s3-ml-uploader", "upload"], flags].concat()).unwrap();
+        let Some(Command::Upload(args)) = cli.command else {
+            unreachable!("parsed an upload")
+        };
+        let classifier = classifier::from_args(&args.classifier).unwrap();
+        let transforms = transform::from_args(&args);
+        let category_names = CategoryNames {
+            misc: args.default_category.clone(),
+            text: args.text_category.clone(),
+            empty: args.empty_category.clone(),
+        };
+        Planner {
+            args: &args,
+            classifier: classifier.as_ref(),
+            transforms: &transforms,
+            predictions: &PredictionLog::new(false),
+            json_schema: None,
+            versioner: None,
+            key_prefix: "",
+            category_names: &category_names,
+            key_map: &KeyMap::new(),
+        }
+        .plan(files)
+        .await
+    }
+
+    fn keys(plan: &Plan) -> Vec<(&str, &str)> {
+        plan.files
+            .iter()
+            .map(|planned| (planned.file.as_str(), planned.key.as_str()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn files_sharing_a_key_collide() {
+        let dir = TestDir::new();
+        let first = dir.write("a/notes.txt", "first");
+        let second = dir.write("b/notes.txt", "second");
+        let other = dir.write("b/todo.txt", "other");
+        let files = [first.clone(), second.clone(), other.clone()];
+
+        let plan = plan_of(&[], &files).await;
+        assert_eq!(
+            keys(&plan),
+            [
+                (first.as_str(), "text/notes.txt"),
+                (second.as_str(), "text/notes.txt"),
+                (other.as_str(), "text/todo.txt"),
+            ]
+        );
+        assert_eq!(
+            plan.collisions(),
+            [("text/notes.txt", vec![first.as_str(), second.as_str()])]
+        );
+
+        // Kept paths tell them apart
+        assert!(plan_of(&["--keep-paths"], &files)
+            .await
+            .collisions()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn identical_content_addressed_files_do_not_collide() {
+        let dir = TestDir::new();
+        let files = [dir.write("a.txt", "same"), dir.write("b.txt", "same")];
+
+        let plan = plan_of(&["--content-addressed"], &files).await;
+        assert_eq!(plan.files[0].key, plan.files[1].key);
+        assert!(plan.collisions().is_empty());
+    }
+
+    #[tokio::test]
+    async fn bytes_count_the_bodies_uploaded() {
+        let dir = TestDir::new();
+        let crlf = dir.write("crlf.txt", "one\r\ntwo\r\n");
+        let plain = dir.write("plain.txt", "0123456789");
+        let files = [crlf, plain];
+
+        assert_eq!(plan_of(&[], &files).await.bytes(), 20);
+        // Transforms rewrite the body, so the whole file is read and sized after them
+        let plan = plan_of(&["--normalize-newlines"], &files).await;
+        assert_eq!(plan.files[0].size, 8);
+        assert_eq!(plan.bytes(), 18);
+        // Only the range is sent, though the classifier reads just a prefix
+        let plan = plan_of(&["--source-range", "2:7"], &files).await;
+        assert_eq!(plan.bytes(), 10);
+        assert!(plan.files.iter().all(|planned| planned.digest.is_none()));
+    }
+
+    #[tokio::test]
+    async fn rejected_files_are_neither_keyed_nor_counted() {
+        let dir = TestDir::new();
+        let files = [
+            dir.write("small.txt", "small"),
+            dir.write("large.txt", "x".repeat(100)),
+        ];
+
+        let plan = plan_of(&["--max-file-size", "50"], &files).await;
+        assert_eq!(keys(&plan), [(files[0].as_str(), "text/small.txt")]);
+        assert_eq!(plan.bytes(), 5);
+        assert_eq!(plan.rejected.len(), 1);
+        assert_eq!(plan.rejected[0].0, files[1]);
+    }
+}
@@ -0,0 +1,231 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{task::JoinHandle, time};
+
+use crate::ml::{Classification, FileCategory};
+
+/// Predictions printed per category before further ones are rolled up
+const INDIVIDUAL_LINES: usize = 10;
+
+/// How often rolled-up predictions are summarized
+const ROLLUP_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Predictions below this confidence are always printed on their own
+const LOW_CONFIDENCE: f32 = 0.6;
+
+/// Prints predictions of concurrently classified files
+///
+/// Each line is a single `println!`, which holds stdout's lock while it writes, so lines of
+/// parallel tasks never tear; the log's own lock keeps the per-category counts and rollups
+/// consistent. Unless `verbose`, only the first few confident predictions per category get
+/// a line of their own; the rest are summarized every few seconds by [`Self::spawn_rollups`],
+/// e.g. "Classified 990 more file(s): images 980, text 10". Low-confidence predictions are
+/// always printed individually.
+pub struct PredictionLog {
+    verbose: bool,
+    state: Mutex<State>,
+}
+
+struct State {
+    printed: HashMap<FileCategory, usize>,
+    // Rolled-up predictions not yet summarized
+    pending: HashMap<FileCategory, usize>,
+}
+
+impl PredictionLog {
+    pub fn new(verbose: bool) -> Self {
+        Self {
+            verbose,
+            state: Mutex::new(State::new()),
+        }
+    }
+
+    /// Print or count the prediction for `file`
+    pub fn record(&self, file: &str, classification: &Classification) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(line) = state.record(self.verbose, file, classification) {
+            println!("{}", line);
+        }
+    }
+
+    /// Summarize predictions still pending, at the end of a run
+    pub fn flush(&self) {
+        self.rollup(|line| println!("{}", line));
+    }
+
+    /// Print rollups every few seconds until the returned task is aborted, whether or not
+    /// further files are classified meanwhile
+    pub fn spawn_rollups(self: &Arc<Self>) -> JoinHandle<()> {
+        let log = Arc::clone(self);
+        tokio::spawn(async move { log.rollups(|line| println!("{}", line)).await })
+    }
+
+    async fn rollups(&self, mut print: impl FnMut(String)) {
+        let mut interval = time::interval(ROLLUP_INTERVAL);
+        // The first tick is immediate
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            self.rollup(&mut print);
+        }
+    }
+
+    fn rollup(&self, print: impl FnOnce(String)) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(line) = state.rollup() {
+            print(line);
+        }
+    }
+}
+
+impl State {
+    fn new() -> Self {
+        Self {
+            printed: HashMap::new(),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// The line to print for the prediction for `file`, unless it is rolled up
+    fn record(
+        &mut self,
+        verbose: bool,
+        file: &str,
+        classification: &Classification,
+    ) -> Option<String> {
+        let low = classification.confidence < LOW_CONFIDENCE;
+        let printed = self.printed.entry(classification.category).or_default();
+
+        if verbose || low || *printed < INDIVIDUAL_LINES {
+            if !low {
+                *printed += 1;
+            }
+            Some(format!(
+                "ML model predicted file type for {}: {} ({:.0}% confidence{})",
+                file,
+                classification.category,
+                classification.confidence * 100.0,
+                if low { ", low" } else { "" }
+            ))
+        } else {
+            *self.pending.entry(classification.category).or_default() += 1;
+            None
+        }
+    }
+
+    /// Summary line of the predictions rolled up since the last one, if there were any
+    fn rollup(&mut self) -> Option<String> {
+        if self.pending.is_empty() {
+            return None;
+        }
+
+        let mut counts: Vec<_> = self.pending.drain().collect();
+        counts.sort_by_key(|(category, _)| category.as_str());
+        let total: usize = counts.iter().map(|(_, count)| count).sum();
+        let breakdown: Vec<_> = counts
+            .iter()
+            .map(|(category, count)| format!("{} {}", category, count))
+            .collect();
+
+        Some(format!(
+            "Classified {} more file(s): {}",
+            total,
+            breakdown.join(", ")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn predicted(category: FileCategory, confidence: f32) -> Classification {
+        Classification {
+            key: String::new(),
+            category,
+            confidence,
+            mime: String::new(),
+        }
+    }
+
+    /// Lines printed for `count` confident predictions of `category`
+    fn record_many(state: &mut State, category: FileCategory, count: usize) -> Vec<String> {
+        (0..count)
+            .flat_map(|i| state.record(false, &format!("file{}", i), &predicted(category, 0.9)))
+            .collect()
+    }
+
+    #[test]
+    fn repeated_predictions_collapse_into_one_rollup_line() {
+        let mut state = State::new();
+
+        let lines = record_many(&mut state, FileCategory::Images, 1000);
+        assert_eq!(lines.len(), INDIVIDUAL_LINES);
+        assert_eq!(
+            lines[0],
+            "ML model predicted file type for file0: images (90% confidence)"
+        );
+        record_many(&mut state, FileCategory::Text, INDIVIDUAL_LINES + 10);
+
+        assert_eq!(
+            state.rollup().as_deref(),
+            Some("Classified 1000 more file(s): images 990, text 10")
+        );
+        assert_eq!(state.rollup(), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rollups_are_printed_on_a_timer_without_further_predictions() {
+        let log = PredictionLog::new(false);
+        {
+            let mut state = log.state.lock().unwrap();
+            record_many(&mut state, FileCategory::Images, INDIVIDUAL_LINES + 5);
+        }
+        let printed = Mutex::new(Vec::new());
+        let rollups = log.rollups(|line| printed.lock().unwrap().push(line));
+        let rollups = async {
+            tokio::select! {
+                _ = rollups => {}
+                _ = time::sleep(ROLLUP_INTERVAL * 3 + Duration::from_secs(1)) => {}
+            }
+        };
+
+        rollups.await;
+        // Once, however many intervals pass with nothing new
+        assert_eq!(
+            *printed.lock().unwrap(),
+            ["Classified 5 more file(s): images 5"]
+        );
+    }
+
+    #[test]
+    fn anomalies_are_printed_at_once() {
+        let mut state = State::new();
+        record_many(&mut state, FileCategory::Images, INDIVIDUAL_LINES + 5);
+
+        let line = state.record(false, "odd.png", &predicted(FileCategory::Images, 0.4));
+        assert_eq!(
+            line.as_deref(),
+            Some("ML model predicted file type for odd.png: images (40% confidence, low)")
+        );
+        // Nor are they held back for, or counted in, the rollup
+        assert_eq!(
+            state.rollup().as_deref(),
+            Some("Classified 5 more file(s): images 5")
+        );
+    }
+
+    #[test]
+    fn verbose_prints_every_prediction() {
+        let mut state = State::new();
+        let lines: Vec<_> = (0..INDIVIDUAL_LINES * 3)
+            .flat_map(|_| state.record(true, "a.png", &predicted(FileCategory::Images, 0.9)))
+            .collect();
+
+        assert_eq!(lines.len(), INDIVIDUAL_LINES * 3);
+        assert_eq!(state.rollup(), None);
+    }
+}
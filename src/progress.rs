@@ -0,0 +1,397 @@
+use std::{
+    collections::HashMap,
+    io::{self, IsTerminal, Write},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{sync::mpsc::UnboundedSender, task::JoinHandle};
+
+use crate::{cli::format_size, events::ProgressEvent, source::SourceRange};
+
+/// How often the meter is redrawn on a terminal
+const TERMINAL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How often a progress line is printed when stderr is not a terminal
+const LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Byte-accurate progress of a whole batch, shown with `--progress`
+///
+/// Every input is stat'd up front so the total is known before the first upload. A file
+/// whose size can't be known then (gone, unreadable, growing) makes the meter indeterminate
+/// until it has been read; a file that turns out larger or smaller corrects the total.
+///
+/// A file being uploaded counts with what its request bodies have sent so far, or with the
+/// objects its targets reported stored where that is more, spread over its `targets` and
+/// never past its size; it counts in full once it is done.
+pub struct Progress {
+    state: Mutex<State>,
+    started: Instant,
+    // Backends and replicas each file is uploaded to
+    targets: u64,
+}
+
+struct State {
+    // Bytes of each pending file: the stat'd size, corrected once the file is read
+    sizes: HashMap<String, Option<u64>>,
+    total: u64,
+    // Files whose size is not known yet
+    unknown: usize,
+    done_bytes: u64,
+    done_files: usize,
+    files: usize,
+    // Files started and not done yet
+    sending: HashMap<String, Sending>,
+}
+
+/// What the uploads of a started file have sent and stored, over all of its targets
+struct Sending {
+    key: String,
+    // Added to by the request bodies as they are sent, retries included
+    sent: Arc<AtomicU64>,
+    // Bytes of the objects stored, from `ProgressEvent::Bytes`
+    stored: u64,
+}
+
+impl Progress {
+    /// Stat every pending file to compute the batch total
+    pub async fn plan(files: &[String], range: Option<SourceRange>, targets: usize) -> Self {
+        let mut sizes = HashMap::new();
+        for file in files {
+            let size = tokio::fs::metadata(file)
+                .await
+                .ok()
+                .filter(|meta| meta.is_file())
+                .and_then(|meta| match range {
+                    Some(range) => range
+                        .resolve(meta.len())
+                        .ok()
+                        .map(|(start, end)| end - start),
+                    None => Some(meta.len()),
+                });
+            sizes.insert(file.clone(), size);
+        }
+
+        let total = sizes.values().flatten().sum();
+        let unknown = sizes.values().filter(|size| size.is_none()).count();
+
+        Self {
+            state: Mutex::new(State {
+                sizes,
+                total,
+                unknown,
+                done_bytes: 0,
+                done_files: 0,
+                files: files.len(),
+                sending: HashMap::new(),
+            }),
+            started: Instant::now(),
+            targets: targets.max(1) as u64,
+        }
+    }
+
+    /// Correct the total with the size `file` actually had when it was read, and start
+    /// counting what its upload under `key` sends
+    fn resolve(&self, file: &str, key: &str, size: u64) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(planned) = state.sizes.get(file).copied() else {
+            return;
+        };
+        state.sending.insert(
+            file.to_string(),
+            Sending {
+                key: key.to_string(),
+                sent: Arc::default(),
+                stored: 0,
+            },
+        );
+
+        match planned {
+            Some(planned) => state.total = state.total - planned + size,
+            None => {
+                state.total += size;
+                state.unknown -= 1;
+            }
+        }
+        state.sizes.insert(file.to_string(), Some(size));
+    }
+
+    /// Update the meter from an event of the run
+    pub fn apply(&self, event: &ProgressEvent) {
+        match event {
+            ProgressEvent::Started { file, key, bytes } => self.resolve(file, key, *bytes),
+            ProgressEvent::Bytes { file, bytes, .. } => self.stored(file, *bytes),
+            ProgressEvent::Completed { file, .. }
+            | ProgressEvent::Failed { file, .. }
+            | ProgressEvent::Skipped { file, .. } => self.complete(file),
+        }
+    }
+
+    /// Counter the request bodies of `key`'s upload add what they send to; `None` for keys
+    /// of no started file, such as a sidecar's, whose bytes count once it is done
+    pub fn sent_counter(&self, key: &str) -> Option<Arc<AtomicU64>> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state
+            .sending
+            .values()
+            .find(|sending| sending.key == key)
+            .map(|sending| Arc::clone(&sending.sent))
+    }
+
+    /// Count an object of `file` that one target stored
+    fn stored(&self, file: &str, bytes: u64) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(sending) = state.sending.get_mut(file) {
+            sending.stored += bytes;
+        }
+    }
+
+    /// Count `file` as processed, whether it succeeded, failed or was skipped
+    fn complete(&self, file: &str) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.sending.remove(file);
+        if let Some(size) = state.sizes.get(file).copied() {
+            state.done_bytes += size.unwrap_or(0);
+            if size.is_none() {
+                state.unknown -= 1;
+            }
+            state.done_files += 1;
+        }
+    }
+
+    /// Bytes of the files still being uploaded, each at most its size
+    fn partial_bytes(&self, state: &State) -> u64 {
+        state
+            .sending
+            .iter()
+            .map(|(file, sending)| {
+                let size = state.sizes.get(file).copied().flatten().unwrap_or(0);
+                let sent = sending.sent.load(Ordering::Relaxed).max(sending.stored);
+                (sent / self.targets).min(size)
+            })
+            .sum()
+    }
+
+    /// e.g. `12.0 MiB / 48.2 MiB (24%), 3/12 files, 4.0 MiB/s, ETA 9s`
+    pub fn render(&self) -> String {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let done_bytes = state.done_bytes + self.partial_bytes(&state);
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let rate = if elapsed > 0.0 {
+            done_bytes as f64 / elapsed
+        } else {
+            0.0
+        };
+        let files = format!("{}/{} files", state.done_files, state.files);
+        let throughput = format!("{}/s", format_size(rate as u64));
+
+        // Without every size the total is a lower bound, so show no percentage or ETA
+        if state.unknown > 0 {
+            return format!(
+                "{} / {}+ ({} of unknown size), {}, {}",
+                format_size(done_bytes),
+                format_size(state.total),
+                state.unknown,
+                files,
+                throughput
+            );
+        }
+
+        let percent = match state.total {
+            0 => 100,
+            total => done_bytes * 100 / total,
+        };
+        let eta = match state.total.saturating_sub(done_bytes) {
+            0 => "0s".to_string(),
+            _ if rate == 0.0 => "?".to_string(),
+            remaining => format!("{:.0}s", remaining as f64 / rate),
+        };
+        format!(
+            "{} / {} ({}%), {}, {}, ETA {}",
+            format_size(done_bytes),
+            format_size(state.total),
+            percent,
+            files,
+            throughput,
+            eta
+        )
+    }
+
+    /// Print the final state once the meter has been stopped
+    pub fn finish(&self) {
+        if io::stderr().is_terminal() {
+            eprintln!("\r\x1b[2KProgress: {}", self.render());
+        } else {
+            eprintln!("Progress: {}", self.render());
+        }
+    }
+
+    /// Redraw the meter on stderr until the returned task is aborted
+    pub fn spawn_meter(self: &Arc<Self>) -> JoinHandle<()> {
+        let progress = Arc::clone(self);
+        let terminal = io::stderr().is_terminal();
+        let interval = if terminal {
+            TERMINAL_INTERVAL
+        } else {
+            LOG_INTERVAL
+        };
+
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                if terminal {
+                    eprint!("\r\x1b[2KProgress: {}", progress.render());
+                    let _ = io::stderr().flush();
+                } else {
+                    eprintln!("Progress: {}", progress.render());
+                }
+            }
+        })
+    }
+}
+
+/// Where the events of a run go: a library consumer's channel and the `--progress` meter
+#[derive(Default)]
+pub struct ProgressSink {
+    sender: Option<UnboundedSender<ProgressEvent>>,
+    meter: Option<Arc<Progress>>,
+}
+
+impl ProgressSink {
+    pub fn new(
+        sender: Option<UnboundedSender<ProgressEvent>>,
+        meter: Option<Arc<Progress>>,
+    ) -> Self {
+        Self { sender, meter }
+    }
+
+    pub fn emit(&self, event: ProgressEvent) {
+        if let Some(meter) = &self.meter {
+            meter.apply(&event);
+        }
+        // A consumer that stopped listening doesn't stop the run
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+
+    fn started(file: &str, bytes: u64) -> ProgressEvent {
+        ProgressEvent::Started {
+            file: file.to_string(),
+            key: format!("text/{}", file),
+            bytes,
+        }
+    }
+
+    fn completed(file: &str) -> ProgressEvent {
+        ProgressEvent::Completed {
+            file: file.to_string(),
+            key: format!("text/{}", file),
+            bytes: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn the_total_is_stat_up_front_and_corrected_once_read() {
+        let dir = TestDir::new();
+        let (a, b) = (
+            dir.write("a.txt", [b'a'; 100]),
+            dir.write("b.txt", [b'b'; 50]),
+        );
+        let progress = Progress::plan(&[a.clone(), b.clone()], None, 1).await;
+        assert!(progress.render().starts_with("0 B / 150 B (0%), 0/2 files"));
+
+        // a.txt grew before it was read
+        progress.apply(&started(&a, 120));
+        progress.apply(&completed(&a));
+        assert!(progress
+            .render()
+            .starts_with("120 B / 170 B (70%), 1/2 files"));
+    }
+
+    #[tokio::test]
+    async fn a_file_of_unknown_size_makes_the_meter_indeterminate_until_read() {
+        let dir = TestDir::new();
+        let known = dir.write("known.txt", [b'k'; 100]);
+        // A pipe or stdin has no size to stat
+        let unknown = dir.path().join("missing.txt").display().to_string();
+        let progress = Progress::plan(&[known, unknown.clone()], None, 1).await;
+
+        let render = progress.render();
+        assert!(
+            render.starts_with("0 B / 100 B+ (1 of unknown size), 0/2 files"),
+            "{}",
+            render
+        );
+        assert!(
+            !render.contains('%') && !render.contains("ETA"),
+            "{}",
+            render
+        );
+
+        progress.apply(&started(&unknown, 30));
+        assert!(progress.render().starts_with("0 B / 130 B (0%)"));
+    }
+
+    #[tokio::test]
+    async fn bytes_sent_move_the_meter_before_a_file_is_done() {
+        let dir = TestDir::new();
+        let file = dir.write("a.txt", [b'a'; 100]);
+        let progress = Progress::plan(std::slice::from_ref(&file), None, 2).await;
+        progress.apply(&started(&file, 100));
+
+        // Both targets stream into the one counter of the file's key
+        let sent = progress.sent_counter(&format!("text/{}", file)).unwrap();
+        assert!(progress.sent_counter("text/other.txt").is_none());
+        sent.fetch_add(60, Ordering::Relaxed);
+        assert!(progress
+            .render()
+            .starts_with("30 B / 100 B (30%), 0/1 files"));
+
+        // A target without a meter reports its object once stored
+        progress.apply(&ProgressEvent::Bytes {
+            file: file.clone(),
+            key: format!("text/{}", file),
+            target: "MinIO".to_string(),
+            bytes: 100,
+        });
+        assert!(progress.render().starts_with("50 B / 100 B (50%)"));
+
+        // Retried bodies count again, but never past the file's size
+        sent.fetch_add(500, Ordering::Relaxed);
+        assert!(progress
+            .render()
+            .starts_with("100 B / 100 B (100%), 0/1 files"));
+
+        progress.apply(&completed(&file));
+        assert!(progress
+            .render()
+            .starts_with("100 B / 100 B (100%), 1/1 files"));
+    }
+
+    #[tokio::test]
+    async fn the_eta_follows_the_rate_so_far() {
+        let dir = TestDir::new();
+        let (a, b) = (
+            dir.write("a.txt", [b'a'; 50]),
+            dir.write("b.txt", [b'b'; 100]),
+        );
+        let mut progress = Progress::plan(&[a.clone(), b], None, 1).await;
+        progress.started = Instant::now() - Duration::from_secs(10);
+        assert!(progress.render().ends_with("0 B/s, ETA ?"));
+
+        // 50 B in 10 s leaves 100 B for 20 s
+        progress.apply(&started(&a, 50));
+        progress.apply(&completed(&a));
+        let render = progress.render();
+        assert!(render.ends_with("B/s, ETA 20s"), "{}", render);
+    }
+}
@@ -0,0 +1,177 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+use crate::{error::AppError, webhook};
+
+/// One stored copy of an object a receipt covers
+pub struct StoredCopy {
+    // Backend name or replica label
+    pub target: String,
+    pub bucket: String,
+    // `None` on stores or buckets that don't version objects
+    pub version_id: Option<String>,
+}
+
+/// What a receipt records of one uploaded file
+pub struct Receipt {
+    pub file: String,
+    pub key: String,
+    // Hex SHA-256 of the object as stored
+    pub sha256: String,
+    pub size: u64,
+    pub uploaded_at: DateTime<Utc>,
+    pub copies: Vec<StoredCopy>,
+}
+
+/// Writes a JSON receipt per uploaded file under `--receipts-dir`
+///
+/// A receipt is one line of JSON with its fields sorted, so the same upload always gives
+/// the same bytes. With a secret it also carries `signature`: `sha256=` and the hex
+/// HMAC-SHA256 of the receipt as written, without that field.
+pub struct Receipts {
+    dir: PathBuf,
+    secret: Option<String>,
+}
+
+impl Receipts {
+    pub fn new(dir: &Path, secret: Option<String>) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            secret,
+        }
+    }
+
+    /// Write `receipt` to `<dir>/<key>.json` and return where it went
+    pub async fn write(&self, receipt: &Receipt) -> Result<PathBuf, AppError> {
+        let copies: Vec<Value> = receipt
+            .copies
+            .iter()
+            .map(|copy| {
+                json!({
+                    "target": copy.target,
+                    "bucket": copy.bucket,
+                    "version_id": copy.version_id,
+                })
+            })
+            .collect();
+        let mut contents = json!({
+            "file": receipt.file,
+            "key": receipt.key,
+            "sha256": receipt.sha256,
+            "size": receipt.size,
+            "uploaded_at": receipt.uploaded_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+            "copies": copies,
+        });
+        if let Some(secret) = &self.secret {
+            let signature = webhook::signature(secret, &contents.to_string())?;
+            contents["signature"] = Value::String(signature);
+        }
+
+        let path = self.path(&receipt.key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::write(&path, format!("{}\n", contents)).await?;
+        Ok(path)
+    }
+
+    /// Where the receipt of `key` goes: its segments as directories, none of them able to
+    /// leave `dir`
+    fn path(&self, key: &str) -> PathBuf {
+        let mut path = self.dir.clone();
+        for segment in key.split('/') {
+            match segment {
+                "" | "." | ".." => path.push("_"),
+                segment => path.push(segment),
+            }
+        }
+        path.set_file_name(format!(
+            "{}.json",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        ));
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+    use chrono::TimeZone;
+
+    fn receipt(key: &str) -> Receipt {
+        Receipt {
+            file: "data/notes.txt".to_string(),
+            key: key.to_string(),
+            sha256: "ab".repeat(32),
+            size: 11,
+            uploaded_at: Utc.with_ymd_and_hms(2026, 10, 14, 9, 30, 0).unwrap(),
+            copies: vec![
+                StoredCopy {
+                    target: "AWS S3".to_string(),
+                    bucket: "primary".to_string(),
+                    version_id: Some("v1".to_string()),
+                },
+                StoredCopy {
+                    target: "eu-west-1".to_string(),
+                    bucket: "dr".to_string(),
+                    version_id: None,
+                },
+            ],
+        }
+    }
+
+    #[tokio::test]
+    async fn receipts_are_one_sorted_line() {
+        let dir = TestDir::new();
+        let receipts = Receipts::new(dir.path(), None);
+
+        let path = receipts.write(&receipt("text/notes.txt")).await.unwrap();
+
+        assert_eq!(path, dir.path().join("text").join("notes.txt.json"));
+        let expected = format!(
+            concat!(
+                r#"{{"copies":[{{"bucket":"primary","target":"AWS S3","version_id":"v1"}},"#,
+                r#"{{"bucket":"dr","target":"eu-west-1","version_id":null}}],"#,
+                r#""file":"data/notes.txt","key":"text/notes.txt","sha256":"{}","#,
+                r#""size":11,"uploaded_at":"2026-10-14T09:30:00.000Z"}}"#,
+                "\n"
+            ),
+            "ab".repeat(32)
+        );
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn signatures_cover_the_receipt_without_themselves() {
+        let dir = TestDir::new();
+        let receipts = Receipts::new(dir.path(), Some("receipt-secret".to_string()));
+
+        let path = receipts.write(&receipt("text/notes.txt")).await.unwrap();
+
+        let mut written: Value =
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        let signature = written
+            .as_object_mut()
+            .unwrap()
+            .remove("signature")
+            .unwrap();
+        assert_eq!(
+            signature,
+            webhook::signature("receipt-secret", &written.to_string()).unwrap()
+        );
+    }
+
+    #[test]
+    fn paths_never_leave_the_directory() {
+        let receipts = Receipts::new(Path::new("/receipts"), None);
+        assert_eq!(
+            receipts.path("../../etc/passwd"),
+            Path::new("/receipts/_/_/etc/passwd.json")
+        );
+        assert_eq!(receipts.path("a//./b"), Path::new("/receipts/a/_/_/b.json"));
+        assert_eq!(receipts.path("text/"), Path::new("/receipts/text/_.json"));
+    }
+}
@@ -0,0 +1,97 @@
+use futures::{stream, StreamExt, TryStreamExt};
+use std::collections::{HashMap, HashSet};
+
+use crate::{cli::UploadArgs, error::AppError, keys, plan::Plan, source, Backends};
+
+/// Give every planned file whose key an earlier file of the run or a stored object already
+/// has a numbered key (`--on-collision rename`); returns the new keys by file
+///
+/// Files are numbered in the order they were given: the first keeps its key if it is free,
+/// the next get `<stem>-1.<ext>`, `<stem>-2.<ext>`, ..., skipping numbers another file of
+/// the run or an object on any backend or replica has. An object already stored under a
+/// candidate with the file's own SHA-256 is that file's upload from an earlier run, so the
+/// file keeps that key and a rerun lands on the same keys. `backends` is `None` for
+/// `--dry-run`, which only numbers collisions within the batch.
+pub async fn assign(
+    plan: &mut Plan,
+    backends: Option<&Backends>,
+    args: &UploadArgs,
+) -> Result<HashMap<String, String>, AppError> {
+    // Planned keys are only ever taken by their own files, so numbers skip them
+    let reserved: HashSet<String> = plan.files.iter().map(|file| file.key.clone()).collect();
+    let stored: HashMap<String, Vec<Option<String>>> = match backends {
+        Some(backends) => {
+            stream::iter(&reserved)
+                .map(|key| async move {
+                    let digests = stored_digests(backends, key).await?;
+                    Ok::<_, AppError>((key.clone(), digests))
+                })
+                .buffer_unordered(args.concurrency.max(1))
+                .try_collect()
+                .await?
+        }
+        None => HashMap::new(),
+    };
+
+    let mut claimed = HashSet::new();
+    let mut renamed = HashMap::new();
+    for planned in &mut plan.files {
+        let mut digest = planned.digest.map(hex::encode);
+        let mut number = 0;
+        let key = loop {
+            let candidate = match number {
+                0 => planned.key.clone(),
+                _ => keys::with_number(&planned.key, number),
+            };
+            number += 1;
+            if claimed.contains(&candidate) || (number > 1 && reserved.contains(&candidate)) {
+                continue;
+            }
+
+            let digests = match (stored.get(&candidate), backends) {
+                (Some(digests), _) => digests.clone(),
+                (None, Some(backends)) => stored_digests(backends, &candidate).await?,
+                (None, None) => Vec::new(),
+            };
+            if digests.is_empty() {
+                break candidate;
+            }
+            // Hashed only once a stored object could be this file's
+            if digest.is_none() {
+                let source = source::hash_source(
+                    &planned.file,
+                    args.source_range,
+                    args.read_buffer_size,
+                    None,
+                )
+                .await?;
+                digest = Some(hex::encode(source));
+            }
+            if digests.iter().all(|stored| stored == &digest) {
+                break candidate;
+            }
+        };
+
+        claimed.insert(key.clone());
+        if key != planned.key {
+            println!(
+                "Note: {} collides on {}; uploading it as {}",
+                planned.file, planned.key, key
+            );
+            renamed.insert(planned.file.clone(), key.clone());
+            planned.key = key;
+        }
+    }
+    Ok(renamed)
+}
+
+/// SHA-256 stamped on each backend and replica that stores `key`; empty where none does
+async fn stored_digests(backends: &Backends, key: &str) -> Result<Vec<Option<String>>, AppError> {
+    let mut digests = Vec::new();
+    for target in backends.targets() {
+        if let Some(stored) = backends.head(target, key).await? {
+            digests.push(stored.sha256);
+        }
+    }
+    Ok(digests)
+}
@@ -0,0 +1,57 @@
+use bytes::Bytes;
+
+use crate::{error::AppError, hashing, Backends, ObjectMeta, Target};
+
+/// Upload `body` to `target`, then read it back and re-upload it while its SHA-256 differs
+///
+/// Catches corruption nothing else reports: a store that acknowledged the write but kept
+/// different bytes. Each re-upload is logged; after `attempts` of them the object still
+/// not matching fails the upload.
+pub async fn put_verified(
+    backends: &Backends,
+    target: Target,
+    body: Bytes,
+    key: &str,
+    meta: &ObjectMeta,
+    attempts: u32,
+) -> Result<(), AppError> {
+    let expected = meta.sha256.unwrap_or_else(|| hashing::sha256(&body));
+    let mut attempt = 0;
+    loop {
+        backends.write(target, body.clone(), key, meta).await?;
+        let stored = hashing::sha256(&backends.get(target, key, None).await?);
+        if stored == expected {
+            if attempt > 0 {
+                println!(
+                    "Repaired {} on {}: it verifies after {} re-upload(s)",
+                    key,
+                    backends.target_name(target),
+                    attempt
+                );
+            }
+            return Ok(());
+        }
+
+        if attempt == attempts {
+            return Err(AppError::Integrity(format!(
+                "{} on {} still reads back with SHA-256 {} instead of {} after {} re-upload(s)",
+                key,
+                backends.target_name(target),
+                hex::encode(stored),
+                hex::encode(expected),
+                attempts
+            )));
+        }
+        attempt += 1;
+        println!(
+            "Warning: {} on {} reads back with SHA-256 {} instead of {}; re-uploading \
+             (repair {} of {})",
+            key,
+            backends.target_name(target),
+            hex::encode(stored),
+            hex::encode(expected),
+            attempt,
+            attempts
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use std::{fmt, sync::Arc};
+
+use crate::error::AppError;
+
+/// Decides whether a request that failed with the error is sent again
+pub type RetryPredicate = dyn Fn(&AppError) -> bool + Send + Sync;
+
+/// Which failed upload requests are tried again
+///
+/// By default a request is retried when it was throttled (503 SlowDown, 429 from GCS) or
+/// stalled below `--min-throughput`; [`RetryPolicy::new`] replaces that decision with a
+/// predicate of its own, which can fall back to [`is_retryable`]. Statuses given with
+/// `--retry-on-status` are retried on top of either. Library users set it as
+/// `UploadArgs::retry_policy`.
+#[derive(Clone, Default)]
+pub struct RetryPolicy {
+    // Replaces the default decision when set
+    predicate: Option<Arc<RetryPredicate>>,
+    // --retry-on-status
+    statuses: Vec<u16>,
+}
+
+impl RetryPolicy {
+    /// Retry exactly the errors `predicate` accepts
+    pub fn new(predicate: impl Fn(&AppError) -> bool + Send + Sync + 'static) -> Self {
+        Self {
+            predicate: Some(Arc::new(predicate)),
+            statuses: Vec::new(),
+        }
+    }
+
+    /// Also retry errors with one of these HTTP statuses
+    pub fn with_statuses(mut self, statuses: &[u16]) -> Self {
+        self.statuses.extend_from_slice(statuses);
+        self
+    }
+
+    /// Whether a request that failed with `err` should be sent again
+    pub fn should_retry(&self, err: &AppError) -> bool {
+        if status(err).is_some_and(|status| self.statuses.contains(&status)) {
+            return true;
+        }
+        match &self.predicate {
+            Some(predicate) => predicate(err),
+            None => is_retryable(err),
+        }
+    }
+}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("custom", &self.predicate.is_some())
+            .field("statuses", &self.statuses)
+            .finish()
+    }
+}
+
+/// The default decision: a throttled or stalled request is retried, nothing else is
+pub fn is_retryable(err: &AppError) -> bool {
+    matches!(err, AppError::Throttled { .. } | AppError::Stalled { .. })
+}
+
+/// HTTP status a backend answered a failed request with, if it got that far
+pub fn status(err: &AppError) -> Option<u16> {
+    match err {
+        AppError::AwsSdk { status, .. } => *status,
+        AppError::HttpStatus { status, .. } | AppError::Gcs { status, .. } => Some(*status),
+        AppError::S3(s3::error::S3Error::HttpFailWithBody(status, _)) => Some(*status),
+        AppError::Http(err) => err.status().map(|status| status.as_u16()),
+        AppError::Replica { source, .. } => status(source),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gcs(status: u16) -> AppError {
+        AppError::Gcs {
+            status,
+            code: None,
+            message: "try again".to_string(),
+        }
+    }
+
+    fn throttled() -> AppError {
+        AppError::Throttled { backend: "GCS" }
+    }
+
+    #[test]
+    fn by_default_only_throttles_and_stalls_are_retried() {
+        let policy = RetryPolicy::default();
+        assert!(policy.should_retry(&throttled()));
+        assert!(policy.should_retry(&AppError::Stalled {
+            backend: "GCS",
+            min_throughput: 1024,
+        }));
+        assert!(!policy.should_retry(&gcs(500)));
+        assert!(!policy.should_retry(&AppError::Config("bad".to_string())));
+    }
+
+    #[test]
+    fn a_predicate_replaces_the_default() {
+        let policy = RetryPolicy::new(|err| status(err) == Some(500));
+        assert!(policy.should_retry(&gcs(500)));
+        assert!(!policy.should_retry(&gcs(503)));
+        assert!(!policy.should_retry(&throttled()));
+
+        let policy = RetryPolicy::new(|err| is_retryable(err) || status(err) == Some(500));
+        assert!(policy.should_retry(&throttled()));
+        assert!(policy.should_retry(&gcs(500)));
+    }
+
+    #[test]
+    fn statuses_are_retried_on_top_of_either_decision() {
+        let policy = RetryPolicy::default().with_statuses(&[502, 504]);
+        assert!(policy.should_retry(&gcs(502)));
+        assert!(policy.should_retry(&throttled()));
+        assert!(!policy.should_retry(&gcs(500)));
+
+        let policy = RetryPolicy::new(|_| false).with_statuses(&[502]);
+        assert!(policy.should_retry(&gcs(502)));
+        assert!(!policy.should_retry(&throttled()));
+    }
+
+    #[test]
+    fn statuses_are_found_through_replica_errors() {
+        let replica = AppError::Replica {
+            replica: "eu-west-1".to_string(),
+            source: Box::new(AppError::HttpStatus {
+                status: 504,
+                code: None,
+            }),
+        };
+        assert_eq!(status(&replica), Some(504));
+        assert_eq!(status(&AppError::Config("bad".to_string())), None);
+    }
+}
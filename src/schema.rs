@@ -0,0 +1,179 @@
+use jsonschema::Validator;
+use serde_json::Value;
+use std::path::Path;
+
+use crate::{
+    error::AppError,
+    ml::{Classification, FileCategory},
+};
+
+/// Errors listed per invalid document; the rest are only counted
+const MAX_REPORTED: usize = 5;
+
+/// A `--validate-json` schema, compiled once for the run
+pub struct JsonSchema {
+    validator: Validator,
+}
+
+impl JsonSchema {
+    pub fn load(path: &Path) -> Result<Self, AppError> {
+        let invalid = |problem: String| {
+            AppError::Config(format!("--validate-json {}: {}", path.display(), problem))
+        };
+        let text = std::fs::read_to_string(path).map_err(|err| invalid(err.to_string()))?;
+        let schema: Value =
+            serde_json::from_str(&text).map_err(|err| invalid(format!("not JSON: {}", err)))?;
+        let validator = jsonschema::validator_for(&schema)
+            .map_err(|err| invalid(format!("not a valid JSON Schema: {}", err)))?;
+        Ok(Self { validator })
+    }
+
+    /// Why `body` doesn't satisfy the schema, if it doesn't
+    pub fn check(&self, body: &[u8]) -> Result<(), String> {
+        let document: Value =
+            serde_json::from_slice(body).map_err(|err| format!("not valid JSON: {}", err))?;
+
+        let errors: Vec<String> = self
+            .validator
+            .iter_errors(&document)
+            .map(|err| match err.instance_path().as_str() {
+                "" => err.to_string(),
+                at => format!("{}: {}", at, err),
+            })
+            .collect();
+        if errors.is_empty() {
+            return Ok(());
+        }
+
+        let mut reason = errors[..errors.len().min(MAX_REPORTED)].join("; ");
+        if errors.len() > MAX_REPORTED {
+            reason.push_str(&format!(" (and {} more)", errors.len() - MAX_REPORTED));
+        }
+        Err(format!(
+            "doesn't match the --validate-json schema: {}",
+            reason
+        ))
+    }
+}
+
+/// Whether a file is JSON: classified with a JSON MIME type, or as text with a `.json` name
+pub fn is_json(path: &str, classification: &Classification) -> bool {
+    let mime = classification.mime.split(';').next().unwrap_or("").trim();
+    if mime == "application/json" || mime.ends_with("+json") {
+        return true;
+    }
+    classification.category == FileCategory::Text
+        && Path::new(path)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+
+    const SCHEMA: &str = r#"{
+        "type": "object",
+        "required": ["label"],
+        "properties": {
+            "label": {"type": "string"},
+            "score": {"type": "number", "minimum": 0}
+        }
+    }"#;
+
+    fn schema() -> JsonSchema {
+        let dir = TestDir::new();
+        JsonSchema::load(Path::new(&dir.write("schema.json", SCHEMA))).unwrap()
+    }
+
+    fn classified(category: FileCategory, mime: &str) -> Classification {
+        Classification {
+            key: String::new(),
+            category,
+            confidence: 1.0,
+            mime: mime.to_string(),
+        }
+    }
+
+    #[test]
+    fn valid_documents_pass() {
+        let schema = schema();
+        assert_eq!(schema.check(br#"{"label": "cat", "score": 0.9}"#), Ok(()));
+        assert_eq!(schema.check(br#"{"label": "dog"}"#), Ok(()));
+    }
+
+    #[test]
+    fn invalid_documents_say_where() {
+        let reason = schema().check(br#"{"label": 3, "score": -1}"#).unwrap_err();
+        assert!(reason.starts_with("doesn't match the --validate-json schema: "));
+        assert!(reason.contains("/label: "), "{}", reason);
+        assert!(reason.contains("/score: "), "{}", reason);
+
+        let reason = schema().check(b"{}").unwrap_err();
+        assert!(
+            reason.contains("\"label\" is a required property"),
+            "{}",
+            reason
+        );
+    }
+
+    #[test]
+    fn malformed_json_is_invalid() {
+        let reason = schema().check(b"{\"label\": ").unwrap_err();
+        assert!(reason.starts_with("not valid JSON: "), "{}", reason);
+    }
+
+    #[test]
+    fn errors_past_the_limit_are_counted() {
+        let dir = TestDir::new();
+        let path = dir.write("schema.json", r#"{"items": {"type": "string"}}"#);
+        let schema = JsonSchema::load(Path::new(&path)).unwrap();
+
+        let reason = schema.check(b"[1, 2, 3, 4, 5, 6, 7]").unwrap_err();
+        assert_eq!(reason.matches("is not of type").count(), MAX_REPORTED);
+        assert!(reason.ends_with(" (and 2 more)"), "{}", reason);
+    }
+
+    #[test]
+    fn bad_schemas_are_config_errors() {
+        let dir = TestDir::new();
+        for (contents, problem) in [
+            ("{\"type\":", "not JSON"),
+            (r#"{"type": "no-such-type"}"#, "not a valid JSON Schema"),
+        ] {
+            let path = dir.write("schema.json", contents);
+            match JsonSchema::load(Path::new(&path)) {
+                Err(AppError::Config(message)) => {
+                    assert!(message.starts_with("--validate-json "), "{}", message);
+                    assert!(message.contains(problem), "{}", message);
+                }
+                other => panic!("{:?}", other.err()),
+            }
+        }
+        let missing = dir.path().join("missing.json");
+        assert!(matches!(
+            JsonSchema::load(&missing),
+            Err(AppError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn json_is_recognized_by_mime_or_text_with_a_json_name() {
+        let text = classified(FileCategory::Text, "text/plain");
+        assert!(is_json(
+            "data.json",
+            &classified(FileCategory::Text, "application/json")
+        ));
+        assert!(is_json(
+            "a.bin",
+            &classified(FileCategory::Misc, "application/ld+json; charset=utf-8")
+        ));
+        assert!(is_json("labels.JSON", &text));
+        assert!(!is_json("labels.txt", &text));
+        assert!(!is_json(
+            "label.json",
+            &classified(FileCategory::Images, "image/png")
+        ));
+    }
+}
@@ -0,0 +1,293 @@
+use aws_credential_types::provider::{error::CredentialsError, future, ProvideCredentials};
+use aws_sdk_s3::config::Credentials;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::{
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use crate::error::AppError;
+
+/// Scheme of a `--secret-ref` naming an AWS Secrets Manager secret
+const AWS_SECRETS_MANAGER: &str = "aws-sm";
+
+/// Accepted names of each field of a secret, first the documented one
+const ACCESS_KEY_FIELDS: [&str; 3] = ["access_key_id", "aws_access_key_id", "access_key"];
+const SECRET_KEY_FIELDS: [&str; 3] = ["secret_access_key", "aws_secret_access_key", "secret_key"];
+const SESSION_TOKEN_FIELDS: [&str; 2] = ["session_token", "aws_session_token"];
+
+/// A `--secret-ref`: `aws-sm:<secret name or ARN>`
+#[derive(Debug, Clone)]
+pub struct SecretRef {
+    secret_id: String,
+}
+
+impl SecretRef {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (scheme, secret_id) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected {}:<secret-id>, got '{}'", AWS_SECRETS_MANAGER, s))?;
+        if scheme != AWS_SECRETS_MANAGER {
+            return Err(format!(
+                "unsupported secret scheme '{}' (supported: {})",
+                scheme, AWS_SECRETS_MANAGER
+            ));
+        }
+        if secret_id.is_empty() {
+            return Err(format!("'{}' names no secret", s));
+        }
+        Ok(Self {
+            secret_id: secret_id.to_string(),
+        })
+    }
+}
+
+/// What a secret holds: a key pair, and optionally a session token, S3 endpoint and expiry
+#[derive(Debug, Clone)]
+pub struct SecretCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+    pub endpoint: Option<String>,
+    expiration: Option<DateTime<Utc>>,
+}
+
+impl SecretCredentials {
+    /// From a secret string like
+    /// `{"access_key_id": "...", "secret_access_key": "...", "endpoint": "https://..."}`
+    fn parse(secret_id: &str, secret: &str) -> Result<Self, AppError> {
+        let invalid = |problem: &str| AppError::Config(format!("secret {} {}", secret_id, problem));
+        let json: Value =
+            serde_json::from_str(secret).map_err(|_| invalid("is not a JSON object"))?;
+        let field = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| json.get(*name).and_then(Value::as_str))
+                .map(str::to_string)
+        };
+
+        let expiration = match field(&["expiration"]) {
+            Some(at) => Some(
+                DateTime::parse_from_rfc3339(&at)
+                    .map_err(|_| invalid("has an expiration that isn't an RFC 3339 timestamp"))?
+                    .with_timezone(&Utc),
+            ),
+            None => None,
+        };
+
+        Ok(Self {
+            access_key_id: field(&ACCESS_KEY_FIELDS)
+                .ok_or_else(|| invalid("has no access_key_id"))?,
+            secret_access_key: field(&SECRET_KEY_FIELDS)
+                .ok_or_else(|| invalid("has no secret_access_key"))?,
+            session_token: field(&SESSION_TOKEN_FIELDS),
+            endpoint: field(&["endpoint"]),
+            expiration,
+        })
+    }
+}
+
+/// Credentials provider of the AWS SDK backed by a Secrets Manager secret
+///
+/// The SDK caches what it returns and calls again shortly before it expires: after
+/// `ttl`, so a rotated secret is picked up during long runs, or earlier at the secret's
+/// own `expiration`.
+#[derive(Debug)]
+pub struct SecretProvider {
+    client: aws_sdk_secretsmanager::Client,
+    secret_id: String,
+    ttl: Duration,
+    // The secret read at startup, handed out first instead of being read again
+    first: Mutex<Option<SecretCredentials>>,
+}
+
+impl SecretProvider {
+    /// Read the secret once, so a missing or malformed one fails before anything runs
+    pub async fn load(
+        client: aws_sdk_secretsmanager::Client,
+        reference: &SecretRef,
+        ttl: Duration,
+    ) -> Result<(Self, SecretCredentials), AppError> {
+        let secret = fetch(&client, &reference.secret_id).await?;
+        let provider = Self {
+            client,
+            secret_id: reference.secret_id.clone(),
+            ttl,
+            first: Mutex::new(Some(secret.clone())),
+        };
+        Ok((provider, secret))
+    }
+
+    async fn credentials(&self) -> Result<Credentials, CredentialsError> {
+        let first = self.first.lock().unwrap().take();
+        let secret = match first {
+            Some(secret) => secret,
+            None => fetch(&self.client, &self.secret_id)
+                .await
+                .map_err(CredentialsError::provider_error)?,
+        };
+
+        let refresh_at = SystemTime::now() + self.ttl;
+        let expiry = match secret.expiration {
+            Some(at) => refresh_at.min(at.into()),
+            None => refresh_at,
+        };
+        Ok(Credentials::new(
+            secret.access_key_id,
+            secret.secret_access_key,
+            secret.session_token,
+            Some(expiry),
+            "secret-ref",
+        ))
+    }
+}
+
+impl ProvideCredentials for SecretProvider {
+    fn provide_credentials<'a>(&'a self) -> future::ProvideCredentials<'a>
+    where
+        Self: 'a,
+    {
+        future::ProvideCredentials::new(self.credentials())
+    }
+}
+
+async fn fetch(
+    client: &aws_sdk_secretsmanager::Client,
+    secret_id: &str,
+) -> Result<SecretCredentials, AppError> {
+    let output = client
+        .get_secret_value()
+        .secret_id(secret_id)
+        .send()
+        .await
+        .map_err(|err| {
+            // Not an S3 request, so not worded as one
+            let reason = match AppError::from(err) {
+                AppError::AwsSdk {
+                    code: Some(code),
+                    message,
+                    ..
+                } => format!("{}: {}", code, message),
+                AppError::AwsSdk { message, .. } => message,
+                other => other.to_string(),
+            };
+            AppError::Config(format!("could not read secret {}: {}", secret_id, reason))
+        })?;
+    let secret = output
+        .secret_string()
+        .ok_or_else(|| AppError::Config(format!("secret {} has no string value", secret_id)))?;
+    SecretCredentials::parse(secret_id, secret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn references_need_the_aws_sm_scheme_and_a_secret() {
+        assert_eq!(
+            SecretRef::parse("aws-sm:ml/uploader").unwrap().secret_id,
+            "ml/uploader"
+        );
+        // ARNs hold colons of their own
+        let arn = "arn:aws:secretsmanager:us-east-1:123456789012:secret:ml-AbCdEf";
+        assert_eq!(
+            SecretRef::parse(&format!("aws-sm:{}", arn))
+                .unwrap()
+                .secret_id,
+            arn
+        );
+
+        assert!(SecretRef::parse("ml/uploader").is_err());
+        assert!(SecretRef::parse("vault:ml/uploader").is_err());
+        assert!(SecretRef::parse("aws-sm:").is_err());
+    }
+
+    #[test]
+    fn secrets_accept_each_field_name() {
+        let secret = SecretCredentials::parse(
+            "ml",
+            r#"{"aws_access_key_id": "AKID", "secret_key": "secret",
+                "aws_session_token": "token", "endpoint": "https://s3.example.com",
+                "expiration": "2030-01-02T03:04:05Z"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(secret.access_key_id, "AKID");
+        assert_eq!(secret.secret_access_key, "secret");
+        assert_eq!(secret.session_token.as_deref(), Some("token"));
+        assert_eq!(secret.endpoint.as_deref(), Some("https://s3.example.com"));
+        assert_eq!(
+            secret.expiration.map(|at| at.to_rfc3339()).as_deref(),
+            Some("2030-01-02T03:04:05+00:00")
+        );
+    }
+
+    #[test]
+    fn malformed_secrets_name_their_problem() {
+        let problem = |secret: &str| {
+            SecretCredentials::parse("ml", secret)
+                .unwrap_err()
+                .to_string()
+        };
+
+        assert!(problem("not json").contains("secret ml is not a JSON object"));
+        assert!(problem(r#"{"secret_access_key": "s"}"#).contains("has no access_key_id"));
+        assert!(problem(r#"{"access_key_id": "a"}"#).contains("has no secret_access_key"));
+        assert!(problem(
+            r#"{"access_key_id": "a", "secret_access_key": "s", "expiration": "tomorrow"}"#
+        )
+        .contains("isn't an RFC 3339 timestamp"));
+    }
+
+    fn provider(secret: &str, ttl: Duration) -> SecretProvider {
+        let config = aws_sdk_secretsmanager::Config::builder()
+            .behavior_version(aws_sdk_secretsmanager::config::BehaviorVersion::latest())
+            .region(aws_sdk_secretsmanager::config::Region::new("us-east-1"))
+            .build();
+        SecretProvider {
+            client: aws_sdk_secretsmanager::Client::from_conf(config),
+            secret_id: "ml".to_string(),
+            ttl,
+            first: Mutex::new(Some(SecretCredentials::parse("ml", secret).unwrap())),
+        }
+    }
+
+    #[tokio::test]
+    async fn credentials_expire_after_the_ttl() {
+        let provider = provider(
+            r#"{"access_key_id": "AKID", "secret_access_key": "secret"}"#,
+            Duration::from_secs(900),
+        );
+
+        let credentials = provider.credentials().await.unwrap();
+
+        assert_eq!(credentials.access_key_id(), "AKID");
+        let left = credentials
+            .expiry()
+            .unwrap()
+            .duration_since(SystemTime::now())
+            .unwrap();
+        assert!(left <= Duration::from_secs(900) && left > Duration::from_secs(890));
+    }
+
+    #[tokio::test]
+    async fn credentials_expire_with_the_secret_if_that_is_sooner() {
+        let expiration = Utc::now() + chrono::Duration::seconds(60);
+        let secret = format!(
+            r#"{{"access_key_id": "AKID", "secret_access_key": "secret", "expiration": "{}"}}"#,
+            expiration.to_rfc3339()
+        );
+        let provider = provider(&secret, Duration::from_secs(900));
+
+        let credentials = provider.credentials().await.unwrap();
+
+        let left = credentials
+            .expiry()
+            .unwrap()
+            .duration_since(SystemTime::now())
+            .unwrap();
+        assert!(left <= Duration::from_secs(60), "{:?}", left);
+    }
+}
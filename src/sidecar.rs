@@ -0,0 +1,135 @@
+use std::{collections::HashSet, path::Path};
+use tokio::fs;
+
+use crate::inputs::ExtFilter;
+
+/// Name of the user metadata entry holding an embedded sidecar
+pub const METADATA_NAME: &str = "sidecar";
+
+/// S3 caps user metadata at 2 KB per object, leave room for other entries
+const MAX_EMBEDDED_LEN: usize = 1536;
+
+/// Path of the sidecar for `file` if one exists on disk
+pub async fn find_sidecar(file: &str, suffix: &str) -> Option<String> {
+    let sidecar = format!("{}{}", file, suffix);
+
+    match fs::metadata(&sidecar).await {
+        Ok(meta) if meta.is_file() => Some(sidecar),
+        _ => None,
+    }
+}
+
+/// Drop inputs that are the sidecar of another input; they are uploaded with their data file
+pub fn without_paired_sidecars(files: &[String], suffix: &str) -> Vec<String> {
+    let inputs: HashSet<&str> = files.iter().map(String::as_str).collect();
+
+    files
+        .iter()
+        .filter(|file| {
+            file.strip_suffix(suffix)
+                .is_none_or(|data| data.is_empty() || !inputs.contains(data))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Whether a file found by `--stream` is the sidecar of a data file the walk also uploads
+pub fn is_paired_sidecar(file: &str, suffix: &str, filter: &ExtFilter) -> bool {
+    file.strip_suffix(suffix)
+        .is_some_and(|data| !data.is_empty() && Path::new(data).is_file() && filter.allows(data))
+}
+
+/// Key of the sidecar object, mirroring the data file's key
+pub fn sidecar_key(data_key: &str, suffix: &str) -> String {
+    format!("{}{}", data_key, suffix)
+}
+
+/// Compact JSON of the sidecar if it is small enough to embed as object metadata
+pub async fn metadata_value(sidecar: &str) -> Option<String> {
+    let content = fs::read(sidecar).await.ok()?;
+
+    let compact = match serde_json::from_slice::<serde_json::Value>(&content) {
+        Ok(value) => value.to_string(),
+        Err(_) => {
+            println!("Sidecar {} is not valid JSON, not embedding it", sidecar);
+            return None;
+        }
+    };
+
+    if compact.len() > MAX_EMBEDDED_LEN {
+        println!("Sidecar {} is too large to embed as metadata", sidecar);
+        return None;
+    }
+    // Metadata values travel as HTTP headers and must stay ASCII
+    if !compact.is_ascii() {
+        println!(
+            "Sidecar {} holds non-ASCII text, which metadata can't carry; not embedding it",
+            sidecar
+        );
+        return None;
+    }
+
+    Some(compact)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+
+    #[tokio::test]
+    async fn sidecar_is_found_only_when_present() {
+        let dir = TestDir::new();
+        let with = dir.write("a.bin", "data");
+        let without = dir.write("b.bin", "data");
+        dir.write("a.bin.json", r#"{"label": "cat"}"#);
+        // A directory named like a sidecar is not one
+        std::fs::create_dir(dir.path().join("b.bin.json")).unwrap();
+
+        assert_eq!(
+            find_sidecar(&with, ".json").await,
+            Some(format!("{}.json", with))
+        );
+        assert_eq!(find_sidecar(&without, ".json").await, None);
+        assert_eq!(find_sidecar(&with, ".meta").await, None);
+    }
+
+    #[test]
+    fn paired_sidecars_are_dropped_from_inputs() {
+        let files = [
+            "a.bin".to_string(),
+            "a.bin.json".to_string(),
+            "orphan.json".to_string(),
+            ".json".to_string(),
+        ];
+        assert_eq!(
+            without_paired_sidecars(&files, ".json"),
+            ["a.bin", "orphan.json", ".json"]
+        );
+    }
+
+    #[test]
+    fn sidecar_key_mirrors_the_data_key() {
+        assert_eq!(
+            sidecar_key("images/cat.png", ".json"),
+            "images/cat.png.json"
+        );
+    }
+
+    #[tokio::test]
+    async fn only_small_ascii_json_is_embedded() {
+        let dir = TestDir::new();
+        let small = dir.write("small.json", r#"{ "label":  "cat" }"#);
+        let large = dir.write("large.json", format!(r#"{{"x": "{}"}}"#, "a".repeat(2000)));
+        let unicode = dir.write("unicode.json", r#"{"label": "кошка"}"#);
+        let invalid = dir.write("invalid.json", "not json");
+
+        assert_eq!(
+            metadata_value(&small).await.as_deref(),
+            Some(r#"{"label":"cat"}"#)
+        );
+        assert_eq!(metadata_value(&large).await, None);
+        assert_eq!(metadata_value(&unicode).await, None);
+        assert_eq!(metadata_value(&invalid).await, None);
+    }
+}
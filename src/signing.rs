@@ -0,0 +1,234 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::Semaphore;
+
+use crate::{
+    error::AppError,
+    hashing::{self, Sha256Digest},
+};
+
+/// A SigV4 signing key: HMAC-SHA256 output
+pub type DerivedKey = [u8; 32];
+
+/// SHA-256 of the secret key, date, region and service a key is derived for
+type Scope = (Sha256Digest, String, String, String);
+
+/// Computes the SigV4 signatures of one set of backends, reusing derived signing keys
+///
+/// Every request of a batch is signed for the same date, region and service, so the four
+/// HMACs of the derivation run once per scope instead of once per request. Scopes are
+/// keyed by a hash of the secret, which is never kept. With `--signing-threads` the
+/// signatures are computed on Tokio's blocking pool, at most that many at once, so a large
+/// batch doesn't spend the async workers on HMACs.
+#[derive(Default)]
+pub struct Signer {
+    keys: Mutex<HashMap<Scope, DerivedKey>>,
+    // `None` signs on the calling task
+    blocking: Option<Semaphore>,
+}
+
+impl Signer {
+    /// Sign on the blocking pool, `threads` signatures at once (`--signing-threads`)
+    pub fn with_threads(threads: Option<usize>) -> Self {
+        Self {
+            keys: Mutex::default(),
+            blocking: threads.map(|threads| Semaphore::new(threads.max(1))),
+        }
+    }
+
+    /// Hex signature of `string_to_sign` for `date` (`YYYYMMDD`, UTC), `region` and `service`
+    pub async fn signature(
+        self: &Arc<Self>,
+        secret_key: &str,
+        date: &str,
+        region: &str,
+        service: &str,
+        string_to_sign: String,
+    ) -> Result<String, AppError> {
+        let Some(blocking) = &self.blocking else {
+            return self.sign(secret_key, date, region, service, &string_to_sign);
+        };
+        let _slot = blocking
+            .acquire()
+            .await
+            .expect("the signing semaphore is never closed");
+        let signer = Arc::clone(self);
+        let (secret_key, date, region, service) = (
+            secret_key.to_string(),
+            date.to_string(),
+            region.to_string(),
+            service.to_string(),
+        );
+        tokio::task::spawn_blocking(move || {
+            signer.sign(&secret_key, &date, &region, &service, &string_to_sign)
+        })
+        .await?
+    }
+
+    fn sign(
+        &self,
+        secret_key: &str,
+        date: &str,
+        region: &str,
+        service: &str,
+        string_to_sign: &str,
+    ) -> Result<String, AppError> {
+        let signing_key = self.signing_key(secret_key, date, region, service)?;
+        Ok(hex::encode(hmac(&signing_key, string_to_sign.as_bytes())?))
+    }
+
+    /// The key requests of `date` to `service` in `region` are signed with
+    ///
+    /// Derived once and reused; keys of other days are dropped when a new day's is derived,
+    /// since a date only changes at UTC midnight.
+    pub fn signing_key(
+        &self,
+        secret_key: &str,
+        date: &str,
+        region: &str,
+        service: &str,
+    ) -> Result<DerivedKey, AppError> {
+        let scope = (
+            hashing::sha256(secret_key.as_bytes()),
+            date.to_string(),
+            region.to_string(),
+            service.to_string(),
+        );
+        // A poisoned cache only ever holds fully derived keys
+        let mut keys = self
+            .keys
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(derived) = keys.get(&scope) {
+            return Ok(*derived);
+        }
+
+        let derived = derive(secret_key, date, region, service)?;
+        keys.retain(|(_, cached_date, _, _), _| cached_date == date);
+        keys.insert(scope, derived);
+        Ok(derived)
+    }
+}
+
+/// The SigV4 derivation: `AWS4<secret>` HMAC'd with the date, region, service and
+/// `aws4_request` in turn
+fn derive(
+    secret_key: &str,
+    date: &str,
+    region: &str,
+    service: &str,
+) -> Result<DerivedKey, AppError> {
+    let mut derived = hmac(format!("AWS4{}", secret_key).as_bytes(), date.as_bytes())?;
+    for part in [region.as_bytes(), service.as_bytes(), b"aws4_request"] {
+        derived = hmac(&derived, part)?;
+    }
+    Ok(derived)
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Result<DerivedKey, AppError> {
+    let mut hmac = Hmac::<Sha256>::new_from_slice(key)?;
+    hmac.update(data);
+    Ok(hmac.finalize().into_bytes().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The example of AWS's "Examples of how to derive a signing key" page
+    const SECRET: &str = "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY";
+    const EXAMPLE_KEY: &str = "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d";
+
+    #[test]
+    fn derivation_matches_the_aws_example() {
+        let derived = derive(SECRET, "20120215", "us-east-1", "iam").unwrap();
+        assert_eq!(hex::encode(derived), EXAMPLE_KEY);
+    }
+
+    #[test]
+    fn cached_keys_equal_a_fresh_derivation() {
+        let signer = Signer::default();
+        for _ in 0..3 {
+            let cached = signer
+                .signing_key(SECRET, "20120215", "us-east-1", "iam")
+                .unwrap();
+            assert_eq!(hex::encode(cached), EXAMPLE_KEY);
+        }
+        assert_eq!(signer.keys.lock().unwrap().len(), 1);
+
+        for (secret, region, service) in [
+            ("other-secret", "us-east-1", "iam"),
+            (SECRET, "eu-west-1", "iam"),
+            (SECRET, "us-east-1", "s3"),
+        ] {
+            let cached = signer
+                .signing_key(secret, "20120215", region, service)
+                .unwrap();
+            assert_eq!(cached, derive(secret, "20120215", region, service).unwrap());
+        }
+        assert_eq!(signer.keys.lock().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn a_new_day_drops_the_keys_of_earlier_ones() {
+        let signer = Signer::default();
+        signer
+            .signing_key(SECRET, "20120215", "us-east-1", "s3")
+            .unwrap();
+        signer
+            .signing_key(SECRET, "20120215", "eu-west-1", "s3")
+            .unwrap();
+
+        let next_day = signer
+            .signing_key(SECRET, "20120216", "us-east-1", "s3")
+            .unwrap();
+
+        assert_eq!(
+            next_day,
+            derive(SECRET, "20120216", "us-east-1", "s3").unwrap()
+        );
+        let keys = signer.keys.lock().unwrap();
+        assert_eq!(keys.len(), 1);
+        assert!(keys.keys().all(|(_, date, _, _)| date == "20120216"));
+    }
+
+    #[test]
+    fn secrets_are_kept_only_hashed() {
+        let signer = Signer::default();
+        signer
+            .signing_key(SECRET, "20120215", "us-east-1", "s3")
+            .unwrap();
+        let keys = signer.keys.lock().unwrap();
+        let (secret, ..) = keys.keys().next().unwrap();
+        assert_eq!(*secret, hashing::sha256(SECRET.as_bytes()));
+    }
+
+    #[tokio::test]
+    async fn the_blocking_pool_signs_like_the_calling_task() {
+        let inline = Arc::new(Signer::default());
+        let pooled = Arc::new(Signer::with_threads(Some(2)));
+        let sign = |signer: Arc<Signer>, n: usize| async move {
+            signer
+                .signature(
+                    SECRET,
+                    "20120215",
+                    "us-east-1",
+                    "s3",
+                    format!("request {}", n),
+                )
+                .await
+                .unwrap()
+        };
+
+        let pooled: Vec<String> =
+            futures::future::join_all((0..8).map(|n| sign(Arc::clone(&pooled), n))).await;
+        for (n, signature) in pooled.iter().enumerate() {
+            assert_eq!(*signature, sign(Arc::clone(&inline), n).await);
+        }
+        assert_eq!(pooled.len(), 8);
+    }
+}
@@ -0,0 +1,826 @@
+use bytes::Bytes;
+use std::{fmt, future::Future, io::SeekFrom, str::FromStr, time::Duration};
+use tokio::{
+    fs::{self, File},
+    io::{AsyncRead, AsyncReadExt, AsyncSeekExt, Take},
+    time::sleep,
+};
+
+use crate::{
+    error::AppError,
+    hashing::{HashingReader, Sha256Digest},
+    merkle,
+    scan::FileScan,
+};
+
+/// Half-open byte range `[start, end)` of a source file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceRange {
+    pub start: u64,
+    // `None` means up to the end of the file
+    pub end: Option<u64>,
+}
+
+impl FromStr for SourceRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected START:END, got '{}'", s))?;
+
+        let parse = |value: &str| {
+            value
+                .trim()
+                .parse::<u64>()
+                .map_err(|_| format!("invalid byte offset '{}'", value))
+        };
+
+        let start = if start.trim().is_empty() {
+            0
+        } else {
+            parse(start)?
+        };
+        let end = if end.trim().is_empty() {
+            None
+        } else {
+            Some(parse(end)?)
+        };
+
+        if let Some(end) = end {
+            if end < start {
+                return Err(format!("inverted range {}:{}", start, end));
+            }
+        }
+
+        Ok(Self { start, end })
+    }
+}
+
+impl fmt::Display for SourceRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.end {
+            Some(end) => write!(f, "{}:{}", self.start, end),
+            None => write!(f, "{}:", self.start),
+        }
+    }
+}
+
+impl SourceRange {
+    /// Resolve the range against a file size, rejecting out-of-bounds ranges
+    pub fn resolve(&self, size: u64) -> Result<(u64, u64), String> {
+        let end = self.end.unwrap_or(size);
+
+        if self.start > size || end > size {
+            return Err(format!(
+                "range {} is out of bounds for a {} byte file",
+                self, size
+            ));
+        }
+
+        Ok((self.start, end))
+    }
+}
+
+/// Attempts made with `--retry-on-change` before giving up on a file
+const CHANGE_ATTEMPTS: u32 = 3;
+
+/// Pause between attempts so the writer has a chance to finish
+const CHANGE_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Upload body of a file and its SHA-256, computed while it was read
+pub struct SourceBody {
+    pub bytes: Bytes,
+    pub sha256: Sha256Digest,
+}
+
+/// Read the upload body of a file, limited to `range` if given
+///
+/// The file is read `buffer_size` bytes per read call. Fails with `AppError::FileChanged`
+/// if the file's size moves while it is read, so a truncated or padded body is never
+/// uploaded.
+pub async fn read_source(
+    path: &str,
+    range: Option<SourceRange>,
+    buffer_size: usize,
+) -> Result<SourceBody, AppError> {
+    let (bytes, reader) = read_through(path, range, buffer_size, HashingReader::new).await?;
+    Ok(SourceBody {
+        bytes,
+        sha256: reader.finish(),
+    })
+}
+
+/// `read_source` without the SHA-256, for a body nothing signs, stamps or checks
+pub async fn read_unhashed(
+    path: &str,
+    range: Option<SourceRange>,
+    buffer_size: usize,
+) -> Result<Bytes, AppError> {
+    let (bytes, _) = read_through(path, range, buffer_size, |reader| reader).await?;
+    Ok(bytes)
+}
+
+/// Read the upload body of a file through the reader `wrap` puts around it, returned with
+/// the body for whatever it took along the way
+async fn read_through<R: AsyncRead + Unpin>(
+    path: &str,
+    range: Option<SourceRange>,
+    buffer_size: usize,
+    wrap: impl FnOnce(Take<File>) -> R,
+) -> Result<(Bytes, R), AppError> {
+    let mut file = File::open(path).await?;
+    // Tokio otherwise caps each blocking read at 2 MiB
+    file.set_max_buf_size(buffer_size);
+    let size = file.metadata().await?.len();
+
+    let (start, end) = match range {
+        Some(range) => range
+            .resolve(size)
+            .map_err(|e| AppError::Config(format!("--source-range for {}: {}", path, e)))?,
+        None => (0, size),
+    };
+
+    // When reading to the end, one byte past it reveals a file that is still growing
+    let limit = if end == size {
+        end - start + 1
+    } else {
+        end - start
+    };
+
+    let mut buffer = Vec::with_capacity((end - start) as usize);
+    file.seek(SeekFrom::Start(start)).await?;
+    let mut reader = wrap(file.take(limit));
+    let (mut chunk, mut offset) = (vec![0; buffer_size], start);
+    loop {
+        let read = read_at(&mut reader, &mut chunk, path, &mut offset).await?;
+        if read == 0 {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+
+    // A short read means the file shrank; a changed size means it was rewritten
+    let actual = fs::metadata(path).await?.len();
+    if buffer.len() as u64 != end - start || actual != size {
+        return Err(AppError::FileChanged {
+            path: path.to_string(),
+            expected: size,
+            actual,
+        });
+    }
+
+    Ok((buffer.into(), reader))
+}
+
+/// Read a source whose size its metadata doesn't tell (a named pipe, a device) as exactly
+/// `len` bytes, the `--content-length` given for it
+///
+/// The source is read to its end either way, so `AppError::LengthMismatch` reports how
+/// many bytes it really had.
+pub async fn read_with_length(
+    path: &str,
+    len: u64,
+    buffer_size: usize,
+) -> Result<SourceBody, AppError> {
+    let mut file = File::open(path).await?;
+    file.set_max_buf_size(buffer_size);
+
+    let mut buffer = Vec::with_capacity(len as usize);
+    let mut reader = HashingReader::new((&mut file).take(len));
+    let (mut chunk, mut actual) = (vec![0; buffer_size], 0);
+    loop {
+        let read = read_at(&mut reader, &mut chunk, path, &mut actual).await?;
+        if read == 0 {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+    let sha256 = reader.finish();
+
+    // Anything past `len` is only counted, for the error
+    while read_at(&mut file, &mut chunk, path, &mut actual).await? > 0 {}
+    if actual != len {
+        return Err(AppError::LengthMismatch {
+            path: path.to_string(),
+            expected: len,
+            actual,
+        });
+    }
+
+    Ok(SourceBody {
+        bytes: buffer.into(),
+        sha256,
+    })
+}
+
+/// At most `len` leading bytes of a file's upload body (of `range` if given)
+///
+/// Lets a classifier that only looks at a prefix see it without the whole file being read.
+pub async fn read_prefix(
+    path: &str,
+    range: Option<SourceRange>,
+    len: usize,
+) -> Result<Bytes, AppError> {
+    let mut file = File::open(path).await?;
+    let size = file.metadata().await?.len();
+
+    let (start, end) = match range {
+        Some(range) => range
+            .resolve(size)
+            .map_err(|e| AppError::Config(format!("--source-range for {}: {}", path, e)))?,
+        None => (0, size),
+    };
+
+    let mut prefix = Vec::with_capacity(len.min((end - start) as usize));
+    file.seek(SeekFrom::Start(start)).await?;
+    (&mut file)
+        .take((end - start).min(len as u64))
+        .read_to_end(&mut prefix)
+        .await
+        .map_err(|err| read_failed(path, start + prefix.len() as u64, err))?;
+    Ok(prefix.into())
+}
+
+/// Open a file at the start of its upload body (of `range` if given)
+///
+/// Returns the file, already positioned, where the body starts, its length and the file's size.
+async fn open_body(
+    path: &str,
+    range: Option<SourceRange>,
+    buffer_size: usize,
+) -> Result<(File, u64, u64, u64), AppError> {
+    let mut file = File::open(path).await?;
+    file.set_max_buf_size(buffer_size);
+    let size = file.metadata().await?.len();
+
+    let (start, end) = match range {
+        Some(range) => range
+            .resolve(size)
+            .map_err(|e| AppError::Config(format!("--source-range for {}: {}", path, e)))?,
+        None => (0, size),
+    };
+    file.seek(SeekFrom::Start(start)).await?;
+    Ok((file, start, end - start, size))
+}
+
+/// Length of a file's upload body, without reading it
+pub async fn body_len(path: &str, range: Option<SourceRange>) -> Result<u64, AppError> {
+    let size = fs::metadata(path).await?.len();
+    match range {
+        Some(range) => {
+            let (start, end) = range
+                .resolve(size)
+                .map_err(|e| AppError::Config(format!("--source-range for {}: {}", path, e)))?;
+            Ok(end - start)
+        }
+        None => Ok(size),
+    }
+}
+
+/// SHA-256 of a file's upload body, hashed as it is read instead of held in memory
+///
+/// Every chunk read is also fed to `scan`, if given.
+pub async fn hash_source(
+    path: &str,
+    range: Option<SourceRange>,
+    buffer_size: usize,
+    mut scan: Option<&mut FileScan<'_>>,
+) -> Result<Sha256Digest, AppError> {
+    let (file, start, len, _) = open_body(path, range, buffer_size).await?;
+    let mut reader = HashingReader::new(file.take(len));
+    let (mut chunk, mut offset) = (vec![0; buffer_size], start);
+    loop {
+        let read = read_at(&mut reader, &mut chunk, path, &mut offset).await?;
+        if read == 0 {
+            break;
+        }
+        if let Some(scan) = scan.as_deref_mut() {
+            scan.feed(&chunk[..read])?;
+        }
+    }
+    Ok(reader.finish())
+}
+
+/// SHA-256 of a file's upload body and the Merkle leaf hash of each of its `part_size` parts
+///
+/// One read like [`hash_source`], so a streamed file gets its tree without another pass.
+pub async fn hash_parts(
+    path: &str,
+    range: Option<SourceRange>,
+    buffer_size: usize,
+    part_size: usize,
+    mut scan: Option<&mut FileScan<'_>>,
+) -> Result<(Sha256Digest, Vec<Sha256Digest>), AppError> {
+    let mut reader = PartReader::open(path, range, buffer_size, part_size).await?;
+    let mut leaves = Vec::new();
+    while let Some(part) = reader.next_part().await? {
+        if let Some(scan) = scan.as_deref_mut() {
+            scan.feed(&part)?;
+        }
+        leaves.push(merkle::leaf(&part));
+    }
+    Ok((reader.finish().await?, leaves))
+}
+
+/// A file's upload body read in parts of a fixed size, hashed on the way
+pub struct PartReader {
+    path: String,
+    reader: HashingReader<Take<File>>,
+    part_size: usize,
+    // Offset in the file of the next byte to read
+    offset: u64,
+    remaining: u64,
+    // Size of the whole file when it was opened
+    size: u64,
+}
+
+impl PartReader {
+    pub async fn open(
+        path: &str,
+        range: Option<SourceRange>,
+        buffer_size: usize,
+        part_size: usize,
+    ) -> Result<Self, AppError> {
+        let (file, start, len, size) = open_body(path, range, buffer_size).await?;
+        Ok(Self {
+            path: path.to_string(),
+            reader: HashingReader::new(file.take(len)),
+            part_size,
+            offset: start,
+            remaining: len,
+            size,
+        })
+    }
+
+    /// The next `part_size` bytes (fewer for the last part), `None` after the last
+    ///
+    /// A read error fails with `AppError::ReadFailed` at the offset it hit, never with a
+    /// short part, so a truncated body is never uploaded.
+    pub async fn next_part(&mut self) -> Result<Option<Bytes>, AppError> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+
+        let len = self.remaining.min(self.part_size as u64) as usize;
+        let mut part = vec![0; len];
+        // The file shrank
+        if fill(&mut self.reader, &mut part, &self.path, &mut self.offset).await? < len {
+            let actual = fs::metadata(&self.path).await?.len();
+            return Err(AppError::FileChanged {
+                path: self.path.clone(),
+                expected: self.size,
+                actual,
+            });
+        }
+        self.remaining -= len as u64;
+        Ok(Some(part.into()))
+    }
+
+    /// Digest of every part read, failing if the file's size changed meanwhile
+    pub async fn finish(self) -> Result<Sha256Digest, AppError> {
+        let actual = fs::metadata(&self.path).await?.len();
+        if actual != self.size {
+            return Err(AppError::FileChanged {
+                path: self.path,
+                expected: self.size,
+                actual,
+            });
+        }
+        Ok(self.reader.finish())
+    }
+}
+
+fn read_failed(path: &str, offset: u64, source: std::io::Error) -> AppError {
+    AppError::ReadFailed {
+        path: path.to_string(),
+        offset,
+        source,
+    }
+}
+
+/// One read of `path` into `buf`, moving `offset` past the bytes read
+///
+/// An error is reported at `offset`, the first byte that couldn't be read.
+async fn read_at<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    buf: &mut [u8],
+    path: &str,
+    offset: &mut u64,
+) -> Result<usize, AppError> {
+    let read = reader
+        .read(buf)
+        .await
+        .map_err(|err| read_failed(path, *offset, err))?;
+    *offset += read as u64;
+    Ok(read)
+}
+
+/// Read until `buf` is full or the end is reached, returning how much was filled
+async fn fill<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    buf: &mut [u8],
+    path: &str,
+    offset: &mut u64,
+) -> Result<usize, AppError> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match read_at(reader, &mut buf[filled..], path, offset).await? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+    Ok(filled)
+}
+
+/// `read_source`, re-reading a file that changed while it was read when `retry` is set
+pub async fn read_source_retrying(
+    path: &str,
+    range: Option<SourceRange>,
+    buffer_size: usize,
+    retry: bool,
+) -> Result<SourceBody, AppError> {
+    retrying(retry, || read_source(path, range, buffer_size)).await
+}
+
+/// `read_unhashed`, re-reading a file that changed while it was read when `retry` is set
+pub async fn read_unhashed_retrying(
+    path: &str,
+    range: Option<SourceRange>,
+    buffer_size: usize,
+    retry: bool,
+) -> Result<Bytes, AppError> {
+    retrying(retry, || read_unhashed(path, range, buffer_size)).await
+}
+
+async fn retrying<T, F>(retry: bool, mut read: impl FnMut() -> F) -> Result<T, AppError>
+where
+    F: Future<Output = Result<T, AppError>>,
+{
+    let attempts = if retry { CHANGE_ATTEMPTS } else { 1 };
+    let mut attempt = 1;
+
+    loop {
+        match read().await {
+            Err(err @ AppError::FileChanged { .. }) if attempt < attempts => {
+                eprintln!("{}, retrying ({}/{})", err, attempt, attempts - 1);
+                sleep(CHANGE_RETRY_DELAY).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hashing::sha256, testdir::TestDir};
+    use std::{
+        io::Write,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    const CONTENT: &[u8] = b"0123456789abcdefghij";
+
+    fn range(s: &str) -> SourceRange {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_open_and_closed_ranges() {
+        assert_eq!(
+            range("4:10"),
+            SourceRange {
+                start: 4,
+                end: Some(10)
+            }
+        );
+        assert_eq!(
+            range(":10"),
+            SourceRange {
+                start: 0,
+                end: Some(10)
+            }
+        );
+        assert_eq!(
+            range("4:"),
+            SourceRange {
+                start: 4,
+                end: None
+            }
+        );
+        assert_eq!(
+            range(":"),
+            SourceRange {
+                start: 0,
+                end: None
+            }
+        );
+        assert_eq!(range("4:").to_string(), "4:");
+
+        assert!("10".parse::<SourceRange>().is_err());
+        assert!("a:10".parse::<SourceRange>().is_err());
+        assert!("10:4".parse::<SourceRange>().is_err());
+    }
+
+    #[test]
+    fn resolves_against_the_file_size() {
+        assert_eq!(range(":").resolve(20), Ok((0, 20)));
+        assert_eq!(range(":5").resolve(20), Ok((0, 5)));
+        assert_eq!(range("15:").resolve(20), Ok((15, 20)));
+        assert_eq!(range("20:").resolve(20), Ok((20, 20)));
+
+        assert!(range(":21").resolve(20).is_err());
+        assert!(range("21:").resolve(20).is_err());
+    }
+
+    async fn read(path: &str, range: Option<&str>) -> SourceBody {
+        read_source(path, range.map(|r| r.parse().unwrap()), 4)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn prefixes_stop_at_their_length_or_the_range() {
+        let dir = TestDir::new();
+        let path = dir.write("source.bin", CONTENT);
+
+        for (bounds, len, expected) in [
+            (None, 4, &CONTENT[..4]),
+            (None, 100, CONTENT),
+            (Some("5:15"), 4, &CONTENT[5..9]),
+            (Some("5:15"), 100, &CONTENT[5..15]),
+            (Some("20:"), 4, &CONTENT[20..]),
+        ] {
+            let prefix = read_prefix(&path, bounds.map(range), len).await.unwrap();
+            assert_eq!(&prefix[..], expected, "range {:?}, len {}", bounds, len);
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_the_whole_file_a_prefix_or_a_suffix() {
+        let dir = TestDir::new();
+        let path = dir.write("source.bin", CONTENT);
+
+        for (range, expected) in [
+            (None, CONTENT),
+            (Some(":"), CONTENT),
+            (Some(":5"), &CONTENT[..5]),
+            (Some("15:"), &CONTENT[15..]),
+            (Some("5:15"), &CONTENT[5..15]),
+        ] {
+            let body = read(&path, range).await;
+            assert_eq!(&body.bytes[..], expected, "range {:?}", range);
+            assert_eq!(body.sha256, sha256(expected), "range {:?}", range);
+
+            let parsed = range.map(|r| r.parse().unwrap());
+            let unhashed = read_unhashed(&path, parsed, 4).await.unwrap();
+            assert_eq!(&unhashed[..], expected, "range {:?}", range);
+            assert_eq!(
+                body_len(&path, parsed).await.unwrap(),
+                expected.len() as u64
+            );
+            assert_eq!(
+                hash_source(&path, parsed, 4, None).await.unwrap(),
+                sha256(expected)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn prefix_reads_stay_within_the_range() {
+        let dir = TestDir::new();
+        let path = dir.write("source.bin", CONTENT);
+
+        let prefix = read_prefix(&path, Some(range("15:")), 8).await.unwrap();
+        assert_eq!(&prefix[..], &CONTENT[15..]);
+        let prefix = read_prefix(&path, Some(range("2:")), 3).await.unwrap();
+        assert_eq!(&prefix[..], &CONTENT[2..5]);
+    }
+
+    #[tokio::test]
+    async fn out_of_bounds_ranges_are_rejected() {
+        let dir = TestDir::new();
+        let path = dir.write("source.bin", CONTENT);
+
+        let err = read_source(&path, Some(range("10:30")), 4)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, AppError::Config(_)), "{:?}", err);
+    }
+
+    /// Append to `path` from another thread until the returned flag is set
+    fn keep_growing(path: &str) -> (Arc<AtomicBool>, thread::JoinHandle<()>) {
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut file = std::fs::OpenOptions::new().append(true).open(path).unwrap();
+        let writer = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                while !stop.load(Ordering::SeqCst) {
+                    file.write_all(b"more").unwrap();
+                }
+            })
+        };
+        (stop, writer)
+    }
+
+    #[tokio::test]
+    async fn a_growing_file_fails_instead_of_uploading_part_of_it() {
+        let dir = TestDir::new();
+        let path = dir.write("growing.log", vec![b'x'; 256 * 1024]);
+        let (stop, writer) = keep_growing(&path);
+
+        let result = read_source(&path, None, 1024).await;
+        stop.store(true, Ordering::SeqCst);
+        writer.join().unwrap();
+
+        match result {
+            Err(AppError::FileChanged {
+                expected, actual, ..
+            }) => assert!(actual > expected, "{} -> {}", expected, actual),
+            other => panic!(
+                "expected FileChanged, got {:?}",
+                other.map(|b| b.bytes.len())
+            ),
+        }
+    }
+
+    #[tokio::test]
+    async fn unhashed_reads_notice_a_growing_file_too() {
+        let dir = TestDir::new();
+        // Without hashing the read is quick, so a bigger file keeps it going while the writer runs
+        let path = dir.write("growing.log", vec![b'x'; 4 * 1024 * 1024]);
+        let (stop, writer) = keep_growing(&path);
+
+        let result = read_unhashed(&path, None, 1024).await;
+        stop.store(true, Ordering::SeqCst);
+        writer.join().unwrap();
+
+        assert!(
+            matches!(result, Err(AppError::FileChanged { .. })),
+            "{:?}",
+            result.map(|bytes| bytes.len())
+        );
+    }
+
+    #[tokio::test]
+    async fn retry_on_change_reads_the_file_once_it_settles() {
+        let dir = TestDir::new();
+        let path = dir.write("growing.log", vec![b'x'; 256 * 1024]);
+        let (stop, writer) = keep_growing(&path);
+        let settle = tokio::spawn(async move {
+            sleep(Duration::from_millis(100)).await;
+            stop.store(true, Ordering::SeqCst);
+            writer.join().unwrap();
+        });
+
+        let body = read_source_retrying(&path, None, 1024, true).await.unwrap();
+        settle.await.unwrap();
+        assert_eq!(body.bytes, std::fs::read(&path).unwrap());
+    }
+
+    #[tokio::test]
+    async fn streamed_parts_notice_a_file_growing_between_them() {
+        let dir = TestDir::new();
+        let path = dir.write("growing.log", CONTENT);
+
+        let mut reader = PartReader::open(&path, None, 4, 8).await.unwrap();
+        assert_eq!(
+            &reader.next_part().await.unwrap().unwrap()[..],
+            &CONTENT[..8]
+        );
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"appended")
+            .unwrap();
+        while reader.next_part().await.unwrap().is_some() {}
+
+        let err = reader.finish().await.err().unwrap();
+        assert!(
+            matches!(
+                err,
+                AppError::FileChanged {
+                    expected: 20,
+                    actual: 28,
+                    ..
+                }
+            ),
+            "{:?}",
+            err
+        );
+    }
+
+    /// Reader of `CONTENT` a few bytes per read, failing once `fail_at` bytes were read
+    struct FailingReader {
+        read: usize,
+        fail_at: usize,
+    }
+
+    impl AsyncRead for FailingReader {
+        fn poll_read(
+            mut self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+            buf: &mut tokio::io::ReadBuf<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            if self.read >= self.fail_at {
+                return std::task::Poll::Ready(Err(std::io::Error::other("disk gone")));
+            }
+            let end = (self.read + 3).min(self.fail_at).min(CONTENT.len());
+            let len = (end - self.read).min(buf.remaining());
+            buf.put_slice(&CONTENT[self.read..self.read + len]);
+            self.read += len;
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    fn failing_after(fail_at: usize) -> FailingReader {
+        FailingReader { read: 0, fail_at }
+    }
+
+    fn failed_at(result: Result<usize, AppError>) -> u64 {
+        match result {
+            Err(AppError::ReadFailed {
+                path,
+                offset,
+                source,
+            }) => {
+                assert_eq!(path, "weights.bin");
+                assert_eq!(source.to_string(), "disk gone");
+                offset
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn read_errors_name_the_offset_they_hit() {
+        let mut reader = HashingReader::new(failing_after(7));
+        let (mut chunk, mut offset) = ([0; 4], 100);
+        let mut read = 0;
+        let err = loop {
+            match read_at(&mut reader, &mut chunk, "weights.bin", &mut offset).await {
+                Ok(n) => read += n,
+                err => break err,
+            }
+        };
+        assert_eq!(read, 7);
+        assert_eq!(failed_at(err), 107);
+    }
+
+    #[tokio::test]
+    async fn parts_fail_instead_of_coming_up_short() {
+        let mut part = [0; 8];
+        let (mut reader, mut offset) = (failing_after(11), 0);
+        let filled = fill(&mut reader, &mut part, "weights.bin", &mut offset).await;
+        assert_eq!(filled.unwrap(), 8);
+        assert_eq!(&part, &CONTENT[..8]);
+
+        let err = fill(&mut reader, &mut part, "weights.bin", &mut offset).await;
+        assert_eq!(failed_at(err), 11);
+    }
+
+    #[tokio::test]
+    async fn parts_come_up_short_only_at_the_end() {
+        let mut part = [0; 32];
+        let filled = fill(&mut &CONTENT[..], &mut part, "weights.bin", &mut 0)
+            .await
+            .unwrap();
+        assert_eq!(filled, CONTENT.len());
+    }
+
+    #[tokio::test]
+    async fn sources_of_the_given_length_are_read_whole() {
+        let dir = TestDir::new();
+        let path = dir.write("source.bin", CONTENT);
+
+        let body = read_with_length(&path, CONTENT.len() as u64, 4)
+            .await
+            .unwrap();
+
+        assert_eq!(body.bytes, CONTENT);
+        assert_eq!(body.sha256, sha256(CONTENT));
+    }
+
+    #[tokio::test]
+    async fn sources_of_another_length_report_theirs() {
+        let dir = TestDir::new();
+        let path = dir.write("source.bin", CONTENT);
+
+        for given in [CONTENT.len() as u64 - 1, CONTENT.len() as u64 + 5] {
+            match read_with_length(&path, given, 4).await {
+                Err(AppError::LengthMismatch {
+                    expected, actual, ..
+                }) => assert_eq!((expected, actual), (given, CONTENT.len() as u64)),
+                other => panic!("{:?}", other.map(|body| body.bytes)),
+            }
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use sha2::{Digest, Sha256};
+use std::io::SeekFrom;
+use tokio::{
+    fs::File,
+    io::{AsyncSeekExt, AsyncWriteExt},
+};
+
+use crate::error::AppError;
+
+/// User metadata with the size of the file a `--sparse` body stands for; marks the encoding
+pub const SIZE_METADATA: &str = "sparse-size";
+
+/// User metadata with the hex SHA-256 of that file, checked once it is rebuilt
+pub const SHA256_METADATA: &str = "sparse-sha256";
+
+/// Start of every `--sparse` body
+const MAGIC: &[u8; 8] = b"S3MLSPR1";
+
+/// Zero runs are left out in whole blocks of this size, that of common filesystems, so
+/// holes (which read as zeros) always line up with them
+const BLOCK: usize = 4096;
+
+/// `body` without its all-zero blocks, or `None` when leaving them out saves nothing
+///
+/// The encoding is `S3MLSPR1`, the file size and the number of extents (u64 little-endian
+/// each), an offset and a length for every extent of data, then the data of the extents
+/// back to back.
+pub fn encode(body: &[u8]) -> Option<Bytes> {
+    let mut extents: Vec<(usize, usize)> = Vec::new();
+    for (index, block) in body.chunks(BLOCK).enumerate() {
+        if block.iter().all(|byte| *byte == 0) {
+            continue;
+        }
+        let offset = index * BLOCK;
+        match extents.last_mut() {
+            Some((start, len)) if *start + *len == offset => *len += block.len(),
+            _ => extents.push((offset, block.len())),
+        }
+    }
+
+    let data: usize = extents.iter().map(|(_, len)| len).sum();
+    let encoded_len = MAGIC.len() + 16 + extents.len() * 16 + data;
+    if encoded_len >= body.len() {
+        return None;
+    }
+
+    let mut encoded = BytesMut::with_capacity(encoded_len);
+    encoded.put_slice(MAGIC);
+    encoded.put_u64_le(body.len() as u64);
+    encoded.put_u64_le(extents.len() as u64);
+    for (offset, len) in &extents {
+        encoded.put_u64_le(*offset as u64);
+        encoded.put_u64_le(*len as u64);
+    }
+    for (offset, len) in &extents {
+        encoded.put_slice(&body[*offset..*offset + *len]);
+    }
+    Some(encoded.freeze())
+}
+
+/// Rebuild the file a `--sparse` body stands for at `path`, its zero runs left as holes
+///
+/// The file is sized first and only the extents are written, so on a filesystem with
+/// sparse files the gaps take no space; elsewhere they are zeros. `size` and `sha256` come
+/// from the object's metadata.
+pub async fn write(
+    path: &str,
+    body: &[u8],
+    size: &str,
+    sha256: Option<&str>,
+) -> Result<(), AppError> {
+    let invalid = |problem: &str| AppError::Integrity(format!("--sparse body {}", problem));
+    let Encoded {
+        size: file_size,
+        extents,
+        mut data,
+    } = parse(body).ok_or_else(|| invalid("is truncated"))?;
+    if size.parse() != Ok(file_size) {
+        return Err(invalid(&format!(
+            "is for {} bytes, but its metadata says {}",
+            file_size, size
+        )));
+    }
+
+    let mut file = File::create(path).await?;
+    file.set_len(file_size).await?;
+    let mut hasher = Sha256::new();
+    let mut hashed = 0;
+    for (offset, len) in extents {
+        if offset < hashed || len > file_size || offset > file_size - len {
+            return Err(invalid("has an extent out of place"));
+        }
+        hash_zeros(&mut hasher, offset - hashed);
+        let (extent, rest) = data.split_at(len as usize);
+        file.seek(SeekFrom::Start(offset)).await?;
+        file.write_all(extent).await?;
+        hasher.update(extent);
+        hashed = offset + len;
+        data = rest;
+    }
+    hash_zeros(&mut hasher, file_size - hashed);
+    file.flush().await?;
+
+    let actual = hex::encode(hasher.finalize());
+    match sha256 {
+        Some(expected) if expected != actual => Err(AppError::Integrity(format!(
+            "{} was rebuilt with SHA-256 {} instead of {}",
+            path, actual, expected
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// An encoded body, split up
+struct Encoded<'a> {
+    size: u64,
+    // Offset and length of every extent of data
+    extents: Vec<(u64, u64)>,
+    data: &'a [u8],
+}
+
+/// Split up an encoded body; `None` if it is cut short
+fn parse(body: &[u8]) -> Option<Encoded<'_>> {
+    let rest = body.strip_prefix(MAGIC)?;
+    let read_u64 = |bytes: &[u8], at: usize| -> Option<u64> {
+        Some(u64::from_le_bytes(bytes.get(at..at + 8)?.try_into().ok()?))
+    };
+    let size = read_u64(rest, 0)?;
+    let count = read_u64(rest, 8)? as usize;
+    if count > rest.len() / 16 {
+        return None;
+    }
+
+    let mut extents = Vec::new();
+    for index in 0..count {
+        let at = 16 + index * 16;
+        extents.push((read_u64(rest, at)?, read_u64(rest, at + 8)?));
+    }
+    let data = rest.get(16 + count * 16..)?;
+    let total = extents
+        .iter()
+        .try_fold(0u64, |total, (_, len)| total.checked_add(*len))?;
+    (data.len() as u64 == total).then_some(Encoded {
+        size,
+        extents,
+        data,
+    })
+}
+
+fn hash_zeros(hasher: &mut Sha256, mut len: u64) {
+    let zeros = [0u8; BLOCK];
+    while len > 0 {
+        let chunk = len.min(BLOCK as u64) as usize;
+        hasher.update(&zeros[..chunk]);
+        len -= chunk as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hashing::sha256, testdir::TestDir};
+
+    /// `len` bytes, zero except for `data` at each of the offsets
+    fn holey(len: usize, data: &[(usize, &[u8])]) -> Vec<u8> {
+        let mut body = vec![0; len];
+        for (offset, bytes) in data {
+            body[*offset..*offset + bytes.len()].copy_from_slice(bytes);
+        }
+        body
+    }
+
+    async fn rebuild(
+        encoded: &[u8],
+        size: &str,
+        sha256: Option<&str>,
+    ) -> Result<Vec<u8>, AppError> {
+        let dir = TestDir::new();
+        let path = dir.path().join("rebuilt.bin");
+        let path = path.to_str().unwrap();
+        write(path, encoded, size, sha256).await?;
+        Ok(std::fs::read(path).unwrap())
+    }
+
+    #[tokio::test]
+    async fn zero_blocks_are_left_out_and_rebuilt() {
+        // Data in blocks 0, 1 and 5, and a partial last block
+        let body = holey(
+            6 * BLOCK + 100,
+            &[
+                (10, b"head"),
+                (BLOCK + 7, b"more"),
+                (5 * BLOCK, b"tail"),
+                (6 * BLOCK + 99, b"!"),
+            ],
+        );
+        let encoded = encode(&body).unwrap();
+
+        let parsed = parse(&encoded).unwrap();
+        assert_eq!(parsed.size, body.len() as u64);
+        assert_eq!(
+            parsed.extents,
+            [
+                (0, 2 * BLOCK as u64),
+                (5 * BLOCK as u64, BLOCK as u64 + 100)
+            ]
+        );
+        let digest = hex::encode(sha256(&body));
+        let rebuilt = rebuild(&encoded, &body.len().to_string(), Some(&digest)).await;
+        assert_eq!(rebuilt.unwrap(), body);
+    }
+
+    #[tokio::test]
+    async fn an_all_zero_file_keeps_only_its_size() {
+        let body = vec![0; 10 * BLOCK];
+        let encoded = encode(&body).unwrap();
+        assert_eq!(encoded.len(), MAGIC.len() + 16);
+        assert_eq!(rebuild(&encoded, "40960", None).await.unwrap(), body);
+    }
+
+    #[test]
+    fn bodies_leaving_out_nothing_are_sent_as_they_are() {
+        assert_eq!(encode(b""), None);
+        assert_eq!(encode(&[1; 3 * BLOCK]), None);
+        // A zero run shorter than a block can't be left out
+        assert_eq!(
+            encode(&holey(2 * BLOCK, &[(BLOCK - 1, b"x"), (BLOCK, b"x")])),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn damaged_bodies_fail_to_rebuild() {
+        let body = holey(4 * BLOCK, &[(0, b"data")]);
+        let encoded = encode(&body).unwrap();
+        let integrity = |result: Result<Vec<u8>, AppError>| match result {
+            Err(AppError::Integrity(message)) => message,
+            other => panic!("{:?}", other.map(|body| body.len())),
+        };
+
+        let truncated = rebuild(&encoded[..encoded.len() - 1], "16384", None).await;
+        assert!(integrity(truncated).contains("is truncated"));
+        let resized = rebuild(&encoded, "16385", None).await;
+        assert!(integrity(resized).contains("its metadata says 16385"));
+        let wrong = rebuild(&encoded, "16384", Some(&hex::encode(sha256(b"other")))).await;
+        assert!(integrity(wrong).contains("was rebuilt with SHA-256"));
+
+        let mut misplaced = encoded.to_vec();
+        // The only extent's offset, moved past the end of the file
+        misplaced[MAGIC.len() + 16..MAGIC.len() + 24]
+            .copy_from_slice(&(4 * BLOCK as u64).to_le_bytes());
+        assert!(integrity(rebuild(&misplaced, "16384", None).await).contains("out of place"));
+    }
+}
@@ -0,0 +1,402 @@
+use aws_sdk_s3::{
+    config::{
+        interceptors::BeforeTransmitInterceptorContextMut, ConfigBag, Intercept, RuntimeComponents,
+    },
+    primitives::SdkBody,
+};
+use bytes::Bytes;
+use futures::{stream, Stream, StreamExt};
+use hyper::{
+    body::{HttpBody, SizeHint},
+    HeaderMap,
+};
+use std::{
+    error::Error,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+use tokio::time::{self, Instant};
+
+use crate::error::AppError;
+
+/// Window over which `--min-throughput` is measured
+pub const WINDOW: Duration = Duration::from_secs(10);
+
+/// Bodies are handed to the connection at most this many bytes at a time, so the meter
+/// follows the socket instead of jumping to the full size on the first write
+const CHUNK: usize = 64 * 1024;
+
+/// Bytes of a request body handed to the connection so far
+#[derive(Debug, Clone, Default)]
+pub struct StallMeter {
+    sent: Arc<AtomicU64>,
+    // The `--progress` count of the object's file, which every request body adds to
+    progress: Option<Arc<AtomicU64>>,
+}
+
+impl StallMeter {
+    fn add(&self, bytes: usize) {
+        self.sent.fetch_add(bytes as u64, Ordering::Relaxed);
+        if let Some(progress) = &self.progress {
+            progress.fetch_add(bytes as u64, Ordering::Relaxed);
+        }
+    }
+
+    fn sent(&self) -> u64 {
+        self.sent.load(Ordering::Relaxed)
+    }
+}
+
+/// What watches the request bodies of one object's upload as they are sent
+#[derive(Debug, Clone, Default)]
+pub struct Watch {
+    // --min-throughput in bytes per second
+    pub min_throughput: Option<u64>,
+    pub progress: Option<Arc<AtomicU64>>,
+}
+
+impl Watch {
+    /// A meter for one request body, when anything watches it
+    pub fn meter(&self) -> Option<StallMeter> {
+        (self.min_throughput.is_some() || self.progress.is_some()).then(|| StallMeter {
+            sent: Arc::default(),
+            progress: self.progress.clone(),
+        })
+    }
+}
+
+/// Run `request`, failing with `AppError::Stalled` once it sends less than `min_throughput`
+/// bytes per second over a whole `WINDOW`
+///
+/// Only the upload of `total` body bytes is watched: once all of them are sent, waiting for
+/// the response is left to the backend. Without a `meter` (transports whose body can't be
+/// observed), the request gets the time the body takes at `min_throughput`, plus one window.
+pub async fn guard<T>(
+    backend: &'static str,
+    min_throughput: Option<u64>,
+    total: u64,
+    meter: Option<&StallMeter>,
+    request: impl Future<Output = Result<T, AppError>>,
+) -> Result<T, AppError> {
+    let Some(min_throughput) = min_throughput else {
+        return request.await;
+    };
+    let stalled = || AppError::Stalled {
+        backend,
+        min_throughput,
+    };
+
+    let Some(meter) = meter else {
+        let deadline = WINDOW + Duration::from_secs_f64(total as f64 / min_throughput as f64);
+        return time::timeout(deadline, request)
+            .await
+            .unwrap_or_else(|_| Err(stalled()));
+    };
+
+    tokio::pin!(request);
+    let mut checks = time::interval_at(Instant::now() + WINDOW, WINDOW);
+    let mut last = meter.sent();
+    loop {
+        tokio::select! {
+            result = &mut request => return result,
+            _ = checks.tick() => {
+                let sent = meter.sent();
+                if sent < total && sent - last < min_throughput * WINDOW.as_secs() {
+                    return Err(stalled());
+                }
+                last = sent;
+            }
+        }
+    }
+}
+
+/// `body` as a stream of chunks for reqwest, counted into `meter` as they are sent
+pub fn metered_stream(
+    body: Bytes,
+    meter: StallMeter,
+) -> impl Stream<Item = Result<Bytes, io::Error>> + Send + Sync + 'static {
+    let chunks: Vec<Bytes> = (0..body.len())
+        .step_by(CHUNK)
+        .map(|start| body.slice(start..body.len().min(start + CHUNK)))
+        .collect();
+    stream::iter(chunks.into_iter().map(move |chunk| {
+        meter.add(chunk.len());
+        Ok(chunk)
+    }))
+}
+
+/// `parts` as they arrive, re-chunked for reqwest like [`metered_stream`] and counted into
+/// `meter` as they are sent
+pub fn metered_parts(
+    parts: impl Stream<Item = Result<Bytes, io::Error>> + Send + Sync + 'static,
+    meter: StallMeter,
+) -> impl Stream<Item = Result<Bytes, io::Error>> + Send + Sync + 'static {
+    parts.flat_map(move |part| {
+        let chunks: Vec<_> = match part {
+            Ok(part) => (0..part.len())
+                .step_by(CHUNK)
+                .map(|start| Ok(part.slice(start..part.len().min(start + CHUNK))))
+                .collect(),
+            Err(err) => vec![Err(err)],
+        };
+        let meter = meter.clone();
+        stream::iter(chunks.into_iter().inspect(move |chunk| {
+            if let Ok(chunk) = chunk {
+                meter.add(chunk.len());
+            }
+        }))
+    })
+}
+
+/// Counts the body of every AWS SDK request it is attached to into a `StallMeter`
+///
+/// The body is wrapped after signing, so its contents and checksums are unchanged.
+#[derive(Debug)]
+pub struct MeterInterceptor(Option<StallMeter>);
+
+impl MeterInterceptor {
+    pub fn new(meter: Option<&StallMeter>) -> Self {
+        Self(meter.cloned())
+    }
+}
+
+impl Intercept for MeterInterceptor {
+    fn name(&self) -> &'static str {
+        "StallMeter"
+    }
+
+    fn modify_before_transmit(
+        &self,
+        context: &mut BeforeTransmitInterceptorContextMut<'_>,
+        _components: &RuntimeComponents,
+        _cfg: &mut ConfigBag,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let Some(meter) = &self.0 else {
+            return Ok(());
+        };
+        let meter = meter.clone();
+        let request = context.request_mut();
+        let body = request.take_body();
+        *request.body_mut() = body.map_preserve_contents(move |body| {
+            SdkBody::from_body_0_4(MeteredBody {
+                inner: body,
+                pending: Bytes::new(),
+                meter: meter.clone(),
+            })
+        });
+        Ok(())
+    }
+}
+
+/// SDK request body re-chunked to `CHUNK` bytes and counted as the connection pulls it
+struct MeteredBody {
+    inner: SdkBody,
+    // Rest of the last frame of `inner`
+    pending: Bytes,
+    meter: StallMeter,
+}
+
+impl HttpBody for MeteredBody {
+    type Data = Bytes;
+    type Error = <SdkBody as HttpBody>::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let this = &mut *self;
+        if this.pending.is_empty() {
+            match ready!(Pin::new(&mut this.inner).poll_data(cx)) {
+                Some(Ok(data)) => this.pending = data,
+                other => return Poll::Ready(other),
+            }
+        }
+
+        let chunk = this.pending.split_to(this.pending.len().min(CHUNK));
+        this.meter.add(chunk.len());
+        Poll::Ready(Some(Ok(chunk)))
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Pin::new(&mut self.inner).poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.pending.is_empty() && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let inner = self.inner.size_hint();
+        let pending = self.pending.len() as u64;
+        let mut hint = SizeHint::new();
+        hint.set_lower(inner.lower() + pending);
+        if let Some(upper) = inner.upper() {
+            hint.set_upper(upper + pending);
+        }
+        hint
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    /// A sink reading `body` one chunk per `delay`, as a throttled connection does
+    async fn trickle(body: Bytes, meter: StallMeter, delay: Duration) -> Result<(), AppError> {
+        let mut chunks = Box::pin(metered_stream(body, meter));
+        while chunks.next().await.is_some() {
+            time::sleep(delay).await;
+        }
+        Ok(())
+    }
+
+    fn is_stalled(result: &Result<(), AppError>) -> bool {
+        matches!(
+            result,
+            Err(AppError::Stalled {
+                backend: "test",
+                min_throughput: 100_000
+            })
+        )
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_trickling_sink_stalls() {
+        let body = Bytes::from(vec![0; 10 * CHUNK]);
+        let meter = StallMeter::default();
+        // One 64 KiB chunk every 2 s is ~32 KiB/s, below 100 kB/s
+        let request = trickle(body.clone(), meter.clone(), Duration::from_secs(2));
+
+        let started = Instant::now();
+        let result = guard(
+            "test",
+            Some(100_000),
+            body.len() as u64,
+            Some(&meter),
+            request,
+        )
+        .await;
+
+        assert!(is_stalled(&result), "{:?}", result);
+        assert_eq!(started.elapsed(), WINDOW);
+        assert!(meter.sent() < body.len() as u64);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_sink_keeping_up_is_not_stalled() {
+        let body = Bytes::from(vec![0; 100 * CHUNK]);
+        let meter = StallMeter::default();
+        // ~320 KiB/s for 20 s, across two checks
+        let request = trickle(body.clone(), meter.clone(), Duration::from_millis(200));
+
+        let result = guard(
+            "test",
+            Some(100_000),
+            body.len() as u64,
+            Some(&meter),
+            request,
+        )
+        .await;
+
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(meter.sent(), body.len() as u64);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn waiting_for_the_response_is_not_a_stall() {
+        let meter = StallMeter::default();
+        let request = async {
+            meter.add(1000);
+            time::sleep(WINDOW * 3).await;
+            Ok(())
+        };
+
+        let result = guard("test", Some(100_000), 1000, Some(&meter), request).await;
+
+        assert!(result.is_ok(), "{:?}", result);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn without_a_meter_the_body_gets_its_time_at_the_minimum_rate() {
+        let slow = async {
+            // 1 MB at 100 kB/s is 10 s, plus the window
+            time::sleep(WINDOW * 2 + Duration::from_secs(1)).await;
+            Ok(())
+        };
+        let result = guard("test", Some(100_000), 1_000_000, None, slow).await;
+        assert!(is_stalled(&result), "{:?}", result);
+
+        let in_time = async {
+            time::sleep(WINDOW * 2 - Duration::from_secs(1)).await;
+            Ok(())
+        };
+        let result = guard("test", Some(100_000), 1_000_000, None, in_time).await;
+        assert!(result.is_ok(), "{:?}", result);
+    }
+
+    #[tokio::test]
+    async fn progress_counts_every_request_body_of_an_object() {
+        let progress = Arc::new(AtomicU64::new(0));
+        let watch = Watch {
+            min_throughput: None,
+            progress: Some(Arc::clone(&progress)),
+        };
+
+        for _ in 0..2 {
+            let meter = watch.meter().unwrap();
+            let chunks = metered_stream(Bytes::from(vec![0; CHUNK + 10]), meter.clone());
+            chunks.for_each(|_| async {}).await;
+            assert_eq!(meter.sent(), CHUNK as u64 + 10);
+        }
+
+        assert_eq!(progress.load(Ordering::Relaxed), 2 * (CHUNK as u64 + 10));
+        assert!(Watch::default().meter().is_none());
+    }
+
+    #[tokio::test]
+    async fn streamed_parts_are_counted_in_chunks_as_they_arrive() {
+        let meter = StallMeter::default();
+        let parts = stream::iter([
+            Ok(Bytes::from(vec![1; CHUNK + 10])),
+            Ok(Bytes::from(vec![2; 5])),
+            Err(io::Error::other("reader stopped")),
+        ]);
+        let mut chunks = Box::pin(metered_parts(parts, meter.clone()));
+
+        let mut sizes = Vec::new();
+        while let Some(Ok(chunk)) = chunks.next().await {
+            sizes.push(chunk.len());
+            assert_eq!(meter.sent(), sizes.iter().sum::<usize>() as u64);
+        }
+        assert_eq!(sizes, [CHUNK, 10, 5]);
+    }
+
+    #[tokio::test]
+    async fn sdk_bodies_are_counted_in_chunks() {
+        let meter = StallMeter::default();
+        let mut body = MeteredBody {
+            inner: SdkBody::from(vec![7; 2 * CHUNK + 10]),
+            pending: Bytes::new(),
+            meter: meter.clone(),
+        };
+
+        let mut sizes = Vec::new();
+        while let Some(chunk) = std::future::poll_fn(|cx| Pin::new(&mut body).poll_data(cx)).await {
+            sizes.push(chunk.unwrap().len());
+        }
+
+        assert_eq!(sizes, [CHUNK, CHUNK, 10]);
+        assert_eq!(meter.sent(), 2 * CHUNK as u64 + 10);
+    }
+}
@@ -0,0 +1,109 @@
+use bytes::Bytes;
+use futures::{stream, Stream, TryStreamExt};
+use std::{collections::HashMap, io::ErrorKind, pin::pin};
+use tokio::io::{self, AsyncWriteExt};
+
+use crate::{cli::format_size, error::AppError, gcs, sparse, Backend, Backends};
+
+/// Stream the object at `key` to stdout as it arrives (`download -`), returning its size
+///
+/// Nothing is buffered beyond the chunk being written, and every message goes to stderr so
+/// stdout carries the body alone. A reader that exits early (`| head`) ends the download
+/// quietly. The body is written as stored: it isn't decompressed, and a `--sparse` one
+/// is refused, since rebuilding it needs a file to seek in.
+pub async fn download(backends: &Backends, backend: Backend, key: &str) -> Result<u64, AppError> {
+    let written = match backend {
+        // The HTTP path uploads into the AWS bucket
+        Backend::Aws | Backend::Http => {
+            let resp = backends
+                .aws_client
+                .get_object()
+                .bucket(&backends.aws_bucket)
+                .key(key)
+                .send()
+                .await?;
+            check_not_sparse(key, resp.metadata())?;
+            let body = stream::try_unfold(resp.body, |mut body| async move {
+                Ok::<_, AppError>(body.try_next().await?.map(|chunk| (chunk, body)))
+            });
+            write_stdout(body).await?
+        }
+        Backend::Gcs => {
+            let res = backends.gcs_bucket.get(key, None).await?;
+            check_not_sparse(key, Some(&gcs::metadata(res.headers())))?;
+            write_stdout(res.bytes_stream().map_err(AppError::from)).await?
+        }
+        Backend::Minio => {
+            let data = backends.minio_bucket.get_object_stream(key).await?;
+            if data.status_code >= 300 {
+                let body: Vec<Bytes> = data.bytes.try_collect().await?;
+                return Err(AppError::S3(s3::error::S3Error::HttpFailWithBody(
+                    data.status_code,
+                    String::from_utf8_lossy(&body.concat()).into_owned(),
+                )));
+            }
+            write_stdout(data.bytes.map_err(AppError::from)).await?
+        }
+    };
+
+    match written {
+        Written::All(bytes) => eprintln!(
+            "Downloaded from {}: {} -> stdout ({})",
+            backend.name(),
+            key,
+            format_size(bytes)
+        ),
+        Written::Closed(bytes) => eprintln!(
+            "stdout was closed after {} of {}; stopped downloading",
+            format_size(bytes),
+            key
+        ),
+    }
+    Ok(written.bytes())
+}
+
+/// How much of a body reached stdout
+enum Written {
+    All(u64),
+    // The reader closed the pipe after this many bytes
+    Closed(u64),
+}
+
+impl Written {
+    fn bytes(&self) -> u64 {
+        match self {
+            Written::All(bytes) | Written::Closed(bytes) => *bytes,
+        }
+    }
+}
+
+async fn write_stdout(
+    body: impl Stream<Item = Result<Bytes, AppError>>,
+) -> Result<Written, AppError> {
+    let mut body = pin!(body);
+    let mut stdout = io::stdout();
+    let mut written = 0;
+    while let Some(chunk) = body.try_next().await? {
+        // Rust ignores SIGPIPE, so a closed pipe is an EPIPE error rather than a kill
+        match stdout.write_all(&chunk).await {
+            Ok(()) => written += chunk.len() as u64,
+            Err(err) if err.kind() == ErrorKind::BrokenPipe => return Ok(Written::Closed(written)),
+            Err(err) => return Err(err.into()),
+        }
+    }
+    match stdout.flush().await {
+        Ok(()) => Ok(Written::All(written)),
+        Err(err) if err.kind() == ErrorKind::BrokenPipe => Ok(Written::Closed(written)),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn check_not_sparse(key: &str, metadata: Option<&HashMap<String, String>>) -> Result<(), AppError> {
+    match metadata.is_some_and(|metadata| metadata.contains_key(sparse::SIZE_METADATA)) {
+        true => Err(AppError::Config(format!(
+            "{} was uploaded with --sparse; download it to a file to rebuild it",
+            key
+        ))),
+        false => Ok(()),
+    }
+}
@@ -0,0 +1,310 @@
+use serde_json::{json, Value};
+use std::{
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use crate::{cli::format_size, Backend};
+
+/// Objects one backend stored or rejected during a run
+#[derive(Debug, Default)]
+struct BackendTally {
+    uploaded: AtomicUsize,
+    // Already stored with the same content (--overwrite-if-different)
+    unchanged: AtomicUsize,
+    failed: AtomicUsize,
+    bytes: AtomicU64,
+}
+
+impl BackendTally {
+    fn record(&self, bytes: u64, uploaded: bool) {
+        if uploaded {
+            self.uploaded.fetch_add(1, Ordering::Relaxed);
+            self.bytes.fetch_add(bytes, Ordering::Relaxed);
+        } else {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Per-backend counts, updated concurrently by the upload tasks
+#[derive(Debug)]
+pub struct BackendTallies {
+    backends: Vec<(Backend, BackendTally)>,
+    // One per --replicate-to bucket, by label
+    replicas: Vec<(String, BackendTally)>,
+}
+
+impl BackendTallies {
+    /// Zeroed counts for every enabled backend and replica, in display order
+    pub fn new(backends: &[Backend], replicas: Vec<String>) -> Self {
+        Self {
+            backends: backends
+                .iter()
+                .map(|&backend| (backend, BackendTally::default()))
+                .collect(),
+            replicas: replicas
+                .into_iter()
+                .map(|label| (label, BackendTally::default()))
+                .collect(),
+        }
+    }
+
+    fn tally(&self, backend: Backend) -> &BackendTally {
+        self.backends
+            .iter()
+            .find(|(b, _)| *b == backend)
+            .map(|(_, tally)| tally)
+            .expect("tally for an enabled backend")
+    }
+
+    /// Count one object sent to `backend`
+    pub fn record(&self, backend: Backend, bytes: u64, uploaded: bool) {
+        self.tally(backend).record(bytes, uploaded);
+    }
+
+    /// Count one object `backend` already stored with the same content
+    pub fn record_unchanged(&self, backend: Backend) {
+        self.tally(backend)
+            .unchanged
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count one object sent to the replica at `index`
+    pub fn record_replica(&self, index: usize, bytes: u64, uploaded: bool) {
+        self.replicas[index].1.record(bytes, uploaded);
+    }
+
+    /// Count one object the replica at `index` already stored with the same content
+    pub fn record_replica_unchanged(&self, index: usize) {
+        self.replicas[index]
+            .1
+            .unchanged
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// `(name, uploaded, unchanged, failed, bytes)` of every enabled backend, then every replica
+    fn counts(&self) -> impl Iterator<Item = (&str, usize, usize, usize, u64)> + '_ {
+        let backends = self
+            .backends
+            .iter()
+            .map(|(backend, tally)| (backend.name(), tally));
+        let replicas = self
+            .replicas
+            .iter()
+            .map(|(label, tally)| (label.as_str(), tally));
+
+        backends.chain(replicas).map(|(name, tally)| {
+            (
+                name,
+                tally.uploaded.load(Ordering::Relaxed),
+                tally.unchanged.load(Ordering::Relaxed),
+                tally.failed.load(Ordering::Relaxed),
+                tally.bytes.load(Ordering::Relaxed),
+            )
+        })
+    }
+}
+
+/// Outcome of an upload run, printed when it ends
+pub struct RunSummary<'a> {
+    pub files: usize,
+    pub succeeded: usize,
+    // Not attempted, e.g. after the failure budget was exceeded
+    pub skipped: usize,
+    pub failed: usize,
+    // Body bytes of the files that succeeded
+    pub bytes: u64,
+    pub elapsed: Duration,
+    pub backends: &'a BackendTallies,
+}
+
+impl RunSummary<'_> {
+    /// Aligned totals followed by a per-backend breakdown
+    pub fn render(&self) -> String {
+        let totals = [
+            ("files", self.files.to_string()),
+            ("succeeded", self.succeeded.to_string()),
+            ("skipped", self.skipped.to_string()),
+            ("failed", self.failed.to_string()),
+            ("bytes", format_size(self.bytes)),
+            ("elapsed", format!("{:.1}s", self.elapsed.as_secs_f64())),
+        ];
+
+        let mut rows = vec![vec![
+            "backend".to_string(),
+            "uploaded".to_string(),
+            "unchanged".to_string(),
+            "failed".to_string(),
+            "bytes".to_string(),
+        ]];
+        for (name, uploaded, unchanged, failed, bytes) in self.backends.counts() {
+            rows.push(vec![
+                name.to_string(),
+                uploaded.to_string(),
+                unchanged.to_string(),
+                failed.to_string(),
+                format_size(bytes),
+            ]);
+        }
+
+        let mut output = String::from("Summary\n");
+        for (name, value) in totals {
+            output.push_str(&format!("  {:<10} {:>10}\n", name, value));
+        }
+        output.push('\n');
+        output.push_str(&table(&rows));
+        output
+    }
+
+    pub fn to_json(&self) -> Value {
+        let backends: Vec<_> = self
+            .backends
+            .counts()
+            .map(|(name, uploaded, unchanged, failed, bytes)| {
+                json!({
+                    "backend": name,
+                    "uploaded": uploaded,
+                    "unchanged": unchanged,
+                    "failed": failed,
+                    "bytes": bytes,
+                })
+            })
+            .collect();
+
+        json!({
+            "files": self.files,
+            "succeeded": self.succeeded,
+            "skipped": self.skipped,
+            "failed": self.failed,
+            "bytes": self.bytes,
+            "elapsed_ms": self.elapsed.as_millis() as u64,
+            "backends": backends,
+        })
+    }
+}
+
+/// Rows padded to the widest cell of each column; the first column is left-aligned
+pub fn table(rows: &[Vec<String>]) -> String {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|column| {
+            rows.iter()
+                .filter_map(|row| row.get(column))
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let mut output = String::new();
+    for row in rows {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(column, (cell, &width))| match column {
+                0 => format!("{:<width$}", cell),
+                _ => format!("{:>width$}", cell),
+            })
+            .collect();
+        output.push_str(cells.join("  ").trim_end());
+        output.push('\n');
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tallies() -> BackendTallies {
+        let tallies = BackendTallies::new(
+            &[Backend::Aws, Backend::Minio],
+            vec!["replica eu-west-1".to_string()],
+        );
+        tallies.record(Backend::Aws, 2048, true);
+        tallies.record(Backend::Aws, 10, true);
+        tallies.record_unchanged(Backend::Aws);
+        tallies.record(Backend::Minio, 2048, false);
+        tallies.record_replica(0, 2048, true);
+        tallies.record_replica_unchanged(0);
+        tallies
+    }
+
+    fn summary(backends: &BackendTallies) -> RunSummary<'_> {
+        RunSummary {
+            files: 4,
+            succeeded: 2,
+            skipped: 1,
+            failed: 1,
+            bytes: 2058,
+            elapsed: Duration::from_millis(1250),
+            backends,
+        }
+    }
+
+    #[test]
+    fn table_pads_columns_to_their_widest_cell() {
+        let rows = vec![
+            vec!["name".to_string(), "n".to_string()],
+            vec!["a".to_string(), "1000".to_string()],
+            vec!["longer".to_string(), "2".to_string()],
+        ];
+
+        assert_eq!(
+            table(&rows),
+            "name       n\n\
+             a       1000\n\
+             longer     2\n"
+        );
+    }
+
+    #[test]
+    fn table_of_no_rows_is_empty() {
+        assert_eq!(table(&[]), "");
+    }
+
+    #[test]
+    fn render_lists_totals_then_backends() {
+        let backends = tallies();
+
+        assert_eq!(
+            summary(&backends).render(),
+            "Summary\n\
+             \x20 files               4\n\
+             \x20 succeeded           2\n\
+             \x20 skipped             1\n\
+             \x20 failed              1\n\
+             \x20 bytes         2.0 KiB\n\
+             \x20 elapsed          1.2s\n\
+             \n\
+             backend            uploaded  unchanged  failed    bytes\n\
+             AWS S3                    2          1       0  2.0 KiB\n\
+             MinIO                     0          0       1      0 B\n\
+             replica eu-west-1         1          1       0  2.0 KiB\n"
+        );
+    }
+
+    #[test]
+    fn json_carries_the_same_counts() {
+        let backends = tallies();
+
+        assert_eq!(
+            summary(&backends).to_json(),
+            json!({
+                "files": 4,
+                "succeeded": 2,
+                "skipped": 1,
+                "failed": 1,
+                "bytes": 2058,
+                "elapsed_ms": 1250,
+                "backends": [
+                    {"backend": "AWS S3", "uploaded": 2, "unchanged": 1, "failed": 0, "bytes": 2058},
+                    {"backend": "MinIO", "uploaded": 0, "unchanged": 0, "failed": 1, "bytes": 0},
+                    {"backend": "replica eu-west-1", "uploaded": 1, "unchanged": 1, "failed": 0, "bytes": 2048},
+                ],
+            })
+        );
+    }
+}
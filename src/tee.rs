@@ -0,0 +1,334 @@
+use bytes::Bytes;
+use futures::{stream, Stream, StreamExt};
+use s3::bucket::Bucket;
+use std::{
+    io,
+    pin::pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::Poll,
+    time::{Instant, SystemTime},
+};
+use tokio::{sync::mpsc, task::JoinSet};
+
+use crate::{
+    capabilities::StorageOptions,
+    cli::format_size,
+    error::AppError,
+    hashing::Sha256Digest,
+    multipart, report_upload,
+    source::{PartReader, SourceRange},
+    upload_via_http, with_object_headers, Backend, Backends, HttpBody, ObjectMeta, Target,
+    UploadRun, MAX_SINGLE_PUT,
+};
+
+/// Leading bytes of a streamed file a classifier without its own bound gets to see
+pub const CLASSIFY_SAMPLE: usize = 1024 * 1024;
+
+/// Parts queued for each target besides the one it is sending; once a target's queue is
+/// full the reader waits for it
+const QUEUE_PARTS: usize = 2;
+
+/// What the reader hands every target
+enum Chunk {
+    Part(Bytes),
+    // The whole file was read unchanged, so the upload may be completed
+    End,
+}
+
+/// Upload one large file to every backend and replica from a single shared read (`--stream-above`)
+///
+/// `digest` comes from a hashing pass made first, because S3 takes the metadata stamped
+/// with it before the first part, and the HTTP and GCS backends sign their single PUT with
+/// it. The file is then read a second time, part by part, and each part is queued to every
+/// target, each running its own multipart upload or streamed PUT; that is
+/// two reads of the file however many targets there are. A full queue holds
+/// the reader back, so a slow target costs time rather than memory: at most a few parts
+/// per target are buffered, however large the file. A failing target, or the file
+/// changing on disk, aborts every upload of the file, since a target only completes its
+/// upload after the reader confirms the whole file was read unchanged.
+#[allow(clippy::too_many_arguments)]
+pub async fn upload(
+    run: &UploadRun,
+    path: &str,
+    range: Option<SourceRange>,
+    buffer_size: usize,
+    len: u64,
+    digest: Sha256Digest,
+    key: String,
+    mut meta: ObjectMeta,
+) -> Result<(), AppError> {
+    meta.stamp(&key, digest);
+    let backends = &run.backends;
+    let (key, meta) = (Arc::new(key), Arc::new(meta));
+
+    let mut queues = Vec::new();
+    let mut uploads = JoinSet::new();
+    for target in backends.targets() {
+        // Checked before anything is read, so an unchanged target needs no queue
+        if let Some(fallback) = backends.overwrite_if_different {
+            let stored = backends.head(target, &key).await?;
+            if stored.is_some_and(|stored| stored.matches(&digest, len, fallback)) {
+                let timing = (SystemTime::now(), Default::default());
+                report_upload(run, path, target, &key, len, timing, Ok(false))?;
+                continue;
+            }
+        }
+
+        let meta = match backends.merged_tags(target, &key, &meta).await? {
+            Some(merged) => Arc::new(merged),
+            None => Arc::clone(&meta),
+        };
+
+        let (sender, receiver) = mpsc::channel(QUEUE_PARTS);
+        queues.push(sender);
+        let (backends, key) = (Arc::clone(backends), Arc::clone(&key));
+        uploads.spawn(async move {
+            let (started, timer) = (SystemTime::now(), Instant::now());
+            let result = upload_target(&backends, target, receiver, len, &key, &meta).await;
+            backends.forget_head(target, &key);
+            (target, started, timer.elapsed(), result.map(|()| true))
+        });
+    }
+    if queues.is_empty() {
+        return Ok(());
+    }
+
+    let targets = queues.len();
+    let part_size = multipart::part_size(len, meta.part_size);
+    let read = tee(path, range, buffer_size, part_size, digest, queues).await;
+
+    // The reader's error is the cause of the others; otherwise the first target that failed
+    // is, and the rest were aborted because of it
+    let mut cause = read.err();
+    let mut aborted = None;
+    while let Some(joined) = uploads.join_next().await {
+        let (target, started, elapsed, result) = joined?;
+        if let Err(err) = report_upload(run, path, target, &key, len, (started, elapsed), result) {
+            match err {
+                AppError::Cancelled => aborted = Some(err),
+                err if cause.is_none() => cause = Some(err),
+                _ => {}
+            }
+        }
+    }
+    match cause.or(aborted) {
+        Some(err) => Err(err),
+        None => {
+            println!(
+                "Streamed {} ({}) to {} target(s), reading the file once to hash it and once \
+                 for all of them",
+                key,
+                format_size(len),
+                targets
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Read the file part by part into every queue, then confirm it with `Chunk::End`
+///
+/// Stops early once a queue is closed, i.e. its target failed; dropping the queues then
+/// aborts the other uploads.
+async fn tee(
+    path: &str,
+    range: Option<SourceRange>,
+    buffer_size: usize,
+    part_size: usize,
+    digest: Sha256Digest,
+    queues: Vec<mpsc::Sender<Chunk>>,
+) -> Result<(), AppError> {
+    let mut reader = PartReader::open(path, range, buffer_size, part_size).await?;
+    while let Some(part) = reader.next_part().await? {
+        for queue in &queues {
+            if queue.send(Chunk::Part(part.clone())).await.is_err() {
+                return Ok(());
+            }
+        }
+    }
+
+    if reader.finish().await? != digest {
+        return Err(AppError::Integrity(format!(
+            "{} changed while it was uploaded: its SHA-256 no longer matches the one it was \
+             stamped with",
+            path
+        )));
+    }
+    for queue in &queues {
+        let _ = queue.send(Chunk::End).await;
+    }
+    Ok(())
+}
+
+/// The parts of one target's queue, failing with `Cancelled` if the reader stopped early
+fn parts(receiver: mpsc::Receiver<Chunk>) -> impl Stream<Item = Result<Bytes, AppError>> {
+    stream::unfold(Some(receiver), |receiver| async move {
+        let mut receiver = receiver?;
+        match receiver.recv().await {
+            Some(Chunk::Part(part)) => Some((Ok(part), Some(receiver))),
+            Some(Chunk::End) => None,
+            None => Some((Err(AppError::Cancelled), None)),
+        }
+    })
+}
+
+/// The parts of one target's queue as the body of a PUT, setting `cancelled` if the reader
+/// stopped early
+fn body_parts(
+    mut receiver: mpsc::Receiver<Chunk>,
+    cancelled: Arc<AtomicBool>,
+) -> impl Stream<Item = Result<Bytes, io::Error>> + Send + Sync + 'static {
+    let mut done = false;
+    stream::poll_fn(move |cx| {
+        if done {
+            return Poll::Ready(None);
+        }
+        let chunk = match receiver.poll_recv(cx) {
+            Poll::Ready(chunk) => chunk,
+            Poll::Pending => return Poll::Pending,
+        };
+        Poll::Ready(match chunk {
+            Some(Chunk::Part(part)) => Some(Ok(part)),
+            Some(Chunk::End) => None,
+            None => {
+                done = true;
+                cancelled.store(true, Ordering::Relaxed);
+                Some(Err(io::Error::new(
+                    io::ErrorKind::Interrupted,
+                    "the streamed upload was cancelled",
+                )))
+            }
+        })
+    })
+}
+
+async fn upload_target(
+    backends: &Backends,
+    target: Target,
+    receiver: mpsc::Receiver<Chunk>,
+    len: u64,
+    key: &str,
+    meta: &ObjectMeta,
+) -> Result<(), AppError> {
+    let default = StorageOptions::default();
+    match target {
+        Target::Backend(Backend::Aws) => {
+            let storage = backends.storage.get(&Backend::Aws).unwrap_or(&default);
+            multipart::upload_stream(
+                &backends.aws_client(),
+                parts(receiver),
+                &backends.aws_bucket,
+                key,
+                meta,
+                storage,
+                &backends.watch(key),
+            )
+            .await
+        }
+        Target::Replica(index) => {
+            let replica = &backends.replicas[index];
+            let storage = backends.storage.get(&Backend::Aws).unwrap_or(&default);
+            multipart::upload_stream(
+                &replica.client,
+                parts(receiver),
+                &replica.bucket,
+                key,
+                meta,
+                storage,
+                &backends.watch(key),
+            )
+            .await
+        }
+        Target::Backend(Backend::Minio) => {
+            let storage = backends.storage.get(&Backend::Minio).unwrap_or(&default);
+            upload_to_minio(&backends.minio_bucket, parts(receiver), key, meta, storage).await
+        }
+        // One PUT of the whole file, sent as the reader queues it
+        Target::Backend(backend @ (Backend::Http | Backend::Gcs)) => {
+            if backend == Backend::Http && len > MAX_SINGLE_PUT {
+                return Err(AppError::Config(format!(
+                    "{} is {}, more than the HTTP backend's single PUT takes ({})",
+                    key,
+                    format_size(len),
+                    format_size(MAX_SINGLE_PUT)
+                )));
+            }
+            let endpoint = match backend {
+                Backend::Http => backends.http_endpoint().await?,
+                _ => backends.gcs_bucket.endpoint(),
+            };
+            let storage = backends.storage.get(&backend).unwrap_or(&default);
+            let cancelled = Arc::new(AtomicBool::new(false));
+            let body = HttpBody::Stream {
+                len,
+                parts: Box::pin(body_parts(receiver, Arc::clone(&cancelled))),
+            };
+            let client = &backends.http_client;
+            let result = upload_via_http(
+                client,
+                body,
+                endpoint,
+                key,
+                meta,
+                storage,
+                &backends.watch(key),
+            )
+            .await;
+            match result {
+                Err(_) if cancelled.load(Ordering::Relaxed) => Err(AppError::Cancelled),
+                result => result,
+            }
+        }
+    }
+}
+
+/// Multipart upload to MinIO of the parts `parts` yields, aborted on any failure
+async fn upload_to_minio(
+    bucket: &Bucket,
+    parts: impl Stream<Item = Result<Bytes, AppError>>,
+    key: &str,
+    meta: &ObjectMeta,
+    storage: &StorageOptions,
+) -> Result<(), AppError> {
+    let bucket = with_object_headers(bucket, meta, storage);
+    let content_type = meta
+        .content_type
+        .as_deref()
+        .unwrap_or("application/octet-stream");
+    let upload_id = bucket
+        .initiate_multipart_upload(key, content_type)
+        .await?
+        .upload_id;
+
+    let result = async {
+        let mut completed = Vec::new();
+        let mut parts = pin!(parts);
+        while let Some(part) = parts.next().await {
+            let part_number = completed.len() as u32 + 1;
+            completed.push(
+                bucket
+                    .put_multipart_chunk(part?.to_vec(), key, part_number, &upload_id, content_type)
+                    .await?,
+            );
+        }
+        bucket
+            .complete_multipart_upload(key, &upload_id, completed)
+            .await?;
+        Ok(())
+    }
+    .await;
+
+    if result.is_err() {
+        if let Err(err) = bucket.abort_upload(key, &upload_id).await {
+            println!(
+                "Warning: could not abort multipart upload of {} on MinIO: {}",
+                key,
+                AppError::from(err)
+            );
+        }
+    }
+    result
+}
@@ -0,0 +1,227 @@
+use std::time::{Duration, SystemTime};
+
+use crate::error::AppError;
+
+/// Upload spans and metrics exported with `--otel-endpoint`
+///
+/// Each object sent to a backend becomes an `upload` span with `backend`, `key` and
+/// `bytes` attributes, and feeds the `upload.bytes` counter and `upload.duration`
+/// histogram. Without the `otel` feature every method is a no-op.
+#[derive(Default)]
+pub struct Telemetry {
+    #[cfg(feature = "otel")]
+    otel: Option<otel::Exporters>,
+}
+
+impl Telemetry {
+    /// Start exporting to the OTLP/HTTP collector at `endpoint`, e.g. `http://localhost:4318`
+    pub async fn init(endpoint: Option<&str>) -> Result<Self, AppError> {
+        let Some(endpoint) = endpoint else {
+            return Ok(Self::default());
+        };
+
+        #[cfg(feature = "otel")]
+        {
+            let endpoint = endpoint.trim_end_matches('/').to_string();
+            let exporters =
+                tokio::task::spawn_blocking(move || otel::Exporters::new(&endpoint)).await??;
+            Ok(Self {
+                otel: Some(exporters),
+            })
+        }
+
+        #[cfg(not(feature = "otel"))]
+        Err(AppError::Config(format!(
+            "--otel-endpoint {} needs a build with the `otel` feature",
+            endpoint
+        )))
+    }
+
+    /// Record one object upload that started at `started` and took `elapsed`
+    #[cfg_attr(not(feature = "otel"), allow(unused_variables))]
+    pub fn record_upload(
+        &self,
+        backend: &str,
+        key: &str,
+        bytes: u64,
+        started: SystemTime,
+        elapsed: Duration,
+        error: Option<&AppError>,
+    ) {
+        #[cfg(feature = "otel")]
+        if let Some(otel) = &self.otel {
+            otel.record_upload(backend, key, bytes, started, elapsed, error);
+        }
+    }
+
+    /// Flush pending spans and metrics before the process exits
+    pub fn shutdown(&self) {
+        #[cfg(feature = "otel")]
+        if let Some(otel) = &self.otel {
+            // Exporting blocks on the HTTP client, which must not stall an async worker
+            tokio::task::block_in_place(|| otel.shutdown());
+        }
+    }
+}
+
+#[cfg(feature = "otel")]
+mod otel {
+    use opentelemetry::{
+        metrics::{Counter, Histogram, MeterProvider},
+        trace::{Span, Status, Tracer, TracerProvider},
+        Key, KeyValue,
+    };
+    use opentelemetry_otlp::{MetricExporter, SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::{
+        metrics::SdkMeterProvider,
+        trace::{SdkTracer, SdkTracerProvider},
+        Resource,
+    };
+    use std::time::{Duration, SystemTime};
+
+    use crate::error::AppError;
+
+    const SCOPE: &str = "s3-ml-uploader";
+    const SERVICE_NAME: &str = "service.name";
+
+    /// The SDK's resource from `OTEL_*` variables, named by `OTEL_SERVICE_NAME`, else by
+    /// `OTEL_RESOURCE_ATTRIBUTES`, else after the crate
+    ///
+    /// The SDK would let the attributes' `service.name` win over the variable.
+    pub(super) fn resource() -> Resource {
+        let detected = Resource::builder()
+            .build()
+            .get(&Key::new(SERVICE_NAME))
+            .map(|name| name.to_string())
+            .filter(|name| name != "unknown_service");
+        let name = std::env::var("OTEL_SERVICE_NAME")
+            .ok()
+            .filter(|name| !name.is_empty())
+            .or(detected)
+            .unwrap_or_else(|| SCOPE.to_string());
+        Resource::builder().with_service_name(name).build()
+    }
+
+    pub struct Exporters {
+        tracer_provider: SdkTracerProvider,
+        meter_provider: SdkMeterProvider,
+        tracer: SdkTracer,
+        bytes: Counter<u64>,
+        duration: Histogram<f64>,
+    }
+
+    impl Exporters {
+        /// OTLP/HTTP exporters; `OTEL_*` variables (service name, headers, sampler) still apply
+        pub fn new(endpoint: &str) -> Result<Self, AppError> {
+            let failed = |err: opentelemetry_otlp::ExporterBuildError| {
+                AppError::Config(format!("OTLP exporter: {}", err))
+            };
+            let resource = resource();
+
+            let spans = SpanExporter::builder()
+                .with_http()
+                .with_endpoint(format!("{}/v1/traces", endpoint))
+                .build()
+                .map_err(failed)?;
+            let tracer_provider = SdkTracerProvider::builder()
+                .with_batch_exporter(spans)
+                .with_resource(resource.clone())
+                .build();
+
+            let metrics = MetricExporter::builder()
+                .with_http()
+                .with_endpoint(format!("{}/v1/metrics", endpoint))
+                .build()
+                .map_err(failed)?;
+            let meter_provider = SdkMeterProvider::builder()
+                .with_periodic_exporter(metrics)
+                .with_resource(resource)
+                .build();
+
+            let meter = meter_provider.meter(SCOPE);
+            Ok(Self {
+                tracer: tracer_provider.tracer(SCOPE),
+                bytes: meter
+                    .u64_counter("upload.bytes")
+                    .with_unit("By")
+                    .with_description("Bytes uploaded per backend")
+                    .build(),
+                duration: meter
+                    .f64_histogram("upload.duration")
+                    .with_unit("s")
+                    .with_description("Duration of one object upload")
+                    .build(),
+                tracer_provider,
+                meter_provider,
+            })
+        }
+
+        pub fn record_upload(
+            &self,
+            backend: &str,
+            key: &str,
+            bytes: u64,
+            started: SystemTime,
+            elapsed: Duration,
+            error: Option<&AppError>,
+        ) {
+            let backend_attr = KeyValue::new("backend", backend.to_string());
+            let mut span = self
+                .tracer
+                .span_builder("upload")
+                .with_start_time(started)
+                .with_attributes([
+                    backend_attr.clone(),
+                    KeyValue::new("key", key.to_string()),
+                    KeyValue::new("bytes", bytes as i64),
+                ])
+                .start(&self.tracer);
+            if let Some(err) = error {
+                span.set_status(Status::error(err.to_string()));
+            }
+            span.end_with_timestamp(started + elapsed);
+
+            let outcome = KeyValue::new("outcome", if error.is_some() { "error" } else { "ok" });
+            if error.is_none() {
+                self.bytes.add(bytes, std::slice::from_ref(&backend_attr));
+            }
+            self.duration
+                .record(elapsed.as_secs_f64(), &[backend_attr, outcome]);
+        }
+
+        pub fn shutdown(&self) {
+            let _ = self.tracer_provider.shutdown();
+            let _ = self.meter_provider.shutdown();
+        }
+    }
+}
+
+#[cfg(all(test, feature = "otel"))]
+mod tests {
+    use opentelemetry::Key;
+
+    use super::otel::resource;
+
+    // One test, since both cases set the same process-wide variables
+    #[test]
+    fn the_service_name_defaults_to_the_crate_unless_otel_variables_name_it() {
+        let service_name = || {
+            resource()
+                .get(&Key::new("service.name"))
+                .unwrap()
+                .to_string()
+        };
+        std::env::remove_var("OTEL_SERVICE_NAME");
+        std::env::remove_var("OTEL_RESOURCE_ATTRIBUTES");
+        assert_eq!(service_name(), "s3-ml-uploader");
+
+        std::env::set_var("OTEL_RESOURCE_ATTRIBUTES", "service.name=from-attributes");
+        assert_eq!(service_name(), "from-attributes");
+
+        std::env::set_var("OTEL_SERVICE_NAME", "trainer-uploads");
+        assert_eq!(service_name(), "trainer-uploads");
+
+        std::env::remove_var("OTEL_SERVICE_NAME");
+        std::env::remove_var("OTEL_RESOURCE_ATTRIBUTES");
+    }
+}
@@ -0,0 +1,333 @@
+use futures::{stream, StreamExt};
+use std::{collections::HashMap, path::Path};
+use tokio::fs;
+
+use crate::{
+    classifier::{self, Classifier},
+    classify_caught,
+    cli::ExportTrainingDataArgs,
+    error::AppError,
+    inputs,
+    ml::{self, Classification, FileCategory},
+    source,
+};
+
+/// Bytes read per call from files a classifier needs whole, `--read-buffer-size`'s default
+const READ_BUFFER_SIZE: usize = 256 * 1024;
+
+/// Columns of the dataset, in order
+const HEADER: &str =
+    "file,size,label,predicted,confidence,overridden,entropy,printable,zeros,prefix_hex";
+
+/// What the dataset records of a file: the input a trained classifier would get
+///
+/// Everything but `size` comes from the file's first `--prefix-len` bytes alone, so a
+/// model trained on it needs no more of a file than that prefix.
+pub struct Features {
+    /// Size of the whole file in bytes
+    pub size: u64,
+    /// Shannon entropy of the prefix in bits per byte, from 0.0 to 8.0
+    pub entropy: f32,
+    /// Share of the prefix that is printable ASCII or whitespace
+    pub printable: f32,
+    /// Share of the prefix that is zero bytes
+    pub zeros: f32,
+    /// The prefix itself
+    pub prefix: Vec<u8>,
+}
+
+/// Features of a file of `size` bytes starting with `prefix`
+///
+/// Ratios of an empty prefix are 0.0.
+pub fn features(prefix: &[u8], size: u64) -> Features {
+    let share = |count: usize| match prefix.len() {
+        0 => 0.0,
+        len => count as f32 / len as f32,
+    };
+    let printable = prefix
+        .iter()
+        .filter(|byte| byte.is_ascii_graphic() || byte.is_ascii_whitespace())
+        .count();
+    let zeros = prefix.iter().filter(|byte| **byte == 0).count();
+
+    Features {
+        size,
+        entropy: ml::entropy(prefix),
+        printable: share(printable),
+        zeros: share(zeros),
+        prefix: prefix.to_vec(),
+    }
+}
+
+/// One file of the dataset
+struct Row {
+    file: String,
+    features: Features,
+    classification: Classification,
+    // Assigned by --labels, replacing the predicted category as the label
+    label: Option<FileCategory>,
+}
+
+/// Classify files the way `upload` would and write their features and labels as CSV
+///
+/// Each row's label is the category from `--labels` when the file has one there (marked
+/// `overridden`), else the predicted one, which is always kept in its own column.
+pub async fn run(args: ExportTrainingDataArgs) -> Result<(), AppError> {
+    let classifier = classifier::from_args(&args.classifier)?;
+    let mut labels = match &args.labels {
+        Some(path) => read_labels(path).await?,
+        None => HashMap::new(),
+    };
+    let filter = inputs::ExtFilter {
+        include: args.include_ext.clone(),
+        exclude: args.exclude_ext.clone(),
+    };
+    let inputs = inputs::expand_inputs_async(
+        args.files.clone(),
+        args.dir.clone(),
+        args.no_glob,
+        args.allow_empty_glob,
+        filter,
+    )
+    .await?;
+
+    let (classifier, prefix_len) = (classifier.as_ref(), args.prefix_len as usize);
+    let results: Vec<_> = stream::iter(&inputs.files)
+        .map(|file| async move { (file, export_file(classifier, file, prefix_len).await) })
+        .buffered(args.concurrency.max(1))
+        .collect()
+        .await;
+
+    let mut csv = format!("{}\n", HEADER);
+    let (mut exported, mut overridden, mut failed) = (0, 0, 0);
+    for (file, result) in results {
+        match result {
+            Ok((features, classification)) => {
+                let row = Row {
+                    file: file.clone(),
+                    features,
+                    classification,
+                    label: labels.remove(file),
+                };
+                overridden += usize::from(row.label.is_some());
+                exported += 1;
+                csv.push_str(&csv_line(&row));
+            }
+            Err(err) => {
+                eprintln!("Could not export {}: {}", file, err);
+                failed += 1;
+            }
+        }
+    }
+    fs::write(&args.output, csv).await?;
+
+    println!(
+        "Exported {} file(s) to {} ({} label(s) overridden)",
+        exported,
+        args.output.display(),
+        overridden
+    );
+    if !labels.is_empty() {
+        println!(
+            "{} label(s) of --labels matched no exported file",
+            labels.len()
+        );
+    }
+    match failed {
+        0 => Ok(()),
+        failed => Err(AppError::ClassifyFailed(failed)),
+    }
+}
+
+/// Read what the classifier and the features need of `file`, and classify it
+async fn export_file(
+    classifier: &dyn Classifier,
+    file: &str,
+    prefix_len: usize,
+) -> Result<(Features, Classification), AppError> {
+    let size = fs::metadata(file).await?.len();
+    let content = match classifier.sample_len() {
+        Some(len) => source::read_prefix(file, None, len.max(prefix_len)).await?,
+        None => {
+            source::read_source(file, None, READ_BUFFER_SIZE)
+                .await?
+                .bytes
+        }
+    };
+
+    // A bounded classifier sees no more than it would during an upload
+    let sample = match classifier.sample_len() {
+        Some(len) => &content[..content.len().min(len)],
+        None => &content[..],
+    };
+    let classification = classify_caught(classifier, file, sample)?;
+    let prefix = &content[..content.len().min(prefix_len)];
+    Ok((features(prefix, size), classification))
+}
+
+/// Labels of a `path,category` file; blank lines and `#` comments are skipped
+async fn read_labels(path: &Path) -> Result<HashMap<String, FileCategory>, AppError> {
+    let contents = fs::read_to_string(path).await?;
+    let mut labels = HashMap::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = |problem: String| {
+            AppError::Config(format!("{}:{}: {}", path.display(), index + 1, problem))
+        };
+        // Paths may hold commas, categories can't
+        let (file, category) = line
+            .rsplit_once(',')
+            .ok_or_else(|| invalid(format!("expected PATH,CATEGORY, got '{}'", line)))?;
+        labels.insert(file.trim().to_string(), category.parse().map_err(invalid)?);
+    }
+    Ok(labels)
+}
+
+fn csv_line(row: &Row) -> String {
+    let predicted = row.classification.category;
+    let features = &row.features;
+    format!(
+        "{},{},{},{},{:.2},{},{:.4},{:.4},{:.4},{}\n",
+        csv_field(&row.file),
+        features.size,
+        row.label.unwrap_or(predicted).as_str(),
+        predicted.as_str(),
+        row.classification.confidence,
+        row.label.is_some(),
+        features.entropy,
+        features.printable,
+        features.zeros,
+        hex::encode(&features.prefix)
+    )
+}
+
+/// A field quoted as RFC 4180 requires when it holds a comma, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+
+    #[test]
+    fn features_describe_the_prefix() {
+        let prefix = b"ab\0\0\x01\x02\n ";
+        let features = features(prefix, 4096);
+
+        assert_eq!(features.size, 4096);
+        assert_eq!(features.prefix, prefix);
+        // a, b, newline and space
+        assert_eq!(features.printable, 0.5);
+        assert_eq!(features.zeros, 0.25);
+        assert_eq!(features.entropy, ml::entropy(prefix));
+    }
+
+    #[test]
+    fn entropy_spans_uniform_to_every_byte() {
+        assert_eq!(features(&[7; 64], 64).entropy, 0.0);
+        let every_byte: Vec<u8> = (0..=255).collect();
+        let entropy = features(&every_byte, 256).entropy;
+        assert!((entropy - 8.0).abs() < 1e-4, "{}", entropy);
+    }
+
+    #[test]
+    fn an_empty_prefix_has_zero_ratios() {
+        let features = features(b"", 0);
+        assert_eq!(
+            (features.entropy, features.printable, features.zeros),
+            (0.0, 0.0, 0.0)
+        );
+        assert!(features.prefix.is_empty());
+    }
+
+    #[test]
+    fn lines_keep_the_prediction_beside_an_overriding_label() {
+        let row = |label| Row {
+            file: "data/a,b.txt".to_string(),
+            features: features(b"hi", 2),
+            classification: Classification {
+                key: "text/a,b.txt".to_string(),
+                category: FileCategory::Text,
+                confidence: 0.875,
+                mime: "text/plain".to_string(),
+            },
+            label,
+        };
+
+        assert_eq!(
+            csv_line(&row(None)),
+            "\"data/a,b.txt\",2,text,text,0.88,false,1.0000,1.0000,0.0000,6869\n"
+        );
+        assert_eq!(
+            csv_line(&row(Some(FileCategory::Documents))),
+            "\"data/a,b.txt\",2,documents,text,0.88,true,1.0000,1.0000,0.0000,6869\n"
+        );
+        assert_eq!(HEADER.split(',').count(), 10);
+    }
+
+    #[test]
+    fn fields_are_quoted_only_when_they_need_it() {
+        assert_eq!(csv_field("plain.txt"), "plain.txt");
+        assert_eq!(csv_field("say \"hi\".txt"), "\"say \"\"hi\"\".txt\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[tokio::test]
+    async fn labels_skip_comments_and_split_on_the_last_comma() {
+        let dir = TestDir::new();
+        let path = dir.write(
+            "labels.csv",
+            "# reviewed by hand\n\ndata/a,b.txt, documents\nphoto.jpg,images\n",
+        );
+
+        let labels = read_labels(Path::new(&path)).await.unwrap();
+        assert_eq!(labels.len(), 2);
+        assert_eq!(labels["data/a,b.txt"], FileCategory::Documents);
+        assert_eq!(labels["photo.jpg"], FileCategory::Images);
+    }
+
+    #[tokio::test]
+    async fn bad_labels_name_their_line() {
+        let dir = TestDir::new();
+        for (contents, problem) in [
+            (
+                "photo.jpg,images\nno-category\n",
+                ":2: expected PATH,CATEGORY",
+            ),
+            ("photo.jpg,pictures\n", ":1: "),
+        ] {
+            let path = dir.write("labels.csv", contents);
+            match read_labels(Path::new(&path)).await {
+                Err(AppError::Config(message)) => {
+                    assert!(message.contains(problem), "{}", message)
+                }
+                other => panic!("expected a Config error, got {:?}", other.map(|_| ())),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn a_bounded_classifier_still_gets_the_whole_prefix() {
+        let dir = TestDir::new();
+        let content = vec![b'x'; 2048];
+        let file = dir.write("notes.txt", &content);
+        let classifier = ml::FileTypePredictor::new();
+
+        let (features, classification) = export_file(&classifier, &file, 1024).await.unwrap();
+        assert_eq!(features.size, 2048);
+        assert_eq!(features.prefix.len(), 1024);
+        assert_eq!(classification.category, FileCategory::Text);
+
+        let (features, _) = export_file(&classifier, &file, 4096).await.unwrap();
+        assert_eq!(features.prefix, content);
+    }
+}
@@ -0,0 +1,504 @@
+use bytes::{Bytes, BytesMut};
+
+use crate::{cli::UploadArgs, error::AppError, ml::FileCategory};
+
+/// Rewrites a file's content before it is classified, hashed and uploaded
+///
+/// `run_upload` applies its transforms in order to every input file; the output of the
+/// last one is what the classifier sees and what lands in the bucket. Implementations
+/// must be thread-safe since files are processed concurrently.
+pub trait ContentTransform: Send + Sync {
+    fn transform(&self, input: Bytes) -> Result<Bytes, AppError>;
+}
+
+/// Built-in transforms enabled on the command line, in the order they run
+pub fn from_args(args: &UploadArgs) -> Vec<Box<dyn ContentTransform>> {
+    let mut transforms: Vec<Box<dyn ContentTransform>> = Vec::new();
+    if args.strip_exif {
+        transforms.push(Box::new(StripExif));
+    }
+    if let Some(categories) = &args.strip_metadata {
+        transforms.push(Box::new(StripMetadata {
+            images: categories.contains(&FileCategory::Images),
+            documents: categories.contains(&FileCategory::Documents),
+        }));
+    }
+    if args.normalize_newlines {
+        transforms.push(Box::new(NormalizeNewlines));
+    }
+    transforms
+}
+
+/// Run `input` through every transform in order
+pub fn apply(transforms: &[Box<dyn ContentTransform>], input: Bytes) -> Result<Bytes, AppError> {
+    transforms
+        .iter()
+        .try_fold(input, |content, transform| transform.transform(content))
+}
+
+/// Removes EXIF metadata (camera, GPS position, ...) from JPEG and PNG images
+///
+/// JPEG APP1 `Exif` segments and PNG `eXIf` chunks are dropped; pixel data and every other
+/// segment are kept byte for byte. Other content passes through unchanged.
+pub struct StripExif;
+
+const JPEG_SOI: [u8; 2] = [0xFF, 0xD8];
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+impl ContentTransform for StripExif {
+    fn transform(&self, input: Bytes) -> Result<Bytes, AppError> {
+        if input.starts_with(&JPEG_SOI) {
+            strip_jpeg_segments(&input, is_exif_segment)
+        } else if input.starts_with(&PNG_SIGNATURE) {
+            strip_png_chunks(&input, |chunk| chunk == b"eXIf")
+        } else {
+            Ok(input)
+        }
+    }
+}
+
+/// Removes embedded metadata from images, documents or both
+///
+/// JPEG loses its EXIF, XMP and IPTC segments and comments; PNG its `eXIf`, text and
+/// `tIME` chunks. Color profiles are kept so images render the same. In PDF files the
+/// strings of the document information dictionary (author, title, producer, dates, ...)
+/// and XMP packets are blanked in place, which keeps every byte offset of the file valid;
+/// an information dictionary inside a compressed object stream is left as it is.
+pub struct StripMetadata {
+    pub images: bool,
+    pub documents: bool,
+}
+
+impl ContentTransform for StripMetadata {
+    fn transform(&self, input: Bytes) -> Result<Bytes, AppError> {
+        if self.images && input.starts_with(&JPEG_SOI) {
+            strip_jpeg_segments(&input, is_metadata_segment)
+        } else if self.images && input.starts_with(&PNG_SIGNATURE) {
+            strip_png_chunks(&input, |chunk| {
+                matches!(chunk, b"eXIf" | b"tEXt" | b"zTXt" | b"iTXt" | b"tIME")
+            })
+        } else if self.documents && input.starts_with(b"%PDF-") {
+            Ok(blank_pdf_metadata(&input))
+        } else {
+            Ok(input)
+        }
+    }
+}
+
+/// APP1 segment holding EXIF
+fn is_exif_segment(marker: u8, payload: &[u8]) -> bool {
+    marker == 0xE1 && payload.starts_with(b"Exif\0\0")
+}
+
+/// EXIF and XMP (APP1), IPTC (APP13) and comment segments
+fn is_metadata_segment(marker: u8, payload: &[u8]) -> bool {
+    match marker {
+        0xE1 => {
+            is_exif_segment(marker, payload)
+                || payload.starts_with(b"http://ns.adobe.com/xap/1.0/\0")
+                || payload.starts_with(b"http://ns.adobe.com/xmp/extension/\0")
+        }
+        0xED => payload.starts_with(b"Photoshop 3.0\0"),
+        0xFE => true,
+        _ => false,
+    }
+}
+
+/// Copy a JPEG without the segments `drop` selects by marker and payload
+fn strip_jpeg_segments(input: &[u8], drop: fn(u8, &[u8]) -> bool) -> Result<Bytes, AppError> {
+    let malformed = |reason: &str| AppError::Transform(format!("malformed JPEG: {}", reason));
+    let mut output = BytesMut::with_capacity(input.len());
+    output.extend_from_slice(&JPEG_SOI);
+    let mut pos = JPEG_SOI.len();
+
+    while pos < input.len() {
+        if input[pos] != 0xFF {
+            return Err(malformed("expected a segment marker"));
+        }
+        let marker = *input
+            .get(pos + 1)
+            .ok_or_else(|| malformed("truncated marker"))?;
+
+        match marker {
+            // Fill byte before a marker
+            0xFF => {
+                pos += 1;
+                continue;
+            }
+            // Start of scan or end of image: the rest is entropy-coded data
+            0xDA | 0xD9 => {
+                output.extend_from_slice(&input[pos..]);
+                break;
+            }
+            // Markers without a length
+            0x01 | 0xD0..=0xD7 => {
+                output.extend_from_slice(&input[pos..pos + 2]);
+                pos += 2;
+                continue;
+            }
+            _ => {}
+        }
+
+        let length = input
+            .get(pos + 2..pos + 4)
+            .map(|len| u16::from_be_bytes([len[0], len[1]]) as usize)
+            .ok_or_else(|| malformed("truncated segment length"))?;
+        let end = pos + 2 + length;
+        if length < 2 || end > input.len() {
+            return Err(malformed("segment runs past the end of the file"));
+        }
+
+        if !drop(marker, &input[pos + 4..end]) {
+            output.extend_from_slice(&input[pos..end]);
+        }
+        pos = end;
+    }
+
+    Ok(output.freeze())
+}
+
+/// Copy a PNG without the chunks `drop` selects by chunk type
+fn strip_png_chunks(input: &[u8], drop: impl Fn(&[u8]) -> bool) -> Result<Bytes, AppError> {
+    let mut output = BytesMut::with_capacity(input.len());
+    output.extend_from_slice(&PNG_SIGNATURE);
+    let mut pos = PNG_SIGNATURE.len();
+
+    // Chunk layout: length (4), type (4), data, CRC (4)
+    while pos < input.len() {
+        let length = input
+            .get(pos..pos + 4)
+            .map(|len| u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize)
+            .ok_or_else(|| AppError::Transform("malformed PNG: truncated chunk".to_string()))?;
+        let end = pos + 12 + length;
+        if end > input.len() {
+            return Err(AppError::Transform(
+                "malformed PNG: chunk runs past the end of the file".to_string(),
+            ));
+        }
+
+        if !drop(&input[pos + 4..pos + 8]) {
+            output.extend_from_slice(&input[pos..end]);
+        }
+        pos = end;
+    }
+
+    Ok(output.freeze())
+}
+
+/// Blank the document information strings and XMP packets of a PDF, keeping its length
+fn blank_pdf_metadata(input: &[u8]) -> Bytes {
+    let mut output = input.to_vec();
+
+    // Every revision's trailer may point at its own information dictionary
+    for reference in find_all(input, b"/Info") {
+        let Some(object) = parse_reference(&input[reference + 5..]) else {
+            continue;
+        };
+        let Some(start) = find_object(input, &object) else {
+            continue;
+        };
+        let Some(dict) = find(&input[start..], b"<<").map(|offset| start + offset) else {
+            continue;
+        };
+        blank_dict_strings(&mut output, dict);
+    }
+
+    // XMP packets are whitespace-padded by design, so spaces are a valid replacement
+    let mut from = 0;
+    while let Some(start) = find(&output[from..], b"<x:xmpmeta").map(|offset| from + offset) {
+        let Some(end) = find(&output[start..], b"</x:xmpmeta>").map(|offset| start + offset + 12)
+        else {
+            break;
+        };
+        output[start..end].fill(b' ');
+        from = end;
+    }
+
+    output.into()
+}
+
+/// `12 0 R` after `/Info`, as the `12 0 obj` header it refers to
+fn parse_reference(input: &[u8]) -> Option<Vec<u8>> {
+    let text = std::str::from_utf8(&input[..input.len().min(32)]).ok()?;
+    let mut parts = text.split_ascii_whitespace();
+    let (number, generation) = (parts.next()?, parts.next()?);
+    let is_number = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+    if !is_number(number) || !is_number(generation) || !parts.next()?.starts_with('R') {
+        return None;
+    }
+    Some(format!("{} {} obj", number, generation).into_bytes())
+}
+
+/// Offset of an object header that starts a line or follows whitespace
+fn find_object(input: &[u8], header: &[u8]) -> Option<usize> {
+    find_all(input, header)
+        .into_iter()
+        .find(|&offset| offset == 0 || input[offset - 1].is_ascii_whitespace())
+}
+
+/// Replace the contents of every string in the dictionary starting at `start` with spaces
+fn blank_dict_strings(output: &mut [u8], start: usize) {
+    let mut depth = 0;
+    let mut pos = start;
+
+    while pos < output.len() {
+        match output[pos] {
+            b'<' if output.get(pos + 1) == Some(&b'<') => {
+                depth += 1;
+                pos += 2;
+            }
+            b'>' if output.get(pos + 1) == Some(&b'>') => {
+                depth -= 1;
+                pos += 2;
+                if depth == 0 {
+                    return;
+                }
+            }
+            // Hex string: whitespace inside is ignored, so this leaves it empty
+            b'<' => {
+                pos += 1;
+                while pos < output.len() && output[pos] != b'>' {
+                    output[pos] = b' ';
+                    pos += 1;
+                }
+                pos += 1;
+            }
+            // Literal string with balanced parentheses and backslash escapes
+            b'(' => {
+                let mut nesting = 1;
+                pos += 1;
+                while pos < output.len() && nesting > 0 {
+                    match output[pos] {
+                        b'\\' => {
+                            output[pos] = b' ';
+                            if let Some(escaped) = output.get_mut(pos + 1) {
+                                *escaped = b' ';
+                            }
+                            pos += 2;
+                            continue;
+                        }
+                        b'(' => nesting += 1,
+                        b')' => nesting -= 1,
+                        _ => {}
+                    }
+                    if nesting > 0 {
+                        output[pos] = b' ';
+                    }
+                    pos += 1;
+                }
+            }
+            _ => pos += 1,
+        }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn find_all(haystack: &[u8], needle: &[u8]) -> Vec<usize> {
+    haystack
+        .windows(needle.len())
+        .enumerate()
+        .filter(|(_, window)| *window == needle)
+        .map(|(offset, _)| offset)
+        .collect()
+}
+
+/// Converts CRLF line endings to LF in UTF-8 text; binary content passes through unchanged
+pub struct NormalizeNewlines;
+
+impl ContentTransform for NormalizeNewlines {
+    fn transform(&self, input: Bytes) -> Result<Bytes, AppError> {
+        let is_text = !input.contains(&0) && std::str::from_utf8(&input).is_ok();
+        if !is_text || !input.windows(2).any(|pair| pair == b"\r\n") {
+            return Ok(input);
+        }
+
+        let mut output = BytesMut::with_capacity(input.len());
+        let mut bytes = input.iter().peekable();
+        while let Some(&byte) = bytes.next() {
+            if byte == b'\r' && bytes.peek() == Some(&&b'\n') {
+                continue;
+            }
+            output.extend_from_slice(&[byte]);
+        }
+        Ok(output.freeze())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A JPEG segment of `marker` around `payload`
+    fn segment(marker: u8, payload: &[u8]) -> Vec<u8> {
+        let length = (payload.len() + 2) as u16;
+        [&[0xFF, marker][..], &length.to_be_bytes(), payload].concat()
+    }
+
+    const JFIF: &[u8] = b"JFIF\0\x01\x02\0\0\x01\0\x01\0\0";
+    const EXIF: &[u8] = b"Exif\0\0MM\0*GPS 51.5N 0.1W";
+    const XMP: &[u8] = b"http://ns.adobe.com/xap/1.0/\0<x:xmpmeta>author</x:xmpmeta>";
+    const SCAN: &[u8] = &[
+        0xFF, 0xDA, 0x00, 0x02, 0x12, 0x34, 0xFF, 0x00, 0x56, 0xFF, 0xD9,
+    ];
+
+    fn jpeg() -> Bytes {
+        [
+            &JPEG_SOI[..],
+            &segment(0xE0, JFIF),
+            &segment(0xE1, EXIF),
+            &segment(0xE1, XMP),
+            &segment(0xFE, b"shot by Jane"),
+            &segment(0xDB, &[0x00; 65]),
+            SCAN,
+        ]
+        .concat()
+        .into()
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        find(haystack, needle).is_some()
+    }
+
+    #[test]
+    fn strip_exif_removes_only_the_exif_segment() {
+        let stripped = StripExif.transform(jpeg()).unwrap();
+
+        let expected: Vec<u8> = [
+            &JPEG_SOI[..],
+            &segment(0xE0, JFIF),
+            &segment(0xE1, XMP),
+            &segment(0xFE, b"shot by Jane"),
+            &segment(0xDB, &[0x00; 65]),
+            SCAN,
+        ]
+        .concat();
+        assert_eq!(stripped, expected);
+        assert!(!contains(&stripped, b"GPS"));
+    }
+
+    #[test]
+    fn strip_metadata_removes_exif_xmp_and_comments() {
+        let strip = StripMetadata {
+            images: true,
+            documents: false,
+        };
+
+        let stripped = strip.transform(jpeg()).unwrap();
+
+        let expected: Vec<u8> = [
+            &JPEG_SOI[..],
+            &segment(0xE0, JFIF),
+            &segment(0xDB, &[0x00; 65]),
+            SCAN,
+        ]
+        .concat();
+        assert_eq!(stripped, expected);
+    }
+
+    #[test]
+    fn truncated_jpegs_are_an_error() {
+        let jpeg = jpeg();
+        let truncated = jpeg.slice(..JPEG_SOI.len() + 10);
+
+        assert!(matches!(
+            StripExif.transform(truncated),
+            Err(AppError::Transform(_))
+        ));
+    }
+
+    /// A PNG chunk of `kind` around `data`, with a dummy CRC
+    fn chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+        [&(data.len() as u32).to_be_bytes()[..], kind, data, &[0; 4]].concat()
+    }
+
+    #[test]
+    fn png_metadata_chunks_are_dropped() {
+        let header = chunk(b"IHDR", &[0; 13]);
+        let profile = chunk(b"iCCP", b"srgb\0\0profile");
+        let pixels = chunk(b"IDAT", b"pixels");
+        let end = chunk(b"IEND", b"");
+        let png: Bytes = [
+            &PNG_SIGNATURE[..],
+            &header,
+            &chunk(b"eXIf", b"MM\0*GPS"),
+            &chunk(b"tEXt", b"Author\0Jane"),
+            &profile,
+            &pixels,
+            &end,
+        ]
+        .concat()
+        .into();
+
+        let exif_only = StripExif.transform(png.clone()).unwrap();
+        assert!(!contains(&exif_only, b"eXIf"));
+        assert!(contains(&exif_only, b"tEXt"));
+
+        let strip = StripMetadata {
+            images: true,
+            documents: false,
+        };
+        let stripped = strip.transform(png).unwrap();
+        let expected: Vec<u8> = [&PNG_SIGNATURE[..], &header, &profile, &pixels, &end].concat();
+        assert_eq!(stripped, expected);
+    }
+
+    #[test]
+    fn pdf_information_is_blanked_in_place() {
+        let pdf = Bytes::from_static(
+            b"%PDF-1.7\n1 0 obj\n<< /Author (Jane \\(J\\) Doe) /Title <4a616e65> >>\nendobj\n\
+              trailer\n<< /Info 1 0 R >>\n%%EOF\n",
+        );
+        let strip = StripMetadata {
+            images: false,
+            documents: true,
+        };
+
+        let stripped = strip.transform(pdf.clone()).unwrap();
+
+        assert_eq!(stripped.len(), pdf.len());
+        assert!(!contains(&stripped, b"Jane"));
+        assert!(!contains(&stripped, b"4a616e65"));
+        assert!(contains(&stripped, b"/Author ("));
+        assert!(contains(&stripped, b"/Info 1 0 R"));
+    }
+
+    #[test]
+    fn categories_not_selected_pass_through() {
+        let strip = StripMetadata {
+            images: false,
+            documents: true,
+        };
+        assert_eq!(strip.transform(jpeg()).unwrap(), jpeg());
+
+        let text = Bytes::from_static(b"GPS 51.5N");
+        assert_eq!(StripExif.transform(text.clone()).unwrap(), text);
+    }
+
+    #[test]
+    fn newlines_are_normalized_in_text_only() {
+        assert_eq!(
+            NormalizeNewlines
+                .transform(Bytes::from_static(b"a\r\nb\r\n\rc"))
+                .unwrap(),
+            Bytes::from_static(b"a\nb\n\rc")
+        );
+        let binary = Bytes::from_static(b"\0a\r\nb");
+        assert_eq!(NormalizeNewlines.transform(binary.clone()).unwrap(), binary);
+    }
+
+    #[test]
+    fn transforms_apply_in_order() {
+        let transforms: Vec<Box<dyn ContentTransform>> =
+            vec![Box::new(NormalizeNewlines), Box::new(StripExif)];
+
+        assert_eq!(
+            apply(&transforms, Bytes::from_static(b"a\r\n")).unwrap(),
+            Bytes::from_static(b"a\n")
+        );
+    }
+}
@@ -0,0 +1,71 @@
+use chrono::Utc;
+
+use crate::{cli::VersionSuffix, error::AppError, hashing::Sha256Digest, keys, Backends};
+
+/// Hex digits of the SHA-256 a `hash` version token keeps
+const HASH_DIGITS: usize = 12;
+
+/// Version tokens of a run (`--version-suffix`)
+pub struct Versioner {
+    strategy: VersionSuffix,
+    // Taken once, so every file of a run carries the same timestamp
+    timestamp: String,
+}
+
+impl Versioner {
+    pub fn new(strategy: VersionSuffix) -> Self {
+        Self {
+            strategy,
+            timestamp: Utc::now().format("%Y-%m-%dT%H-%M-%SZ").to_string(),
+        }
+    }
+
+    /// Whether tokens depend on the content's SHA-256
+    pub fn needs_digest(&self) -> bool {
+        self.strategy == VersionSuffix::Hash
+    }
+
+    /// The key `key` would get without asking any backend; a `counter` shows as `<n>`
+    pub fn preview(&self, key: &str, digest: Option<&Sha256Digest>) -> String {
+        match (self.strategy, digest) {
+            (VersionSuffix::Timestamp, _) => keys::with_version(key, &self.timestamp),
+            (VersionSuffix::Hash, Some(digest)) => {
+                keys::with_version(key, &hex::encode(digest)[..HASH_DIGITS])
+            }
+            _ => keys::with_version(key, "<n>"),
+        }
+    }
+
+    /// `key` with this run's version token before its extension
+    ///
+    /// A `counter` HEADs `<stem>.1.<ext>`, `<stem>.2.<ext>`, ... on every backend and replica
+    /// until one is free everywhere, so it costs a request per earlier version and target.
+    pub async fn key(
+        &self,
+        backends: &Backends,
+        key: &str,
+        digest: &Sha256Digest,
+    ) -> Result<String, AppError> {
+        match self.strategy {
+            VersionSuffix::Timestamp => Ok(keys::with_version(key, &self.timestamp)),
+            VersionSuffix::Hash => Ok(keys::with_version(key, &hex::encode(digest)[..HASH_DIGITS])),
+            VersionSuffix::Counter => {
+                let mut version = 1u64;
+                loop {
+                    let candidate = keys::with_version(key, &version.to_string());
+                    let mut taken = false;
+                    for target in backends.targets() {
+                        if backends.head(target, &candidate).await?.is_some() {
+                            taken = true;
+                            break;
+                        }
+                    }
+                    if !taken {
+                        return Ok(candidate);
+                    }
+                    version += 1;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use hmac::{Hmac, Mac};
+use reqwest::Client as ReqwestClient;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::task::JoinSet;
+
+use crate::{cli::WebhookMode, error::AppError};
+
+/// Deliveries attempted per payload before giving up
+const ATTEMPTS: u32 = 4;
+
+/// Delay before the first retry, doubled after each failed attempt
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Timeout of one delivery attempt
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Header carrying `sha256=<hex HMAC-SHA256 of the body>` when a secret is set
+pub const SIGNATURE_HEADER: &str = "X-Signature-256";
+
+/// Notifies `--webhook-url` about finished files
+///
+/// Deliveries run in the background and are retried with exponential backoff; one that
+/// still fails is logged and never fails the upload.
+pub struct Webhook {
+    url: String,
+    client: ReqwestClient,
+    secret: Option<String>,
+    mode: WebhookMode,
+    // Per-file deliveries still in flight
+    deliveries: Mutex<JoinSet<()>>,
+    // File events collected for the batch payload
+    events: Mutex<Vec<Value>>,
+}
+
+impl Webhook {
+    pub fn new(url: &str, secret: Option<String>, mode: WebhookMode) -> Result<Self, AppError> {
+        Ok(Self {
+            url: url.to_string(),
+            client: ReqwestClient::builder().timeout(TIMEOUT).build()?,
+            secret,
+            mode,
+            deliveries: Mutex::new(JoinSet::new()),
+            events: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Report one processed file: sent right away per file, or kept for the batch payload
+    pub fn file_done(self: &Arc<Self>, event: Value) {
+        match self.mode {
+            WebhookMode::PerFile => {
+                let webhook = Arc::clone(self);
+                self.deliveries
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .spawn(async move { webhook.deliver(&event).await });
+            }
+            WebhookMode::Batch => self
+                .events
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(event),
+        }
+    }
+
+    /// Send the batch payload if any and wait for every delivery to finish
+    pub async fn finish(&self, summary: Value) {
+        if self.mode == WebhookMode::Batch {
+            let files = std::mem::take(&mut *self.events.lock().unwrap_or_else(|e| e.into_inner()));
+            self.deliver(&json!({ "files": files, "summary": summary }))
+                .await;
+        }
+
+        let mut deliveries =
+            std::mem::take(&mut *self.deliveries.lock().unwrap_or_else(|e| e.into_inner()));
+        while deliveries.join_next().await.is_some() {}
+    }
+
+    /// POST `payload`, retrying network errors, 429 and 5xx responses
+    async fn deliver(&self, payload: &Value) {
+        let body = payload.to_string();
+        let mut backoff = INITIAL_BACKOFF;
+
+        for attempt in 1..=ATTEMPTS {
+            let error = match self.send(&body).await {
+                Ok(status) if status.is_success() => return,
+                Ok(status) if status.as_u16() != 429 && !status.is_server_error() => {
+                    println!(
+                        "Warning: webhook {} rejected the notification: {}",
+                        self.url, status
+                    );
+                    return;
+                }
+                Ok(status) => status.to_string(),
+                Err(err) => err.to_string(),
+            };
+
+            if attempt == ATTEMPTS {
+                println!(
+                    "Warning: webhook {} not delivered after {} attempts: {}",
+                    self.url, ATTEMPTS, error
+                );
+                return;
+            }
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+    }
+
+    async fn send(&self, body: &str) -> Result<reqwest::StatusCode, AppError> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header("Content-Type", "application/json");
+        if let Some(secret) = &self.secret {
+            request = request.header(SIGNATURE_HEADER, signature(secret, body)?);
+        }
+
+        Ok(request.body(body.to_string()).send().await?.status())
+    }
+}
+
+/// `sha256=<hex>` HMAC of `body`, which receivers recompute with the shared secret
+pub fn signature(secret: &str, body: &str) -> Result<String, AppError> {
+    let mut hmac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())?;
+    hmac.update(body.as_bytes());
+    Ok(format!(
+        "sha256={}",
+        hex::encode(hmac.finalize().into_bytes())
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::{
+        body::to_bytes,
+        service::{make_service_fn, service_fn},
+        Body, Response, Server,
+    };
+    use std::{convert::Infallible, net::SocketAddr};
+
+    /// Signature header and body of one delivery
+    type Received = Arc<Mutex<Vec<(Option<String>, Value)>>>;
+
+    /// Receiver answering with `statuses` in turn, then 200, and what it was sent
+    fn receiver(statuses: &[u16]) -> (String, Received) {
+        let statuses = Arc::new(Mutex::new(statuses.to_vec()));
+        let received = Received::default();
+        let seen = received.clone();
+        let make = make_service_fn(move |_| {
+            let (statuses, seen) = (statuses.clone(), seen.clone());
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: hyper::Request<Body>| {
+                    let (statuses, seen) = (statuses.clone(), seen.clone());
+                    async move {
+                        let signature = request
+                            .headers()
+                            .get(SIGNATURE_HEADER)
+                            .map(|value| value.to_str().unwrap().to_string());
+                        let body = to_bytes(request.into_body()).await.unwrap();
+                        let body = serde_json::from_slice(&body).unwrap();
+                        seen.lock().unwrap().push((signature, body));
+                        let mut statuses = statuses.lock().unwrap();
+                        let status = match statuses.is_empty() {
+                            true => 200,
+                            false => statuses.remove(0),
+                        };
+                        let response = Response::builder().status(status).body(Body::empty());
+                        Ok::<_, Infallible>(response.unwrap())
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make);
+        let url = format!("http://{}/hook", server.local_addr());
+        tokio::spawn(server);
+        (url, received)
+    }
+
+    fn event(file: &str) -> Value {
+        json!({ "file": file, "key": format!("text/{}", file), "status": "uploaded" })
+    }
+
+    #[tokio::test]
+    async fn per_file_deliveries_are_signed() {
+        let (url, received) = receiver(&[]);
+        let webhook =
+            Arc::new(Webhook::new(&url, Some("shared".to_string()), WebhookMode::PerFile).unwrap());
+
+        webhook.file_done(event("notes.txt"));
+        webhook.finish(json!({})).await;
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        let (signature, body) = &received[0];
+        assert_eq!(body, &event("notes.txt"));
+        assert_eq!(
+            signature.as_deref(),
+            Some(signature_of("shared", &body.to_string()).as_str())
+        );
+    }
+
+    fn signature_of(secret: &str, body: &str) -> String {
+        signature(secret, body).unwrap()
+    }
+
+    #[test]
+    fn signatures_are_hmac_sha256() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            signature_of("Jefe", "what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[tokio::test]
+    async fn batch_mode_sends_one_payload_at_the_end() {
+        let (url, received) = receiver(&[]);
+        let webhook = Arc::new(Webhook::new(&url, None, WebhookMode::Batch).unwrap());
+
+        webhook.file_done(event("a.txt"));
+        webhook.file_done(event("b.txt"));
+        assert!(received.lock().unwrap().is_empty());
+        webhook.finish(json!({ "succeeded": 2 })).await;
+
+        let received = received.lock().unwrap();
+        assert_eq!(
+            *received,
+            vec![(
+                None,
+                json!({
+                    "files": [event("a.txt"), event("b.txt")],
+                    "summary": { "succeeded": 2 },
+                })
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_retried() {
+        let (url, received) = receiver(&[503, 500]);
+        let webhook = Arc::new(Webhook::new(&url, None, WebhookMode::PerFile).unwrap());
+
+        webhook.file_done(event("notes.txt"));
+        webhook.finish(json!({})).await;
+
+        assert_eq!(received.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn a_rejected_notification_is_not_retried() {
+        let (url, received) = receiver(&[400]);
+        let webhook = Arc::new(Webhook::new(&url, None, WebhookMode::PerFile).unwrap());
+
+        webhook.file_done(event("notes.txt"));
+        webhook.finish(json!({})).await;
+
+        assert_eq!(received.lock().unwrap().len(), 1);
+    }
+}
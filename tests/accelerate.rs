@@ -0,0 +1,43 @@
+//! `--accelerate` settings refused before anything is sent
+
+mod common;
+
+use common::{run, Env, MockS3, TestDir};
+use s3_ml_uploader::error::AppError;
+
+async fn accelerated(bucket: &str) -> (MockS3, AppError) {
+    let mock = MockS3::start().await;
+    let env = Env::aws(&mock).await;
+    env.set("AWS_BUCKET", bucket);
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+
+    let err = run(&["upload", "--backends", "aws", "--accelerate", &file])
+        .await
+        .unwrap_err();
+    (mock, err)
+}
+
+#[tokio::test]
+async fn dotted_bucket_names_are_refused() {
+    let (mock, err) = accelerated("my.bucket").await;
+
+    assert!(
+        matches!(&err, AppError::Config(message) if message.contains("without dots")),
+        "{:?}",
+        err
+    );
+    assert!(mock.requests().is_empty());
+}
+
+#[tokio::test]
+async fn access_point_arns_are_refused() {
+    let (mock, err) = accelerated("arn:aws:s3:us-west-2:123456789012:accesspoint/models").await;
+
+    assert!(
+        matches!(&err, AppError::Config(message) if message.contains("access point")),
+        "{:?}",
+        err
+    );
+    assert!(mock.requests().is_empty());
+}
@@ -0,0 +1,63 @@
+//! `--no-sign-request`: anonymous uploads and downloads, without credentials
+
+mod common;
+
+use common::{run, Env, MockS3, TestDir};
+use hyper::Method;
+
+/// `mock`'s environment with every credential variable removed
+async fn without_credentials(mock: &MockS3) -> Env {
+    let env = Env::aws(mock).await;
+    for name in [
+        "AWS_ACCESS_KEY",
+        "AWS_SECRET_KEY",
+        "AWS_ACCESS_KEY_ID",
+        "AWS_SECRET_ACCESS_KEY",
+    ] {
+        std::env::remove_var(name);
+    }
+    env
+}
+
+#[tokio::test]
+async fn uploads_are_sent_unsigned() {
+    let mock = MockS3::start().await;
+    let _env = without_credentials(&mock).await;
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+
+    run(&["upload", "--backends", "aws", "--no-sign-request", &file])
+        .await
+        .unwrap();
+
+    let puts = mock.requests_for(Method::PUT, "text/notes.txt");
+    assert_eq!(puts.len(), 1);
+    assert_eq!(puts[0].header("authorization"), None);
+    assert_eq!(puts[0].header("x-amz-security-token"), None);
+    assert_eq!(mock.object("text/notes.txt").unwrap().body, b"plain words");
+}
+
+#[tokio::test]
+async fn downloads_are_sent_unsigned() {
+    let mock = MockS3::start().await;
+    let _env = without_credentials(&mock).await;
+    mock.insert("text/notes.txt", b"plain words");
+    let dir = TestDir::new();
+    let output = dir.path().join("notes.txt");
+
+    run(&[
+        "download",
+        "text/notes.txt",
+        output.to_str().unwrap(),
+        "--no-sign-request",
+    ])
+    .await
+    .unwrap();
+
+    assert_eq!(std::fs::read(&output).unwrap(), b"plain words");
+    let requests = mock.requests();
+    assert!(!requests.is_empty());
+    assert!(requests
+        .iter()
+        .all(|request| request.header("authorization").is_none()));
+}
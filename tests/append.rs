@@ -0,0 +1,91 @@
+//! `--append`: adding a file's bytes to the object already at its key
+
+mod common;
+
+use common::{run, sha256_hex, Env, MockS3, TestDir};
+use hyper::Method;
+
+const KEY: &str = "text/app.log";
+
+/// Methods of the requests for `KEY`, in order
+fn methods(mock: &MockS3) -> Vec<Method> {
+    mock.requests()
+        .into_iter()
+        .filter(|request| request.key == KEY)
+        .map(|request| request.method)
+        .collect()
+}
+
+async fn append(lines: &str, strategy: &str) {
+    let dir = TestDir::new();
+    let file = dir.write("app.log", lines);
+
+    run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--append",
+        "--append-strategy",
+        strategy,
+        &file,
+    ])
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn read_modify_write_appends_to_the_stored_object() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    mock.insert(KEY, b"line 1\n".to_vec());
+
+    append("line 2\n", "rmw").await;
+
+    assert_eq!(methods(&mock), [Method::HEAD, Method::GET, Method::PUT]);
+    let object = mock.object(KEY).unwrap();
+    assert_eq!(object.body, b"line 1\nline 2\n");
+    // The digest is of the whole object, not of the appended bytes
+    assert_eq!(
+        object.metadata("sha256"),
+        Some(sha256_hex(b"line 1\nline 2\n").as_str())
+    );
+}
+
+#[tokio::test]
+async fn successive_runs_keep_appending() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+
+    append("line 1\n", "rmw").await;
+    append("line 2\n", "rmw").await;
+    append("line 3\n", "rmw").await;
+
+    assert_eq!(mock.object(KEY).unwrap().body, b"line 1\nline 2\nline 3\n");
+}
+
+#[tokio::test]
+async fn a_missing_object_is_created() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+
+    append("line 1\n", "rmw").await;
+
+    assert_eq!(methods(&mock), [Method::HEAD, Method::PUT]);
+    assert_eq!(mock.object(KEY).unwrap().body, b"line 1\n");
+}
+
+#[tokio::test]
+async fn a_store_without_native_appends_falls_back_to_read_modify_write() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    mock.insert(KEY, b"line 1\n".to_vec());
+
+    // The mock ignores x-amz-write-offset-bytes and replaces the probe
+    append("line 2\n", "native").await;
+
+    assert_eq!(mock.object(KEY).unwrap().body, b"line 1\nline 2\n");
+    assert_eq!(mock.keys(), [KEY]);
+    let probes = mock.requests_for(Method::PUT, "s3-ml-uploader-append-probe");
+    assert_eq!(probes.len(), 2);
+    assert_eq!(probes[1].header("x-amz-write-offset-bytes"), Some("1"));
+}
@@ -0,0 +1,72 @@
+//! File attributes stored as object metadata and restored on download
+
+mod common;
+
+use common::{run, Env, MockS3, TestDir};
+use std::{
+    fs::{self, File, FileTimes},
+    time::{Duration, UNIX_EPOCH},
+};
+
+#[cfg(unix)]
+#[tokio::test]
+async fn mode_and_mtime_are_restored() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let file = dir.write("run.sh", "#!/bin/sh\necho hello\n");
+    let mtime = UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789);
+    fs::set_permissions(&file, fs::Permissions::from_mode(0o750)).unwrap();
+    File::options()
+        .write(true)
+        .open(&file)
+        .unwrap()
+        .set_times(FileTimes::new().set_modified(mtime))
+        .unwrap();
+
+    run(&["upload", "--backends", "aws", "--preserve-attrs", &file])
+        .await
+        .unwrap();
+    let key = mock.keys().pop().expect("uploaded");
+    let object = mock.object(&key).unwrap();
+    assert_eq!(object.metadata("file-mode"), Some("750"));
+    assert_eq!(object.metadata("file-mtime"), Some("1700000000.123456789"));
+
+    let restored = dir.path().join("restored.sh");
+    let restored = restored.to_str().unwrap();
+    run(&["download", &key, restored, "--restore-attrs"])
+        .await
+        .unwrap();
+
+    let meta = fs::metadata(restored).unwrap();
+    assert_eq!(meta.permissions().mode() & 0o7777, 0o750);
+    assert_eq!(meta.modified().unwrap(), mtime);
+    assert_eq!(fs::read(restored).unwrap(), fs::read(&file).unwrap());
+}
+
+#[tokio::test]
+async fn a_plain_download_leaves_attributes_alone() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "notes\n");
+    let old = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    File::options()
+        .write(true)
+        .open(&file)
+        .unwrap()
+        .set_times(FileTimes::new().set_modified(old))
+        .unwrap();
+
+    run(&["upload", "--backends", "aws", "--preserve-attrs", &file])
+        .await
+        .unwrap();
+    let key = mock.keys().pop().expect("uploaded");
+    let downloaded = dir.path().join("downloaded.txt");
+    let downloaded = downloaded.to_str().unwrap();
+    run(&["download", &key, downloaded]).await.unwrap();
+
+    assert_ne!(fs::metadata(downloaded).unwrap().modified().unwrap(), old);
+}
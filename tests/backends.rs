@@ -0,0 +1,68 @@
+//! `--backends`: uploading to a subset of the configured backends
+//!
+//! The HTTP backend always addresses AWS itself, so the non-SDK uploads here go through
+//! GCS's XML API, which a `GCS_ENDPOINT` points at the mock.
+
+mod common;
+
+use common::{run, Env, MockS3, Request, TestDir};
+use hyper::Method;
+
+/// Whether the AWS SDK sent `request`, rather than the GCS backend
+fn from_sdk(request: &Request) -> bool {
+    request.header("x-amz-user-agent").is_some()
+}
+
+async fn puts(mock: &MockS3, args: &[&str]) -> Vec<Request> {
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+    let args: Vec<&str> = ["upload"]
+        .into_iter()
+        .chain(args.iter().copied())
+        .chain([file.as_str()])
+        .collect();
+
+    run(&args).await.unwrap();
+
+    mock.requests()
+        .into_iter()
+        .filter(|request| request.method == Method::PUT)
+        .collect()
+}
+
+#[tokio::test]
+async fn only_the_aws_sdk_uploads_with_backends_aws() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await.with_gcs(&mock);
+
+    // The HTTP backend, also configured, would fail to reach AWS
+    let puts = puts(&mock, &["--backends", "aws"]).await;
+
+    assert_eq!(puts.len(), 1);
+    assert!(from_sdk(&puts[0]));
+}
+
+#[tokio::test]
+async fn skipped_backends_need_no_configuration() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await.with_gcs(&mock);
+    std::env::remove_var("AWS_BUCKET");
+
+    let puts = puts(&mock, &["--backends", "gcs"]).await;
+
+    assert_eq!(puts.len(), 1);
+    assert!(!from_sdk(&puts[0]));
+}
+
+#[tokio::test]
+async fn every_configured_backend_uploads_by_default() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await.with_gcs(&mock);
+    // Without its own key pair the HTTP backend is not configured
+    std::env::remove_var("AWS_ACCESS_KEY");
+
+    let puts = puts(&mock, &[]).await;
+
+    assert_eq!(puts.iter().filter(|put| from_sdk(put)).count(), 1);
+    assert_eq!(puts.iter().filter(|put| !from_sdk(put)).count(), 1);
+}
@@ -0,0 +1,51 @@
+//! Storage options a backend can't honour are caught before anything is uploaded
+
+mod common;
+
+use common::{run, Env, MockS3, TestDir};
+use s3_ml_uploader::error::AppError;
+
+#[tokio::test]
+async fn unsupported_options_fail_the_run_before_any_upload() {
+    let (aws, minio) = (MockS3::start().await, MockS3::start().await);
+    let _env = Env::aws(&aws).await.with_minio(&minio);
+    let dir = TestDir::new();
+    let file = dir.write("report.txt", "quarterly numbers\n");
+
+    for options in [
+        &["--sse", "kms"][..],
+        &["--storage-class", "GLACIER"],
+        &[
+            "--object-lock-mode",
+            "governance",
+            "--object-lock-retain",
+            "1d",
+        ],
+    ] {
+        let mut args = vec![
+            "upload",
+            "--backends",
+            "aws,minio",
+            "--on-unsupported",
+            "error",
+        ];
+        args.extend_from_slice(options);
+        args.push(&file);
+
+        let err = run(&args).await.unwrap_err();
+        assert!(
+            matches!(
+                err,
+                AppError::Unsupported {
+                    backend: "MinIO",
+                    ..
+                }
+            ),
+            "{:?}: {:?}",
+            options,
+            err
+        );
+    }
+    assert!(aws.requests().is_empty(), "{:?}", aws.requests());
+    assert!(minio.requests().is_empty(), "{:?}", minio.requests());
+}
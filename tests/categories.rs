@@ -0,0 +1,69 @@
+//! Configured names of the catch-all, text and empty categories, as keys use them
+
+mod common;
+
+use clap::Parser;
+use common::{run, Env, MockS3, TestDir};
+use s3_ml_uploader::cli::Cli;
+
+#[tokio::test]
+async fn configured_names_lead_the_keys() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let blob = dir.write("blob.bin", [0x00, 0x9f, 0x13, 0xc4, 0x00, 0xfe, 0x01, 0x80]);
+    let text = dir.write("notes.txt", "plain words\n");
+    let empty = dir.write("empty.txt", "");
+
+    run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--default-category",
+        "unsorted",
+        "--text-category",
+        "docs.txt",
+        "--empty-category",
+        "zero-bytes",
+        &blob,
+        &text,
+        &empty,
+    ])
+    .await
+    .unwrap();
+
+    assert_eq!(
+        mock.keys(),
+        [
+            "docs.txt/notes.txt",
+            "unsorted/blob.bin",
+            "zero-bytes/empty.txt"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn misc_stays_the_default() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let blob = dir.write("blob.bin", [0x00, 0x9f, 0x13, 0xc4, 0x00, 0xfe, 0x01, 0x80]);
+
+    run(&["upload", "--backends", "aws", &blob]).await.unwrap();
+
+    assert_eq!(mock.keys(), ["misc/blob.bin"]);
+}
+
+#[test]
+fn names_must_be_safe_key_segments() {
+    for name in ["", "..", "a/b", "with space", "ünï"] {
+        let parsed = Cli::try_parse_from([
+            "s3-ml-uploader",
+            "upload",
+            "--default-category",
+            name,
+            "file.txt",
+        ]);
+        assert!(parsed.is_err(), "{:?}", name);
+    }
+}
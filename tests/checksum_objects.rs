@@ -0,0 +1,113 @@
+//! `--emit-checksum-objects`: a `sha256sum`-format digest object next to each upload
+
+mod common;
+
+use common::{run, sha256_hex, Env, MockS3, TestDir};
+
+const SHA512: &str = "1e49dc8147952c66810b3f13617fe3b5e9fe277cf3eee57ce6d65c3e4d3b67db\
+                      0a3dd54d8e522180f55aba2aa2891f8a94e91083d1c37983d53c57eeb2b344d7";
+
+/// Keys and checksum object bodies of uploading `notes.txt` with `args`
+async fn checksum_objects(args: &[&str]) -> Vec<(String, String)> {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+
+    let mut all = vec!["upload", "--backends", "aws", "--emit-checksum-objects"];
+    all.extend(args);
+    all.push(&file);
+    run(&all).await.unwrap();
+
+    assert_eq!(mock.object("text/notes.txt").unwrap().body, b"plain words");
+    mock.keys()
+        .into_iter()
+        .filter(|key| key != "text/notes.txt")
+        .map(|key| {
+            let object = mock.object(&key).unwrap();
+            assert_eq!(object.headers["content-type"], "text/plain");
+            (key, String::from_utf8(object.body).unwrap())
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn sha256_objects_are_sha256sum_lines() {
+    assert_eq!(
+        checksum_objects(&[]).await,
+        [(
+            "text/notes.txt.sha256".to_string(),
+            format!("{}  notes.txt\n", sha256_hex(b"plain words"))
+        )]
+    );
+}
+
+#[tokio::test]
+async fn other_algorithms_name_their_own_suffix() {
+    assert_eq!(
+        checksum_objects(&["--checksum-object-algorithm", "sha512"]).await,
+        [(
+            "text/notes.txt.sha512".to_string(),
+            format!("{}  notes.txt\n", SHA512)
+        )]
+    );
+    assert_eq!(
+        checksum_objects(&["--checksum-object-algorithm", "md5"]).await,
+        [(
+            "text/notes.txt.md5".to_string(),
+            "099ab51adef87ba48e482af1d8a89d5d  notes.txt\n".to_string()
+        )]
+    );
+}
+
+#[tokio::test]
+async fn a_suffix_replaces_the_algorithm_name() {
+    assert_eq!(
+        checksum_objects(&[
+            "--checksum-object-algorithm",
+            "md5",
+            "--checksum-object-suffix",
+            ".digest",
+        ])
+        .await,
+        [(
+            "text/notes.txt.digest".to_string(),
+            "099ab51adef87ba48e482af1d8a89d5d  notes.txt\n".to_string()
+        )]
+    );
+}
+
+#[tokio::test]
+async fn streamed_files_are_checksummed_from_their_read() {
+    assert_eq!(
+        checksum_objects(&["--stream-above", "1"]).await,
+        [(
+            "text/notes.txt.sha256".to_string(),
+            format!("{}  notes.txt\n", sha256_hex(b"plain words"))
+        )]
+    );
+}
+
+#[tokio::test]
+async fn streamed_files_only_take_sha256() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+
+    let err = run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--emit-checksum-objects",
+        "--checksum-object-algorithm",
+        "md5",
+        "--stream-above",
+        "1",
+        &file,
+    ])
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("only takes"), "{}", err);
+    assert!(mock.keys().is_empty());
+}
@@ -0,0 +1,203 @@
+//! Classifiers plugged into the library in place of the built-in heuristics
+
+mod common;
+
+use common::{upload_args, Env, MockS3, TestDir};
+use s3_ml_uploader::{
+    error::AppError,
+    ml::{Classification, FileCategory},
+    run_upload, Classifier,
+};
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+/// File names a classifier was asked about, with the content it was given
+type Seen = Arc<Mutex<Vec<(String, Vec<u8>)>>>;
+
+/// Files everything under `stub/`, recording what it was asked about
+#[derive(Default)]
+struct StubClassifier {
+    seen: Seen,
+}
+
+impl Classifier for StubClassifier {
+    fn classify(&self, path: &Path, content: &[u8]) -> Result<Classification, AppError> {
+        let name = path.file_name().unwrap().to_str().unwrap();
+        self.seen
+            .lock()
+            .unwrap()
+            .push((name.to_string(), content.to_vec()));
+        Ok(Classification {
+            key: format!("stub/{}", name),
+            category: FileCategory::Documents,
+            confidence: 0.5,
+            mime: "application/x-stub".to_string(),
+        })
+    }
+}
+
+#[tokio::test]
+async fn a_stub_classifier_decides_keys_and_content_types() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let image = dir.write("photo.png", b"\x89PNG\r\n\x1a\nnot really");
+    let text = dir.write("notes.txt", "plain text\n");
+
+    let classifier = StubClassifier::default();
+    let seen = classifier.seen.clone();
+    run_upload(
+        upload_args(&["--backends", "aws", &image, &text]),
+        Box::new(classifier),
+        Vec::new(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(mock.keys(), ["stub/notes.txt", "stub/photo.png"]);
+    let object = mock.object("stub/photo.png").unwrap();
+    assert_eq!(
+        object.headers.get("content-type").map(String::as_str),
+        Some("application/x-stub")
+    );
+
+    let mut seen = seen.lock().unwrap().clone();
+    seen.sort();
+    assert_eq!(
+        seen,
+        [
+            ("notes.txt".to_string(), b"plain text\n".to_vec()),
+            (
+                "photo.png".to_string(),
+                b"\x89PNG\r\n\x1a\nnot really".to_vec()
+            ),
+        ]
+    );
+}
+
+/// Fails every file, by returning an error or by panicking
+struct BrokenClassifier {
+    panics: bool,
+}
+
+impl Classifier for BrokenClassifier {
+    fn classify(&self, path: &Path, _content: &[u8]) -> Result<Classification, AppError> {
+        if self.panics {
+            panic!("model crashed on {}", path.display());
+        }
+        Err(AppError::Classification {
+            path: path.display().to_string(),
+            reason: "model unavailable".to_string(),
+        })
+    }
+}
+
+async fn upload_broken(panics: bool, args: &[&str]) -> (MockS3, Result<(), AppError>) {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain text\n");
+    let args: Vec<&str> = ["--backends", "aws"]
+        .into_iter()
+        .chain(args.iter().copied())
+        .chain([file.as_str()])
+        .collect();
+
+    let result = run_upload(
+        upload_args(&args),
+        Box::new(BrokenClassifier { panics }),
+        Vec::new(),
+    )
+    .await;
+    (mock, result)
+}
+
+#[tokio::test]
+async fn a_failing_classifier_files_under_misc_by_default() {
+    let (mock, result) = upload_broken(false, &[]).await;
+
+    result.unwrap();
+    assert_eq!(mock.keys(), ["misc/notes.txt"]);
+    assert_eq!(
+        mock.object("misc/notes.txt").unwrap().headers["content-type"],
+        "application/octet-stream"
+    );
+}
+
+#[tokio::test]
+async fn a_panicking_classifier_falls_back_as_well() {
+    let (mock, result) = upload_broken(true, &[]).await;
+
+    result.unwrap();
+    assert_eq!(mock.keys(), ["misc/notes.txt"]);
+}
+
+#[tokio::test]
+async fn on_classify_error_skip_uploads_nothing() {
+    let (mock, result) = upload_broken(false, &["--on-classify-error", "skip"]).await;
+
+    result.unwrap();
+    assert!(mock.keys().is_empty());
+}
+
+#[tokio::test]
+async fn on_classify_error_fail_fails_the_file() {
+    let (mock, result) = upload_broken(false, &["--on-classify-error", "fail"]).await;
+
+    assert!(
+        matches!(result, Err(AppError::UploadsFailed(1))),
+        "{:?}",
+        result
+    );
+    assert!(mock.keys().is_empty());
+}
+
+/// Looks at `len` leading bytes only, recording how many it was given
+struct PrefixClassifier {
+    len: usize,
+    seen: Seen,
+}
+
+impl Classifier for PrefixClassifier {
+    fn sample_len(&self) -> Option<usize> {
+        Some(self.len)
+    }
+
+    fn classify(&self, path: &Path, content: &[u8]) -> Result<Classification, AppError> {
+        StubClassifier {
+            seen: self.seen.clone(),
+        }
+        .classify(path, content)
+    }
+}
+
+#[tokio::test]
+async fn a_bounded_classifier_sees_only_its_prefix() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let content: Vec<u8> = (0..1024 * 1024u32).map(|i| (i % 251) as u8).collect();
+    let file = dir.write("weights.bin", &content);
+
+    let seen = Seen::default();
+    let classifier = PrefixClassifier {
+        len: 16,
+        seen: seen.clone(),
+    };
+    run_upload(
+        upload_args(&["--backends", "aws", &file]),
+        Box::new(classifier),
+        Vec::new(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        *seen.lock().unwrap(),
+        [("weights.bin".to_string(), content[..16].to_vec())]
+    );
+    // The upload still has the whole file
+    assert_eq!(mock.object("stub/weights.bin").unwrap().body, content);
+}
@@ -0,0 +1,188 @@
+//! The `classify` subcommand: categories printed without uploading anything
+
+mod common;
+
+use std::process::{Command, Output, Stdio};
+
+use common::TestDir;
+use serde_json::Value;
+
+/// Run the uploader binary in `dir`, with no backend configured
+async fn classify(dir: &TestDir, args: &[&str]) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_s3-ml-uploader"));
+    command
+        .arg("classify")
+        .args(args)
+        .current_dir(dir.path())
+        .env_clear()
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    tokio::task::spawn_blocking(move || command.output().unwrap())
+        .await
+        .unwrap()
+}
+
+/// `(file, category, mime)` of every classified file of a `--json` run
+fn categories(output: &Output) -> Vec<(String, String, String)> {
+    let json: Value = serde_json::from_slice(&output.stdout).unwrap();
+    json["files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|file| {
+            let field = |name: &str| file[name].as_str().unwrap_or_default().to_string();
+            (field("file"), field("category"), field("mime"))
+        })
+        .collect()
+}
+
+/// A file of each built-in category
+fn sample_tree() -> TestDir {
+    let dir = TestDir::new();
+    dir.write("data/photo.png", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR");
+    dir.write("data/report.pdf", b"%PDF-1.7\n%\xe2\xe3\xcf\xd3\n");
+    dir.write("data/bundle.zip", b"PK\x03\x04\x14\0\0\0");
+    dir.write("data/notes.txt", "plain words\n");
+    dir.write("data/blob.bin", [0u8, 1, 2, 0xfe, 0xff, 0x80, 0x90, 7]);
+    dir
+}
+
+#[tokio::test]
+async fn every_file_is_classified_and_nothing_needs_configuring() {
+    let dir = sample_tree();
+
+    let output = classify(&dir, &["--dir", "data", "--json"]).await;
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let mut categories = categories(&output);
+    categories.sort();
+    let expected = [
+        ("data/blob.bin", "misc", "application/octet-stream"),
+        ("data/bundle.zip", "archives", "application/zip"),
+        ("data/notes.txt", "text", "text/plain"),
+        ("data/photo.png", "images", "image/png"),
+        ("data/report.pdf", "documents", "application/pdf"),
+    ];
+    assert_eq!(categories.len(), expected.len());
+    for ((file, category, mime), (expected_file, expected_category, expected_mime)) in
+        categories.iter().zip(expected)
+    {
+        assert!(file.ends_with(expected_file), "{}", file);
+        assert_eq!(
+            (category.as_str(), mime.as_str()),
+            (expected_category, expected_mime),
+            "{}",
+            file
+        );
+    }
+}
+
+#[tokio::test]
+async fn classifier_options_apply_as_they_do_to_upload() {
+    let dir = sample_tree();
+
+    let output = classify(
+        &dir,
+        &[
+            "data/notes.txt",
+            "data/photo.png",
+            "--classify-by",
+            "extension",
+            "--ext-category",
+            "txt=documents",
+            "--json",
+        ],
+    )
+    .await;
+
+    assert!(output.status.success());
+    let categories: Vec<String> = categories(&output)
+        .into_iter()
+        .map(|(_, category, _)| category)
+        .collect();
+    assert_eq!(categories, ["documents", "images"]);
+}
+
+#[tokio::test]
+async fn the_table_counts_each_category() {
+    let dir = sample_tree();
+
+    let output = classify(&dir, &["--dir", "data"]).await;
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Classified 5 file(s): archives 1, documents 1, images 1, misc 1, text 1"),
+        "{}",
+        stdout
+    );
+}
+
+/// `sample_tree` spread over `images/` and `docs/`, with one file directly in `data/`
+fn nested_tree() -> TestDir {
+    let dir = TestDir::new();
+    dir.write("data/images/photo.png", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR");
+    dir.write("data/images/raw/scan.png", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR");
+    dir.write("data/images/caption.txt", "a cat\n");
+    dir.write("data/docs/report.pdf", b"%PDF-1.7\n%\xe2\xe3\xcf\xd3\n");
+    dir.write("data/notes.txt", "plain words\n");
+    dir
+}
+
+#[tokio::test]
+async fn by_dir_counts_each_top_level_directory() {
+    let dir = nested_tree();
+
+    let output = classify(&dir, &["--dir", "data", "--by-dir", "--json"]).await;
+
+    assert!(output.status.success());
+    let json: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        json["directories"],
+        serde_json::json!({
+            "./": {"text": 1, "total": 1},
+            "docs/": {"documents": 1, "total": 1},
+            "images/": {"images": 2, "text": 1, "total": 3},
+        })
+    );
+    assert_eq!(json["files"].as_array().unwrap().len(), 5);
+}
+
+#[tokio::test]
+async fn by_dir_tables_show_counts_and_shares() {
+    let dir = nested_tree();
+
+    let output = classify(&dir, &["--dir", "data", "--by-dir"]).await;
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let rows: Vec<Vec<&str>> = stdout
+        .lines()
+        .skip_while(|line| !line.starts_with("directory"))
+        .map(|line| line.split_whitespace().collect())
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            vec!["directory", "files", "documents", "images", "text"],
+            vec!["./", "1", "-", "-", "1", "(100%)"],
+            vec!["docs/", "1", "1", "(100%)", "-", "-"],
+            vec!["images/", "3", "-", "2", "(67%)", "1", "(33%)"],
+        ]
+    );
+}
+
+#[tokio::test]
+async fn without_by_dir_there_is_no_breakdown() {
+    let dir = nested_tree();
+
+    let output = classify(&dir, &["--dir", "data", "--json"]).await;
+
+    let json: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json.get("directories"), None);
+}
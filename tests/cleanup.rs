@@ -0,0 +1,67 @@
+//! `cleanup`: incomplete multipart uploads listed over every page, then aborted
+
+mod common;
+
+use common::{run, Env, MockS3};
+use hyper::Method;
+
+/// Key markers of the ListMultipartUploads requests to the AWS bucket, in order
+fn key_markers(mock: &MockS3) -> Vec<Option<String>> {
+    mock.requests()
+        .into_iter()
+        .filter(|request| {
+            request.method == Method::GET
+                && request.key.is_empty()
+                && request.query.contains_key("uploads")
+        })
+        .map(|request| request.query.get("key-marker").cloned())
+        .collect()
+}
+
+#[tokio::test]
+async fn uploads_on_every_page_are_aborted() {
+    let mock = MockS3::start().await;
+    let minio = MockS3::start().await;
+    let ids = [
+        mock.insert_upload("models/a.bin", &[b"part one", b"part two"]),
+        mock.insert_upload("models/b.bin", &[b"part"]),
+        mock.insert_upload("models/c.bin", &[]),
+    ];
+    mock.insert_upload("other/d.bin", &[b"kept"]);
+    mock.page_size(2);
+    // rust-s3 addresses MinIO buckets by subdomain, which an IP endpoint can't take; that
+    // listing fails and is skipped
+    let _env = Env::aws(&mock).await.with_minio(&minio);
+
+    // Without --yes and without a terminal the uploads are only reported
+    run(&["cleanup", "--prefix", "models/", "--older-than", "1d"])
+        .await
+        .unwrap();
+    assert_eq!(mock.upload_ids().len(), 4);
+    assert_eq!(key_markers(&mock), [None, Some("models/b.bin".to_string())]);
+    // The parts of each upload are listed to size it
+    for id in &ids {
+        let listed = mock
+            .requests()
+            .into_iter()
+            .filter(|request| {
+                request.method == Method::GET && request.query.get("uploadId") == Some(id)
+            })
+            .count();
+        assert_eq!(listed, 1, "{}", id);
+    }
+
+    run(&[
+        "cleanup",
+        "--prefix",
+        "models/",
+        "--older-than",
+        "1d",
+        "--yes",
+    ])
+    .await
+    .unwrap();
+    let left = mock.upload_ids();
+    assert_eq!(left.len(), 1);
+    assert!(!ids.contains(&left[0]));
+}
@@ -0,0 +1,907 @@
+//! In-process S3 endpoint for the integration tests, and running the uploader against it
+//!
+//! The uploader reads its buckets and credentials from the environment, which every test
+//! in a binary shares, so tests hold [`Env`] for as long as they run.
+
+#![allow(dead_code)]
+
+use aws_sdk_s3::config::{BehaviorVersion, Credentials, Region};
+use clap::Parser;
+use hyper::{
+    body::to_bytes, server::conn::Http, service::service_fn, Body, Method, Request as HyperRequest,
+    Response, StatusCode,
+};
+use s3_ml_uploader::{
+    cli::{Cli, Command, UploadArgs},
+    error::AppError,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::Infallible,
+    sync::{Arc, Mutex},
+};
+use tokio::{net::TcpListener, sync::MutexGuard};
+
+#[path = "../../src/testdir.rs"]
+mod testdir;
+// Not every test binary writes files
+#[allow(unused_imports)]
+pub use testdir::TestDir;
+
+pub const BUCKET: &str = "bucket";
+
+/// One request the endpoint received, its body decoded
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: Method,
+    pub bucket: String,
+    // Object key, without the bucket
+    pub key: String,
+    pub query: HashMap<String, String>,
+    // Lowercased names
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn is_part(&self) -> bool {
+        self.query.contains_key("partNumber")
+    }
+}
+
+/// What a hook answers instead of the endpoint
+#[derive(Debug, Clone)]
+pub struct Reply {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    // Carry the request out as usual first, so only its response is replaced
+    pub handled: bool,
+}
+
+impl Reply {
+    pub fn new(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+            handled: false,
+        }
+    }
+
+    /// An S3 error document, e.g. `Reply::error(503, "SlowDown")`
+    pub fn error(status: u16, code: &str) -> Self {
+        Self::new(status).with_body(format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?><Error><Code>{}</Code><Message>{}</Message></Error>",
+            code, code
+        ))
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn with_body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Store what the request sends, then answer with this reply, as when the response
+    /// of a successful request is lost
+    pub fn after_handling(mut self) -> Self {
+        self.handled = true;
+        self
+    }
+}
+
+/// Answers a request itself with `Some`, or leaves it to the endpoint
+pub type Hook = Box<dyn FnMut(&Request) -> Option<Reply> + Send>;
+
+/// An object as stored
+#[derive(Debug, Clone, Default)]
+pub struct Object {
+    pub body: Vec<u8>,
+    // Headers of the PUT worth returning on HEAD and GET, lowercased
+    pub headers: BTreeMap<String, String>,
+    pub tags: Vec<(String, String)>,
+}
+
+impl Object {
+    /// User metadata, without the `x-amz-meta-` prefix
+    pub fn metadata(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&format!("x-amz-meta-{}", name))
+            .map(String::as_str)
+    }
+
+    fn etag(&self) -> String {
+        format!("\"{}\"", hex::encode(md5::compute(&self.body).0))
+    }
+}
+
+/// An incomplete multipart upload: its key, the initiating request and the parts so far
+type Upload = (String, Object, BTreeMap<u32, Vec<u8>>);
+
+#[derive(Default)]
+struct State {
+    objects: BTreeMap<String, Object>,
+    // Incomplete multipart uploads by upload id
+    uploads: HashMap<String, Upload>,
+    requests: Vec<Request>,
+    hook: Option<Hook>,
+    next_upload: usize,
+    // Most keys or uploads a listing page holds when the request asks for no fewer
+    page_size: Option<usize>,
+}
+
+/// An S3 endpoint holding one bucket in memory
+///
+/// Understands the requests the uploader makes: PUT, HEAD, GET (with ranges), DELETE,
+/// multipart uploads, copies, tagging and paginated listings of objects, uploads and parts.
+/// Path-style and virtual-hosted addressing both work.
+#[derive(Clone)]
+pub struct MockS3 {
+    pub endpoint: String,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockS3 {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind mock");
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let state: Arc<Mutex<State>> = Arc::default();
+
+        let shared = state.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let state = shared.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |request| {
+                        let state = state.clone();
+                        async move { Ok::<_, Infallible>(handle(&state, request).await) }
+                    });
+                    let _ = Http::new().serve_connection(stream, service).await;
+                });
+            }
+        });
+
+        Self { endpoint, state }
+    }
+
+    /// Answer requests with `hook` first
+    pub fn hook(&self, hook: impl FnMut(&Request) -> Option<Reply> + Send + 'static) {
+        self.state.lock().unwrap().hook = Some(Box::new(hook));
+    }
+
+    pub fn object(&self, key: &str) -> Option<Object> {
+        self.state.lock().unwrap().objects.get(key).cloned()
+    }
+
+    pub fn keys(&self) -> Vec<String> {
+        self.state.lock().unwrap().objects.keys().cloned().collect()
+    }
+
+    /// Store an object as if an earlier run had uploaded it
+    pub fn insert(&self, key: &str, body: impl Into<Vec<u8>>) {
+        self.insert_with_headers(key, body, &[]);
+    }
+
+    /// [`insert`](Self::insert) with the headers, e.g. `content-encoding`, it was PUT with
+    pub fn insert_with_headers(
+        &self,
+        key: &str,
+        body: impl Into<Vec<u8>>,
+        headers: &[(&str, &str)],
+    ) {
+        let object = Object {
+            body: body.into(),
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            ..Object::default()
+        };
+        self.state
+            .lock()
+            .unwrap()
+            .objects
+            .insert(key.to_string(), object);
+    }
+
+    /// [`insert`](Self::insert) with the tags it was PUT with
+    pub fn insert_with_tags(&self, key: &str, body: impl Into<Vec<u8>>, tags: &[(&str, &str)]) {
+        self.insert(key, body);
+        if let Some(object) = self.state.lock().unwrap().objects.get_mut(key) {
+            object.tags = tags
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect();
+        }
+    }
+
+    /// Cut listings into pages of at most `size` entries, as S3 does past 1000
+    pub fn page_size(&self, size: usize) {
+        self.state.lock().unwrap().page_size = Some(size);
+    }
+
+    /// Start a multipart upload of `key` holding `parts`, left incomplete; returns its id
+    pub fn insert_upload(&self, key: &str, parts: &[&[u8]]) -> String {
+        let mut state = self.state.lock().unwrap();
+        state.next_upload += 1;
+        let id = format!("upload-{}", state.next_upload);
+        let parts = (1..).zip(parts.iter().map(|part| part.to_vec())).collect();
+        state
+            .uploads
+            .insert(id.clone(), (key.to_string(), Object::default(), parts));
+        id
+    }
+
+    /// Ids of the multipart uploads neither completed nor aborted
+    pub fn upload_ids(&self) -> Vec<String> {
+        let mut ids: Vec<_> = self.state.lock().unwrap().uploads.keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Every request received so far
+    pub fn requests(&self) -> Vec<Request> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Requests with `method` for `key`
+    pub fn requests_for(&self, method: Method, key: &str) -> Vec<Request> {
+        self.requests()
+            .into_iter()
+            .filter(|request| request.method == method && request.key == key)
+            .collect()
+    }
+}
+
+async fn handle(state: &Mutex<State>, request: HyperRequest<Body>) -> Response<Body> {
+    let (parts, body) = request.into_parts();
+    let body = to_bytes(body).await.map(|b| b.to_vec()).unwrap_or_default();
+    let headers: Vec<(String, String)> = parts
+        .headers
+        .iter()
+        .map(|(name, value)| {
+            let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+            (name.as_str().to_string(), value)
+        })
+        .collect();
+    let host = parts
+        .headers
+        .get("host")
+        .and_then(|host| host.to_str().ok())
+        .unwrap_or_default();
+    let (bucket, key) = bucket_and_key(host, parts.uri.path());
+    let mut request = Request {
+        method: parts.method.clone(),
+        bucket,
+        key,
+        query: parse_query(parts.uri.query().unwrap_or_default()),
+        headers,
+        body,
+    };
+    if request
+        .header("content-encoding")
+        .is_some_and(|encoding| encoding.contains("aws-chunked"))
+        || request
+            .header("x-amz-content-sha256")
+            .is_some_and(|sha| sha.starts_with("STREAMING-"))
+    {
+        let (body, trailers) = decode_aws_chunked(&request.body);
+        request.body = body;
+        // Trailing checksums count as if they had been headers
+        request.headers.extend(trailers);
+    }
+
+    let mut state = state.lock().unwrap();
+    state.requests.push(request.clone());
+    let reply = match state.hook.as_mut().and_then(|hook| hook(&request)) {
+        Some(reply) if reply.handled => {
+            respond(&mut state, &request);
+            reply
+        }
+        Some(reply) => reply,
+        None => respond(&mut state, &request),
+    };
+
+    let mut response = Response::builder().status(StatusCode::from_u16(reply.status).unwrap());
+    for (name, value) in &reply.headers {
+        response = response.header(name, value);
+    }
+    if request.method == Method::HEAD {
+        return response.body(Body::empty()).unwrap();
+    }
+    response
+        .header("content-length", reply.body.len())
+        .body(Body::from(reply.body))
+        .unwrap()
+}
+
+/// The bucket and key addressed by `path`, the bucket taken from either the host or the path
+///
+/// Every bucket shares the one store; tests tell them apart by the requests.
+fn bucket_and_key(host: &str, path: &str) -> (String, String) {
+    let path = percent_decode(path.trim_start_matches('/'));
+    if host.starts_with(&format!("{}.", BUCKET)) {
+        return (BUCKET.to_string(), path);
+    }
+    match path.split_once('/') {
+        Some((bucket, key)) => (bucket.to_string(), key.to_string()),
+        None => (path, String::new()),
+    }
+}
+
+fn respond(state: &mut State, request: &Request) -> Reply {
+    let query = &request.query;
+    if request.method == Method::PUT && !checksums_match(request) {
+        return Reply::error(400, "BadDigest");
+    }
+    match request.method {
+        Method::PUT if request.is_part() => {
+            let Some((_, _, parts)) = state.uploads.get_mut(&query["uploadId"]) else {
+                return Reply::error(404, "NoSuchUpload");
+            };
+            let number: u32 = query["partNumber"].parse().unwrap();
+            parts.insert(number, request.body.clone());
+            let etag = format!("\"{}\"", hex::encode(md5::compute(&request.body).0));
+            echo_checksums(request, Reply::new(200).with_header("etag", &etag))
+        }
+        Method::PUT if query.contains_key("tagging") => {
+            let Some(object) = state.objects.get_mut(&request.key) else {
+                return Reply::error(404, "NoSuchKey");
+            };
+            object.tags = parse_tagging_xml(&String::from_utf8_lossy(&request.body));
+            Reply::new(200)
+        }
+        Method::PUT => {
+            let mut object = stored(request);
+            if let Some(source) = request.header("x-amz-copy-source") {
+                let source = percent_decode(source.trim_start_matches('/'));
+                let source = source
+                    .split_once('/')
+                    .map(|(_, key)| key)
+                    .unwrap_or_default();
+                let Some(copied) = state.objects.get(source).cloned() else {
+                    return Reply::error(404, "NoSuchKey");
+                };
+                if request.header("x-amz-metadata-directive") != Some("REPLACE") {
+                    object.headers = copied.headers;
+                }
+                object.body = copied.body;
+                let etag = object.etag();
+                state.objects.insert(request.key.clone(), object);
+                return Reply::new(200).with_body(format!(
+                    "<CopyObjectResult><ETag>{}</ETag><LastModified>2024-01-01T00:00:00.000Z</LastModified></CopyObjectResult>",
+                    etag
+                ));
+            }
+            let etag = object.etag();
+            state.objects.insert(request.key.clone(), object);
+            echo_checksums(request, Reply::new(200).with_header("etag", &etag))
+        }
+        Method::POST if query.contains_key("uploads") => {
+            state.next_upload += 1;
+            let id = format!("upload-{}", state.next_upload);
+            state.uploads.insert(
+                id.clone(),
+                (request.key.clone(), stored(request), BTreeMap::new()),
+            );
+            Reply::new(200).with_body(format!(
+                "<InitiateMultipartUploadResult><Bucket>{}</Bucket><Key>{}</Key><UploadId>{}</UploadId></InitiateMultipartUploadResult>",
+                BUCKET, request.key, id
+            ))
+        }
+        Method::POST if query.contains_key("uploadId") => {
+            let Some((_, mut object, parts)) = state.uploads.remove(&query["uploadId"]) else {
+                return Reply::error(404, "NoSuchUpload");
+            };
+            object.body = parts.into_values().flatten().collect();
+            let etag = object.etag();
+            state.objects.insert(request.key.clone(), object);
+            Reply::new(200).with_body(format!(
+                "<CompleteMultipartUploadResult><Bucket>{}</Bucket><Key>{}</Key><ETag>{}</ETag></CompleteMultipartUploadResult>",
+                BUCKET, request.key, etag
+            ))
+        }
+        Method::DELETE if query.contains_key("uploadId") => {
+            state.uploads.remove(&query["uploadId"]);
+            Reply::new(204)
+        }
+        Method::DELETE => {
+            state.objects.remove(&request.key);
+            Reply::new(204)
+        }
+        Method::GET if query.contains_key("tagging") => match state.objects.get(&request.key) {
+            Some(object) => Reply::new(200).with_body(tagging_xml(&object.tags)),
+            None => Reply::error(404, "NoSuchKey"),
+        },
+        Method::GET if request.key.is_empty() && query.contains_key("uploads") => {
+            list_uploads(state, request)
+        }
+        Method::GET if request.key.is_empty() => list(state, request),
+        Method::GET if query.contains_key("uploadId") => {
+            match state.uploads.get(&query["uploadId"]) {
+                Some((_, _, parts)) => list_parts(parts),
+                None => Reply::error(404, "NoSuchUpload"),
+            }
+        }
+        Method::GET | Method::HEAD => match state.objects.get(&request.key) {
+            Some(object) => read(object, request),
+            None if request.method == Method::HEAD => Reply::new(404),
+            None => Reply::error(404, "NoSuchKey"),
+        },
+        _ => Reply::error(501, "NotImplemented"),
+    }
+}
+
+/// The object a PUT or multipart initiation stores, before its body
+fn stored(request: &Request) -> Object {
+    let kept = |name: &str| {
+        name.starts_with("x-amz-meta-")
+            || name.starts_with("x-amz-checksum-")
+            || [
+                "content-type",
+                "content-disposition",
+                "content-language",
+                "cache-control",
+                "expires",
+                "x-amz-storage-class",
+            ]
+            .contains(&name)
+    };
+    let mut headers: BTreeMap<String, String> = request
+        .headers
+        .iter()
+        .filter(|(name, _)| kept(name) && name != "x-amz-checksum-algorithm")
+        .cloned()
+        .collect();
+    // aws-chunked is the transfer's encoding, not the object's
+    if let Some(encoding) = request.header("content-encoding") {
+        let encoding: Vec<&str> = encoding
+            .split(',')
+            .map(str::trim)
+            .filter(|encoding| *encoding != "aws-chunked")
+            .collect();
+        if !encoding.is_empty() {
+            headers.insert("content-encoding".to_string(), encoding.join(","));
+        }
+    }
+    let tags = request
+        .header("x-amz-tagging")
+        .map(|tagging| {
+            parse_query(tagging)
+                .into_iter()
+                .collect::<BTreeMap<_, _>>()
+                .into_iter()
+                .collect()
+        })
+        .unwrap_or_default();
+    Object {
+        body: request.body.clone(),
+        headers,
+        tags,
+    }
+}
+
+/// HEAD or GET of a stored object, honouring `Range` and `If-Match`
+///
+/// Like S3, a ranged read leaves out the whole object's `x-amz-checksum-*`, which its
+/// bytes wouldn't match.
+fn read(object: &Object, request: &Request) -> Reply {
+    if request
+        .header("if-match")
+        .is_some_and(|etag| etag != object.etag())
+    {
+        return Reply::error(412, "PreconditionFailed");
+    }
+    let size = object.body.len();
+    let ranged = request.header("range").is_some();
+    let mut reply = Reply::new(200);
+    for (name, value) in &object.headers {
+        if !(ranged && name.starts_with("x-amz-checksum-")) {
+            reply = reply.with_header(name, value);
+        }
+    }
+    reply = reply
+        .with_header("etag", &object.etag())
+        .with_header("last-modified", "Mon, 01 Jan 2024 00:00:00 GMT")
+        .with_header("accept-ranges", "bytes");
+    if !object.tags.is_empty() {
+        reply = reply.with_header("x-amz-tagging-count", &object.tags.len().to_string());
+    }
+
+    let range = request
+        .header("range")
+        .and_then(|range| range.strip_prefix("bytes="))
+        .and_then(|range| range.split_once('-'));
+    let (start, end) = match range {
+        Some((start, end)) => {
+            let start: usize = start.parse().unwrap_or(0);
+            let end: usize = end.parse().map_or(size, |end: usize| (end + 1).min(size));
+            if start >= size && size > 0 {
+                return Reply::error(416, "InvalidRange");
+            }
+            reply.status = 206;
+            reply = reply.with_header(
+                "content-range",
+                &format!("bytes {}-{}/{}", start, end.saturating_sub(1), size),
+            );
+            (start, end)
+        }
+        None => (0, size),
+    };
+    if request.method == Method::HEAD {
+        return reply.with_header("content-length", &(end - start).to_string());
+    }
+    reply.with_body(&object.body[start..end])
+}
+
+/// Entries a listing page holds: `max-keys` (or `max-uploads`), capped by the page size
+fn page_len(state: &State, request: &Request, param: &str) -> usize {
+    let asked = request
+        .query
+        .get(param)
+        .and_then(|max| max.parse().ok())
+        .unwrap_or(1000);
+    state.page_size.map_or(asked, |size| asked.min(size))
+}
+
+/// ListObjectsV2: keys under `prefix`, those past the next `delimiter` rolled up into
+/// common prefixes, a page at a time; the continuation token is the last entry listed
+fn list(state: &State, request: &Request) -> Reply {
+    let query = &request.query;
+    let prefix = query.get("prefix").cloned().unwrap_or_default();
+    let delimiter = query.get("delimiter").filter(|d| !d.is_empty());
+    let after = query.get("continuation-token");
+
+    // Keys are sorted, so the keys one prefix rolls up follow each other
+    let mut entries: Vec<(String, Option<&Object>)> = Vec::new();
+    for (key, object) in state.objects.range(prefix.clone()..) {
+        if !key.starts_with(&prefix) {
+            break;
+        }
+        let rolled_up = delimiter.and_then(|delimiter| {
+            let end = key[prefix.len()..].find(delimiter.as_str())?;
+            Some(key[..prefix.len() + end + delimiter.len()].to_string())
+        });
+        match rolled_up {
+            Some(common) if entries.last().is_some_and(|(last, _)| *last == common) => {}
+            Some(common) => entries.push((common, None)),
+            None => entries.push((key.clone(), Some(object))),
+        }
+    }
+    entries.retain(|(entry, _)| after.is_none_or(|after| entry > after));
+
+    let len = page_len(state, request, "max-keys");
+    let truncated = entries.len() > len;
+    entries.truncate(len);
+
+    let mut body = format!(
+        "<ListBucketResult><Name>{}</Name><Prefix>{}</Prefix><KeyCount>{}</KeyCount><MaxKeys>{}</MaxKeys><IsTruncated>{}</IsTruncated>",
+        BUCKET,
+        prefix,
+        entries.len(),
+        len,
+        truncated
+    );
+    if let Some(after) = after {
+        body += &format!("<ContinuationToken>{}</ContinuationToken>", after);
+    }
+    if let (true, Some((last, _))) = (truncated, entries.last()) {
+        body += &format!("<NextContinuationToken>{}</NextContinuationToken>", last);
+    }
+    for (entry, object) in &entries {
+        body += &match object {
+            Some(object) => format!(
+                "<Contents><Key>{}</Key><Size>{}</Size><ETag>{}</ETag><LastModified>2024-01-01T00:00:00.000Z</LastModified></Contents>",
+                entry,
+                object.body.len(),
+                object.etag()
+            ),
+            None => format!("<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>", entry),
+        };
+    }
+    Reply::new(200).with_body(body + "</ListBucketResult>")
+}
+
+/// ListMultipartUploads: incomplete uploads under `prefix` by key and id, a page at a time
+/// after `key-marker` and `upload-id-marker`
+fn list_uploads(state: &State, request: &Request) -> Reply {
+    let query = &request.query;
+    let prefix = query.get("prefix").cloned().unwrap_or_default();
+    let marker = query.get("key-marker").filter(|marker| !marker.is_empty());
+    let id_marker = query.get("upload-id-marker").cloned().unwrap_or_default();
+
+    let mut uploads: Vec<(&String, &String)> = state
+        .uploads
+        .iter()
+        .map(|(id, (key, _, _))| (key, id))
+        .filter(|(key, _)| key.starts_with(&prefix))
+        .filter(|(key, id)| marker.is_none_or(|marker| (*key, *id) > (marker, &id_marker)))
+        .collect();
+    uploads.sort();
+
+    let len = page_len(state, request, "max-uploads");
+    let truncated = uploads.len() > len;
+    uploads.truncate(len);
+
+    let mut body = format!(
+        "<ListMultipartUploadsResult><Bucket>{}</Bucket><Prefix>{}</Prefix><MaxUploads>{}</MaxUploads><IsTruncated>{}</IsTruncated>",
+        BUCKET, prefix, len, truncated
+    );
+    if let (true, Some((key, id))) = (truncated, uploads.last()) {
+        body += &format!(
+            "<NextKeyMarker>{}</NextKeyMarker><NextUploadIdMarker>{}</NextUploadIdMarker>",
+            key, id
+        );
+    }
+    for (key, id) in &uploads {
+        body += &format!(
+            "<Upload><Key>{}</Key><UploadId>{}</UploadId><Initiated>2024-01-01T00:00:00.000Z</Initiated></Upload>",
+            key, id
+        );
+    }
+    Reply::new(200).with_body(body + "</ListMultipartUploadsResult>")
+}
+
+/// ListParts of an incomplete upload, in one page
+fn list_parts(parts: &BTreeMap<u32, Vec<u8>>) -> Reply {
+    let parts: String = parts
+        .iter()
+        .map(|(number, body)| {
+            format!(
+                "<Part><PartNumber>{}</PartNumber><Size>{}</Size><ETag>\"{}\"</ETag></Part>",
+                number,
+                body.len(),
+                hex::encode(md5::compute(body).0)
+            )
+        })
+        .collect();
+    Reply::new(200).with_body(format!(
+        "<ListPartsResult><Bucket>{}</Bucket><IsTruncated>false</IsTruncated>{}</ListPartsResult>",
+        BUCKET, parts
+    ))
+}
+
+/// Whether the body matches every checksum sent with it, as S3 checks them
+fn checksums_match(request: &Request) -> bool {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    let body = &request.body;
+    request.headers.iter().all(|(name, value)| {
+        let expected = match name.as_str() {
+            "x-amz-checksum-sha256" => STANDARD.encode(Sha256::digest(body)),
+            "x-amz-checksum-crc32c" => STANDARD.encode(crc32c::crc32c(body).to_be_bytes()),
+            "content-md5" => STANDARD.encode(md5::compute(body).0),
+            _ => return true,
+        };
+        *value == expected
+    })
+}
+
+/// Return the checksums a PUT sent, as S3 does
+fn echo_checksums(request: &Request, mut reply: Reply) -> Reply {
+    for (name, value) in &request.headers {
+        if name.starts_with("x-amz-checksum-") && name != "x-amz-checksum-algorithm" {
+            reply = reply.with_header(name, value);
+        }
+    }
+    reply
+}
+
+/// Payload and trailers of an `aws-chunked` body: `<hex size>[;chunk-signature=...]\r\n`
+/// `<data>\r\n` chunks up to an empty one, then `name:value\r\n` trailers
+fn decode_aws_chunked(body: &[u8]) -> (Vec<u8>, Vec<(String, String)>) {
+    let mut decoded = Vec::new();
+    let mut rest = body;
+    while let Some(end) = rest.windows(2).position(|w| w == b"\r\n") {
+        let line = String::from_utf8_lossy(&rest[..end]);
+        let size =
+            usize::from_str_radix(line.split(';').next().unwrap_or("0").trim(), 16).unwrap_or(0);
+        rest = &rest[end + 2..];
+        if size == 0 {
+            break;
+        }
+        decoded.extend_from_slice(&rest[..size]);
+        rest = &rest[(size + 2).min(rest.len())..];
+    }
+    let trailers = String::from_utf8_lossy(rest)
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .filter(|(name, _)| name != "x-amz-trailer-signature")
+        .collect();
+    (decoded, trailers)
+}
+
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(name), percent_decode(value))
+        })
+        .collect()
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+                match u8::from_str_radix(hex, 16) {
+                    Ok(byte) => {
+                        decoded.push(byte);
+                        i += 3;
+                    }
+                    Err(_) => {
+                        decoded.push(b'%');
+                        i += 1;
+                    }
+                }
+            }
+            b'+' => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            byte => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn tagging_xml(tags: &[(String, String)]) -> String {
+    let tags: String = tags
+        .iter()
+        .map(|(key, value)| format!("<Tag><Key>{}</Key><Value>{}</Value></Tag>", key, value))
+        .collect();
+    format!("<Tagging><TagSet>{}</TagSet></Tagging>", tags)
+}
+
+fn parse_tagging_xml(xml: &str) -> Vec<(String, String)> {
+    let field = |tag: &str, name: &str| {
+        let open = format!("<{}>", name);
+        let close = format!("</{}>", name);
+        let start = tag.find(&open)? + open.len();
+        let end = tag[start..].find(&close)? + start;
+        Some(tag[start..end].to_string())
+    };
+    xml.split("<Tag>")
+        .skip(1)
+        .filter_map(|tag| Some((field(tag, "Key")?, field(tag, "Value")?)))
+        .collect()
+}
+
+pub fn sha256_hex(content: &[u8]) -> String {
+    hex::encode(Sha256::digest(content))
+}
+
+/// The environment pointing the AWS S3 and HTTP backends at one mock, held for a test
+///
+/// Variables a test [`set`](Env::set) are removed again when it drops.
+pub struct Env {
+    set: Mutex<Vec<String>>,
+    _guard: MutexGuard<'static, ()>,
+}
+
+impl Env {
+    pub async fn aws(mock: &MockS3) -> Self {
+        static LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+        let guard = LOCK.lock().await;
+        for name in [
+            "AWS_ENDPOINT_URL_S3",
+            "AWS_PROFILE",
+            "AWS_SESSION_TOKEN",
+            "S3_ENDPOINT",
+            "S3_BUCKET",
+            "GCS_BUCKET",
+            "GCS_ENDPOINT",
+        ] {
+            std::env::remove_var(name);
+        }
+        for (name, value) in [
+            ("AWS_ENDPOINT_URL", mock.endpoint.as_str()),
+            ("AWS_BUCKET", BUCKET),
+            ("AWS_REGION", "us-east-1"),
+            ("AWS_ACCESS_KEY", "AKIDEXAMPLE"),
+            ("AWS_SECRET_KEY", "secret"),
+            ("AWS_ACCESS_KEY_ID", "AKIDEXAMPLE"),
+            ("AWS_SECRET_ACCESS_KEY", "secret"),
+            ("AWS_EC2_METADATA_DISABLED", "true"),
+        ] {
+            std::env::set_var(name, value);
+        }
+        Self {
+            set: Mutex::default(),
+            _guard: guard,
+        }
+    }
+
+    /// Point the MinIO backend at `mock` too
+    pub fn with_minio(self, mock: &MockS3) -> Self {
+        self.set("S3_ENDPOINT", &mock.endpoint);
+        self.set("S3_BUCKET", BUCKET);
+        self.set("S3_ACCESS_KEY", "minio");
+        self.set("S3_SECRET_KEY", "minio-secret");
+        self
+    }
+
+    /// Point the GCS backend at `mock` too; its XML API is S3's, path-style
+    pub fn with_gcs(self, mock: &MockS3) -> Self {
+        self.set("GCS_ENDPOINT", &mock.endpoint);
+        self.set("GCS_BUCKET", BUCKET);
+        self.set("GCS_ACCESS_KEY", "GOOGEXAMPLE");
+        self.set("GCS_SECRET_KEY", "gcs-secret");
+        self
+    }
+
+    pub fn set(&self, name: &str, value: &str) {
+        std::env::set_var(name, value);
+        self.set.lock().unwrap().push(name.to_string());
+    }
+}
+
+impl Drop for Env {
+    fn drop(&mut self) {
+        for name in self.set.lock().unwrap().drain(..) {
+            std::env::remove_var(name);
+        }
+    }
+}
+
+/// An AWS SDK client of `mock`, for reading back what the uploader stored
+pub fn sdk_client(mock: &MockS3) -> aws_sdk_s3::Client {
+    let config = aws_sdk_s3::Config::builder()
+        .behavior_version(BehaviorVersion::latest())
+        .endpoint_url(&mock.endpoint)
+        .region(Region::new("us-east-1"))
+        .credentials_provider(Credentials::new(
+            "AKIDEXAMPLE",
+            "secret",
+            None,
+            None,
+            "test",
+        ))
+        .force_path_style(true)
+        .build();
+    aws_sdk_s3::Client::from_conf(config)
+}
+
+/// Run the uploader with `args`, as its binary would
+pub async fn run(args: &[&str]) -> Result<(), AppError> {
+    let cli = Cli::try_parse_from(std::iter::once("s3-ml-uploader").chain(args.iter().copied()))
+        .expect("valid arguments");
+    s3_ml_uploader::run(cli).await
+}
+
+/// `upload` options as parsed from `args`, for the library's `run_upload`
+pub fn upload_args(args: &[&str]) -> UploadArgs {
+    let cli = Cli::try_parse_from(
+        ["s3-ml-uploader", "upload"]
+            .into_iter()
+            .chain(args.iter().copied()),
+    )
+    .expect("valid arguments");
+    match cli.command {
+        Some(Command::Upload(args)) => args,
+        _ => unreachable!("parsed as upload"),
+    }
+}
@@ -0,0 +1,64 @@
+//! Uploads over `--confirm-files` asking first, and refused when nobody can answer
+
+mod common;
+
+use std::process::{Command, Output, Stdio};
+
+use common::{Env, MockS3, TestDir};
+
+/// Run the uploader binary with stdin closed, so it isn't a terminal
+async fn run_without_terminal(dir: &TestDir, args: &[&str]) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_s3-ml-uploader"));
+    // Away from the repo's `.env`, whose settings would add backends
+    command
+        .args(args)
+        .current_dir(dir.path())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    tokio::task::spawn_blocking(move || command.output().unwrap())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn without_a_terminal_the_upload_is_refused_unless_yes_is_given() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+    let args = ["upload", "--backends", "aws", "--confirm-files", "0", &file];
+
+    let output = run_without_terminal(&dir, &args).await;
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("pass --yes to proceed"), "{}", stderr);
+    assert!(mock.requests().is_empty());
+
+    let output = run_without_terminal(&dir, &[&args[..], &["--yes"]].concat()).await;
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(mock.object("text/notes.txt").is_some());
+}
+
+#[tokio::test]
+async fn small_uploads_need_no_confirmation() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+
+    let output = run_without_terminal(&dir, &["upload", "--backends", "aws", &file]).await;
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(mock.object("text/notes.txt").is_some());
+}
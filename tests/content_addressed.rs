@@ -0,0 +1,68 @@
+//! `--content-addressed`: objects keyed by the SHA-256 of their content
+
+mod common;
+
+use common::{run, sha256_hex, Env, MockS3, TestDir};
+use hyper::Method;
+
+fn blob_key(content: &[u8]) -> String {
+    let hex = sha256_hex(content);
+    format!("blobs/{}/{}/{}", &hex[..2], &hex[2..4], hex)
+}
+
+#[tokio::test]
+async fn identical_files_share_one_sharded_key() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let first = dir.write("a/weights.bin", "same bytes");
+    let copy = dir.write("b/weights-copy.bin", "same bytes");
+    let other = dir.write("c/other.bin", "other bytes");
+
+    run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--content-addressed",
+        "--shard-depth",
+        "2",
+        &first,
+        &copy,
+        &other,
+    ])
+    .await
+    .unwrap();
+
+    let mut expected = vec![blob_key(b"same bytes"), blob_key(b"other bytes")];
+    expected.sort();
+    assert_eq!(mock.keys(), expected);
+    assert_eq!(
+        mock.object(&blob_key(b"same bytes")).unwrap().body,
+        b"same bytes"
+    );
+}
+
+#[tokio::test]
+async fn re_uploading_stores_nothing_new() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let file = dir.write("weights.bin", "same bytes");
+    let args = [
+        "upload",
+        "--backends",
+        "aws",
+        "--content-addressed",
+        "--shard-depth",
+        "2",
+        "--overwrite-if-different",
+        &file,
+    ];
+
+    run(&args).await.unwrap();
+    run(&args).await.unwrap();
+
+    let key = blob_key(b"same bytes");
+    assert_eq!(mock.keys(), std::slice::from_ref(&key));
+    assert_eq!(mock.requests_for(Method::PUT, &key).len(), 1);
+}
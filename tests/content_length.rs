@@ -0,0 +1,73 @@
+//! `--content-length`: sources whose size their metadata doesn't tell, such as named pipes
+#![cfg(unix)]
+
+mod common;
+
+use std::{fs::OpenOptions, io::Write, process::Command, thread};
+
+use common::{run, Env, MockS3, TestDir};
+use hyper::Method;
+use s3_ml_uploader::error::AppError;
+
+/// A named pipe at `dir/name` a thread writes `contents` into once it is opened
+fn pipe(dir: &TestDir, name: &str, contents: &'static [u8]) -> (String, thread::JoinHandle<()>) {
+    let path = dir.path().join(name);
+    let status = Command::new("mkfifo").arg(&path).status().unwrap();
+    assert!(status.success());
+    let writer_path = path.clone();
+    let writer = thread::spawn(move || {
+        let mut pipe = OpenOptions::new().write(true).open(&writer_path).unwrap();
+        // The reader may stop early; a broken pipe is its business
+        let _ = pipe.write_all(contents);
+    });
+    (path.to_string_lossy().into_owned(), writer)
+}
+
+async fn upload(path: &str, length: &str) -> Result<(), AppError> {
+    run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--content-length",
+        length,
+        path,
+    ])
+    .await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_pipe_of_the_given_length_uploads_in_one_put() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let (path, writer) = pipe(&dir, "stream.txt", b"plain words");
+
+    upload(&path, "11").await.unwrap();
+    writer.join().unwrap();
+
+    let puts = mock.requests_for(Method::PUT, "text/stream.txt");
+    assert_eq!(puts.len(), 1);
+    assert_eq!(puts[0].header("content-length"), Some("11"));
+    assert_eq!(mock.object("text/stream.txt").unwrap().body, b"plain words");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_pipe_of_another_length_fails_without_uploading() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+
+    for (name, length) in [("short.txt", "20"), ("long.txt", "5")] {
+        let (path, writer) = pipe(&dir, name, b"plain words");
+        let err = upload(&path, length).await.unwrap_err();
+        writer.join().unwrap();
+        assert!(
+            matches!(err, AppError::UploadsFailed(1)),
+            "{}: {:?}",
+            name,
+            err
+        );
+    }
+
+    assert!(mock.keys().is_empty());
+}
@@ -0,0 +1,116 @@
+//! `--content-md5`: the header on every path, and BadDigest reported as corruption
+
+mod common;
+
+use common::{run, Env, MockS3, Reply, TestDir};
+use hyper::Method;
+use s3_ml_uploader::error::AppError;
+
+const KEY: &str = "text/notes.txt";
+
+/// Base64 MD5 of `plain words`
+const NOTES_MD5: &str = "CZq1Gt74e6SOSCrx2KidXQ==";
+
+fn md5_base64(content: &[u8]) -> String {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    STANDARD.encode(md5::compute(content).0)
+}
+
+#[tokio::test]
+async fn the_sdk_sends_the_md5_of_the_body() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+
+    run(&["upload", "--backends", "aws", "--content-md5", &file])
+        .await
+        .unwrap();
+
+    let puts = mock.requests_for(Method::PUT, KEY);
+    assert_eq!(puts[0].header("content-md5"), Some(NOTES_MD5));
+}
+
+#[tokio::test]
+async fn the_http_path_signs_the_md5() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await.with_gcs(&mock);
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+
+    run(&["upload", "--backends", "gcs", "--content-md5", &file])
+        .await
+        .unwrap();
+
+    let put = &mock.requests_for(Method::PUT, KEY)[0];
+    assert_eq!(put.header("content-md5"), Some(NOTES_MD5));
+    let authorization = put.header("authorization").unwrap();
+    assert!(authorization.contains("content-md5;"), "{}", authorization);
+}
+
+#[tokio::test]
+async fn without_the_flag_no_md5_is_sent() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+
+    run(&["upload", "--backends", "aws", &file]).await.unwrap();
+
+    assert_eq!(
+        mock.requests_for(Method::PUT, KEY)[0].header("content-md5"),
+        None
+    );
+}
+
+#[tokio::test]
+async fn every_part_carries_its_own_md5() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let content: Vec<u8> = (0..12 * 1024 * 1024u32).map(|i| (i % 251) as u8).collect();
+    let file = dir.write("weights.bin", &content);
+
+    run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--stream-above",
+        "1MiB",
+        "--part-size",
+        "5MiB",
+        "--content-md5",
+        &file,
+    ])
+    .await
+    .unwrap();
+
+    let parts: Vec<_> = mock
+        .requests()
+        .into_iter()
+        .filter(|request| request.method == Method::PUT && request.query.contains_key("partNumber"))
+        .collect();
+    assert_eq!(parts.len(), 3);
+    for part in &parts {
+        assert_eq!(
+            part.header("content-md5"),
+            Some(md5_base64(&part.body).as_str())
+        );
+    }
+}
+
+#[tokio::test]
+async fn a_bad_digest_fails_the_upload() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    mock.hook(|request| (request.method == Method::PUT).then(|| Reply::error(400, "BadDigest")));
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+
+    let err = run(&["upload", "--backends", "aws", "--content-md5", &file])
+        .await
+        .unwrap_err();
+
+    assert!(matches!(err, AppError::UploadsFailed(1)), "{:?}", err);
+    assert!(mock.object(KEY).is_none());
+}
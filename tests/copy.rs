@@ -0,0 +1,130 @@
+//! `copy`: server-side copies and the metadata and storage class they carry
+
+mod common;
+
+use common::{run, Env, MockS3, Request};
+use hyper::Method;
+
+const SOURCE: &str = "raw/my notes.txt";
+
+/// An object uploaded with metadata and a storage class, to copy from
+fn stored_source(mock: &MockS3) {
+    mock.insert_with_headers(
+        SOURCE,
+        b"plain words".to_vec(),
+        &[
+            ("content-type", "text/plain"),
+            ("x-amz-meta-owner", "data"),
+            ("x-amz-meta-sha256", "abc"),
+            ("x-amz-storage-class", "STANDARD_IA"),
+        ],
+    );
+}
+
+/// The one `CopyObject` request sent
+fn copy_request(mock: &MockS3) -> Request {
+    let copies: Vec<_> = mock
+        .requests()
+        .into_iter()
+        .filter(|request| {
+            request.method == Method::PUT && request.header("x-amz-copy-source").is_some()
+        })
+        .collect();
+    assert_eq!(copies.len(), 1, "{:?}", copies);
+    copies.into_iter().next().unwrap()
+}
+
+#[tokio::test]
+async fn a_copy_keeps_the_source_metadata_and_storage_class() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    stored_source(&mock);
+
+    run(&["copy", SOURCE, "cooked/notes.txt"]).await.unwrap();
+
+    let copy = copy_request(&mock);
+    assert_eq!(copy.bucket, "bucket");
+    assert_eq!(copy.key, "cooked/notes.txt");
+    assert_eq!(
+        copy.header("x-amz-copy-source"),
+        Some("bucket/raw/my%20notes.txt")
+    );
+    // S3 keeps the metadata itself, but not the storage class
+    assert_eq!(copy.header("x-amz-metadata-directive"), None);
+    assert_eq!(copy.header("x-amz-storage-class"), Some("STANDARD_IA"));
+
+    let object = mock.object("cooked/notes.txt").unwrap();
+    assert_eq!(object.body, b"plain words");
+    assert_eq!(object.metadata("owner"), Some("data"));
+    // Nothing was downloaded
+    assert!(mock.requests_for(Method::GET, SOURCE).is_empty());
+}
+
+#[tokio::test]
+async fn remapped_metadata_replaces_the_source_metadata() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    stored_source(&mock);
+
+    run(&[
+        "copy",
+        SOURCE,
+        "cooked/notes.txt",
+        "--metadata",
+        "Owner=ml",
+        "--metadata",
+        "stage=cooked",
+        "--remove-metadata",
+        "sha256",
+        "--storage-class",
+        "GLACIER",
+    ])
+    .await
+    .unwrap();
+
+    let copy = copy_request(&mock);
+    assert_eq!(copy.header("x-amz-metadata-directive"), Some("REPLACE"));
+    assert_eq!(copy.header("x-amz-storage-class"), Some("GLACIER"));
+    // Headers a replaced copy would otherwise lose are sent again
+    assert_eq!(copy.header("content-type"), Some("text/plain"));
+
+    let object = mock.object("cooked/notes.txt").unwrap();
+    assert_eq!(object.metadata("owner"), Some("ml"));
+    assert_eq!(object.metadata("stage"), Some("cooked"));
+    assert_eq!(object.metadata("sha256"), None);
+}
+
+#[tokio::test]
+async fn a_copy_into_another_bucket_names_the_source_bucket() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    stored_source(&mock);
+
+    run(&["copy", SOURCE, "notes.txt", "--dest-bucket", "archive"])
+        .await
+        .unwrap();
+
+    let copy = copy_request(&mock);
+    assert_eq!(copy.bucket, "archive");
+    assert_eq!(copy.key, "notes.txt");
+    assert_eq!(
+        copy.header("x-amz-copy-source"),
+        Some("bucket/raw/my%20notes.txt")
+    );
+    let heads = mock.requests_for(Method::HEAD, SOURCE);
+    assert_eq!(heads.len(), 1);
+    assert_eq!(heads[0].bucket, "bucket");
+}
+
+#[tokio::test]
+async fn a_missing_source_fails_before_copying() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+
+    assert!(run(&["copy", SOURCE, "cooked/notes.txt"]).await.is_err());
+    assert!(mock.object("cooked/notes.txt").is_none());
+    assert!(mock
+        .requests()
+        .iter()
+        .all(|request| request.header("x-amz-copy-source").is_none()));
+}
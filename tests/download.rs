@@ -0,0 +1,67 @@
+//! Downloads of stored objects to local files
+
+mod common;
+
+use common::{run, Env, MockS3, TestDir};
+use std::{fs, io::Write};
+
+/// Source bytes compressible enough that the encoded body differs clearly
+fn source() -> Vec<u8> {
+    "timestamp,value\n"
+        .bytes()
+        .chain((0..2000).flat_map(|i| format!("{},{}\n", i, i * 7).into_bytes()))
+        .collect()
+}
+
+fn gzip(content: &[u8]) -> Vec<u8> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(content).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[tokio::test]
+async fn compressed_uploads_round_trip_through_decompress() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let source = source();
+    mock.insert_with_headers(
+        "text/metrics.csv.gz",
+        gzip(&source),
+        &[("content-encoding", "gzip")],
+    );
+    mock.insert_with_headers(
+        "text/metrics.csv.zst",
+        zstd::encode_all(&source[..], 3).unwrap(),
+        &[("content-encoding", "zstd")],
+    );
+
+    for key in ["text/metrics.csv.gz", "text/metrics.csv.zst"] {
+        let output = dir.path().join("metrics.csv");
+        let output = output.to_str().unwrap();
+        run(&["download", key, output, "--decompress"])
+            .await
+            .unwrap();
+        assert_eq!(fs::read(output).unwrap(), source, "{}", key);
+    }
+}
+
+#[tokio::test]
+async fn without_decompress_the_stored_bytes_are_kept() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let compressed = gzip(&source());
+    mock.insert_with_headers(
+        "text/metrics.csv.gz",
+        compressed.clone(),
+        &[("content-encoding", "gzip")],
+    );
+
+    let output = dir.path().join("metrics.csv.gz");
+    let output = output.to_str().unwrap();
+    run(&["download", "text/metrics.csv.gz", output])
+        .await
+        .unwrap();
+    assert_eq!(fs::read(output).unwrap(), compressed);
+}
@@ -0,0 +1,138 @@
+//! `download -` and `--stdout`: the body alone on stdout, every message on stderr
+
+mod common;
+
+use std::{
+    io::Read,
+    process::{Command, Output, Stdio},
+};
+
+use common::{Env, MockS3, TestDir};
+
+/// The uploader binary downloading with `args`, run in its own directory
+fn download(dir: &TestDir, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_s3-ml-uploader"));
+    command
+        .arg("download")
+        .args(args)
+        .current_dir(dir.path())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    command
+}
+
+async fn output(mut command: Command) -> Output {
+    tokio::task::spawn_blocking(move || command.output().unwrap())
+        .await
+        .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_dash_writes_the_body_to_stdout() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let body: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+    mock.insert("archives/weights.bin", body.clone());
+    let dir = TestDir::new();
+
+    for args in [
+        &["archives/weights.bin", "-"][..],
+        &["archives/weights.bin", "--stdout"],
+    ] {
+        let output = output(download(&dir, args)).await;
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(output.status.success(), "{}", stderr);
+        assert!(output.stdout == body, "stdout is not the body alone");
+        assert!(
+            stderr.contains("archives/weights.bin -> stdout"),
+            "{}",
+            stderr
+        );
+    }
+    // Nothing was written next to the key's name
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn gcs_objects_stream_to_stdout() {
+    let mock = MockS3::start().await;
+    let gcs = MockS3::start().await;
+    let _env = Env::aws(&mock).await.with_gcs(&gcs);
+    gcs.insert("text/notes.txt", b"plain words");
+    let dir = TestDir::new();
+
+    let output = output(download(&dir, &["text/notes.txt", "-", "--backend", "gcs"])).await;
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(output.stdout, b"plain words");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_reader_that_stops_early_ends_the_download_quietly() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    mock.insert("archives/weights.bin", vec![7u8; 8 * 1024 * 1024]);
+    let dir = TestDir::new();
+    let mut command = download(&dir, &["archives/weights.bin", "-"]);
+
+    let (head, output) = tokio::task::spawn_blocking(move || {
+        let mut child = command.spawn().unwrap();
+        // `| head -c 16`: read a little, then close the pipe
+        let mut head = [0u8; 16];
+        let mut stdout = child.stdout.take().unwrap();
+        stdout.read_exact(&mut head).unwrap();
+        drop(stdout);
+        (head, child.wait_with_output().unwrap())
+    })
+    .await
+    .unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(head, [7u8; 16]);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stderr.contains("stdout was closed after"), "{}", stderr);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn options_that_need_a_file_are_refused() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    mock.insert("text/notes.txt", b"plain words");
+    let dir = TestDir::new();
+
+    let output = output(download(&dir, &["text/notes.txt", "-", "--resume"])).await;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(
+        stderr.contains("--resume needs a file to write to"),
+        "{}",
+        stderr
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sparse_objects_are_refused() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    mock.insert_with_headers(
+        "archives/disk.img",
+        b"extents",
+        &[("x-amz-meta-sparse-size", "8388608")],
+    );
+    let dir = TestDir::new();
+
+    let output = output(download(&dir, &["archives/disk.img", "--stdout"])).await;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(stderr.contains("was uploaded with --sparse"), "{}", stderr);
+}
@@ -0,0 +1,145 @@
+//! Uploads refused with `ExpiredToken`, sent again once the credentials are refreshed
+//!
+//! Keys come from a `--secret-ref` secret the mock rotates after its first read, as a
+//! temporary key revoked mid-run would be; the first key is refused as expired.
+
+mod common;
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use common::{run, Env, MockS3, Reply, Request, TestDir};
+use hyper::Method;
+use s3_ml_uploader::error::AppError;
+
+const OLD: &str = r#"{"access_key_id": "AKIDOLD", "secret_access_key": "old-secret", "session_token": "old-token"}"#;
+const NEW: &str = r#"{"access_key_id": "AKIDNEW", "secret_access_key": "new-secret", "session_token": "new-token"}"#;
+
+/// Serve `OLD` for the first read of the secret and `NEW` after it, or `OLD` forever
+/// unless `rotate`, and refuse requests signed with `AKIDOLD` as expired
+///
+/// With `refuse_sdk`, the SDK's PUTs are refused as unsupported first, so every upload
+/// goes through `--sdk-fallback-http`'s plain PUT.
+fn expire_old_key(mock: &MockS3, rotate: bool, refuse_sdk: bool) -> Arc<AtomicUsize> {
+    let reads = Arc::new(AtomicUsize::new(0));
+    let counted = Arc::clone(&reads);
+    mock.hook(move |request| {
+        if request.header("x-amz-target") == Some("secretsmanager.GetSecretValue") {
+            let read = counted.fetch_add(1, Ordering::SeqCst);
+            let secret = if rotate && read > 0 { NEW } else { OLD };
+            let body = serde_json::json!({
+                "ARN": "arn:aws:secretsmanager:us-east-1:123456789012:secret:ml/uploader-AbCdEf",
+                "Name": "ml/uploader",
+                "SecretString": secret,
+            });
+            return Some(
+                Reply::new(200)
+                    .with_header("content-type", "application/x-amz-json-1.1")
+                    .with_body(body.to_string()),
+            );
+        }
+        if refuse_sdk
+            && request.method == Method::PUT
+            && request.header("x-amz-user-agent").is_some()
+        {
+            return Some(Reply::error(501, "NotImplemented"));
+        }
+        let authorization = request.header("authorization").unwrap_or_default();
+        match authorization.contains("Credential=AKIDOLD/") {
+            true => Some(Reply::error(400, "ExpiredToken")),
+            false => None,
+        }
+    });
+    reads
+}
+
+/// Access key and session token of every PUT, in order
+fn puts(mock: &MockS3) -> Vec<(String, String)> {
+    puts_where(mock, |_| true)
+}
+
+fn puts_where(mock: &MockS3, matching: impl Fn(&Request) -> bool) -> Vec<(String, String)> {
+    mock.requests()
+        .into_iter()
+        .filter(|request| request.method == Method::PUT && matching(request))
+        .map(|request| {
+            let authorization = request.header("authorization").unwrap_or_default();
+            let key = authorization
+                .split("Credential=")
+                .nth(1)
+                .and_then(|credential| credential.split('/').next())
+                .unwrap_or_default()
+                .to_string();
+            let token = request
+                .header("x-amz-security-token")
+                .unwrap_or_default()
+                .to_string();
+            (key, token)
+        })
+        .collect()
+}
+
+async fn upload(args: &[&str]) -> Result<(), AppError> {
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+    let mut all = vec![
+        "upload",
+        "--backends",
+        "aws",
+        "--secret-ref",
+        "aws-sm:ml/uploader",
+    ];
+    all.extend(args);
+    all.push(&file);
+    run(&all).await
+}
+
+fn expected_puts() -> Vec<(String, String)> {
+    vec![
+        ("AKIDOLD".to_string(), "old-token".to_string()),
+        ("AKIDNEW".to_string(), "new-token".to_string()),
+    ]
+}
+
+#[tokio::test]
+async fn the_sdk_retries_with_refreshed_credentials() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let reads = expire_old_key(&mock, true, false);
+
+    upload(&[]).await.unwrap();
+
+    assert_eq!(puts(&mock), expected_puts());
+    assert_eq!(reads.load(Ordering::SeqCst), 2);
+    assert_eq!(mock.object("text/notes.txt").unwrap().body, b"plain words");
+}
+
+#[tokio::test]
+async fn the_http_path_signs_again_with_refreshed_credentials() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    expire_old_key(&mock, true, true);
+
+    upload(&["--sdk-fallback-http"]).await.unwrap();
+
+    let plain = puts_where(&mock, |request| {
+        request.header("x-amz-user-agent").is_none()
+    });
+    assert_eq!(plain, expected_puts());
+    assert_eq!(mock.object("text/notes.txt").unwrap().body, b"plain words");
+}
+
+#[tokio::test]
+async fn keys_that_stay_the_same_fail_without_a_second_attempt() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    expire_old_key(&mock, false, false);
+
+    let err = upload(&[]).await.unwrap_err();
+
+    assert!(matches!(err, AppError::UploadsFailed(1)), "{:?}", err);
+    assert_eq!(puts(&mock).len(), 1);
+    assert!(mock.keys().is_empty());
+}
@@ -0,0 +1,74 @@
+//! `export-training-data`: one CSV row of features and labels per classified file
+
+mod common;
+
+use common::{run, TestDir};
+
+#[tokio::test]
+async fn labels_from_a_file_override_the_prediction() {
+    let dir = TestDir::new();
+    let notes = dir.write("notes.txt", "plain words");
+    let blob = dir.write("blob.bin", [0u8, 1, 2, 3]);
+    let labels = dir.write("labels.csv", format!("# by hand\n{},documents\n", notes));
+    let output = dir.path().join("dataset.csv");
+
+    run(&[
+        "export-training-data",
+        &notes,
+        &blob,
+        "--labels",
+        &labels,
+        "--output",
+        output.to_str().unwrap(),
+    ])
+    .await
+    .unwrap();
+
+    let csv = std::fs::read_to_string(&output).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(
+        lines[0],
+        "file,size,label,predicted,confidence,overridden,entropy,printable,zeros,prefix_hex"
+    );
+    assert_eq!(lines.len(), 3);
+
+    let row = |file: &str| -> Vec<String> {
+        let line = lines.iter().find(|line| line.starts_with(file)).unwrap();
+        line.split(',').map(str::to_string).collect()
+    };
+    let notes = row(&notes);
+    assert_eq!(notes[1..4], ["11", "documents", "text"]);
+    assert_eq!(notes[5], "true");
+    assert_eq!(notes[7], "1.0000");
+    assert_eq!(notes[9], hex::encode("plain words"));
+
+    let blob = row(&blob);
+    assert_eq!(blob[1], "4");
+    assert_eq!(blob[2], blob[3]);
+    assert_eq!(blob[5], "false");
+    assert_eq!(blob[8], "0.2500");
+    assert_eq!(blob[9], "00010203");
+}
+
+#[tokio::test]
+async fn prefix_len_bounds_the_features() {
+    let dir = TestDir::new();
+    let notes = dir.write("notes.txt", "plain words");
+    let output = dir.path().join("dataset.csv");
+
+    run(&[
+        "export-training-data",
+        &notes,
+        "--prefix-len",
+        "5",
+        "--output",
+        output.to_str().unwrap(),
+    ])
+    .await
+    .unwrap();
+
+    let csv = std::fs::read_to_string(&output).unwrap();
+    let row: Vec<&str> = csv.lines().nth(1).unwrap().split(',').collect();
+    assert_eq!(row[1], "11");
+    assert_eq!(row[9], hex::encode("plain"));
+}
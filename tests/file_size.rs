@@ -0,0 +1,71 @@
+//! `--min-file-size`, `--max-file-size` and `--on-oversize`: files outside the limits next to
+//! one within them
+
+mod common;
+
+use common::{run, Env, MockS3, TestDir};
+use hyper::Method;
+use s3_ml_uploader::error::AppError;
+
+/// Upload a 3 B `tiny.txt`, an 11 B `notes.txt` and a 2 KiB `big.txt` with `args`
+async fn upload_with(args: &[&str]) -> Result<(), AppError> {
+    let dir = TestDir::new();
+    let tiny = dir.write("tiny.txt", "abc");
+    let notes = dir.write("notes.txt", "plain words");
+    let big = dir.write("big.txt", "x".repeat(2048));
+    let mut all = vec!["upload", "--backends", "aws"];
+    all.extend(args);
+    all.extend([tiny.as_str(), notes.as_str(), big.as_str()]);
+    run(&all).await
+}
+
+#[tokio::test]
+async fn files_under_the_minimum_are_skipped() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+
+    upload_with(&["--min-file-size", "10"]).await.unwrap();
+
+    assert_eq!(mock.keys(), ["text/big.txt", "text/notes.txt"]);
+    // Checked from a stat, so nothing of it was sent
+    assert!(mock.requests_for(Method::PUT, "text/tiny.txt").is_empty());
+}
+
+#[tokio::test]
+async fn oversize_files_are_skipped_by_default() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+
+    upload_with(&["--max-file-size", "1KiB"]).await.unwrap();
+
+    assert_eq!(mock.keys(), ["text/notes.txt", "text/tiny.txt"]);
+    assert!(mock.requests_for(Method::PUT, "text/big.txt").is_empty());
+}
+
+#[tokio::test]
+async fn oversize_files_fail_the_run_under_error() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+
+    let err = upload_with(&["--max-file-size", "1KiB", "--on-oversize", "error"])
+        .await
+        .unwrap_err();
+
+    assert!(matches!(err, AppError::UploadsFailed(1)), "{:?}", err);
+    assert_eq!(err.exit_code(), 1);
+    // The other files are still uploaded
+    assert_eq!(mock.keys(), ["text/notes.txt", "text/tiny.txt"]);
+    assert!(mock.requests_for(Method::PUT, "text/big.txt").is_empty());
+}
+
+#[tokio::test]
+async fn both_limits_apply_together() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+
+    upload_with(&["--min-file-size", "10", "--max-file-size", "1KiB"])
+        .await
+        .unwrap();
+
+    assert_eq!(mock.keys(), ["text/notes.txt"]);
+}
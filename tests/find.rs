@@ -0,0 +1,68 @@
+//! `find`: tag lookups over every page of the listing
+
+mod common;
+
+use std::process::{Command, Stdio};
+
+use common::{Env, MockS3};
+use hyper::Method;
+use serde_json::{json, Value};
+
+/// What the uploader binary prints for `find --json` with `args`
+async fn find_json(args: &[&str]) -> Value {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_s3-ml-uploader"));
+    command
+        .args(["find", "--json"])
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit());
+    let output = tokio::task::spawn_blocking(move || command.output().unwrap())
+        .await
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn matches_are_found_on_every_page() {
+    let mock = MockS3::start().await;
+    for (key, filetype) in [
+        ("data/a.csv", "text"),
+        ("data/b.png", "images"),
+        ("data/c.txt", "text"),
+        ("data/d.png", "images"),
+        ("data/e.txt", "text"),
+    ] {
+        mock.insert_with_tags(key, "content", &[("filetype", filetype)]);
+    }
+    mock.page_size(2);
+    let _env = Env::aws(&mock).await;
+
+    let found = find_json(&["--tag", "filetype=text", "--prefix", "data/"]).await;
+
+    let keys: Vec<_> = found["objects"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|object| object["key"].as_str().unwrap())
+        .collect();
+    assert_eq!(keys, ["data/a.csv", "data/c.txt", "data/e.txt"]);
+    assert_eq!(
+        found["objects"][2],
+        json!({
+            "key": "data/e.txt",
+            "size": 7,
+            "last_modified": "2024-01-01T00:00:00+00:00",
+            "tags": { "filetype": "text" },
+        })
+    );
+
+    let pages = mock
+        .requests()
+        .into_iter()
+        .filter(|request| request.method == Method::GET && request.query.contains_key("list-type"))
+        .count();
+    assert_eq!(pages, 3);
+    // One tag lookup per listed object, on the last page too
+    assert_eq!(mock.requests_for(Method::GET, "data/e.txt").len(), 1);
+}
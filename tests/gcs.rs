@@ -0,0 +1,126 @@
+//! The GCS backend, over the XML API's S3 interoperability with an HMAC key
+//!
+//! `round_trip_on_real_gcs` runs against Google itself when `GCS_TEST_BUCKET`,
+//! `GCS_TEST_ACCESS_KEY` and `GCS_TEST_SECRET_KEY` are set, and passes without doing
+//! anything otherwise.
+
+mod common;
+
+use common::{run, Env, MockS3, Reply, TestDir, BUCKET};
+use hyper::Method;
+use s3_ml_uploader::error::AppError;
+
+const KEY: &str = "text/s3-ml-uploader-interop-test.txt";
+
+/// Upload a file named after `KEY` to GCS and download it back
+async fn round_trip() {
+    let dir = TestDir::new();
+    let contents = format!("uploaded at {:?}\n", std::time::SystemTime::now());
+    let file = dir.write("s3-ml-uploader-interop-test.txt", &contents);
+    run(&["upload", "--backends", "gcs", &file]).await.unwrap();
+
+    let output = dir.path().join("downloaded.txt");
+    run(&[
+        "download",
+        KEY,
+        output.to_str().unwrap(),
+        "--backend",
+        "gcs",
+    ])
+    .await
+    .unwrap();
+    assert_eq!(std::fs::read_to_string(&output).unwrap(), contents);
+}
+
+#[tokio::test]
+async fn uploads_are_signed_with_the_hmac_key_for_region_auto() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await.with_gcs(&mock);
+
+    round_trip().await;
+
+    let puts = mock.requests_for(Method::PUT, KEY);
+    assert_eq!(puts.len(), 1);
+    assert_eq!(puts[0].bucket, BUCKET);
+    let authorization = puts[0].header("authorization").unwrap();
+    assert!(
+        authorization.starts_with("AWS4-HMAC-SHA256 Credential=GOOGEXAMPLE/"),
+        "{}",
+        authorization
+    );
+    assert!(
+        authorization.contains("/auto/s3/aws4_request"),
+        "{}",
+        authorization
+    );
+    assert!(puts
+        .iter()
+        .all(|put| put.header("x-amz-user-agent").is_none()));
+}
+
+#[tokio::test]
+async fn gcs_errors_fail_the_upload() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await.with_gcs(&mock);
+    mock.hook(|_| Some(Reply::error(403, "AccessDenied")));
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+
+    let err = run(&["upload", "--backends", "gcs", &file])
+        .await
+        .unwrap_err();
+
+    assert!(matches!(err, AppError::UploadsFailed(1)), "{:?}", err);
+}
+
+#[tokio::test]
+async fn round_trip_on_real_gcs() {
+    let var = |name| std::env::var(name).ok();
+    let (Some(bucket), Some(access_key), Some(secret_key)) = (
+        var("GCS_TEST_BUCKET"),
+        var("GCS_TEST_ACCESS_KEY"),
+        var("GCS_TEST_SECRET_KEY"),
+    ) else {
+        eprintln!(
+            "skipped: GCS_TEST_BUCKET, GCS_TEST_ACCESS_KEY and GCS_TEST_SECRET_KEY are unset"
+        );
+        return;
+    };
+
+    // The mock only stands in for AWS, which `--backends gcs` never reaches
+    let mock = MockS3::start().await;
+    let env = Env::aws(&mock).await;
+    env.set("GCS_BUCKET", &bucket);
+    env.set("GCS_ACCESS_KEY", &access_key);
+    env.set("GCS_SECRET_KEY", &secret_key);
+    std::env::remove_var("GCS_ENDPOINT");
+
+    round_trip().await;
+
+    assert!(mock.requests().is_empty());
+}
+
+#[tokio::test]
+async fn signing_on_the_blocking_pool_signs_every_request() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await.with_gcs(&mock);
+    let dir = TestDir::new();
+    let files: Vec<String> = (0..6)
+        .map(|n| dir.write(&format!("notes-{}.txt", n), format!("file {}", n)))
+        .collect();
+    let mut args = vec!["upload", "--backends", "gcs", "--signing-threads", "2"];
+    args.extend(files.iter().map(String::as_str));
+
+    run(&args).await.unwrap();
+
+    let puts: Vec<_> = mock
+        .requests()
+        .into_iter()
+        .filter(|request| request.method == Method::PUT)
+        .collect();
+    assert_eq!(puts.len(), 6);
+    for put in puts {
+        let authorization = put.header("authorization").unwrap();
+        assert!(authorization.contains("Signature="), "{}", authorization);
+    }
+}
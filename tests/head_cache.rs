@@ -0,0 +1,83 @@
+//! `--head-cache`: repeated checks of one object within a run share a HEAD
+
+mod common;
+
+use common::{run, sha256_hex, Env, MockS3, TestDir};
+use hyper::Method;
+
+const KEY: &str = "text/notes.txt";
+
+/// Two files with the same name and content, checked against the same stored object
+async fn check_twice(mock: &MockS3, extra: &[&str]) {
+    mock.insert_with_headers(
+        KEY,
+        b"plain words".to_vec(),
+        &[("x-amz-meta-sha256", &sha256_hex(b"plain words"))],
+    );
+    let dir = TestDir::new();
+    let first = dir.write("a/notes.txt", "plain words");
+    let second = dir.write("b/notes.txt", "plain words");
+
+    let mut args = vec![
+        "upload",
+        "--backends",
+        "aws",
+        "--overwrite-if-different",
+        // One at a time, so the second check comes after the first is cached
+        "--concurrency",
+        "1",
+    ];
+    args.extend(extra);
+    args.extend([first.as_str(), second.as_str()]);
+    run(&args).await.unwrap();
+}
+
+#[tokio::test]
+async fn only_one_head_is_issued() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+
+    check_twice(&mock, &["--head-cache", "100"]).await;
+
+    assert_eq!(mock.requests_for(Method::HEAD, KEY).len(), 1);
+    assert!(mock.requests_for(Method::PUT, KEY).is_empty());
+}
+
+#[tokio::test]
+async fn without_the_cache_each_check_sends_its_own() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+
+    check_twice(&mock, &[]).await;
+
+    assert_eq!(mock.requests_for(Method::HEAD, KEY).len(), 2);
+    assert!(mock.requests_for(Method::PUT, KEY).is_empty());
+}
+
+#[tokio::test]
+async fn a_write_drops_the_cached_result() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let first = dir.write("a/notes.txt", "plain words");
+    let second = dir.write("b/notes.txt", "other words");
+
+    run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--overwrite-if-different",
+        "--concurrency",
+        "1",
+        "--head-cache",
+        "100",
+        &first,
+        &second,
+    ])
+    .await
+    .unwrap();
+
+    // The second file's check sees the first one's upload, not the cached 404
+    assert_eq!(mock.requests_for(Method::HEAD, KEY).len(), 2);
+    assert_eq!(mock.requests_for(Method::PUT, KEY).len(), 2);
+}
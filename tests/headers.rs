@@ -0,0 +1,116 @@
+//! Content-Disposition and Expires set at upload, as a HEAD returns them
+
+mod common;
+
+use clap::Parser;
+use common::{run, sdk_client, Env, MockS3, TestDir, BUCKET};
+use hyper::Method;
+use s3_ml_uploader::cli::Cli;
+
+async fn head(mock: &MockS3, key: &str) -> aws_sdk_s3::operation::head_object::HeadObjectOutput {
+    sdk_client(mock)
+        .head_object()
+        .bucket(BUCKET)
+        .key(key)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn disposition_and_expiry_round_trip() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let file = dir.write("report.pdf", "%PDF-1.7 not much of a report");
+
+    run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--content-disposition",
+        "inline",
+        "--expires",
+        "2030-01-02T03:04:05Z",
+        &file,
+    ])
+    .await
+    .unwrap();
+
+    let head = head(&mock, "documents/report.pdf").await;
+    assert_eq!(head.content_disposition(), Some("inline"));
+    assert_eq!(head.expires_string(), Some("Wed, 02 Jan 2030 03:04:05 GMT"));
+}
+
+#[tokio::test]
+async fn force_download_names_the_file() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+
+    run(&["upload", "--backends", "aws", "--force-download", &file])
+        .await
+        .unwrap();
+
+    let head = head(&mock, "text/notes.txt").await;
+    assert_eq!(
+        head.content_disposition(),
+        Some("attachment; filename=\"notes.txt\"")
+    );
+}
+
+#[tokio::test]
+async fn the_http_path_signs_the_headers() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await.with_gcs(&mock);
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+
+    run(&[
+        "upload",
+        "--backends",
+        "gcs",
+        "--force-download",
+        "--expires",
+        "2030-01-02T03:04:05Z",
+        &file,
+    ])
+    .await
+    .unwrap();
+
+    let put = &mock.requests_for(Method::PUT, "text/notes.txt")[0];
+    let authorization = put.header("authorization").unwrap();
+    let signed = authorization
+        .split("SignedHeaders=")
+        .nth(1)
+        .unwrap()
+        .split(',')
+        .next()
+        .unwrap();
+    assert!(
+        signed.split(';').any(|h| h == "content-disposition"),
+        "{}",
+        signed
+    );
+    assert!(signed.split(';').any(|h| h == "expires"), "{}", signed);
+    let head = head(&mock, "text/notes.txt").await;
+    assert_eq!(
+        head.content_disposition(),
+        Some("attachment; filename=\"notes.txt\"")
+    );
+}
+
+#[test]
+fn header_values_with_line_breaks_are_rejected() {
+    for value in ["inline\r\nx-amz-acl: public-read", "inline\nbad"] {
+        let parsed = Cli::try_parse_from([
+            "s3-ml-uploader",
+            "upload",
+            "--content-disposition",
+            value,
+            "file.txt",
+        ]);
+        assert!(parsed.is_err(), "{:?}", value);
+    }
+}
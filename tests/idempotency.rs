@@ -0,0 +1,86 @@
+//! Retries of an upload whose first attempt reached the store but lost its response
+
+mod common;
+
+use common::{run, Env, MockS3, Reply, TestDir};
+use hyper::Method;
+
+const KEY: &str = "text/notes.txt";
+
+/// Store every PUT of `KEY`, but answer it as throttled
+///
+/// The SDK retries throttled requests itself first; once it gives up, the upload is
+/// retried as a whole.
+fn lose_put_responses(mock: &MockS3) {
+    mock.hook(|request| {
+        (request.method == Method::PUT && request.key == KEY)
+            .then(|| Reply::error(503, "SlowDown").after_handling())
+    });
+}
+
+#[tokio::test]
+async fn a_retry_finds_the_stored_object_and_sends_nothing() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    lose_put_responses(&mock);
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+
+    run(&["upload", "--backends", "aws", &file]).await.unwrap();
+
+    // The SDK's own attempts, then the retry's HEAD, which finds the object stored
+    let requests: Vec<_> = mock
+        .requests()
+        .into_iter()
+        .filter(|request| request.key == KEY)
+        .map(|request| request.method)
+        .collect();
+    assert_eq!(requests.last(), Some(&Method::HEAD), "{:?}", requests);
+    assert!(requests[..requests.len() - 1]
+        .iter()
+        .all(|method| method == Method::PUT));
+    let object = mock.object(KEY).unwrap();
+    assert_eq!(object.body, b"plain words");
+    assert!(object.metadata("idempotency-key").is_some());
+}
+
+#[tokio::test]
+async fn a_retry_over_other_content_uploads_again() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    // Something else answers for the key, then the first PUT is refused outright
+    mock.insert(KEY, b"older words");
+    let mut puts = 0;
+    mock.hook(move |request| {
+        if request.method != Method::PUT {
+            return None;
+        }
+        puts += 1;
+        (puts == 1).then(|| Reply::error(503, "SlowDown"))
+    });
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+
+    run(&["upload", "--backends", "aws", &file]).await.unwrap();
+
+    assert_eq!(mock.requests_for(Method::PUT, KEY).len(), 2);
+    assert_eq!(mock.object(KEY).unwrap().body, b"plain words");
+}
+
+#[tokio::test]
+async fn the_marker_is_stable_across_runs() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+
+    run(&["upload", "--backends", "aws", &file]).await.unwrap();
+    let first = mock.object(KEY).unwrap();
+    run(&["upload", "--backends", "aws", &file]).await.unwrap();
+    let second = mock.object(KEY).unwrap();
+
+    assert_eq!(
+        first.metadata("idempotency-key"),
+        second.metadata("idempotency-key")
+    );
+}
@@ -0,0 +1,78 @@
+//! `--key-map`: listed files go to their exact keys, the rest are named as usual
+
+mod common;
+
+use common::{run, Env, MockS3, TestDir};
+
+#[tokio::test]
+async fn mapped_files_use_their_keys_and_the_rest_are_classified() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let mapped = dir.write("notes.txt", "plain words");
+    let unmapped = dir.write("other.txt", "more words");
+    let map = dir.write("keys.csv", format!("{},datasets/v1/notes.txt\n", mapped));
+
+    run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--key-map",
+        &map,
+        &mapped,
+        &unmapped,
+    ])
+    .await
+    .unwrap();
+
+    assert_eq!(mock.keys(), ["datasets/v1/notes.txt", "text/other.txt"]);
+    assert_eq!(
+        mock.object("datasets/v1/notes.txt").unwrap().body,
+        b"plain words"
+    );
+}
+
+#[tokio::test]
+async fn mapped_keys_are_not_versioned() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let mapped = dir.write("notes.txt", "plain words");
+    let unmapped = dir.write("other.txt", "more words");
+    let map = dir.write(
+        "keys.json",
+        format!(r#"{{"{}": "datasets/notes.txt"}}"#, mapped),
+    );
+
+    run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--key-map",
+        &map,
+        "--version-suffix",
+        "counter",
+        &mapped,
+        &unmapped,
+    ])
+    .await
+    .unwrap();
+
+    assert_eq!(mock.keys(), ["datasets/notes.txt", "text/other.1.txt"]);
+}
+
+#[tokio::test]
+async fn a_conflicting_map_fails_before_uploading() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+    let map = dir.write("keys.csv", format!("{0},one.txt\n{0},two.txt\n", file));
+
+    let err = run(&["upload", "--backends", "aws", "--key-map", &map, &file])
+        .await
+        .unwrap_err();
+
+    assert!(err.to_string().contains("is mapped to both"), "{}", err);
+    assert!(mock.keys().is_empty());
+}
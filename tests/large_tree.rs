@@ -0,0 +1,90 @@
+//! Benchmark of `--dir` on a large tree, with and without `--stream`
+//!
+//! Ignored by default; run it with
+//! `cargo test --release --test large_tree -- --ignored --nocapture`, and set
+//! `LARGE_TREE_FILES` for a bigger tree than 20,000 files.
+
+mod common;
+
+use std::{
+    fs,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use common::{run, Env, MockS3, TestDir};
+
+const FILES_PER_DIR: usize = 100;
+
+/// A tree of `files` one-line files, `FILES_PER_DIR` to a directory, two levels deep
+fn large_tree(files: usize) -> TestDir {
+    let dir = TestDir::new();
+    for n in 0..files {
+        let sub = dir
+            .path()
+            .join(format!("{:03}", n / FILES_PER_DIR / 10))
+            .join(format!("{:03}", n / FILES_PER_DIR));
+        if n % FILES_PER_DIR == 0 {
+            fs::create_dir_all(&sub).unwrap();
+        }
+        fs::write(sub.join(format!("{}.txt", n)), format!("file {}\n", n)).unwrap();
+    }
+    dir
+}
+
+/// Upload all of `tree`, returning when the first PUT arrived and when the run ended
+async fn upload(tree: &TestDir, files: usize, stream: bool) -> (Duration, Duration) {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let first = Arc::new(Mutex::new(None));
+    let seen = Arc::clone(&first);
+    mock.hook(move |_| {
+        seen.lock().unwrap().get_or_insert_with(Instant::now);
+        None
+    });
+
+    let root = tree.path().to_string_lossy().into_owned();
+    let mut args = vec![
+        "upload",
+        "--backends",
+        "aws",
+        "--dir",
+        &root,
+        "--keep-paths",
+        "--concurrency",
+        "32",
+        "--yes",
+    ];
+    if stream {
+        args.push("--stream");
+    }
+    let started = Instant::now();
+    run(&args).await.unwrap();
+    let took = started.elapsed();
+
+    assert_eq!(mock.keys().len(), files);
+    let first = first.lock().unwrap().expect("nothing was uploaded");
+    (first - started, took)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "benchmark; builds a large tree"]
+async fn walking_a_large_tree() {
+    let files = std::env::var("LARGE_TREE_FILES")
+        .ok()
+        .and_then(|files| files.parse().ok())
+        .unwrap_or(20_000);
+    let tree = large_tree(files);
+
+    for stream in [false, true] {
+        let (first, took) = upload(&tree, files, stream).await;
+        println!(
+            "{} files{}: first upload after {:.2?}, all done in {:.2?} ({:.0} files/s)",
+            files,
+            if stream { " with --stream" } else { "" },
+            first,
+            took,
+            files as f64 / took.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,118 @@
+//! `list`: ListObjectsV2 pages followed to the end, with and without the `/` delimiter
+
+mod common;
+
+use std::process::{Command, Stdio};
+
+use common::{Env, MockS3};
+use hyper::Method;
+use serde_json::{json, Value};
+
+/// What the uploader binary prints for `list --json` with `args`
+async fn list_json(args: &[&str]) -> Value {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_s3-ml-uploader"));
+    command
+        .args(["list", "--json"])
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit());
+    let output = tokio::task::spawn_blocking(move || command.output().unwrap())
+        .await
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+/// Continuation tokens of the ListObjectsV2 requests, in order; the first has none
+fn continuation_tokens(mock: &MockS3) -> Vec<Option<String>> {
+    mock.requests()
+        .into_iter()
+        .filter(|request| request.method == Method::GET && request.query.contains_key("list-type"))
+        .map(|request| request.query.get("continuation-token").cloned())
+        .collect()
+}
+
+fn keys(listing: &Value) -> Vec<&str> {
+    listing["objects"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|object| object["key"].as_str().unwrap())
+        .collect()
+}
+
+async fn bucket_of_five() -> MockS3 {
+    let mock = MockS3::start().await;
+    for key in [
+        "images/a.png",
+        "images/b.png",
+        "text/notes/1.txt",
+        "text/notes/2.txt",
+        "text/readme.txt",
+    ] {
+        mock.insert(key, key.as_bytes());
+    }
+    mock.page_size(2);
+    mock
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_recursive_listing_follows_every_page() {
+    let mock = bucket_of_five().await;
+    let _env = Env::aws(&mock).await;
+
+    let listing = list_json(&["--recursive"]).await;
+
+    assert_eq!(
+        keys(&listing),
+        [
+            "images/a.png",
+            "images/b.png",
+            "text/notes/1.txt",
+            "text/notes/2.txt",
+            "text/readme.txt"
+        ]
+    );
+    assert_eq!(listing["prefixes"], json!([]));
+    assert_eq!(
+        listing["objects"][2],
+        json!({
+            "key": "text/notes/1.txt",
+            "size": 16,
+            "last_modified": "2024-01-01T00:00:00+00:00",
+        })
+    );
+    assert_eq!(
+        continuation_tokens(&mock),
+        [
+            None,
+            Some("images/b.png".to_string()),
+            Some("text/notes/2.txt".to_string())
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn folders_roll_up_into_prefixes_across_pages() {
+    let mock = bucket_of_five().await;
+    mock.page_size(1);
+    let _env = Env::aws(&mock).await;
+
+    // A page ending on a rolled-up folder resumes after every key below it
+    let listing = list_json(&["text/"]).await;
+    assert_eq!(listing["prefixes"], json!(["text/notes/"]));
+    assert_eq!(keys(&listing), ["text/readme.txt"]);
+    assert_eq!(listing["objects"][0]["size"], 15);
+    assert_eq!(
+        continuation_tokens(&mock),
+        [None, Some("text/notes/".to_string())]
+    );
+
+    let listing = list_json(&[]).await;
+    assert_eq!(listing["prefixes"], json!(["images/", "text/"]));
+    assert_eq!(keys(&listing), Vec::<&str>::new());
+    assert_eq!(
+        continuation_tokens(&mock)[2..],
+        [None, Some("images/".to_string())]
+    );
+}
@@ -0,0 +1,95 @@
+//! `--max-key-length` and `--on-long-key`: keys at S3's 1024-byte limit and past it
+
+mod common;
+
+use common::{run, Env, MockS3, TestDir};
+use s3_ml_uploader::error::AppError;
+
+/// A `len`-byte key under `text/` ending in `.txt`
+fn key_of_len(len: usize) -> String {
+    format!("text/{}.txt", "a".repeat(len - "text/.txt".len()))
+}
+
+/// Upload `notes.txt` under the exact key `key` (`--key-map`) with `args`
+async fn upload_as(key: &str, args: &[&str]) -> Result<(), AppError> {
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+    let map = dir.write("keys.csv", format!("{},{}\n", file, key));
+    let mut all = vec!["upload", "--backends", "aws", "--key-map", &map];
+    all.extend(args);
+    all.push(&file);
+    run(&all).await
+}
+
+#[tokio::test]
+async fn a_key_of_exactly_the_limit_uploads_as_is() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let key = key_of_len(1024);
+
+    upload_as(&key, &[]).await.unwrap();
+
+    assert_eq!(mock.keys(), [key]);
+}
+
+#[tokio::test]
+async fn a_byte_over_fails_the_file_by_default() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+
+    let err = upload_as(&key_of_len(1025), &[]).await.unwrap_err();
+
+    assert!(matches!(err, AppError::UploadsFailed(1)), "{:?}", err);
+    assert!(mock.keys().is_empty());
+}
+
+#[tokio::test]
+async fn truncated_keys_end_at_the_limit() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+
+    upload_as(&key_of_len(1025), &["--on-long-key", "truncate"])
+        .await
+        .unwrap();
+
+    let keys = mock.keys();
+    assert_eq!(keys.len(), 1);
+    assert_eq!(keys[0].len(), 1024);
+    assert!(keys[0].starts_with("text/aaa") && keys[0].ends_with(".txt"));
+    assert_eq!(mock.object(&keys[0]).unwrap().body, b"plain words");
+}
+
+#[tokio::test]
+async fn hashed_keys_replace_everything_after_the_first_segment() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let key = key_of_len(1025);
+
+    upload_as(&key, &["--on-long-key", "hash"]).await.unwrap();
+
+    assert_eq!(
+        mock.keys(),
+        [format!("text/{}.txt", common::sha256_hex(key.as_bytes()))]
+    );
+}
+
+#[tokio::test]
+async fn checksum_objects_are_kept_within_the_limit() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+
+    // 1024 bytes would leave no room for `.sha256`
+    let err = upload_as(&key_of_len(1024), &["--emit-checksum-objects"])
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AppError::UploadsFailed(1)), "{:?}", err);
+
+    upload_as(
+        &key_of_len(1024 - ".sha256".len()),
+        &["--emit-checksum-objects"],
+    )
+    .await
+    .unwrap();
+    assert!(mock.keys().iter().all(|key| key.len() <= 1024));
+    assert_eq!(mock.keys().len(), 2);
+}
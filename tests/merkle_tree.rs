@@ -0,0 +1,105 @@
+//! `--merkle-tree`: the root on the object, the tree next to it, loaded and streamed
+
+mod common;
+
+use common::{run, Env, MockS3, TestDir};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+const MIB: usize = 1024 * 1024;
+
+fn leaf(part: &[u8]) -> String {
+    hex::encode(
+        Sha256::new()
+            .chain_update([0x00])
+            .chain_update(part)
+            .finalize(),
+    )
+}
+
+fn node(left: &str, right: &str) -> String {
+    let digest = Sha256::new()
+        .chain_update([0x01])
+        .chain_update(hex::decode(left).unwrap())
+        .chain_update(hex::decode(right).unwrap())
+        .finalize();
+    hex::encode(digest)
+}
+
+/// Upload `content` as `data.bin` with `--merkle-tree` and `args`; its key and stored tree
+async fn upload_with_tree(mock: &MockS3, content: &[u8], args: &[&str]) -> (String, Value) {
+    let dir = TestDir::new();
+    let file = dir.write("data.bin", content);
+    let mut all = vec!["upload", "--backends", "aws", "--merkle-tree"];
+    all.extend(args);
+    all.push(&file);
+    run(&all).await.unwrap();
+
+    let keys = mock.keys();
+    let tree_key = keys
+        .iter()
+        .find(|key| key.ends_with(".merkle.json"))
+        .expect("tree object");
+    let key = tree_key.trim_end_matches(".merkle.json").to_string();
+    let tree = serde_json::from_slice(&mock.object(tree_key).unwrap().body).unwrap();
+    (key, tree)
+}
+
+#[tokio::test]
+async fn a_small_file_is_one_leaf() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+
+    let (key, tree) = upload_with_tree(&mock, b"plain words", &[]).await;
+
+    let root = leaf(b"plain words");
+    assert_eq!(tree["key"], key.as_str());
+    assert_eq!(tree["size"], 11);
+    assert_eq!(tree["root"], root.as_str());
+    assert_eq!(tree["levels"], serde_json::json!([[root]]));
+    let object = mock.object(&key).unwrap();
+    assert_eq!(object.metadata("merkle-root"), Some(root.as_str()));
+    assert_eq!(
+        object.metadata("merkle-part-size"),
+        Some(tree["part_size"].to_string().as_str())
+    );
+}
+
+#[tokio::test]
+async fn leaves_follow_the_part_size_and_one_part_checks_alone() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let content: Vec<u8> = (0..11 * MIB as u32).map(|i| (i % 251) as u8).collect();
+
+    let (key, tree) = upload_with_tree(&mock, &content, &["--part-size", "5MiB"]).await;
+
+    let leaves: Vec<String> = content.chunks(5 * MIB).map(leaf).collect();
+    assert_eq!(leaves.len(), 3);
+    let root = node(&node(&leaves[0], &leaves[1]), &leaves[2]);
+    assert_eq!(tree["part_size"], 5 * MIB);
+    assert_eq!(tree["root"], root.as_str());
+    assert_eq!(tree["levels"][0], serde_json::json!(leaves));
+    let object = mock.object(&key).unwrap();
+    assert_eq!(object.metadata("merkle-root"), Some(root.as_str()));
+    assert_eq!(object.metadata("merkle-part-size"), Some("5242880"));
+
+    // The second part against its leaf, without the rest of the object
+    let part = &object.body[5 * MIB..10 * MIB];
+    assert_eq!(tree["levels"][0][1], leaf(part).as_str());
+}
+
+#[tokio::test]
+async fn streamed_files_get_the_tree_of_loaded_ones() {
+    let content: Vec<u8> = (0..11 * MIB as u32).map(|i| (i % 13) as u8).collect();
+    let mut roots = Vec::new();
+    for args in [&[][..], &["--stream-above", "1"]] {
+        let mock = MockS3::start().await;
+        let _env = Env::aws(&mock).await;
+        let mut all = vec!["--part-size", "5MiB"];
+        all.extend(args);
+        let (_, tree) = upload_with_tree(&mock, &content, &all).await;
+        roots.push(tree["root"].clone());
+    }
+
+    assert_eq!(roots[0], roots[1]);
+}
@@ -0,0 +1,38 @@
+//! The content hash stamped on uploads, read back by later runs
+
+mod common;
+
+use common::{run, sha256_hex, Env, MockS3, TestDir};
+use hyper::Method;
+
+#[tokio::test]
+async fn stamped_sha256_round_trips_through_head() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "hello, metadata\n");
+
+    run(&["upload", "--backends", "aws", &file]).await.unwrap();
+
+    let keys = mock.keys();
+    assert_eq!(keys.len(), 1, "one object uploaded: {:?}", keys);
+    let object = mock.object(&keys[0]).unwrap();
+    assert_eq!(object.body, b"hello, metadata\n");
+    assert_eq!(
+        object.metadata("sha256"),
+        Some(sha256_hex(b"hello, metadata\n").as_str())
+    );
+
+    // The second run reads the stamp back with HEAD and finds nothing to upload
+    run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--overwrite-if-different",
+        &file,
+    ])
+    .await
+    .unwrap();
+    assert_eq!(mock.requests_for(Method::PUT, &keys[0]).len(), 1);
+    assert!(!mock.requests_for(Method::HEAD, &keys[0]).is_empty());
+}
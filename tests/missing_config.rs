@@ -0,0 +1,45 @@
+//! Unset environment configuration fails at startup, before any request is sent
+
+mod common;
+
+use common::{run, Env, MockS3, TestDir};
+use s3_ml_uploader::error::AppError;
+
+#[tokio::test]
+async fn a_missing_aws_bucket_is_named() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    std::env::remove_var("AWS_BUCKET");
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+
+    let err = run(&["upload", "--backends", "aws", &file])
+        .await
+        .unwrap_err();
+
+    assert!(
+        matches!(&err, AppError::MissingConfig(missing) if *missing == ["AWS_BUCKET"]),
+        "{:?}",
+        err
+    );
+    assert!(mock.requests().is_empty());
+}
+
+#[tokio::test]
+async fn allow_defaults_uploads_to_the_placeholder_bucket() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    std::env::remove_var("AWS_BUCKET");
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+
+    run(&["upload", "--backends", "aws", "--allow-defaults", &file])
+        .await
+        .unwrap();
+
+    let requests = mock.requests();
+    assert!(!requests.is_empty());
+    assert!(requests
+        .iter()
+        .all(|request| request.bucket == "aws-bucket"));
+}
@@ -0,0 +1,156 @@
+//! Multipart uploads to AWS S3, their parts checked as they are sent
+
+mod common;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use common::{run, sha256_hex, Env, MockS3, TestDir};
+use hyper::Method;
+use s3_ml_uploader::error::AppError;
+
+/// 12 MiB, three parts at the smallest part size
+fn content() -> Vec<u8> {
+    (0..12 * 1024 * 1024u32).map(|i| (i % 251) as u8).collect()
+}
+
+async fn streamed_upload(part_checksum: &str) -> (MockS3, String) {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let file = dir.write("weights.bin", content());
+
+    run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--stream-above",
+        "1MiB",
+        "--part-size",
+        "5MiB",
+        "--part-checksum",
+        part_checksum,
+        &file,
+    ])
+    .await
+    .unwrap();
+
+    let key = mock.keys().pop().expect("object uploaded");
+    (mock, key)
+}
+
+#[tokio::test]
+async fn parts_carry_trailing_sha256_checksums() {
+    let (mock, key) = streamed_upload("sha256").await;
+
+    let object = mock.object(&key).unwrap();
+    assert_eq!(sha256_hex(&object.body), sha256_hex(&content()));
+    let parts = mock.requests_for(Method::PUT, &key);
+    assert_eq!(parts.len(), 3);
+    for part in parts {
+        // Sent after the body, not computed before it
+        assert_eq!(part.header("x-amz-trailer"), Some("x-amz-checksum-sha256"));
+        assert!(part.header("x-amz-checksum-sha256").is_some());
+    }
+}
+
+#[tokio::test]
+async fn parts_can_carry_crc32c_instead() {
+    let (mock, key) = streamed_upload("crc32c").await;
+
+    assert_eq!(mock.object(&key).unwrap().body, content());
+    for part in mock.requests_for(Method::PUT, &key) {
+        assert_eq!(part.header("x-amz-trailer"), Some("x-amz-checksum-crc32c"));
+        assert!(part.header("x-amz-checksum-sha256").is_none());
+    }
+}
+
+#[tokio::test]
+async fn a_part_acknowledged_with_another_checksum_fails_the_upload() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let file = dir.write("weights.bin", content());
+    mock.hook(|request| {
+        let part = request.query.get("partNumber")?;
+        (part == "2").then(|| {
+            common::Reply::new(200)
+                .with_header("etag", "\"part-2\"")
+                .with_header("x-amz-checksum-sha256", &STANDARD.encode([0; 32]))
+        })
+    });
+
+    let err = run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--stream-above",
+        "1MiB",
+        "--part-size",
+        "5MiB",
+        &file,
+    ])
+    .await
+    .unwrap_err();
+
+    assert!(matches!(err, AppError::UploadsFailed(1)), "{:?}", err);
+    assert!(mock.keys().is_empty());
+    // The upload was aborted rather than completed
+    let aborted = mock
+        .requests()
+        .into_iter()
+        .any(|request| request.method == Method::DELETE && request.query.contains_key("uploadId"));
+    assert!(aborted);
+}
+
+#[tokio::test]
+async fn a_part_rejected_as_corrupted_fails_the_upload() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let file = dir.write("weights.bin", content());
+    // What S3 answers when the bytes it got don't match the part's checksum
+    mock.hook(|request| {
+        let part = request.query.get("partNumber")?;
+        (part == "3").then(|| common::Reply::error(400, "BadDigest"))
+    });
+
+    let err = run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--stream-above",
+        "1MiB",
+        "--part-size",
+        "5MiB",
+        &file,
+    ])
+    .await
+    .unwrap_err();
+
+    assert!(matches!(err, AppError::UploadsFailed(1)), "{:?}", err);
+    assert!(mock.keys().is_empty());
+    let completed = mock
+        .requests()
+        .into_iter()
+        .any(|request| request.method == Method::POST && request.query.contains_key("uploadId"));
+    assert!(!completed);
+}
+
+#[tokio::test]
+async fn completion_lists_each_part_checksum() {
+    let (mock, key) = streamed_upload("sha256").await;
+
+    let complete = mock
+        .requests_for(Method::POST, &key)
+        .into_iter()
+        .find(|request| request.query.contains_key("uploadId"))
+        .expect("upload completed");
+    let body = String::from_utf8(complete.body).unwrap();
+    for part in mock.requests_for(Method::PUT, &key) {
+        let checksum = part.header("x-amz-checksum-sha256").unwrap();
+        assert!(
+            body.contains(&format!("<ChecksumSHA256>{}</ChecksumSHA256>", checksum)),
+            "{}",
+            body
+        );
+    }
+}
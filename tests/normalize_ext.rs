@@ -0,0 +1,46 @@
+//! `--normalize-ext`: lowercased extensions in keys, files on disk untouched
+
+mod common;
+
+use common::{run, Env, MockS3, TestDir};
+
+#[tokio::test]
+async fn mixed_case_extensions_are_lowercased_in_keys() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let upper = dir.write("README.TXT", "plain words");
+    let mixed = dir.write("Notes.Md", "more words");
+    let bare = dir.write("LICENSE", "license words");
+
+    run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--normalize-ext",
+        &upper,
+        &mixed,
+        &bare,
+    ])
+    .await
+    .unwrap();
+
+    let mut keys = mock.keys();
+    keys.sort();
+    assert_eq!(keys, ["text/LICENSE", "text/Notes.md", "text/README.txt"]);
+    // Only the keys changed
+    assert!(dir.path().join("README.TXT").exists());
+    assert!(dir.path().join("Notes.Md").exists());
+}
+
+#[tokio::test]
+async fn extensions_keep_their_case_by_default() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let upper = dir.write("README.TXT", "plain words");
+
+    run(&["upload", "--backends", "aws", &upper]).await.unwrap();
+
+    assert_eq!(mock.keys(), ["text/README.TXT"]);
+}
@@ -0,0 +1,90 @@
+//! `--on-collision rename`: numbered keys for files whose key is taken, stable across runs
+
+mod common;
+
+use common::{run, Env, MockS3, TestDir};
+
+/// Upload `a/notes.txt` and `b/notes.txt`, which both map to `text/notes.txt`
+async fn upload_both(dir: &TestDir) {
+    let first = dir.write("a/notes.txt", "first");
+    let second = dir.write("b/notes.txt", "second");
+    run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--on-collision",
+        "rename",
+        &first,
+        &second,
+    ])
+    .await
+    .unwrap();
+}
+
+fn body(mock: &MockS3, key: &str) -> String {
+    String::from_utf8(mock.object(key).unwrap().body).unwrap()
+}
+
+#[tokio::test]
+async fn later_files_of_a_run_are_numbered() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+
+    upload_both(&TestDir::new()).await;
+
+    assert_eq!(mock.keys(), ["text/notes-1.txt", "text/notes.txt"]);
+    assert_eq!(body(&mock, "text/notes.txt"), "first");
+    assert_eq!(body(&mock, "text/notes-1.txt"), "second");
+}
+
+#[tokio::test]
+async fn numbers_skip_keys_stored_with_other_content() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    mock.insert("text/notes.txt", "stored");
+    mock.insert("text/notes-1.txt", "stored too");
+
+    upload_both(&TestDir::new()).await;
+
+    assert_eq!(body(&mock, "text/notes.txt"), "stored");
+    assert_eq!(body(&mock, "text/notes-1.txt"), "stored too");
+    assert_eq!(body(&mock, "text/notes-2.txt"), "first");
+    assert_eq!(body(&mock, "text/notes-3.txt"), "second");
+}
+
+#[tokio::test]
+async fn a_rerun_lands_on_the_same_keys() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+
+    upload_both(&dir).await;
+    upload_both(&dir).await;
+
+    assert_eq!(mock.keys(), ["text/notes-1.txt", "text/notes.txt"]);
+    assert_eq!(body(&mock, "text/notes.txt"), "first");
+    assert_eq!(body(&mock, "text/notes-1.txt"), "second");
+}
+
+#[tokio::test]
+async fn overwrite_lets_the_last_file_win() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let first = dir.write("a/notes.txt", "first");
+    let second = dir.write("b/notes.txt", "second");
+
+    run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--concurrency",
+        "1",
+        &first,
+        &second,
+    ])
+    .await
+    .unwrap();
+
+    assert_eq!(mock.keys(), ["text/notes.txt"]);
+}
@@ -0,0 +1,78 @@
+//! `--on-empty`: a zero-byte file next to a non-empty one under each policy
+
+mod common;
+
+use bytes::Bytes;
+use common::{run, upload_args, Env, MockS3, TestDir};
+use s3_ml_uploader::{error::AppError, ml::FileTypePredictor, run_upload, ContentTransform};
+
+/// Upload `empty.txt` and `notes.txt` with `args`
+async fn upload_with(args: &[&str]) -> Result<(), AppError> {
+    let dir = TestDir::new();
+    let empty = dir.write("empty.txt", "");
+    let notes = dir.write("notes.txt", "plain words");
+    let mut all = vec!["upload", "--backends", "aws"];
+    all.extend(args);
+    all.push(&empty);
+    all.push(&notes);
+    run(&all).await
+}
+
+#[tokio::test]
+async fn empty_files_upload_by_default() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+
+    upload_with(&[]).await.unwrap();
+
+    assert_eq!(mock.keys(), ["text/empty.txt", "text/notes.txt"]);
+    assert!(mock.object("text/empty.txt").unwrap().body.is_empty());
+}
+
+#[tokio::test]
+async fn skipped_empty_files_leave_the_run_successful() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+
+    upload_with(&["--on-empty", "skip"]).await.unwrap();
+
+    assert_eq!(mock.keys(), ["text/notes.txt"]);
+}
+
+#[tokio::test]
+async fn empty_files_fail_under_error() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+
+    let err = upload_with(&["--on-empty", "error"]).await.unwrap_err();
+
+    assert!(matches!(err, AppError::UploadsFailed(1)), "{:?}", err);
+    assert_eq!(mock.keys(), ["text/notes.txt"]);
+}
+
+/// Leaves nothing of any content
+struct Truncate;
+
+impl ContentTransform for Truncate {
+    fn transform(&self, _input: Bytes) -> Result<Bytes, AppError> {
+        Ok(Bytes::new())
+    }
+}
+
+#[tokio::test]
+async fn content_a_transform_empties_counts_as_empty() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let notes = dir.write("notes.txt", "plain words");
+
+    run_upload(
+        upload_args(&["--backends", "aws", "--on-empty", "skip", &notes]),
+        Box::new(FileTypePredictor::new()),
+        vec![Box::new(Truncate)],
+    )
+    .await
+    .unwrap();
+
+    assert!(mock.keys().is_empty());
+}
@@ -0,0 +1,94 @@
+//! `upload --store-original-name` and `download --restore-names`
+
+mod common;
+
+use std::process::{Command, Output, Stdio};
+
+use common::{run, Env, MockS3, TestDir};
+
+/// Download `key` with `--restore-names` from the binary, run in `dir`
+async fn download_restoring(dir: &TestDir, key: &str) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_s3-ml-uploader"));
+    command
+        .args(["download", key, "--restore-names"])
+        .current_dir(dir.path())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    tokio::task::spawn_blocking(move || command.output().unwrap())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn slugified_keys_download_under_the_original_name() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    for name in ["Q3 Report (final).txt", "résumé.txt"] {
+        let file = dir.write(name, name);
+        run(&[
+            "upload",
+            "--backends",
+            "aws",
+            "--slugify",
+            "--store-original-name",
+            &file,
+        ])
+        .await
+        .unwrap();
+    }
+    let keys = mock.keys();
+    assert_eq!(keys.len(), 2);
+    assert!(keys.iter().all(|key| !key.contains(' ')), "{:?}", keys);
+
+    let downloads = TestDir::new();
+    for key in &keys {
+        let output = download_restoring(&downloads, key).await;
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    for name in ["Q3 Report (final).txt", "résumé.txt"] {
+        let restored = downloads.path().join(name);
+        assert_eq!(std::fs::read_to_string(restored).unwrap(), name);
+    }
+}
+
+#[tokio::test]
+async fn objects_without_a_stored_name_keep_their_keys_name() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    mock.insert("text/notes.txt", "plain words");
+    let downloads = TestDir::new();
+
+    let output = download_restoring(&downloads, "text/notes.txt").await;
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("has no stored original name"));
+    assert_eq!(
+        std::fs::read_to_string(downloads.path().join("notes.txt")).unwrap(),
+        "plain words"
+    );
+}
+
+#[tokio::test]
+async fn a_stored_name_with_a_path_is_refused() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    mock.insert_with_headers(
+        "text/notes.txt",
+        "plain words",
+        &[("x-amz-meta-original-name", "../escaped.txt")],
+    );
+    let downloads = TestDir::new();
+
+    let output = download_restoring(&downloads, "text/notes.txt").await;
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is not a plain file name"));
+    assert_eq!(std::fs::read_dir(downloads.path()).unwrap().count(), 0);
+}
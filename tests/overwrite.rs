@@ -0,0 +1,79 @@
+//! `--overwrite-if-different`: uploading only what changed since the last run
+
+mod common;
+
+use common::{run, Env, MockS3, TestDir};
+use hyper::Method;
+
+const KEY: &str = "text/notes.txt";
+
+async fn upload(file: &str, extra: &[&str]) {
+    let args: Vec<&str> = ["upload", "--backends", "aws", "--overwrite-if-different"]
+        .into_iter()
+        .chain(extra.iter().copied())
+        .chain([file])
+        .collect();
+    run(&args).await.unwrap();
+}
+
+fn puts(mock: &MockS3) -> usize {
+    mock.requests_for(Method::PUT, KEY).len()
+}
+
+#[tokio::test]
+async fn unchanged_content_is_skipped() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+
+    upload(&file, &[]).await;
+    upload(&file, &[]).await;
+
+    assert_eq!(puts(&mock), 1);
+}
+
+#[tokio::test]
+async fn changed_content_is_uploaded() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+
+    upload(&file, &[]).await;
+    // Same size, so only the checksum tells them apart
+    dir.write("notes.txt", "plain wordz");
+    upload(&file, &[]).await;
+
+    assert_eq!(puts(&mock), 2);
+    assert_eq!(mock.object(KEY).unwrap().body, b"plain wordz");
+}
+
+#[tokio::test]
+async fn objects_without_a_checksum_are_replaced_by_default() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+    mock.insert(KEY, b"plain words");
+
+    upload(&file, &[]).await;
+
+    assert_eq!(puts(&mock), 1);
+}
+
+#[tokio::test]
+async fn if_no_checksum_size_trusts_a_matching_size() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+    mock.insert(KEY, b"other words");
+
+    upload(&file, &["--if-no-checksum", "size"]).await;
+    assert_eq!(puts(&mock), 0);
+
+    dir.write("notes.txt", "longer plain words");
+    upload(&file, &["--if-no-checksum", "size"]).await;
+    assert_eq!(puts(&mock), 1);
+}
@@ -0,0 +1,155 @@
+//! `--pack`: small files concatenated into packs with a JSON index, and read back out of them
+
+mod common;
+
+use common::{run, sha256_hex, Env, MockS3, TestDir};
+use s3_ml_uploader::error::AppError;
+use serde_json::Value;
+
+const FILES: [(&str, &str); 3] = [
+    ("a.txt", "first file\n"),
+    ("b.txt", "second\n"),
+    ("c.txt", "the third file\n"),
+];
+
+/// Upload `FILES` and a file too large to pack with `extra` args
+async fn upload(dir: &TestDir, extra: &[&str]) {
+    let mut files: Vec<String> = FILES
+        .iter()
+        .map(|(name, contents)| dir.write(name, contents))
+        .collect();
+    files.push(dir.write("large.txt", "x".repeat(4096)));
+    let mut args = vec![
+        "upload",
+        "--backends",
+        "aws",
+        "--pack",
+        "--pack-below",
+        "1KiB",
+    ];
+    args.extend(extra);
+    args.extend(files.iter().map(String::as_str));
+    run(&args).await.unwrap();
+}
+
+/// Keys of the stored pack indexes
+fn indexes(mock: &MockS3) -> Vec<String> {
+    mock.keys()
+        .into_iter()
+        .filter(|key| key.ends_with(".index.json"))
+        .collect()
+}
+
+fn index(mock: &MockS3, key: &str) -> Value {
+    serde_json::from_slice(&mock.object(key).unwrap().body).unwrap()
+}
+
+#[tokio::test]
+async fn small_files_share_a_pack_its_index_locates() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+
+    upload(&dir, &[]).await;
+
+    let indexes = indexes(&mock);
+    assert_eq!(indexes.len(), 1);
+    let index = index(&mock, &indexes[0]);
+    let pack_key = index["pack"].as_str().unwrap();
+    let pack = mock.object(pack_key).unwrap().body;
+    assert_eq!(pack_key, format!("packs/{}.pack", sha256_hex(&pack)));
+    assert_eq!(
+        indexes[0],
+        format!("packs/{}.index.json", sha256_hex(&pack))
+    );
+    assert_eq!(index["size"], pack.len());
+
+    let files = index["files"].as_array().unwrap();
+    assert_eq!(files.len(), FILES.len());
+    for (entry, (name, contents)) in files.iter().zip(FILES) {
+        assert_eq!(entry["key"], format!("text/{}", name));
+        let offset = entry["offset"].as_u64().unwrap() as usize;
+        let length = entry["length"].as_u64().unwrap() as usize;
+        assert_eq!(&pack[offset..offset + length], contents.as_bytes());
+        assert_eq!(entry["sha256"], sha256_hex(contents.as_bytes()));
+    }
+
+    // Only the large file is stored on its own
+    assert!(mock.object("text/large.txt").is_some());
+    assert!(mock.object("text/a.txt").is_none());
+}
+
+#[tokio::test]
+async fn files_are_downloaded_out_of_their_pack() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    upload(&dir, &[]).await;
+    let index_key = indexes(&mock).remove(0);
+
+    for (name, contents) in FILES {
+        let output = dir.path().join(format!("downloaded-{}", name));
+        run(&[
+            "download",
+            &format!("text/{}", name),
+            output.to_str().unwrap(),
+            "--pack-index",
+            &index_key,
+        ])
+        .await
+        .unwrap();
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), contents);
+    }
+}
+
+#[tokio::test]
+async fn pack_size_splits_packs_and_repacking_lands_on_the_same_keys() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+
+    upload(&dir, &["--pack-size", "20"]).await;
+    let keys = mock.keys();
+    upload(&dir, &["--pack-size", "20"]).await;
+
+    // 11 + 7 bytes fit in 20; the third file starts another pack
+    let indexes = indexes(&mock);
+    assert_eq!(indexes.len(), 2);
+    let packed: Vec<usize> = indexes
+        .iter()
+        .map(|key| index(&mock, key)["files"].as_array().unwrap().len())
+        .collect();
+    assert_eq!(packed.iter().sum::<usize>(), 3);
+    assert!(packed.contains(&2) && packed.contains(&1), "{:?}", packed);
+    assert_eq!(mock.keys(), keys);
+}
+
+#[tokio::test]
+async fn a_corrupted_pack_fails_the_download() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    upload(&dir, &[]).await;
+    let index_key = indexes(&mock).remove(0);
+    let pack_key = index(&mock, &index_key)["pack"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let mut pack = mock.object(&pack_key).unwrap().body;
+    pack[0] ^= 0xff;
+    mock.insert(&pack_key, pack);
+
+    let output = dir.path().join("downloaded.txt");
+    let err = run(&[
+        "download",
+        "text/a.txt",
+        output.to_str().unwrap(),
+        "--pack-index",
+        &index_key,
+    ])
+    .await
+    .unwrap_err();
+
+    assert!(matches!(err, AppError::Integrity(_)), "{:?}", err);
+    assert!(!output.exists());
+}
@@ -0,0 +1,126 @@
+//! Keys of `--dir` files: their directories kept, stripped or dropped
+
+mod common;
+
+use std::process::{Command, Stdio};
+
+use common::{run, Env, MockS3, TestDir};
+
+/// Upload a nested tree with `args`, returning the stored keys
+async fn keys(args: &[&str]) -> Vec<String> {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    dir.write("train/cats/001.txt", "a cat");
+    dir.write("train/dogs/001.txt", "a dog");
+    dir.write("readme.txt", "about the data");
+    let root = dir.path().to_string_lossy().into_owned();
+
+    let args: Vec<&str> = ["upload", "--backends", "aws", "--dir", root.as_str()]
+        .into_iter()
+        .chain(args.iter().copied())
+        .collect();
+    run(&args).await.unwrap();
+    mock.keys()
+}
+
+#[tokio::test]
+async fn keep_paths_keeps_directories_under_the_root() {
+    assert_eq!(
+        keys(&["--keep-paths"]).await,
+        [
+            "text/readme.txt",
+            "text/train/cats/001.txt",
+            "text/train/dogs/001.txt"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn strip_components_drops_leading_directories() {
+    assert_eq!(
+        keys(&["--keep-paths", "--strip-components", "1"]).await,
+        ["text/cats/001.txt", "text/dogs/001.txt", "text/readme.txt"]
+    );
+}
+
+#[tokio::test]
+async fn files_of_the_same_name_share_a_key_and_the_last_one_wins() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let cats = dir.write("train/cats/001.txt", "a cat");
+    let dogs = dir.write("train/dogs/001.txt", "a dog");
+
+    // One file at a time, so the uploads land in argument order
+    run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--concurrency",
+        "1",
+        &cats,
+        &dogs,
+    ])
+    .await
+    .unwrap();
+
+    assert_eq!(mock.keys(), ["text/001.txt"]);
+    assert_eq!(mock.object("text/001.txt").unwrap().body, b"a dog");
+}
+
+#[tokio::test]
+async fn rename_numbers_files_of_the_same_name() {
+    assert_eq!(
+        keys(&["--on-collision", "rename"]).await,
+        ["text/001-1.txt", "text/001.txt", "text/readme.txt"]
+    );
+}
+
+/// The note `upload --dir --dry-run` prints about files sharing a name, if any
+async fn shared_name_note(tree: &[&str], args: &[&str]) -> Option<String> {
+    let dir = TestDir::new();
+    for file in tree {
+        dir.write(file, "some text");
+    }
+    let mut command = Command::new(env!("CARGO_BIN_EXE_s3-ml-uploader"));
+    command
+        .args(["upload", "--dry-run", "--dir"])
+        .arg(dir.path())
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit());
+    let output = tokio::task::spawn_blocking(move || command.output().unwrap())
+        .await
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .find(|line| line.starts_with("Note: ") && line.contains("file name"))
+        .map(str::to_string)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_note_names_files_that_would_share_a_key() {
+    let tree = ["train/cats/001.txt", "train/dogs/001.txt", "readme.txt"];
+
+    let note = shared_name_note(&tree, &[]).await.expect("a note");
+    assert!(
+        note.starts_with("Note: 1 file name(s) are shared by several files, e.g. 001.txt ("),
+        "{}",
+        note
+    );
+    assert!(note.contains("--keep-paths"), "{}", note);
+    // Nothing collides with the directories kept, and numbered keys are asked for
+    assert_eq!(shared_name_note(&tree, &["--keep-paths"]).await, None);
+    assert_eq!(
+        shared_name_note(&tree, &["--on-collision", "rename"]).await,
+        None
+    );
+    // Nor is there anything to say when every name is distinct
+    assert_eq!(
+        shared_name_note(&["train/cats/001.txt", "readme.txt"], &[]).await,
+        None
+    );
+}
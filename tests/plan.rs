@@ -0,0 +1,113 @@
+//! `--dry-run` and `--plan`: the plan's keys, sizes and collisions are what the upload does
+
+mod common;
+
+use std::process::{Command, Stdio};
+
+use common::{run, Env, MockS3, TestDir};
+use s3_ml_uploader::{cli::format_size, error::AppError};
+
+/// What the uploader binary prints for `upload --dry-run` with `args`
+async fn dry_run(args: &[&str]) -> String {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_s3-ml-uploader"));
+    command
+        .args(["upload", "--dry-run"])
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit());
+    let output = tokio::task::spawn_blocking(move || command.output().unwrap())
+        .await
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// `(size, key)` of every planned line, e.g. `        12 B text/a.txt <- a.txt (text, 90%)`
+fn planned(output: &str) -> Vec<(String, String)> {
+    let mut planned: Vec<_> = output
+        .lines()
+        .filter_map(|line| line.split_once(" <- ")?.0.trim().rsplit_once(' '))
+        .map(|(size, key)| (size.to_string(), key.to_string()))
+        .collect();
+    planned.sort_by(|a, b| a.1.cmp(&b.1));
+    planned
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn the_upload_sends_the_planned_keys_and_bytes() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let files = [
+        dir.write("notes.txt", "one\r\ntwo\r\nthree\r\n"),
+        dir.write("data/table.csv", "a,b\r\n1,2\r\n"),
+        dir.write("image.png", b"\x89PNG\r\n\x1a\n pixels"),
+    ];
+    let mut args = vec!["--backends", "aws", "--normalize-newlines"];
+    args.extend(files.iter().map(String::as_str));
+
+    let output = dry_run(&args).await;
+    assert!(mock.keys().is_empty(), "a dry run uploads nothing");
+
+    run(&[&["upload"], &args[..]].concat()).await.unwrap();
+
+    let uploaded: Vec<_> = mock
+        .keys()
+        .into_iter()
+        .map(|key| {
+            (
+                format_size(mock.object(&key).unwrap().body.len() as u64),
+                key,
+            )
+        })
+        .collect();
+    assert_eq!(planned(&output), uploaded);
+    let total: usize = uploaded
+        .iter()
+        .map(|(_, key)| mock.object(key).unwrap().body.len())
+        .sum();
+    let header = format!("Plan: 3 file(s), {} to AWS S3", format_size(total as u64));
+    assert!(
+        output.lines().any(|line| line.starts_with(&header)),
+        "{}",
+        output
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn planned_collisions_are_the_overwrites_of_an_upload() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let first = dir.write("a/notes.txt", "first");
+    let second = dir.write("b/notes.txt", "second");
+    let args = ["--backends", "aws", &first, &second];
+
+    let output = dry_run(&args).await;
+    assert!(
+        output.contains(&format!(
+            "Collision: text/notes.txt <- {}, {}",
+            first, second
+        )),
+        "{}",
+        output
+    );
+
+    // With a plan the upload refuses the batch
+    let err = run(&[&["upload", "--plan"], &args[..]].concat())
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(&err, AppError::Config(message) if message.starts_with("1 key collision(s)")),
+        "{}",
+        err
+    );
+    assert!(mock.keys().is_empty());
+
+    // Without one, the second file replaces the first under the key the plan named
+    run(&[&["upload", "--concurrency", "1"], &args[..]].concat())
+        .await
+        .unwrap();
+    assert_eq!(mock.keys(), ["text/notes.txt"]);
+    assert_eq!(mock.object("text/notes.txt").unwrap().body, b"second");
+}
@@ -0,0 +1,138 @@
+//! `run_upload_with_progress`: the `ProgressEvent`s of each file, in order
+
+mod common;
+
+use std::collections::BTreeMap;
+
+use common::{upload_args, Env, MockS3, Reply, TestDir};
+use s3_ml_uploader::{
+    error::AppError, ml::FileTypePredictor, run_upload_with_progress, ProgressEvent,
+};
+use tokio::sync::mpsc;
+
+/// Events of a run over `args`, by file, and how the run ended
+async fn events_of(args: &[&str]) -> (BTreeMap<String, Vec<ProgressEvent>>, Result<(), AppError>) {
+    let (events, mut received) = mpsc::unbounded_channel();
+    let result = run_upload_with_progress(
+        upload_args(args),
+        Box::new(FileTypePredictor::new()),
+        Vec::new(),
+        events,
+    )
+    .await;
+
+    let mut by_file: BTreeMap<String, Vec<ProgressEvent>> = BTreeMap::new();
+    while let Some(event) = received.recv().await {
+        by_file
+            .entry(event.file().to_string())
+            .or_default()
+            .push(event);
+    }
+    (by_file, result)
+}
+
+#[tokio::test]
+async fn each_file_ends_completed_failed_or_skipped() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    mock.hook(|request| {
+        (request.key == "text/denied.txt").then(|| Reply::error(403, "AccessDenied"))
+    });
+    let dir = TestDir::new();
+    let notes = dir.write("notes.txt", "plain words");
+    let denied = dir.write("denied.txt", "no");
+    let big = dir.write("big.txt", "x".repeat(2048));
+
+    let (events, result) = events_of(&[
+        "--backends",
+        "aws",
+        "--max-file-size",
+        "1KiB",
+        &notes,
+        &denied,
+        &big,
+    ])
+    .await;
+
+    assert!(
+        matches!(result, Err(AppError::UploadsFailed(1))),
+        "{:?}",
+        result
+    );
+    assert_eq!(
+        events[&notes],
+        [
+            ProgressEvent::Started {
+                file: notes.clone(),
+                key: "text/notes.txt".to_string(),
+                bytes: 11,
+            },
+            ProgressEvent::Bytes {
+                file: notes.clone(),
+                key: "text/notes.txt".to_string(),
+                target: "AWS S3".to_string(),
+                bytes: 11,
+            },
+            ProgressEvent::Completed {
+                file: notes.clone(),
+                key: "text/notes.txt".to_string(),
+                bytes: 11,
+            },
+        ]
+    );
+
+    let denied_events = &events[&denied];
+    assert_eq!(denied_events.len(), 2, "{:?}", denied_events);
+    assert!(matches!(
+        &denied_events[0],
+        ProgressEvent::Started { bytes: 2, .. }
+    ));
+    assert!(matches!(&denied_events[1], ProgressEvent::Failed { .. }));
+
+    match &events[&big][..] {
+        [ProgressEvent::Skipped { reason, .. }] => {
+            assert!(reason.contains("--max-file-size"), "{}", reason)
+        }
+        other => panic!("{:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn every_target_reports_its_bytes() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let notes = dir.write("notes.txt", "plain words");
+
+    let (events, result) = events_of(&[
+        "--backends",
+        "aws",
+        "--replicate-to",
+        "eu-west-1=dr-bucket",
+        &notes,
+    ])
+    .await;
+
+    result.unwrap();
+    let events = &events[&notes];
+    assert!(matches!(
+        events.first(),
+        Some(ProgressEvent::Started { .. })
+    ));
+    assert!(matches!(
+        events.last(),
+        Some(ProgressEvent::Completed { bytes: 11, .. })
+    ));
+    let targets: Vec<&str> = events
+        .iter()
+        .filter_map(|event| match event {
+            ProgressEvent::Bytes { target, bytes, .. } => {
+                assert_eq!(*bytes, 11);
+                Some(target.as_str())
+            }
+            _ => None,
+        })
+        .collect();
+    assert_eq!(targets.len(), 2, "{:?}", targets);
+    assert!(targets.contains(&"AWS S3"), "{:?}", targets);
+}
@@ -0,0 +1,122 @@
+//! `--receipts-dir`: a JSON receipt of every uploaded file and where it was stored
+
+mod common;
+
+use common::{run, sha256_hex, Env, MockS3, Reply, TestDir, BUCKET};
+use hyper::Method;
+use serde_json::{json, Value};
+
+/// Answer HEADs of stored objects with `header` set to `version`, as a versioned bucket would
+fn versioned(mock: &MockS3, header: &'static str, version: &'static str) {
+    mock.hook(move |request| {
+        (request.method == Method::HEAD).then(|| {
+            Reply::new(200)
+                .with_header("content-length", "11")
+                .with_header(header, version)
+        })
+    });
+}
+
+fn read_receipt(dir: &TestDir, key: &str) -> Value {
+    let path = dir.path().join("receipts").join(format!("{}.json", key));
+    serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn receipts_record_the_object_and_every_copy() {
+    let mock = MockS3::start().await;
+    let gcs = MockS3::start().await;
+    let _env = Env::aws(&mock).await.with_gcs(&gcs);
+    versioned(&mock, "x-amz-version-id", "3HL4kqtJlcpXroDTDmJ");
+    versioned(&gcs, "x-goog-generation", "1700000000000001");
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+    let receipts = dir.path().join("receipts");
+
+    run(&[
+        "upload",
+        "--backends",
+        "aws,gcs",
+        "--receipts-dir",
+        receipts.to_str().unwrap(),
+        &file,
+    ])
+    .await
+    .unwrap();
+
+    let mut receipt = read_receipt(&dir, "text/notes.txt");
+    let uploaded_at = receipt
+        .as_object_mut()
+        .unwrap()
+        .remove("uploaded_at")
+        .unwrap();
+    assert!(
+        chrono::DateTime::parse_from_rfc3339(uploaded_at.as_str().unwrap()).is_ok(),
+        "{}",
+        uploaded_at
+    );
+    assert_eq!(
+        receipt,
+        json!({
+            "file": file,
+            "key": "text/notes.txt",
+            "sha256": sha256_hex(b"plain words"),
+            "size": 11,
+            "copies": [
+                {"target": "AWS S3", "bucket": BUCKET, "version_id": "3HL4kqtJlcpXroDTDmJ"},
+                {"target": "GCS", "bucket": BUCKET, "version_id": "1700000000000001"},
+            ],
+        })
+    );
+}
+
+#[tokio::test]
+async fn unversioned_buckets_have_no_version_id() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+    let receipts = dir.path().join("receipts");
+
+    run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--receipts-dir",
+        receipts.to_str().unwrap(),
+        "--receipt-secret",
+        "receipt-secret",
+        &file,
+    ])
+    .await
+    .unwrap();
+
+    let receipt = read_receipt(&dir, "text/notes.txt");
+    assert_eq!(receipt["copies"][0]["version_id"], Value::Null);
+    let signature = receipt["signature"].as_str().unwrap();
+    assert!(signature.starts_with("sha256="), "{}", signature);
+    assert_eq!(signature.len(), "sha256=".len() + 64);
+}
+
+#[tokio::test]
+async fn failed_uploads_get_no_receipt() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    mock.hook(|request| (request.method == Method::PUT).then(|| Reply::error(403, "AccessDenied")));
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+    let receipts = dir.path().join("receipts");
+
+    let result = run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--receipts-dir",
+        receipts.to_str().unwrap(),
+        &file,
+    ])
+    .await;
+
+    assert!(result.is_err());
+    assert!(!receipts.join("text").join("notes.txt.json").exists());
+}
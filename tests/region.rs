@@ -0,0 +1,61 @@
+//! A bucket in another region than the client's, as S3 redirects to it
+
+mod common;
+
+use common::{run, Env, MockS3, Reply, TestDir};
+use hyper::Method;
+use s3_ml_uploader::error::AppError;
+
+/// Answer requests signed for any region but eu-west-1 as S3 answers them for a bucket there
+fn in_eu_west_1(mock: &MockS3) {
+    mock.hook(|request| {
+        let signed_for_eu = request
+            .header("authorization")
+            .is_some_and(|auth| auth.contains("/eu-west-1/s3/"));
+        (!signed_for_eu).then(|| {
+            Reply::error(301, "PermanentRedirect").with_header("x-amz-bucket-region", "eu-west-1")
+        })
+    });
+}
+
+#[tokio::test]
+async fn auto_region_retries_in_the_bucket_region() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    in_eu_west_1(&mock);
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+
+    run(&["upload", "--backends", "aws", "--auto-region", &file])
+        .await
+        .unwrap();
+
+    assert_eq!(mock.object("text/notes.txt").unwrap().body, b"plain words");
+    let puts = mock.requests_for(Method::PUT, "text/notes.txt");
+    assert_eq!(puts.len(), 2);
+    assert!(puts[0]
+        .header("authorization")
+        .unwrap()
+        .contains("/us-east-1/"));
+    assert!(puts[1]
+        .header("authorization")
+        .unwrap()
+        .contains("/eu-west-1/"));
+}
+
+#[tokio::test]
+async fn without_auto_region_the_upload_fails_once() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    in_eu_west_1(&mock);
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+
+    let err = run(&["upload", "--backends", "aws", &file])
+        .await
+        .unwrap_err();
+
+    assert!(matches!(err, AppError::UploadsFailed(1)), "{:?}", err);
+    assert!(mock.keys().is_empty());
+    assert_eq!(mock.requests_for(Method::PUT, "text/notes.txt").len(), 1);
+}
@@ -0,0 +1,65 @@
+//! `--replicate-to`: every AWS S3 upload written to a bucket in another region as well
+//!
+//! Both regions' requests reach the one mock; the bucket and the signing region tell them
+//! apart.
+
+mod common;
+
+use common::{run, Env, MockS3, Reply, Request, TestDir, BUCKET};
+use hyper::Method;
+use s3_ml_uploader::error::AppError;
+
+fn signed_in(request: &Request, region: &str) -> bool {
+    request
+        .header("authorization")
+        .is_some_and(|auth| auth.contains(&format!("/{}/s3/", region)))
+}
+
+async fn replicate() -> Result<(), AppError> {
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+    run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--replicate-to",
+        "eu-west-1=dr-bucket",
+        &file,
+    ])
+    .await
+}
+
+#[tokio::test]
+async fn each_region_gets_the_same_object() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+
+    replicate().await.unwrap();
+
+    let mut puts = mock.requests_for(Method::PUT, "text/notes.txt");
+    puts.sort_by(|a, b| a.bucket.cmp(&b.bucket));
+    assert_eq!(puts.len(), 2);
+    assert_eq!(puts[0].bucket, BUCKET);
+    assert!(signed_in(&puts[0], "us-east-1"));
+    assert_eq!(puts[1].bucket, "dr-bucket");
+    assert!(signed_in(&puts[1], "eu-west-1"));
+    assert!(puts.iter().all(|put| put.body == b"plain words"));
+}
+
+#[tokio::test]
+async fn a_failing_region_fails_the_file_but_not_the_other_region() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    mock.hook(|request| (request.bucket == "dr-bucket").then(|| Reply::error(403, "AccessDenied")));
+
+    let err = replicate().await.unwrap_err();
+
+    assert!(matches!(err, AppError::UploadsFailed(1)), "{:?}", err);
+    let stored = mock
+        .requests_for(Method::PUT, "text/notes.txt")
+        .into_iter()
+        .filter(|put| put.bucket == BUCKET)
+        .count();
+    assert_eq!(stored, 1);
+    assert!(mock.object("text/notes.txt").is_some());
+}
@@ -0,0 +1,82 @@
+//! Batch progress, recorded when a run asks for it, and `--resume-batch` skipping what it completed
+
+mod common;
+
+use common::{run, Env, MockS3, Reply, TestDir};
+use hyper::Method;
+use s3_ml_uploader::error::AppError;
+
+/// Refuse uploads of `text/b.txt`, as an interruption partway through the batch would leave it
+fn refuse_b(mock: &MockS3) {
+    mock.hook(|request| {
+        (request.method == Method::PUT && request.key == "text/b.txt")
+            .then(|| Reply::error(403, "AccessDenied"))
+    });
+}
+
+async fn upload(files: &[String], args: &[&str]) -> Result<(), AppError> {
+    let mut all = vec!["upload", "--backends", "aws"];
+    all.extend(args);
+    all.extend(files.iter().map(String::as_str));
+    run(&all).await
+}
+
+fn puts(mock: &MockS3, key: &str) -> usize {
+    mock.requests_for(Method::PUT, key).len()
+}
+
+#[tokio::test]
+async fn a_batch_recorded_in_the_checkpoint_dir_is_resumed_and_removed() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let progress = TestDir::new();
+    let files = [dir.write("a.txt", "first"), dir.write("b.txt", "second")];
+    let checkpoint_dir = progress.path().to_string_lossy().into_owned();
+
+    refuse_b(&mock);
+    let err = upload(&files, &["--checkpoint-dir", &checkpoint_dir])
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AppError::UploadsFailed(1)), "{:?}", err);
+    let recorded: Vec<_> = std::fs::read_dir(progress.path()).unwrap().collect();
+    assert_eq!(recorded.len(), 1);
+
+    mock.hook(|_| None);
+    upload(
+        &files,
+        &["--resume-batch", "--checkpoint-dir", &checkpoint_dir],
+    )
+    .await
+    .unwrap();
+
+    // a.txt was recorded by the first run and isn't sent again
+    assert_eq!(puts(&mock, "text/a.txt"), 1);
+    assert_eq!(puts(&mock, "text/b.txt"), 2);
+    assert_eq!(mock.keys(), ["text/a.txt", "text/b.txt"]);
+    assert_eq!(std::fs::read_dir(progress.path()).unwrap().count(), 0);
+}
+
+#[tokio::test]
+async fn a_run_that_asks_for_nothing_leaves_nothing_to_resume() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let progress = TestDir::new();
+    let files = [dir.write("a.txt", "first"), dir.write("b.txt", "second")];
+    let checkpoint_dir = progress.path().to_string_lossy().into_owned();
+
+    refuse_b(&mock);
+    upload(&files, &[]).await.unwrap_err();
+
+    mock.hook(|_| None);
+    upload(
+        &files,
+        &["--resume-batch", "--checkpoint-dir", &checkpoint_dir],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(puts(&mock, "text/a.txt"), 2);
+    assert_eq!(puts(&mock, "text/b.txt"), 2);
+}
@@ -0,0 +1,119 @@
+//! `download --resume`: continuing a `.part` left by an interrupted download
+
+mod common;
+
+use std::fs;
+
+use common::{run, sha256_hex, Env, MockS3, Reply, TestDir};
+use hyper::Method;
+use s3_ml_uploader::error::AppError;
+
+const KEY: &str = "models/weights.bin";
+
+/// A body long enough to be interrupted part way, stored with its SHA-256
+fn stored_object(mock: &MockS3) -> Vec<u8> {
+    let body: Vec<u8> = (0..4096u32).map(|n| (n % 251) as u8).collect();
+    let sha256 = sha256_hex(&body);
+    mock.insert_with_headers(KEY, body.clone(), &[("x-amz-meta-sha256", &sha256)]);
+    body
+}
+
+fn etag(body: &[u8]) -> String {
+    format!("\"{}\"", hex::encode(md5::compute(body).0))
+}
+
+async fn download(output: &str) -> Result<(), AppError> {
+    run(&["download", KEY, output, "--resume"]).await
+}
+
+#[tokio::test]
+async fn an_interrupted_download_continues_where_it_stopped() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let body = stored_object(&mock);
+    let dir = TestDir::new();
+    let output = dir.path().join("weights.bin");
+    let output = output.to_str().unwrap();
+    // What an earlier run received before it was interrupted
+    fs::write(format!("{}.part", output), &body[..1000]).unwrap();
+    fs::write(format!("{}.part.etag", output), etag(&body)).unwrap();
+
+    download(output).await.unwrap();
+
+    assert_eq!(fs::read(output).unwrap(), body);
+    let gets = mock.requests_for(Method::GET, KEY);
+    assert_eq!(gets.len(), 1);
+    assert_eq!(gets[0].header("range"), Some("bytes=1000-"));
+    assert_eq!(gets[0].header("if-match"), Some(etag(&body).as_str()));
+    assert!(!dir.path().join("weights.bin.part").exists());
+    assert!(!dir.path().join("weights.bin.part.etag").exists());
+}
+
+#[tokio::test]
+async fn a_part_of_another_version_is_started_over() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let body = stored_object(&mock);
+    let dir = TestDir::new();
+    let output = dir.path().join("weights.bin");
+    let output = output.to_str().unwrap();
+    fs::write(format!("{}.part", output), b"an older version").unwrap();
+    fs::write(format!("{}.part.etag", output), etag(b"an older version")).unwrap();
+
+    download(output).await.unwrap();
+
+    assert_eq!(fs::read(output).unwrap(), body);
+    let gets = mock.requests_for(Method::GET, KEY);
+    assert_eq!(gets[0].header("range"), Some("bytes=0-"));
+}
+
+#[tokio::test]
+async fn an_object_replaced_since_the_head_fails_and_forgets_the_part() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let body = stored_object(&mock);
+    let dir = TestDir::new();
+    let output = dir.path().join("weights.bin");
+    let output = output.to_str().unwrap();
+    fs::write(format!("{}.part", output), &body[..1000]).unwrap();
+    fs::write(format!("{}.part.etag", output), etag(&body)).unwrap();
+    // Replaced between the HEAD and the ranged GET, whose If-Match then fails
+    mock.hook(|request| {
+        (request.method == Method::GET && request.header("if-match").is_some())
+            .then(|| Reply::error(412, "PreconditionFailed"))
+    });
+
+    let err = download(output).await.unwrap_err();
+
+    assert!(
+        matches!(&err, AppError::Integrity(message) if message.contains("changed")),
+        "{:?}",
+        err
+    );
+    assert!(!dir.path().join("weights.bin").exists());
+    assert!(!dir.path().join("weights.bin.part").exists());
+}
+
+#[tokio::test]
+async fn a_corrupted_part_is_deleted() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let body = stored_object(&mock);
+    let dir = TestDir::new();
+    let output = dir.path().join("weights.bin");
+    let output = output.to_str().unwrap();
+    let mut corrupted = body[..1000].to_vec();
+    corrupted[10] ^= 0xff;
+    fs::write(format!("{}.part", output), &corrupted).unwrap();
+    fs::write(format!("{}.part.etag", output), etag(&body)).unwrap();
+
+    let err = download(output).await.unwrap_err();
+
+    assert!(
+        matches!(&err, AppError::Integrity(message) if message.contains("SHA-256")),
+        "{:?}",
+        err
+    );
+    assert!(!dir.path().join("weights.bin").exists());
+    assert!(!dir.path().join("weights.bin.part").exists());
+}
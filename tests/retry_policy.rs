@@ -0,0 +1,117 @@
+//! Which failed uploads are retried: `RetryPolicy` predicates and `--retry-on-status`
+//!
+//! On GCS, whose requests the app sends itself, so no SDK retries get in between.
+
+mod common;
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use common::{run, upload_args, Env, MockS3, Reply, TestDir};
+use hyper::Method;
+use s3_ml_uploader::{error::AppError, ml::FileTypePredictor, retry, run_upload, RetryPolicy};
+
+const KEY: &str = "text/notes.txt";
+
+/// Fail the first `failures` PUTs of `KEY` with `status`
+fn fail_puts(mock: &MockS3, failures: usize, status: u16) {
+    let puts = Arc::new(AtomicUsize::new(0));
+    mock.hook(move |request| {
+        let put = request.method == Method::PUT && request.key == KEY;
+        (put && puts.fetch_add(1, Ordering::SeqCst) < failures)
+            .then(|| Reply::error(status, "InternalError"))
+    });
+}
+
+async fn upload_with(policy: Option<RetryPolicy>, extra: &[&str]) -> Result<(), AppError> {
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+    let mut args = upload_args(&[&["--backends", "gcs"], extra, &[&file]].concat());
+    if let Some(policy) = policy {
+        args.retry_policy = policy;
+    }
+    run_upload(args, Box::new(FileTypePredictor::new()), Vec::new()).await
+}
+
+#[tokio::test]
+async fn server_errors_are_not_retried_by_default() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await.with_gcs(&mock);
+    fail_puts(&mock, 1, 500);
+
+    let err = upload_with(None, &[]).await.unwrap_err();
+
+    assert!(matches!(err, AppError::UploadsFailed(1)), "{:?}", err);
+    assert_eq!(mock.requests_for(Method::PUT, KEY).len(), 1);
+}
+
+#[tokio::test]
+async fn a_custom_predicate_decides_what_is_retried() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await.with_gcs(&mock);
+    fail_puts(&mock, 1, 500);
+    let asked = Arc::new(AtomicUsize::new(0));
+    let counted = Arc::clone(&asked);
+    let policy = RetryPolicy::new(move |err| {
+        counted.fetch_add(1, Ordering::SeqCst);
+        retry::is_retryable(err) || retry::status(err) == Some(500)
+    });
+
+    upload_with(Some(policy), &[]).await.unwrap();
+
+    assert_eq!(asked.load(Ordering::SeqCst), 1);
+    assert_eq!(mock.requests_for(Method::PUT, KEY).len(), 2);
+    assert_eq!(mock.object(KEY).unwrap().body, b"plain words");
+}
+
+#[tokio::test]
+async fn a_predicate_can_refuse_what_the_default_retries() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await.with_gcs(&mock);
+    fail_puts(&mock, 1, 429);
+
+    let err = upload_with(Some(RetryPolicy::new(|_| false)), &[])
+        .await
+        .unwrap_err();
+
+    assert!(matches!(err, AppError::UploadsFailed(1)), "{:?}", err);
+    assert_eq!(mock.requests_for(Method::PUT, KEY).len(), 1);
+}
+
+#[tokio::test]
+async fn retry_on_status_gives_up_after_its_attempts() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await.with_gcs(&mock);
+    fail_puts(&mock, usize::MAX, 502);
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+
+    let err = run(&[
+        "upload",
+        "--backends",
+        "gcs",
+        "--retry-on-status",
+        "502,504",
+        &file,
+    ])
+    .await
+    .unwrap_err();
+
+    assert!(matches!(err, AppError::UploadsFailed(1)), "{:?}", err);
+    assert_eq!(mock.requests_for(Method::PUT, KEY).len(), 3);
+}
+
+#[tokio::test]
+async fn retry_on_status_retries_the_statuses_it_names() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await.with_gcs(&mock);
+    fail_puts(&mock, 2, 504);
+
+    upload_with(None, &["--retry-on-status", "502,504"])
+        .await
+        .unwrap();
+
+    assert_eq!(mock.requests_for(Method::PUT, KEY).len(), 3);
+}
@@ -0,0 +1,88 @@
+//! `--sdk-fallback-http`: uploads the store refuses through the SDK go once more as a plain PUT
+
+mod common;
+
+use common::{run, Env, MockS3, Reply, TestDir};
+use hyper::Method;
+use s3_ml_uploader::error::AppError;
+
+/// Refuse the SDK's PUTs, which carry `x-amz-user-agent`, with `status` and `code`; plain
+/// signed PUTs are stored
+fn refuse_sdk_puts(mock: &MockS3, status: u16, code: &'static str) {
+    mock.hook(move |request| {
+        (request.method == Method::PUT && request.header("x-amz-user-agent").is_some())
+            .then(|| Reply::error(status, code))
+    });
+}
+
+async fn upload(args: &[&str]) -> (TestDir, Result<(), AppError>) {
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+    let mut all = vec!["upload", "--backends", "aws"];
+    all.extend(args);
+    all.push(&file);
+    let result = run(&all).await;
+    (dir, result)
+}
+
+#[tokio::test]
+async fn refused_uploads_are_retried_over_http() {
+    for (status, code) in [(501, "NotImplemented"), (400, "InvalidRequest")] {
+        let mock = MockS3::start().await;
+        let _env = Env::aws(&mock).await;
+        refuse_sdk_puts(&mock, status, code);
+
+        let (_dir, result) = upload(&["--sdk-fallback-http"]).await;
+
+        result.unwrap();
+        let puts = mock.requests_for(Method::PUT, "text/notes.txt");
+        assert_eq!(puts.len(), 2, "{}", code);
+        assert!(puts[0].header("x-amz-user-agent").is_some());
+        // The fallback is a path-style PUT signed with the HTTP backend's key
+        assert_eq!(puts[1].header("x-amz-user-agent"), None);
+        let authorization = puts[1].header("authorization").unwrap();
+        assert!(
+            authorization.contains("Credential=AKIDEXAMPLE/"),
+            "{}",
+            authorization
+        );
+        assert_eq!(mock.object("text/notes.txt").unwrap().body, b"plain words");
+    }
+}
+
+#[tokio::test]
+async fn refusals_fail_without_the_flag() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    refuse_sdk_puts(&mock, 501, "NotImplemented");
+
+    let (_dir, result) = upload(&[]).await;
+
+    assert!(
+        matches!(result, Err(AppError::UploadsFailed(1))),
+        "{:?}",
+        result
+    );
+    assert_eq!(mock.requests_for(Method::PUT, "text/notes.txt").len(), 1);
+    assert!(mock.keys().is_empty());
+}
+
+#[tokio::test]
+async fn other_errors_are_not_retried_over_http() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    refuse_sdk_puts(&mock, 403, "AccessDenied");
+
+    let (_dir, result) = upload(&["--sdk-fallback-http"]).await;
+
+    assert!(
+        matches!(result, Err(AppError::UploadsFailed(1))),
+        "{:?}",
+        result
+    );
+    assert!(mock
+        .requests_for(Method::PUT, "text/notes.txt")
+        .iter()
+        .all(|put| put.header("x-amz-user-agent").is_some()));
+    assert!(mock.keys().is_empty());
+}
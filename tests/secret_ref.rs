@@ -0,0 +1,155 @@
+//! `--secret-ref`: AWS SDK credentials read from a Secrets Manager secret
+//!
+//! The Secrets Manager client shares `AWS_ENDPOINT_URL` with S3, so the mock answers its
+//! `GetSecretValue` calls as well.
+
+mod common;
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use common::{run, Env, MockS3, Reply, Request, TestDir};
+use hyper::Method;
+use s3_ml_uploader::error::AppError;
+
+const SECRET: &str = r#"{"access_key_id": "AKIDFROMSECRET", "secret_access_key": "from-secret"}"#;
+
+fn is_get_secret_value(request: &Request) -> bool {
+    request.header("x-amz-target") == Some("secretsmanager.GetSecretValue")
+}
+
+/// Answer `GetSecretValue` with `secret`, counting the calls
+fn serve_secret(mock: &MockS3, secret: &'static str) -> Arc<AtomicUsize> {
+    let reads = Arc::new(AtomicUsize::new(0));
+    let counted = Arc::clone(&reads);
+    mock.hook(move |request| {
+        if !is_get_secret_value(request) {
+            return None;
+        }
+        counted.fetch_add(1, Ordering::SeqCst);
+        let body = serde_json::json!({
+            "ARN": "arn:aws:secretsmanager:us-east-1:123456789012:secret:ml/uploader-AbCdEf",
+            "Name": "ml/uploader",
+            "SecretString": secret,
+        });
+        Some(
+            Reply::new(200)
+                .with_header("content-type", "application/x-amz-json-1.1")
+                .with_body(body.to_string()),
+        )
+    });
+    reads
+}
+
+/// Authorization headers of the PUTs sent
+fn put_credentials(mock: &MockS3) -> Vec<String> {
+    mock.requests()
+        .into_iter()
+        .filter(|request| request.method == Method::PUT)
+        .map(|request| {
+            request
+                .header("authorization")
+                .unwrap_or_default()
+                .to_string()
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn uploads_are_signed_with_the_secret() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let reads = serve_secret(&mock, SECRET);
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+
+    run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--secret-ref",
+        "aws-sm:ml/uploader",
+        &file,
+    ])
+    .await
+    .unwrap();
+
+    let secret_reads: Vec<_> = mock
+        .requests()
+        .into_iter()
+        .filter(is_get_secret_value)
+        .collect();
+    assert_eq!(secret_reads.len(), 1);
+    let read: serde_json::Value = serde_json::from_slice(&secret_reads[0].body).unwrap();
+    assert_eq!(read["SecretId"], "ml/uploader");
+    assert_eq!(reads.load(Ordering::SeqCst), 1);
+
+    let credentials = put_credentials(&mock);
+    assert_eq!(credentials.len(), 1);
+    assert!(
+        credentials[0].contains("Credential=AKIDFROMSECRET/"),
+        "{}",
+        credentials[0]
+    );
+}
+
+#[tokio::test]
+async fn a_short_ttl_reads_the_secret_again() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let reads = serve_secret(&mock, SECRET);
+    let dir = TestDir::new();
+    let first = dir.write("a.txt", "plain words");
+    let second = dir.write("b.txt", "more words");
+
+    run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--secret-ref",
+        "aws-sm:ml/uploader",
+        "--secret-ttl",
+        "1s",
+        "--concurrency",
+        "1",
+        &first,
+        &second,
+    ])
+    .await
+    .unwrap();
+
+    // Credentials this close to expiring are fetched again for every request
+    assert!(reads.load(Ordering::SeqCst) >= 2);
+    assert!(put_credentials(&mock)
+        .iter()
+        .all(|authorization| authorization.contains("Credential=AKIDFROMSECRET/")));
+}
+
+#[tokio::test]
+async fn a_malformed_secret_fails_before_uploading() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    serve_secret(&mock, r#"{"access_key_id": "AKIDFROMSECRET"}"#);
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+
+    let err = run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--secret-ref",
+        "aws-sm:ml/uploader",
+        &file,
+    ])
+    .await
+    .unwrap_err();
+
+    assert!(
+        matches!(&err, AppError::Config(message) if message.contains("has no secret_access_key")),
+        "{:?}",
+        err
+    );
+    assert!(put_credentials(&mock).is_empty());
+}
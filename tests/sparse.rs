@@ -0,0 +1,84 @@
+//! `--sparse`: a sparse file uploaded without its holes, and rebuilt with them on download
+
+#![cfg(unix)]
+
+mod common;
+
+use std::{
+    fs::OpenOptions,
+    io::{Seek, SeekFrom, Write},
+    os::unix::fs::MetadataExt,
+};
+
+use common::{run, sha256_hex, Env, MockS3, TestDir};
+
+const SIZE: u64 = 8 * 1024 * 1024;
+
+#[tokio::test]
+async fn a_sparse_file_round_trips_with_its_holes() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    // 8 MiB, of which only a block at the start and one in the middle hold data
+    let path = dir.path().join("disk.img");
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(&path)
+        .unwrap();
+    file.set_len(SIZE).unwrap();
+    file.write_all(b"boot sector").unwrap();
+    file.seek(SeekFrom::Start(SIZE / 2)).unwrap();
+    file.write_all(b"superblock").unwrap();
+    drop(file);
+    let content = std::fs::read(&path).unwrap();
+
+    run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--sparse",
+        path.to_str().unwrap(),
+    ])
+    .await
+    .unwrap();
+
+    let key = "misc/disk.img";
+    let object = mock.object(key).unwrap();
+    assert!(object.body.len() < 16 * 1024, "{} bytes", object.body.len());
+    assert_eq!(object.metadata("sparse-size"), Some("8388608"));
+    assert_eq!(
+        object.metadata("sparse-sha256"),
+        Some(sha256_hex(&content).as_str())
+    );
+
+    let output = dir.path().join("restored.img");
+    run(&["download", key, output.to_str().unwrap()])
+        .await
+        .unwrap();
+
+    assert_eq!(std::fs::read(&output).unwrap(), content);
+    // Where the filesystem has holes, the gaps take no blocks
+    let allocated = std::fs::metadata(&output).unwrap().blocks() * 512;
+    let source_allocated = std::fs::metadata(&path).unwrap().blocks() * 512;
+    if source_allocated < SIZE {
+        assert!(allocated < SIZE / 4, "{} bytes allocated", allocated);
+    }
+}
+
+#[tokio::test]
+async fn files_without_zero_blocks_upload_as_they_are() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+
+    run(&["upload", "--backends", "aws", "--sparse", &file])
+        .await
+        .unwrap();
+
+    let object = mock.object("text/notes.txt").unwrap();
+    assert_eq!(object.body, b"plain words");
+    assert_eq!(object.metadata("sparse-size"), None);
+}
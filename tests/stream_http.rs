@@ -0,0 +1,80 @@
+//! `--stream-above` to the HTTP and GCS backends: one PUT, signed with the first pass's
+//! digest and sent as the file is read
+
+mod common;
+
+use common::{run, sha256_hex, Env, MockS3, TestDir};
+use hyper::Method;
+
+/// 12 MiB, read in several parts
+fn content() -> Vec<u8> {
+    (0..12 * 1024 * 1024u32).map(|i| (i % 251) as u8).collect()
+}
+
+async fn streamed_upload(mock: &MockS3, backend: &str) -> String {
+    let dir = TestDir::new();
+    let file = dir.write("weights.bin", content());
+
+    run(&[
+        "upload",
+        "--backends",
+        backend,
+        "--stream-above",
+        "1MiB",
+        "--part-size",
+        "5MiB",
+        &file,
+    ])
+    .await
+    .unwrap();
+
+    mock.keys().pop().expect("object uploaded")
+}
+
+#[tokio::test]
+async fn the_http_backend_streams_one_signed_put() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+
+    let key = streamed_upload(&mock, "http").await;
+
+    let puts = mock.requests_for(Method::PUT, &key);
+    assert_eq!(puts.len(), 1);
+    let put = &puts[0];
+    assert_eq!(put.query.get("uploadId"), None);
+    assert_eq!(
+        put.header("content-length"),
+        Some(content().len().to_string().as_str())
+    );
+    // Signed with the digest of the hashing pass, not UNSIGNED-PAYLOAD
+    assert_eq!(
+        put.header("x-amz-content-sha256"),
+        Some(sha256_hex(&content()).as_str())
+    );
+    assert!(put.header("authorization").is_some());
+    assert_eq!(put.header("content-md5"), None);
+    assert_eq!(put.body, content());
+    assert_eq!(mock.object(&key).unwrap().body, content());
+}
+
+#[tokio::test]
+async fn the_gcs_backend_streams_one_signed_put() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await.with_gcs(&mock);
+
+    let key = streamed_upload(&mock, "gcs").await;
+
+    let puts = mock.requests_for(Method::PUT, &key);
+    assert_eq!(puts.len(), 1);
+    let authorization = puts[0].header("authorization").unwrap();
+    assert!(
+        authorization.contains("Credential=GOOGEXAMPLE/"),
+        "{}",
+        authorization
+    );
+    assert_eq!(
+        puts[0].header("x-amz-content-sha256"),
+        Some(sha256_hex(&content()).as_str())
+    );
+    assert_eq!(mock.object(&key).unwrap().body, content());
+}
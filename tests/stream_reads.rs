@@ -0,0 +1,71 @@
+//! Benchmark of how often a large file is read from disk, with and without `--stream-above`
+//!
+//! Ignored by default; run it alone with
+//! `cargo test --release --test stream_reads -- --ignored --nocapture`. Linux only: bytes
+//! read are taken from `rchar` of `/proc/self/io`, which counts file reads but not the
+//! `recv` calls the in-process mock reads the uploaded bodies with.
+
+mod common;
+
+use common::{run, Env, MockS3, TestDir};
+
+const SIZE: usize = 64 * 1024 * 1024;
+
+/// Bytes this process has read through `read` calls so far
+fn bytes_read() -> u64 {
+    let io = std::fs::read_to_string("/proc/self/io").expect("/proc/self/io");
+    io.lines()
+        .find_map(|line| line.strip_prefix("rchar: "))
+        .and_then(|n| n.parse().ok())
+        .expect("rchar in /proc/self/io")
+}
+
+/// How many times the file was read uploading it with `args`
+async fn reads_of_file(file: &str, args: &[&str]) -> f64 {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+
+    let before = bytes_read();
+    let mut all = vec!["upload", "--backends", "aws"];
+    all.extend(args);
+    all.push(file);
+    run(&all).await.unwrap();
+    (bytes_read() - before) as f64 / SIZE as f64
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "benchmark; uploads a 64 MiB file several times"]
+async fn disk_reads_of_a_large_file() {
+    let dir = TestDir::new();
+    let content: Vec<u8> = (0..SIZE as u32).map(|i| (i % 251) as u8).collect();
+    let file = dir.write("weights.bin", &content);
+    let replicas = [
+        "--replicate-to",
+        "eu-west-1=dr-one",
+        "--replicate-to",
+        "eu-west-2=dr-two",
+    ];
+
+    for (label, streamed, args) in [
+        ("loaded, 1 target", false, vec![]),
+        ("loaded, 3 targets", false, replicas.to_vec()),
+        ("streamed, 1 target", true, vec!["--stream-above", "1MiB"]),
+        (
+            "streamed, 3 targets",
+            true,
+            [&["--stream-above", "1MiB"][..], &replicas].concat(),
+        ),
+    ] {
+        let reads = reads_of_file(&file, &args).await;
+        println!("{}: the file was read {:.2} time(s)", label, reads);
+
+        // Streaming hashes first, then reads once for every target
+        let expected = if streamed { 2.0 } else { 1.0 };
+        assert!(
+            reads > expected - 0.05 && reads < expected + 0.1,
+            "{}: {}",
+            label,
+            reads
+        );
+    }
+}
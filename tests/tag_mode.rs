@@ -0,0 +1,110 @@
+//! `--tag-mode`: an upload's tags replace the ones its key has, or are merged into them
+
+mod common;
+
+use common::{run, Env, MockS3, TestDir};
+use hyper::Method;
+use s3_ml_uploader::error::AppError;
+
+fn tags(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+async fn upload(args: &[&str]) -> Result<(), AppError> {
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+    let mut all = vec!["upload", "--backends", "aws", "--tag-classification"];
+    all.extend(args);
+    all.push(&file);
+    run(&all).await
+}
+
+#[tokio::test]
+async fn replace_drops_the_stored_tags() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    mock.insert_with_tags(
+        "text/notes.txt",
+        b"older words",
+        &[("owner", "alice"), ("filetype", "misc")],
+    );
+
+    upload(&[]).await.unwrap();
+
+    let object = mock.object("text/notes.txt").unwrap();
+    assert_eq!(object.body, b"plain words");
+    assert_eq!(
+        object.tags,
+        tags(&[("confidence", "1.00"), ("filetype", "text")])
+    );
+    // Replacing needs no look at the stored tags
+    assert!(mock
+        .requests()
+        .iter()
+        .all(|request| !request.query.contains_key("tagging")));
+}
+
+#[tokio::test]
+async fn merge_keeps_the_stored_tags_and_overrides_shared_keys() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    mock.insert_with_tags(
+        "text/notes.txt",
+        b"older words",
+        &[("owner", "alice"), ("filetype", "misc")],
+    );
+
+    upload(&["--tag-mode", "merge"]).await.unwrap();
+
+    assert_eq!(
+        mock.object("text/notes.txt").unwrap().tags,
+        tags(&[
+            ("confidence", "1.00"),
+            ("filetype", "text"),
+            ("owner", "alice")
+        ])
+    );
+    let lookups: Vec<_> = mock
+        .requests_for(Method::GET, "text/notes.txt")
+        .into_iter()
+        .filter(|request| request.query.contains_key("tagging"))
+        .collect();
+    assert_eq!(lookups.len(), 1);
+}
+
+#[tokio::test]
+async fn merging_into_a_new_key_gives_the_new_tags() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+
+    upload(&["--tag-mode", "merge"]).await.unwrap();
+
+    assert_eq!(
+        mock.object("text/notes.txt").unwrap().tags,
+        tags(&[("confidence", "1.00"), ("filetype", "text")])
+    );
+}
+
+#[tokio::test]
+async fn a_merge_over_ten_tags_fails_the_file() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let stored: Vec<(String, String)> = (0..9)
+        .map(|n| (format!("team{}", n), "yes".to_string()))
+        .collect();
+    let stored: Vec<(&str, &str)> = stored
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_str()))
+        .collect();
+    mock.insert_with_tags("text/notes.txt", b"older words", &stored);
+
+    let err = upload(&["--tag-mode", "merge"]).await.unwrap_err();
+
+    assert!(matches!(err, AppError::UploadsFailed(1)), "{:?}", err);
+    let object = mock.object("text/notes.txt").unwrap();
+    assert_eq!(object.body, b"older words");
+    assert_eq!(object.tags.len(), 9);
+}
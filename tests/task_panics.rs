@@ -0,0 +1,48 @@
+//! A per-file task that panics fails its file, not the batch
+
+mod common;
+
+use bytes::Bytes;
+use common::{upload_args, Env, MockS3, TestDir};
+use s3_ml_uploader::{error::AppError, ml::FileTypePredictor, run_upload, ContentTransform};
+
+/// Panics on a body of `boom`, standing in for a bug one file runs into
+struct PanicOnBoom;
+
+impl ContentTransform for PanicOnBoom {
+    fn transform(&self, input: Bytes) -> Result<Bytes, AppError> {
+        assert_ne!(&input[..], b"boom", "transform bug");
+        Ok(input)
+    }
+}
+
+#[tokio::test]
+async fn a_panicking_file_is_counted_as_failed_and_the_rest_upload() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let boom = dir.write("boom.txt", "boom");
+    let notes = dir.write("notes.txt", "plain words");
+    let more = dir.write("more.txt", "more words");
+
+    // One file at a time, so the others are only started after the panic
+    let err = run_upload(
+        upload_args(&[
+            "--backends",
+            "aws",
+            "--concurrency",
+            "1",
+            &boom,
+            &notes,
+            &more,
+        ]),
+        Box::new(FileTypePredictor::new()),
+        vec![Box::new(PanicOnBoom)],
+    )
+    .await
+    .unwrap_err();
+
+    assert!(matches!(err, AppError::UploadsFailed(1)), "{:?}", err);
+    assert_eq!(err.exit_code(), 1);
+    assert_eq!(mock.keys(), ["text/more.txt", "text/notes.txt"]);
+}
@@ -0,0 +1,127 @@
+//! Content transforms plugged into the library, run before classification and upload
+
+mod common;
+
+use bytes::Bytes;
+use common::{run, sha256_hex, upload_args, Env, MockS3, TestDir};
+use s3_ml_uploader::{error::AppError, ml::FileTypePredictor, run_upload, ContentTransform};
+
+struct Identity;
+
+impl ContentTransform for Identity {
+    fn transform(&self, input: Bytes) -> Result<Bytes, AppError> {
+        Ok(input)
+    }
+}
+
+struct Uppercase;
+
+impl ContentTransform for Uppercase {
+    fn transform(&self, input: Bytes) -> Result<Bytes, AppError> {
+        Ok(input.to_ascii_uppercase().into())
+    }
+}
+
+/// Turns any content into something the predictor takes for a PDF
+struct PdfHeader;
+
+impl ContentTransform for PdfHeader {
+    fn transform(&self, input: Bytes) -> Result<Bytes, AppError> {
+        Ok([b"%PDF-1.7\n".as_slice(), &input].concat().into())
+    }
+}
+
+async fn upload(file: &str, transforms: Vec<Box<dyn ContentTransform>>) {
+    run_upload(
+        upload_args(&["--backends", "aws", file]),
+        Box::new(FileTypePredictor::new()),
+        transforms,
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn the_transformed_content_is_hashed_and_uploaded() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words\n");
+
+    upload(&file, vec![Box::new(Identity), Box::new(Uppercase)]).await;
+
+    let object = mock.object("text/notes.txt").unwrap();
+    assert_eq!(object.body, b"PLAIN WORDS\n");
+    assert_eq!(
+        object.metadata("sha256"),
+        Some(sha256_hex(b"PLAIN WORDS\n").as_str())
+    );
+}
+
+#[tokio::test]
+async fn identity_leaves_content_alone() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words\n");
+
+    upload(&file, vec![Box::new(Identity)]).await;
+
+    assert_eq!(
+        mock.object("text/notes.txt").unwrap().body,
+        b"plain words\n"
+    );
+}
+
+#[tokio::test]
+async fn classification_sees_the_transformed_content() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words\n");
+
+    // In order: the header is added after uppercasing, so it stays as it is
+    upload(&file, vec![Box::new(Uppercase), Box::new(PdfHeader)]).await;
+
+    assert_eq!(mock.keys(), ["documents/notes.txt"]);
+    assert_eq!(
+        mock.object("documents/notes.txt").unwrap().body,
+        b"%PDF-1.7\nPLAIN WORDS\n"
+    );
+}
+
+#[tokio::test]
+async fn strip_metadata_removes_exif_from_uploaded_jpegs() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let exif = b"Exif\0\0MM\0*GPS 51.5N 0.1W";
+    let jpeg: Vec<u8> = [
+        &[0xFF, 0xD8, 0xFF, 0xE1][..],
+        &((exif.len() + 2) as u16).to_be_bytes(),
+        exif,
+        &[0xFF, 0xDA, 0x00, 0x02, 0x12, 0x34, 0xFF, 0xD9],
+    ]
+    .concat();
+    let file = dir.write("photo.jpg", &jpeg);
+
+    run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--strip-metadata=images",
+        &file,
+    ])
+    .await
+    .unwrap();
+
+    let object = mock.object("images/photo.jpg").unwrap();
+    assert_eq!(
+        object.body,
+        [0xFF, 0xD8, 0xFF, 0xDA, 0x00, 0x02, 0x12, 0x34, 0xFF, 0xD9]
+    );
+    assert_eq!(
+        object.metadata("sha256"),
+        Some(sha256_hex(&object.body).as_str())
+    );
+}
@@ -0,0 +1,64 @@
+//! `--user-agent`: how requests identify themselves
+
+mod common;
+
+use common::{run, Env, MockS3, Request, TestDir};
+use hyper::Method;
+
+const KEY: &str = "text/notes.txt";
+
+async fn put(backend: &str, extra: &[&str]) -> Request {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await.with_gcs(&mock);
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+
+    let mut args = vec!["upload", "--backends", backend];
+    args.extend(extra);
+    args.push(&file);
+    run(&args).await.unwrap();
+
+    mock.requests_for(Method::PUT, KEY).remove(0)
+}
+
+#[tokio::test]
+async fn http_requests_carry_the_given_user_agent() {
+    let put = put("gcs", &["--user-agent", "ml-ingest/2.1 (team-vision)"]).await;
+
+    assert_eq!(
+        put.header("user-agent"),
+        Some("ml-ingest/2.1 (team-vision)")
+    );
+}
+
+#[tokio::test]
+async fn http_requests_name_the_tool_by_default() {
+    let put = put("gcs", &[]).await;
+
+    assert_eq!(
+        put.header("user-agent"),
+        Some(concat!("s3-ml-uploader/", env!("CARGO_PKG_VERSION")))
+    );
+}
+
+#[tokio::test]
+async fn the_sdk_appends_the_user_agent_as_its_app_name() {
+    let put = put("aws", &["--user-agent", "ml-ingest/2.1"]).await;
+
+    let user_agent = put.header("x-amz-user-agent").unwrap();
+    assert!(user_agent.contains("app/ml-ingest-2.1"), "{}", user_agent);
+}
+
+#[test]
+fn empty_and_non_ascii_user_agents_are_rejected() {
+    use clap::Parser;
+    use s3_ml_uploader::cli::Cli;
+
+    for user_agent in ["", "  ", "uploadér/1.0"] {
+        assert!(
+            Cli::try_parse_from(["s3-ml-uploader", "upload", "--user-agent", user_agent]).is_err(),
+            "{:?}",
+            user_agent
+        );
+    }
+}
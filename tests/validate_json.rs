@@ -0,0 +1,50 @@
+//! `--validate-json`: JSON files checked against a schema, skipped or failed when invalid
+
+mod common;
+
+use common::{run, Env, MockS3, TestDir};
+use s3_ml_uploader::error::AppError;
+
+const SCHEMA: &str = r#"{"type": "object", "required": ["label"]}"#;
+
+async fn upload(on_invalid: &str) -> (MockS3, Result<(), AppError>) {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    let dir = TestDir::new();
+    let schema = dir.write("schema.json", SCHEMA);
+    let valid = dir.write("good.json", r#"{"label": "cat"}"#);
+    let invalid = dir.write("bad.json", r#"{"score": 1}"#);
+    let result = run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--validate-json",
+        &schema,
+        "--on-invalid",
+        on_invalid,
+        &valid,
+        &invalid,
+    ])
+    .await;
+    (mock, result)
+}
+
+#[tokio::test]
+async fn invalid_documents_are_skipped() {
+    let (mock, result) = upload("skip").await;
+
+    result.unwrap();
+    assert_eq!(mock.keys(), ["text/good.json"]);
+}
+
+#[tokio::test]
+async fn invalid_documents_fail_with_on_invalid_fail() {
+    let (mock, result) = upload("fail").await;
+
+    assert!(
+        matches!(result, Err(AppError::UploadsFailed(1))),
+        "{:?}",
+        result
+    );
+    assert_eq!(mock.keys(), ["text/good.json"]);
+}
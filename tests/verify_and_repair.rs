@@ -0,0 +1,83 @@
+//! `--verify-and-repair`: uploads read back, and re-uploaded while they don't match
+//!
+//! The mock stands in for a store that acknowledges a PUT but keeps other bytes: it
+//! answers a PUT as stored while leaving a corrupted object in place.
+
+mod common;
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use common::{run, sha256_hex, Env, MockS3, Reply, TestDir};
+use hyper::Method;
+use s3_ml_uploader::error::AppError;
+
+const KEY: &str = "text/notes.txt";
+
+/// Acknowledge the first `lost` PUTs of `KEY` without storing them, over corrupted bytes
+fn lose_puts(mock: &MockS3, lost: usize) {
+    mock.insert(KEY, b"plain wordz".to_vec());
+    let puts = Arc::new(AtomicUsize::new(0));
+    mock.hook(move |request| {
+        let put = request.method == Method::PUT && request.key == KEY;
+        (put && puts.fetch_add(1, Ordering::SeqCst) < lost).then(|| {
+            let etag = format!("\"{}\"", hex::encode(md5::compute(&request.body).0));
+            Reply::new(200).with_header("etag", &etag)
+        })
+    });
+}
+
+async fn upload(attempts: &str) -> Result<(), AppError> {
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+    run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--verify-and-repair",
+        attempts,
+        &file,
+    ])
+    .await
+}
+
+#[tokio::test]
+async fn a_corrupted_upload_is_repaired() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    lose_puts(&mock, 1);
+
+    upload("3").await.unwrap();
+
+    let object = mock.object(KEY).unwrap();
+    assert_eq!(sha256_hex(&object.body), sha256_hex(b"plain words"));
+    assert_eq!(mock.requests_for(Method::PUT, KEY).len(), 2);
+    assert_eq!(mock.requests_for(Method::GET, KEY).len(), 2);
+}
+
+#[tokio::test]
+async fn an_upload_that_verifies_is_read_back_once() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+
+    upload("3").await.unwrap();
+
+    assert_eq!(mock.requests_for(Method::PUT, KEY).len(), 1);
+    assert_eq!(mock.requests_for(Method::GET, KEY).len(), 1);
+}
+
+#[tokio::test]
+async fn corruption_past_the_attempts_fails_the_upload() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    lose_puts(&mock, usize::MAX);
+
+    let err = upload("2").await.unwrap_err();
+
+    assert!(matches!(err, AppError::UploadsFailed(1)), "{:?}", err);
+    // The first upload and two repairs
+    assert_eq!(mock.requests_for(Method::PUT, KEY).len(), 3);
+    assert_eq!(mock.object(KEY).unwrap().body, b"plain wordz");
+}
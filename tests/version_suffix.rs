@@ -0,0 +1,84 @@
+//! `--version-suffix`: version tokens before the extension, so uploads don't replace each other
+
+mod common;
+
+use common::{run, sha256_hex, Env, MockS3, TestDir};
+use hyper::Method;
+
+async fn upload(mock: &MockS3, strategy: &str, contents: &str) {
+    let _env = Env::aws(mock).await;
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", contents);
+    run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--version-suffix",
+        strategy,
+        &file,
+    ])
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn counters_take_the_lowest_free_number() {
+    let mock = MockS3::start().await;
+    mock.insert("text/notes.1.txt", "first");
+    mock.insert("text/notes.2.txt", "second");
+
+    upload(&mock, "counter", "third").await;
+
+    assert_eq!(mock.object("text/notes.3.txt").unwrap().body, b"third");
+    assert_eq!(mock.object("text/notes.1.txt").unwrap().body, b"first");
+    let heads: Vec<String> = mock
+        .requests()
+        .into_iter()
+        .filter(|request| request.method == Method::HEAD)
+        .map(|request| request.key)
+        .collect();
+    assert_eq!(
+        heads,
+        ["text/notes.1.txt", "text/notes.2.txt", "text/notes.3.txt"]
+    );
+}
+
+#[tokio::test]
+async fn successive_counters_keep_every_version() {
+    let mock = MockS3::start().await;
+
+    upload(&mock, "counter", "first").await;
+    upload(&mock, "counter", "second").await;
+
+    assert_eq!(mock.keys(), ["text/notes.1.txt", "text/notes.2.txt"]);
+}
+
+#[tokio::test]
+async fn hashes_name_the_content() {
+    let mock = MockS3::start().await;
+
+    upload(&mock, "hash", "plain words").await;
+    upload(&mock, "hash", "plain words").await;
+
+    let key = format!("text/notes.{}.txt", &sha256_hex(b"plain words")[..12]);
+    assert_eq!(mock.keys(), [key]);
+}
+
+#[tokio::test]
+async fn timestamps_are_taken_at_the_start_of_the_run() {
+    let mock = MockS3::start().await;
+
+    upload(&mock, "timestamp", "plain words").await;
+
+    let keys = mock.keys();
+    assert_eq!(keys.len(), 1);
+    let token = keys[0]
+        .strip_prefix("text/notes.")
+        .and_then(|rest| rest.strip_suffix(".txt"))
+        .unwrap();
+    assert!(
+        chrono::NaiveDateTime::parse_from_str(token, "%Y-%m-%dT%H-%M-%SZ").is_ok(),
+        "{}",
+        token
+    );
+}
@@ -0,0 +1,92 @@
+//! `--webhook-url` notifications, received by the mock as POSTs outside the bucket
+
+mod common;
+
+use common::{run, Env, MockS3, Reply, Request, TestDir};
+use hyper::Method;
+use serde_json::Value;
+
+fn notifications(mock: &MockS3) -> Vec<Value> {
+    mock.requests()
+        .into_iter()
+        .filter(|request| request.bucket == "webhook")
+        .map(|request| serde_json::from_slice(&request.body).unwrap())
+        .collect()
+}
+
+fn receive(mock: &MockS3, status: u16) {
+    mock.hook(move |request: &Request| {
+        (request.bucket == "webhook" && request.method == Method::POST).then(|| Reply::new(status))
+    });
+}
+
+#[tokio::test]
+async fn each_finished_file_is_reported() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    receive(&mock, 200);
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+    let url = format!("{}/webhook", mock.endpoint);
+
+    run(&["upload", "--backends", "aws", "--webhook-url", &url, &file])
+        .await
+        .unwrap();
+
+    let notifications = notifications(&mock);
+    assert_eq!(notifications.len(), 1);
+    let event = &notifications[0];
+    assert_eq!(event["file"], file.as_str());
+    assert_eq!(event["key"], "text/notes.txt");
+    assert_eq!(event["backends"], serde_json::json!(["AWS S3"]));
+    assert_eq!(event["status"], "uploaded");
+    assert_eq!(event["bytes"], 11);
+    assert!(event["duration_ms"].is_u64());
+}
+
+#[tokio::test]
+async fn batch_mode_reports_the_summary_once() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    receive(&mock, 200);
+    let dir = TestDir::new();
+    let first = dir.write("a.txt", "plain words");
+    let second = dir.write("b.txt", "more words");
+    let url = format!("{}/webhook", mock.endpoint);
+
+    run(&[
+        "upload",
+        "--backends",
+        "aws",
+        "--webhook-url",
+        &url,
+        "--webhook-mode",
+        "batch",
+        &first,
+        &second,
+    ])
+    .await
+    .unwrap();
+
+    let notifications = notifications(&mock);
+    assert_eq!(notifications.len(), 1);
+    assert_eq!(notifications[0]["files"].as_array().unwrap().len(), 2);
+    assert_eq!(notifications[0]["summary"]["succeeded"], 2);
+}
+
+#[tokio::test]
+async fn an_undeliverable_notification_does_not_fail_the_upload() {
+    let mock = MockS3::start().await;
+    let _env = Env::aws(&mock).await;
+    receive(&mock, 403);
+    let dir = TestDir::new();
+    let file = dir.write("notes.txt", "plain words");
+    let url = format!("{}/webhook", mock.endpoint);
+
+    run(&["upload", "--backends", "aws", "--webhook-url", &url, &file])
+        .await
+        .unwrap();
+
+    assert_eq!(notifications(&mock).len(), 1);
+    assert!(mock.object("text/notes.txt").is_some());
+}